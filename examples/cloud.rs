@@ -0,0 +1,88 @@
+//! Shows how to draw nodes with a custom renderer. The 'internet' node is
+//! drawn as a cloud, while its size, position and edges are computed like
+//! the size, position and edges of a regular box.
+
+use layout::backends::svg::SVGWriter;
+use layout::core::base::Orientation;
+use layout::core::color::Color;
+use layout::core::format::RenderBackend;
+use layout::core::geometry::Point;
+use layout::core::style::StyleAttr;
+use layout::core::utils::save_to_file;
+use layout::std_shapes::render::get_shape_size;
+use layout::std_shapes::shapes::*;
+use layout::topo::layout::VisualGraph;
+
+/// The centers and the radii of the puffs of the cloud, relative to a unit
+/// square.
+const PUFFS: [(f64, f64, f64); 5] = [
+    (0.25, 0.6, 0.25),
+    (0.45, 0.35, 0.3),
+    (0.7, 0.4, 0.25),
+    (0.75, 0.65, 0.22),
+    (0.5, 0.68, 0.25),
+];
+
+/// Draw a cloud with the label \p label in the box at \p top_left.
+fn draw_cloud(
+    label: &str,
+    canvas: &mut dyn RenderBackend,
+    top_left: Point,
+    size: Point,
+    look: &StyleAttr,
+) {
+    let puff = |i: usize| {
+        let (x, y, r) = PUFFS[i];
+        let center = top_left.add(Point::new(x * size.x, y * size.y));
+        (center, Point::new(2. * r * size.x, 2. * r * size.y))
+    };
+
+    // Draw the outline of all of the puffs, and then cover the inner lines
+    // with puffs that have no outline.
+    for i in 0..PUFFS.len() {
+        let (center, sz) = puff(i);
+        canvas.draw_circle(center, sz, look, Option::None);
+    }
+    let mut inner = look.clone();
+    inner.line_width = 0;
+    for i in 0..PUFFS.len() {
+        let (center, sz) = puff(i);
+        canvas.draw_circle(center, sz, &inner, Option::None);
+    }
+    let center = top_left.add(size.scale(0.5));
+    canvas.draw_text(center, label, look);
+}
+
+fn main() {
+    let mut vg = VisualGraph::new(Orientation::TopToBottom);
+    let dir = Orientation::LeftToRight;
+
+    let add_box = |vg: &mut VisualGraph, label: &str| {
+        let shape = ShapeKind::new_box(label);
+        let size = get_shape_size(dir, &shape, 15, false);
+        vg.add_node(Element::create(shape, StyleAttr::simple(), dir, size))
+    };
+    let laptop = add_box(&mut vg, "laptop");
+    let phone = add_box(&mut vg, "phone");
+    let server = add_box(&mut vg, "server");
+
+    let mut look = StyleAttr::simple();
+    look.fill_color = Option::Some(Color::fast("lightblue"));
+    let shape = ShapeKind::new_box("internet");
+    let size = get_shape_size(dir, &shape, 15, false).scale(1.5);
+    let mut cloud = Element::create(shape, look, dir, size);
+    cloud.set_renderer(|canvas, top_left, size, look| {
+        draw_cloud("internet", canvas, top_left, size, look);
+    });
+    let internet = vg.add_node(cloud);
+
+    vg.add_edge(Arrow::simple(""), laptop, internet);
+    vg.add_edge(Arrow::simple(""), phone, internet);
+    vg.add_edge(Arrow::simple(""), internet, server);
+
+    let mut svg = SVGWriter::new();
+    vg.do_it(false, false, false, &mut svg);
+    let filename = "/tmp/cloud.svg";
+    let _ = save_to_file(filename, &svg.finalize());
+    println!("Wrote {}", filename);
+}
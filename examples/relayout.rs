@@ -0,0 +1,57 @@
+//! Measures the time it takes to repeatedly build and lay out the same graph,
+//! with and without a shared text size cache.
+
+use layout::backends::svg::SVGWriter;
+use layout::core::geometry::TextSizeCache;
+use layout::gv::{DotParser, GraphBuilder};
+use std::time::Instant;
+
+const ITERATIONS: usize = 20;
+
+fn generate_graph(n: usize) -> String {
+    let mut program = String::from("digraph {\n");
+    for i in 0..n {
+        program.push_str(&format!(
+            "n{} [shape=record, label=\"{{node number {}|{{in|out}}}}\"];\n",
+            i, i
+        ));
+        if i > 0 {
+            program.push_str(&format!(
+                "n{} -> n{} [label=\"edge {}\"];\n",
+                i / 2,
+                i,
+                i
+            ));
+        }
+    }
+    program.push('}');
+    program
+}
+
+/// Build and lay out \p program 'ITERATIONS' times.
+/// \returns the time it took, in milliseconds.
+fn relayout(program: &str, cache: Option<&TextSizeCache>) -> u128 {
+    let ast = DotParser::new(program).process().unwrap();
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let mut gb = GraphBuilder::new();
+        if let Option::Some(cache) = cache {
+            gb.set_text_cache(cache.clone());
+        }
+        gb.visit_graph(&ast);
+        let mut vg = gb.get();
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+    }
+    start.elapsed().as_millis()
+}
+
+fn main() {
+    let program = generate_graph(60);
+    let cache = TextSizeCache::new();
+    let uncached = relayout(&program, None);
+    let cached = relayout(&program, Some(&cache));
+    println!("{} layouts without a cache: {}ms", ITERATIONS, uncached);
+    println!("{} layouts with a shared cache: {}ms", ITERATIONS, cached);
+    println!("The cache holds {} entries.", cache.len());
+}
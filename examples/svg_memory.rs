@@ -0,0 +1,76 @@
+//! Measures the peak memory that is used when saving a large SVG drawing,
+//! with 'finalize', which builds the document in a string, and with
+//! 'finalize_to', which writes the document to the output directly.
+
+use layout::backends::svg::SVGWriter;
+use layout::gv::{DotParser, GraphBuilder};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// An allocator that keeps track of the peak number of allocated bytes.
+struct PeakAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            let now = CURRENT.fetch_add(layout.size(), Ordering::SeqCst);
+            PEAK.fetch_max(now + layout.size(), Ordering::SeqCst);
+        }
+        ptr
+    }
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::SeqCst);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAlloc = PeakAlloc;
+
+fn generate_graph(n: usize) -> String {
+    let mut program = String::from("digraph {\n");
+    for i in 1..n {
+        program.push_str(&format!(
+            "n{} -> n{} [label=\"edge {}\"];\n",
+            i / 3,
+            i,
+            i
+        ));
+    }
+    program.push('}');
+    program
+}
+
+/// Run \p save and \returns the peak number of bytes that were allocated
+/// on top of the memory that was in use before the call.
+fn measure_peak<F: FnOnce()>(save: F) -> usize {
+    let base = CURRENT.load(Ordering::SeqCst);
+    PEAK.store(base, Ordering::SeqCst);
+    save();
+    PEAK.load(Ordering::SeqCst) - base
+}
+
+fn main() {
+    let program = generate_graph(300);
+    let ast = DotParser::new(&program).process().unwrap();
+    let mut gb = GraphBuilder::new();
+    gb.visit_graph(&ast);
+    let mut vg = gb.get();
+    let mut svg = SVGWriter::new();
+    vg.do_it(false, false, false, &mut svg);
+
+    let with_string = measure_peak(|| {
+        let content = svg.finalize();
+        io::sink().write_all(content.as_bytes()).unwrap();
+    });
+    let streaming = measure_peak(|| {
+        svg.finalize_to(&mut io::sink()).unwrap();
+    });
+    println!("Peak memory with finalize: {} KiB", with_string / 1024);
+    println!("Peak memory with finalize_to: {} KiB", streaming / 1024);
+}
@@ -329,6 +329,48 @@ fn test8(offset_x: f64, offset_y: f64, svg: &mut SVGWriter) {
     es0.render(LAYOUT_HELPER, svg);
 }
 
+// Draw an edge that crosses a translucent node. The edge is drawn first, and
+// should be visible through the fill of the node.
+fn test9(offset_x: f64, offset_y: f64, svg: &mut SVGWriter) {
+    let sz = Point::new(100., 50.);
+    let mut look = StyleAttr::simple();
+    look.fill_color = Some(Color::fast("pink"));
+
+    let mut es0 = Element::create(
+        ShapeKind::new_box("from"),
+        look.clone(),
+        Orientation::LeftToRight,
+        sz,
+    );
+    let mut es1 = Element::create(
+        ShapeKind::new_box("to"),
+        look,
+        Orientation::LeftToRight,
+        sz,
+    );
+    es0.position_mut().move_to(Point::new(offset_x, offset_y));
+    es1.position_mut()
+        .move_to(Point::new(offset_x, offset_y + 300.));
+
+    let mut look2 = StyleAttr::simple();
+    look2.fill_color = Color::from_name("#4682b480");
+    let mut es2 = Element::create(
+        ShapeKind::new_circle("translucent"),
+        look2,
+        Orientation::LeftToRight,
+        Point::new(150., 150.),
+    );
+    es2.position_mut()
+        .move_to(Point::new(offset_x, offset_y + 150.));
+
+    let stl = Arrow::simple("x");
+    let vec: Vec<Element> = vec![es0.clone(), es1.clone()];
+    render::render_arrow(svg, LAYOUT_HELPER, &vec[..], &stl);
+    es0.render(LAYOUT_HELPER, svg);
+    es1.render(LAYOUT_HELPER, svg);
+    es2.render(LAYOUT_HELPER, svg);
+}
+
 fn main() {
     let mut svg = SVGWriter::new();
     test0(0., 0., &mut svg, 0);
@@ -356,6 +398,7 @@ fn main() {
     test6(1900., 1550., 150., 150., &mut svg);
     test7(3600., 1450., &mut svg);
     test8(2600., 1450., &mut svg);
+    test9(3000., 1800., &mut svg);
 
     let content = svg.finalize();
     let filename = "/tmp/shapes.svg";
@@ -0,0 +1,124 @@
+//! Benchmarks for the phases of the layout, on graphs of different sizes.
+//! Run with 'cargo bench'. The benchmarks of the internal phases of the
+//! layout (ranking, crossing minimization, placement) need the 'timings'
+//! feature: 'cargo bench --features timings'.
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use layout::backends::svg::SVGWriter;
+use layout::gv::{DotParser, GraphBuilder};
+use layout::topo::layout::VisualGraph;
+
+/// The number of nodes in the benchmarked graphs.
+const SIZES: [usize; 3] = [20, 100, 300];
+
+/// Generate a layered graph with \p n nodes, where some of the edges skip
+/// ranks and some have labels.
+fn generate_graph(n: usize) -> String {
+    let mut program = String::from("digraph {\n");
+    for i in 1..n {
+        program.push_str(&format!("n{} -> n{};\n", i / 3, i));
+        if i % 5 == 0 {
+            program.push_str(&format!(
+                "n{} -> n{} [label=\"e{}\"];\n",
+                i / 9,
+                i,
+                i
+            ));
+        }
+    }
+    program.push('}');
+    program
+}
+
+fn build_graph(program: &str) -> VisualGraph {
+    let graph = DotParser::new(program).process().unwrap();
+    let mut gb = GraphBuilder::new();
+    gb.visit_graph(&graph);
+    gb.get()
+}
+
+fn bench_parse(c: &mut Criterion) {
+    for n in SIZES {
+        let program = generate_graph(n);
+        c.bench_function(&format!("parse/{}", n), |b| {
+            b.iter(|| DotParser::new(&program).process().unwrap())
+        });
+    }
+}
+
+fn bench_build(c: &mut Criterion) {
+    for n in SIZES {
+        let graph = DotParser::new(&generate_graph(n)).process().unwrap();
+        c.bench_function(&format!("build/{}", n), |b| {
+            b.iter(|| {
+                let mut gb = GraphBuilder::new();
+                gb.visit_graph(&graph);
+                gb.get()
+            })
+        });
+    }
+}
+
+fn bench_layout(c: &mut Criterion) {
+    for n in SIZES {
+        let program = generate_graph(n);
+        c.bench_function(&format!("layout/{}", n), |b| {
+            b.iter_batched(
+                || build_graph(&program),
+                |mut vg| vg.layout(false, false),
+                BatchSize::SmallInput,
+            )
+        });
+    }
+}
+
+fn bench_render(c: &mut Criterion) {
+    for n in SIZES {
+        let mut vg = build_graph(&generate_graph(n));
+        vg.layout(false, false);
+        c.bench_function(&format!("render/{}", n), |b| {
+            b.iter(|| {
+                let mut svg = SVGWriter::new();
+                vg.render_filtered(&mut svg, |_| true);
+                svg.finalize()
+            })
+        });
+    }
+}
+
+/// Measure the internal phases of the layout with the timings that the
+/// graph records.
+#[cfg(feature = "timings")]
+fn bench_phases(c: &mut Criterion) {
+    use std::time::Duration;
+
+    for n in SIZES {
+        let program = generate_graph(n);
+        for phase in ["lower", "rank", "cross", "place"] {
+            c.bench_function(&format!("{}/{}", phase, n), |b| {
+                b.iter_custom(|iters| {
+                    let mut total = Duration::ZERO;
+                    for _ in 0..iters {
+                        let mut vg = build_graph(&program);
+                        vg.layout(false, false);
+                        total += vg.phase_timings().get(phase).unwrap();
+                    }
+                    total
+                })
+            });
+        }
+    }
+}
+
+#[cfg(not(feature = "timings"))]
+fn bench_phases(_: &mut Criterion) {}
+
+criterion_group!(
+    benches,
+    bench_parse,
+    bench_build,
+    bench_layout,
+    bench_render,
+    bench_phases
+);
+criterion_main!(benches);
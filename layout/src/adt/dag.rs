@@ -15,6 +15,9 @@ pub struct DAG {
     /// Places nodes in levels.
     ranks: RankType,
 
+    /// Sets of nodes that need to be placed at the same level.
+    same_levels: Vec<Vec<NodeHandle>>,
+
     /// Perform validation checks.
     validate: bool,
 }
@@ -45,6 +48,10 @@ struct Node {
     // Points to other edges.
     successors: Vec<NodeHandle>,
     predecessors: Vec<NodeHandle>,
+    // The level that the user assigned to the node, if any.
+    pinned_level: Option<usize>,
+    // Set if the node needs to be at the same level as other nodes.
+    same_level: bool,
 }
 
 pub type RankType = Vec<Vec<NodeHandle>>;
@@ -54,6 +61,8 @@ impl Node {
         Node {
             successors: Vec::new(),
             predecessors: Vec::new(),
+            pinned_level: None,
+            same_level: false,
         }
     }
 }
@@ -84,6 +93,7 @@ impl DAG {
         DAG {
             nodes: Vec::new(),
             ranks: Vec::new(),
+            same_levels: Vec::new(),
             validate: true,
         }
     }
@@ -95,6 +105,7 @@ impl DAG {
     pub fn clear(&mut self) {
         self.nodes.clear();
         self.ranks.clear();
+        self.same_levels.clear();
     }
 
     pub fn iter(&self) -> NodeIterator {
@@ -151,6 +162,46 @@ impl DAG {
         self.verify();
     }
 
+    /// Place the node \p node at the level \p level when the graph is ranked.
+    /// The ranking moves the other nodes around the pinned node. If the
+    /// chain of predecessors of the node is longer than \p level then the
+    /// node is placed at the closest level below \p level.
+    pub fn pin_level(&mut self, node: NodeHandle, level: usize) {
+        self.nodes[node.idx].pinned_level = Some(level);
+    }
+
+    /// \returns the level that the node \p node is pinned to, if any.
+    pub fn pinned_level(&self, node: NodeHandle) -> Option<usize> {
+        self.nodes[node.idx].pinned_level
+    }
+
+    /// Place the nodes \p nodes at the same level when the graph is ranked.
+    /// The nodes are placed at the lowest level that any of them requires. If
+    /// the constraint can't be satisfied, because there is a path between two
+    /// of the nodes or because it conflicts with other sets, then the nodes
+    /// are ranked as if it was not there.
+    pub fn add_same_level(&mut self, nodes: &[NodeHandle]) {
+        for node in nodes {
+            self.nodes[node.idx].same_level = true;
+        }
+        self.same_levels.push(nodes.to_vec());
+    }
+
+    /// \returns true if the nodes \p a and \p b are in one of the sets of
+    /// nodes that are placed at the same level (see 'add_same_level').
+    pub fn in_same_level_set(&self, a: NodeHandle, b: NodeHandle) -> bool {
+        self.same_levels
+            .iter()
+            .any(|set| set.contains(&a) && set.contains(&b))
+    }
+
+    /// \returns true if the level of the node \p node is constrained, either
+    /// by pinning it or by placing it at the same level as other nodes.
+    pub fn is_level_constrained(&self, node: NodeHandle) -> bool {
+        let node = &self.nodes[node.idx];
+        node.pinned_level.is_some() || node.same_level
+    }
+
     pub fn successors(&self, from: NodeHandle) -> &Vec<NodeHandle> {
         &self.nodes[from.idx].successors
     }
@@ -183,14 +234,7 @@ impl DAG {
             }
 
             // Check that the graph is a DAG.
-            for (i, node) in self.nodes.iter().enumerate() {
-                let from = NodeHandle::from(i);
-                for dest in node.successors.iter() {
-                    let reachable =
-                        self.is_reachable(*dest, from) && from != *dest;
-                    assert!(!reachable, "We found a cycle!");
-                }
-            }
+            assert!(!self.has_cycle(), "We found a cycle!");
 
             // Make sure that all of the nodes are in ranks.
             assert_eq!(self.count_nodes_in_ranks(), self.len());
@@ -205,47 +249,68 @@ impl DAG {
         self.nodes.is_empty()
     }
 
-    /// \returns True if the node \to is reachable from the node \p from.
-    /// This internal method is used for the verification of the graph.
-    fn is_reachable_inner(
-        &self,
-        from: NodeHandle,
-        to: NodeHandle,
-        visited: &mut Vec<bool>,
-    ) -> bool {
+    /// \returns True if there is a path from \p 'from' to \p 'to'.
+    pub fn is_reachable(&self, from: NodeHandle, to: NodeHandle) -> bool {
         if from == to {
             return true;
         }
 
-        // Don't step into a cycle.
-        if visited[from.idx] {
-            return false;
-        }
-
-        // Push to the dfs stack.
+        // A node that was visited once can't reach the target, so there is
+        // no need to visit it again.
+        let mut visited = vec![false; self.nodes.len()];
+        let mut worklist = vec![from];
         visited[from.idx] = true;
 
-        let from_node = &self.nodes[from.idx];
-        for edge in &from_node.successors {
-            if self.is_reachable_inner(*edge, to, visited) {
-                return true;
+        while let Some(current) = worklist.pop() {
+            for succ in &self.nodes[current.idx].successors {
+                if *succ == to {
+                    return true;
+                }
+                if !visited[succ.idx] {
+                    visited[succ.idx] = true;
+                    worklist.push(*succ);
+                }
             }
         }
-
-        // Pop from the dfs stack.
-        visited[from.idx] = false;
         false
     }
 
-    /// \returns True if there is a path from \p 'from' to \p 'to'.
-    pub fn is_reachable(&self, from: NodeHandle, to: NodeHandle) -> bool {
-        if from == to {
-            return true;
-        }
+    /// \returns True if the graph contains a cycle. Self edges are ignored.
+    /// This internal method is used for the verification of the graph.
+    fn has_cycle(&self) -> bool {
+        // The nodes that are on the dfs stack, and the nodes that were done.
+        let mut on_stack = vec![false; self.nodes.len()];
+        let mut done = vec![false; self.nodes.len()];
 
-        let mut visited = Vec::new();
-        visited.resize(self.nodes.len(), false);
-        self.is_reachable_inner(from, to, &mut visited)
+        for n in self.iter() {
+            if done[n.idx] {
+                continue;
+            }
+            // A stack of nodes, and the index of the next successor to visit.
+            let mut stack: Vec<(NodeHandle, usize)> = vec![(n, 0)];
+            on_stack[n.idx] = true;
+
+            while let Some((current, next)) = stack.pop() {
+                let succs = &self.nodes[current.idx].successors;
+                if next == succs.len() {
+                    on_stack[current.idx] = false;
+                    done[current.idx] = true;
+                    continue;
+                }
+                stack.push((current, next + 1));
+
+                let succ = succs[next];
+                if succ == current || done[succ.idx] {
+                    continue;
+                }
+                if on_stack[succ.idx] {
+                    return true;
+                }
+                on_stack[succ.idx] = true;
+                stack.push((succ, 0));
+            }
+        }
+        false
     }
 
     /// Return the topological sort order of the nodes in the dag.
@@ -297,6 +362,51 @@ impl DAG {
         order
     }
 
+    /// \returns the longest path in the dag, from a source to a sink, where
+    /// the length of the edge from 'from' to 'to' is \p weight(from, to). Use
+    /// a constant weight to find the path with the largest number of edges.
+    /// The dag must not have cycles. Self edges are ignored.
+    pub fn longest_path<F>(&self, weight: F) -> Vec<NodeHandle>
+    where
+        F: Fn(NodeHandle, NodeHandle) -> f64,
+    {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        // The length of the longest path that ends at each node, and the
+        // node before it on that path.
+        let mut dist = vec![0.; self.len()];
+        let mut prev: Vec<Option<NodeHandle>> = vec![None; self.len()];
+        let order = self.topological_sort();
+        for src in order.iter() {
+            for dest in self.nodes[src.idx].successors.iter() {
+                if src == dest {
+                    continue;
+                }
+                let len = dist[src.idx] + weight(*src, *dest);
+                if len > dist[dest.idx] {
+                    dist[dest.idx] = len;
+                    prev[dest.idx] = Some(*src);
+                }
+            }
+        }
+
+        // Start at the end of the longest path and walk back.
+        let mut last = order[0];
+        for node in order.iter() {
+            if dist[node.idx] > dist[last.idx] {
+                last = *node;
+            }
+        }
+        let mut path = vec![last];
+        while let Some(node) = prev[path[path.len() - 1].idx] {
+            path.push(node);
+        }
+        path.reverse();
+        path
+    }
+
     // The methods below are related to the rank (placing nodes in levels). //
 
     /// \returns the number of ranks in the dag.
@@ -435,12 +545,74 @@ impl DAG {
     /// Computes and returns the level of each node in the graph based
     /// on the traversal order \p order.
     fn compute_levels(&self, order: &[NodeHandle]) -> Vec<usize> {
-        let mut levels: Vec<usize> = Vec::new();
         assert_eq!(order.len(), self.nodes.len());
 
-        // Levels has the same layout as the DAG node list.
-        levels.resize(self.nodes.len(), 0);
+        // Sets of nodes with a path between two of the nodes can't share a
+        // level, and are ignored.
+        let mut sets: Vec<&Vec<NodeHandle>> = self
+            .same_levels
+            .iter()
+            .filter(|set| {
+                !set.iter().any(|a| {
+                    set.iter().any(|b| a != b && self.is_reachable(*a, *b))
+                })
+            })
+            .collect();
+
+        // Sets can also conflict with each other. Drop the last set that could
+        // not be aligned, and rank the graph again without it.
+        loop {
+            let levels = self.compute_levels_with_sets(order, &sets);
+            let conflict = sets.iter().rposition(|set| {
+                set.iter().any(|n| levels[n.idx] != levels[set[0].idx])
+            });
+            match conflict {
+                Option::Some(idx) => sets.remove(idx),
+                Option::None => return levels,
+            };
+        }
+    }
+
+    /// Computes the level of each node in the graph, like 'compute_levels',
+    /// and places the nodes of each set in \p sets at the same level, if
+    /// possible.
+    fn compute_levels_with_sets(
+        &self,
+        order: &[NodeHandle],
+        sets: &[&Vec<NodeHandle>],
+    ) -> Vec<usize> {
+        // Levels has the same layout as the DAG node list. Pinned nodes start
+        // at their level, and only move down if their predecessors require.
+        let mut levels: Vec<usize> = vec![0; self.nodes.len()];
+        for (i, node) in self.nodes.iter().enumerate() {
+            levels[i] = node.pinned_level.unwrap_or(0);
+        }
+
+        // Push the nodes down until the sets of nodes that share a level are
+        // aligned. Each round moves at least one node down, so if the sets
+        // are still not aligned after a round per node then the sets can't
+        // be satisfied.
+        for _ in 0..=self.nodes.len() {
+            self.propagate_levels(order, &mut levels);
+            if !Self::align_same_levels(sets, &mut levels) {
+                break;
+            }
+        }
+        self.propagate_levels(order, &mut levels);
+
+        // For each node in the order.
+        for src in order {
+            for dest in self.nodes[src.idx].successors.iter() {
+                assert!(levels[dest.idx] >= levels[src.idx]);
+            }
+        }
 
+        levels
+    }
+
+    /// Move the successors of the nodes below the nodes, by visiting the
+    /// nodes in the order \p order, and updating \p levels.
+    fn propagate_levels(&self, order: &[NodeHandle], levels: &mut [usize]) {
         // For each node in the order (starting with a node of level zero).
         for src in order {
             // Update the level of all successors.
@@ -453,15 +625,26 @@ impl DAG {
                     cmp::max(levels[dest.idx], levels[src.idx] + 1);
             }
         }
+    }
 
-        // For each node in the order.
-        for src in order {
-            for dest in self.nodes[src.idx].successors.iter() {
-                assert!(levels[dest.idx] >= levels[src.idx]);
+    /// Move the nodes in each set of nodes in \p sets down to the lowest
+    /// level in the set.
+    /// \returns true if any node was moved.
+    fn align_same_levels(
+        sets: &[&Vec<NodeHandle>],
+        levels: &mut [usize],
+    ) -> bool {
+        let mut changed = false;
+        for set in sets {
+            let level = set.iter().map(|n| levels[n.idx]).max().unwrap_or(0);
+            for node in set.iter() {
+                if levels[node.idx] != level {
+                    levels[node.idx] = level;
+                    changed = true;
+                }
             }
         }
-
-        levels
+        changed
     }
 }
 
@@ -503,6 +686,35 @@ fn test_simple_construction() {
     }
 }
 
+#[test]
+fn test_verify_large_graph() {
+    use std::time::Instant;
+
+    // Build a dense layered graph with 1000 nodes.
+    let mut g = DAG::new();
+    let layers: Vec<Vec<NodeHandle>> = (0..20)
+        .map(|_| (0..50).map(|_| g.new_node()).collect())
+        .collect();
+    for pair in layers.windows(2) {
+        for (i, from) in pair[0].iter().enumerate() {
+            for j in 0..5 {
+                g.add_edge(*from, pair[1][(i * 7 + j) % 50]);
+            }
+        }
+    }
+    g.recompute_node_ranks();
+
+    let start = Instant::now();
+    g.verify();
+    assert!(!g.is_reachable(layers[19][0], layers[0][0]));
+    assert!(g.is_reachable(layers[0][0], layers[19][0]));
+    assert!(start.elapsed().as_millis() < 500);
+
+    // Closing a cycle is detected.
+    g.add_edge(layers[19][0], layers[0][0]);
+    assert!(g.has_cycle());
+}
+
 #[test]
 fn test_rank_api() {
     let mut g = DAG::new();
@@ -527,3 +739,81 @@ fn test_rank_api() {
     // The edge should no longer be there!
     assert!(!r2);
 }
+
+#[test]
+fn test_longest_path() {
+    let mut g = DAG::new();
+    let h: Vec<NodeHandle> = (0..6).map(|_| g.new_node()).collect();
+    let edges = [
+        (0, 1, 3.),
+        (0, 2, 1.),
+        (1, 3, 1.),
+        (2, 3, 5.),
+        (3, 4, 2.),
+        (1, 5, 1.),
+        (4, 4, 10.),
+    ];
+    for (from, to, _) in edges {
+        g.add_edge(h[from], h[to]);
+    }
+    let weight = |from: NodeHandle, to: NodeHandle| {
+        let edge = edges.iter().find(|e| h[e.0] == from && h[e.1] == to);
+        edge.unwrap().2
+    };
+
+    // The heavy path goes through the node 2.
+    assert_eq!(g.longest_path(weight), vec![h[0], h[2], h[3], h[4]]);
+    // Counting edges, both paths have three edges.
+    assert_eq!(g.longest_path(|_, _| 1.).len(), 4);
+    // The path through 1 is longer if the edge from 2 to 3 is light.
+    let light = |from: NodeHandle, to: NodeHandle| {
+        if from == h[2] {
+            0.
+        } else {
+            weight(from, to)
+        }
+    };
+    assert_eq!(g.longest_path(light), vec![h[0], h[1], h[3], h[4]]);
+
+    assert!(DAG::new().longest_path(|_, _| 1.).is_empty());
+}
+
+#[test]
+fn test_same_level() {
+    let mut g = DAG::new();
+    let h: Vec<NodeHandle> = (0..5).map(|_| g.new_node()).collect();
+    g.add_edge(h[0], h[1]);
+    g.add_edge(h[1], h[2]);
+    g.add_edge(h[0], h[3]);
+    g.add_edge(h[3], h[4]);
+    g.add_same_level(&[h[2], h[3]]);
+    g.recompute_node_ranks();
+    g.verify();
+
+    // The node 3 is moved down to the level of 2, and pushes 4 down.
+    assert_eq!(g.level(h[2]), 2);
+    assert_eq!(g.level(h[3]), 2);
+    assert_eq!(g.level(h[4]), 3);
+    assert!(g.is_level_constrained(h[3]));
+    assert!(!g.is_level_constrained(h[4]));
+
+    // Nodes on the same path can't share a level, and the set is ignored.
+    g.add_same_level(&[h[0], h[4]]);
+    g.recompute_node_ranks();
+    g.verify();
+    let levels: Vec<usize> = h.iter().map(|n| g.level(*n)).collect();
+    assert_eq!(levels, [0, 1, 2, 2, 3]);
+
+    // Sets that conflict with each other are ignored too. Here 'a' and 'd'
+    // can't share a level if 'b' and 'c' do.
+    let mut g = DAG::new();
+    let h: Vec<NodeHandle> = (0..4).map(|_| g.new_node()).collect();
+    g.add_edge(h[0], h[1]);
+    g.add_edge(h[2], h[3]);
+    g.add_same_level(&[h[1], h[2]]);
+    g.add_same_level(&[h[0], h[3]]);
+    g.recompute_node_ranks();
+    g.verify();
+    let levels: Vec<usize> = h.iter().map(|n| g.level(*n)).collect();
+    assert_eq!(levels, [0, 1, 1, 2]);
+}
@@ -0,0 +1,440 @@
+//! Exports a laid-out graph as JSON that follows the schema of the GraphViz
+//! '-Tjson' output format, so that tools that consume GraphViz JSON can read
+//! the output of this crate.
+//!
+//! Only a subset of the schema is supported:
+//!  - The graph: name, directed, bb and the lists of objects and edges.
+//!  - Nodes: _gvid, name, label, shape, pos, width, height, and the _draw_
+//!    and _ldraw_ operations (colors, ellipses, polygons and text).
+//!  - Edges: _gvid, tail, head, pos (the spline points) and the _draw_
+//!    operations (color and bezier curve). Edges with labels also have the
+//!    label and its position (lp), and the head and tail labels and their
+//!    positions (headlabel, head_lp, taillabel and tail_lp).
+//!
+//! Like GraphViz, the y axis points up, sizes are in points and the node
+//! width and height are in inches.
+
+use crate::core::format::Visible;
+use crate::core::geometry::Point;
+use crate::std_shapes::render::{
+    generate_curve_for_elements, get_end_label_locations, get_polygon_vertices,
+    path_to_bezier_points,
+};
+use crate::std_shapes::shapes::{Element, ShapeKind};
+use crate::topo::layout::VisualGraph;
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Serialize)]
+#[serde(tag = "op")]
+enum DrawOp {
+    #[serde(rename = "c")]
+    Color { grad: String, color: String },
+    #[serde(rename = "C")]
+    FillColor { grad: String, color: String },
+    #[serde(rename = "e")]
+    Ellipse { rect: [f64; 4] },
+    #[serde(rename = "E")]
+    FilledEllipse { rect: [f64; 4] },
+    #[serde(rename = "p")]
+    Polygon { points: Vec<[f64; 2]> },
+    #[serde(rename = "P")]
+    FilledPolygon { points: Vec<[f64; 2]> },
+    #[serde(rename = "b")]
+    Bezier { points: Vec<[f64; 2]> },
+    #[serde(rename = "T")]
+    Text {
+        pt: [f64; 2],
+        align: String,
+        width: f64,
+        text: String,
+    },
+}
+
+#[derive(Serialize)]
+struct GvNode {
+    #[serde(rename = "_gvid")]
+    gvid: usize,
+    name: String,
+    label: String,
+    shape: String,
+    pos: String,
+    width: String,
+    height: String,
+    #[serde(rename = "_draw_")]
+    draw: Vec<DrawOp>,
+    #[serde(rename = "_ldraw_")]
+    ldraw: Vec<DrawOp>,
+}
+
+#[derive(Serialize)]
+struct GvEdge {
+    #[serde(rename = "_gvid")]
+    gvid: usize,
+    tail: usize,
+    head: usize,
+    pos: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    headlabel: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    head_lp: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    taillabel: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tail_lp: Option<String>,
+    #[serde(rename = "_draw_")]
+    draw: Vec<DrawOp>,
+}
+
+#[derive(Serialize)]
+struct GvGraph {
+    name: String,
+    directed: bool,
+    strict: bool,
+    bb: String,
+    objects: Vec<GvNode>,
+    edges: Vec<GvEdge>,
+}
+
+fn shape_name_and_label(shape: &ShapeKind) -> (&str, &str) {
+    match shape {
+        ShapeKind::None => ("none", ""),
+        ShapeKind::Box(text) => ("box", text),
+        ShapeKind::Circle(text) => ("circle", text),
+        ShapeKind::DoubleCircle(text) => ("doublecircle", text),
+        ShapeKind::Polygon(text, _) => ("polygon", text),
+        ShapeKind::Point => ("point", ""),
+        ShapeKind::Record(_) => ("record", ""),
+        ShapeKind::Connector(_) => ("none", ""),
+    }
+}
+
+/// Converts coordinates from the y-down system of this crate to the y-up
+/// system of GraphViz.
+struct Flipper {
+    height: f64,
+}
+
+impl Flipper {
+    fn pt(&self, p: Point) -> [f64; 2] {
+        [p.x, self.height - p.y]
+    }
+
+    /// \returns the point \p p in the GraphViz 'x,y' format.
+    fn pos(&self, p: Point) -> String {
+        let p = self.pt(p);
+        format!("{:.2},{:.2}", p[0], p[1])
+    }
+}
+
+fn node_draw_ops(elem: &Element, flip: &Flipper) -> Vec<DrawOp> {
+    let mut ops = Vec::new();
+    let look = &elem.look;
+    let center = flip.pt(elem.pos.center());
+    let size = elem.pos.size(false);
+    let filled = look.fill_color.is_some();
+    if let Option::Some(fill) = &look.fill_color {
+        ops.push(DrawOp::FillColor {
+            grad: "none".to_string(),
+            color: fill.to_web_color(),
+        });
+    }
+    ops.push(DrawOp::Color {
+        grad: "none".to_string(),
+        color: look.line_color.to_web_color(),
+    });
+    match elem.shape {
+        ShapeKind::Circle(_)
+        | ShapeKind::DoubleCircle(_)
+        | ShapeKind::Point => {
+            let rect = [center[0], center[1], size.x / 2., size.y / 2.];
+            if filled {
+                ops.push(DrawOp::FilledEllipse { rect });
+            } else {
+                ops.push(DrawOp::Ellipse { rect });
+            }
+        }
+        ShapeKind::Box(_) | ShapeKind::Record(_) => {
+            let bb = elem.pos.bbox(false);
+            let points = vec![
+                flip.pt(bb.0),
+                flip.pt(Point::new(bb.1.x, bb.0.y)),
+                flip.pt(bb.1),
+                flip.pt(Point::new(bb.0.x, bb.1.y)),
+            ];
+            if filled {
+                ops.push(DrawOp::FilledPolygon { points });
+            } else {
+                ops.push(DrawOp::Polygon { points });
+            }
+        }
+        ShapeKind::Polygon(_, ref poly) => {
+            let vertices = get_polygon_vertices(poly, elem.pos.center(), size);
+            let points = vertices.iter().map(|p| flip.pt(*p)).collect();
+            if filled {
+                ops.push(DrawOp::FilledPolygon { points });
+            } else {
+                ops.push(DrawOp::Polygon { points });
+            }
+        }
+        ShapeKind::None | ShapeKind::Connector(_) => {}
+    }
+    ops
+}
+
+impl VisualGraph {
+    /// Export the graph in a format that is compatible with the GraphViz
+    /// '-Tjson' output. This method needs to be called after the graph was
+    /// laid out (see 'do_it'). See the module documentation for the list of
+    /// supported fields.
+    pub fn to_gv_json(&self) -> String {
+        // Compute the paths of all of the edges, and the locations of the
+        // head and tail labels.
+        let mut paths: Vec<Vec<Point>> = Vec::new();
+        let mut end_labels: Vec<(Point, Point)> = Vec::new();
+        for (arrow, chain) in self.edge_list() {
+            let elements: Vec<Element> =
+                chain.iter().map(|h| self.element(*h).clone()).collect();
+            let path = generate_curve_for_elements(
+                &elements,
+                arrow,
+                self.arrow_force(),
+            );
+            end_labels.push(get_end_label_locations(&path));
+            paths.push(path_to_bezier_points(&path));
+        }
+
+        // Compute the bounding box of the drawing.
+        let mut bottom_right = Point::zero();
+        for node in self.iter_nodes() {
+            let bb = self.pos(node).bbox(false).1;
+            bottom_right =
+                Point::new(bottom_right.x.max(bb.x), bottom_right.y.max(bb.y));
+        }
+        for p in paths.iter().flatten() {
+            bottom_right =
+                Point::new(bottom_right.x.max(p.x), bottom_right.y.max(p.y));
+        }
+        let flip = Flipper {
+            height: bottom_right.y,
+        };
+
+        // Export the visible nodes, and number them.
+        let mut gvids: HashMap<usize, usize> = HashMap::new();
+        let mut objects = Vec::new();
+        for node in self.iter_nodes() {
+            let elem = self.element(node);
+            if elem.is_connector() {
+                continue;
+            }
+            let gvid = objects.len();
+            gvids.insert(node.get_index(), gvid);
+            let (shape, label) = shape_name_and_label(&elem.shape);
+            let center = flip.pt(elem.pos.center());
+            let size = elem.pos.size(false);
+            let ldraw = vec![DrawOp::Text {
+                pt: center,
+                align: "c".to_string(),
+                width: size.x,
+                text: label.to_string(),
+            }];
+            objects.push(GvNode {
+                gvid,
+                name: match self.name_of(node) {
+                    Option::Some(name) => name.to_string(),
+                    Option::None => format!("node{}", node.get_index()),
+                },
+                label: label.to_string(),
+                shape: shape.to_string(),
+                pos: format!("{:.2},{:.2}", center[0], center[1]),
+                width: format!("{:.4}", size.x / 72.),
+                height: format!("{:.4}", size.y / 72.),
+                draw: node_draw_ops(elem, &flip),
+                ldraw,
+            });
+        }
+
+        // Export the edges.
+        let mut edges = Vec::new();
+        for (i, (arrow, chain)) in self.edge_list().iter().enumerate() {
+            let mut tail = chain[0];
+            let mut head = chain[chain.len() - 1];
+            let (mut tail_loc, mut head_loc) = end_labels[i];
+            let mut head_label = arrow.head_label.clone();
+            let mut tail_label = arrow.tail_label.clone();
+            let mut points: Vec<[f64; 2]> =
+                paths[i].iter().map(|p| flip.pt(*p)).collect();
+            // Edges that were reversed during lowering are exported in their
+            // original direction.
+            if self.is_edge_reversed(i) {
+                std::mem::swap(&mut tail, &mut head);
+                std::mem::swap(&mut tail_loc, &mut head_loc);
+                std::mem::swap(&mut tail_label, &mut head_label);
+                points.reverse();
+            }
+            let pos: Vec<String> = points
+                .iter()
+                .map(|p| format!("{:.2},{:.2}", p[0], p[1]))
+                .collect();
+
+            // The label of the edge is in the connector that it passes
+            // through.
+            let mut label = Option::None;
+            let mut lp = Option::None;
+            for h in chain.iter() {
+                if let ShapeKind::Connector(Some(text)) =
+                    &self.element(*h).shape
+                {
+                    label = Option::Some(text.clone());
+                    lp = Option::Some(flip.pos(self.pos(*h).center()));
+                }
+            }
+            let head_lp = head_label.as_ref().map(|_| flip.pos(head_loc));
+            let tail_lp = tail_label.as_ref().map(|_| flip.pos(tail_loc));
+            let draw = vec![
+                DrawOp::Color {
+                    grad: "none".to_string(),
+                    color: arrow.look.line_color.to_web_color(),
+                },
+                DrawOp::Bezier { points },
+            ];
+            edges.push(GvEdge {
+                gvid: i,
+                tail: gvids[&tail.get_index()],
+                head: gvids[&head.get_index()],
+                pos: pos.join(" "),
+                label,
+                lp,
+                headlabel: head_label,
+                head_lp,
+                taillabel: tail_label,
+                tail_lp,
+                draw,
+            });
+        }
+
+        let graph = GvGraph {
+            name: String::new(),
+            directed: self.is_directed(),
+            strict: false,
+            bb: format!("0,0,{:.2},{:.2}", bottom_right.x, bottom_right.y),
+            objects,
+            edges,
+        };
+        serde_json::to_string_pretty(&graph).unwrap()
+    }
+}
+
+#[test]
+fn test_gv_json_export() {
+    use crate::backends::svg::SVGWriter;
+    use crate::gv::{DotParser, GraphBuilder};
+
+    let dot = "digraph { a -> b -> c; a -> c [label=\"x\"]; c -> a; }";
+    let ast = DotParser::new(dot).process().unwrap();
+    let mut gb = GraphBuilder::new();
+    gb.visit_graph(&ast);
+    let mut vg = gb.get();
+    vg.do_it(false, false, false, &mut SVGWriter::new());
+
+    let json = vg.to_gv_json();
+    let val: serde_json::Value = serde_json::from_str(&json).unwrap();
+    assert_eq!(val["directed"], true);
+    assert_eq!(val["objects"].as_array().unwrap().len(), 3);
+    assert_eq!(val["objects"][1]["name"], "b");
+    let edges = val["edges"].as_array().unwrap();
+    assert_eq!(edges.len(), 4);
+    for edge in edges {
+        assert!(edge["_draw_"][1]["op"] == "b");
+        // A bezier spline has 3n+1 points.
+        let points = edge["_draw_"][1]["points"].as_array().unwrap().len();
+        assert_eq!(points % 3, 1);
+    }
+    // The back edge 'c -> a' keeps its original direction.
+    assert_eq!(edges[3]["tail"], 2);
+    assert_eq!(edges[3]["head"], 0);
+}
+
+#[test]
+fn test_gv_json_edge_direction() {
+    use crate::backends::svg::SVGWriter;
+    use crate::gv::{DotParser, GraphBuilder};
+
+    let export = |dot: &str| -> serde_json::Value {
+        let ast = DotParser::new(dot).process().unwrap();
+        let mut gb = GraphBuilder::new();
+        gb.visit_graph(&ast);
+        let mut vg = gb.get();
+        vg.do_it(false, false, false, &mut SVGWriter::new());
+        serde_json::from_str(&vg.to_gv_json()).unwrap()
+    };
+
+    // The arrow of 'dir=back' points at the tail, and the tail is still 'a'.
+    let val = export("digraph { a -> b [dir=back, taillabel=\"t\"]; }");
+    assert_eq!(val["directed"], true);
+    let edge = &val["edges"][0];
+    assert_eq!(edge["tail"], 0);
+    assert_eq!(edge["head"], 1);
+    assert_eq!(edge["taillabel"], "t");
+    assert!(edge.get("headlabel").is_none());
+
+    // Edges without arrows are still edges of a directed graph, and keep
+    // their direction when lowering reverses them.
+    let val = export("digraph { a -> b [dir=none]; b -> a [dir=none]; }");
+    assert_eq!(val["directed"], true);
+    let edges = val["edges"].as_array().unwrap();
+    assert_eq!(
+        (&edges[0]["tail"], &edges[0]["head"]),
+        (&0.into(), &1.into())
+    );
+    assert_eq!(
+        (&edges[1]["tail"], &edges[1]["head"]),
+        (&1.into(), &0.into())
+    );
+
+    let val = export("graph { a -- b; }");
+    assert_eq!(val["directed"], false);
+}
+
+#[test]
+fn test_gv_json_label_positions() {
+    use crate::backends::svg::SVGWriter;
+    use crate::gv::{DotParser, GraphBuilder};
+
+    let dot = "digraph { a -> b [label=\"x\", headlabel=\"h\"]; a -> c; }";
+    let ast = DotParser::new(dot).process().unwrap();
+    let mut gb = GraphBuilder::new();
+    gb.visit_graph(&ast);
+    let mut vg = gb.get();
+    vg.do_it(false, false, false, &mut SVGWriter::new());
+
+    let json = vg.to_gv_json();
+    let val: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let edges = val["edges"].as_array().unwrap();
+    let labeled = &edges[0];
+    assert_eq!(labeled["label"], "x");
+    assert_eq!(labeled["headlabel"], "h");
+    assert!(labeled.get("taillabel").is_none());
+    assert!(labeled.get("tail_lp").is_none());
+
+    // The label is between the ends of the edge.
+    let parse = |s: &serde_json::Value| -> Vec<f64> {
+        let s = s.as_str().unwrap();
+        s.split([',', ' ']).map(|x| x.parse().unwrap()).collect()
+    };
+    let lp = parse(&labeled["lp"]);
+    let pos = parse(&labeled["pos"]);
+    let (first_y, last_y) = (pos[1], pos[pos.len() - 1]);
+    assert!(lp[1] < first_y && lp[1] > last_y);
+    // The head label is near the head of the edge.
+    let head_lp = parse(&labeled["head_lp"]);
+    assert!((head_lp[1] - last_y).abs() < 30.);
+
+    // Edges without labels don't have label positions.
+    assert!(edges[1].get("lp").is_none());
+    assert!(edges[1].get("head_lp").is_none());
+}
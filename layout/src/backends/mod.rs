@@ -1,2 +1,9 @@
 //! Defines and keeps the implementation of the rendering backends.
+#[cfg(feature = "serde")]
+pub mod json;
+#[cfg(feature = "raster")]
+pub mod raster;
+#[cfg(feature = "raster")]
+mod stroke_font;
 pub mod svg;
+pub mod tikz;
@@ -0,0 +1,635 @@
+//! Raster rendering backend, that draws the shapes into an image and saves it
+//! in the PNG format. The drawing is recorded, and is rasterized when the
+//! size of the image is known. The text is drawn with a simple stroke font
+//! (see 'stroke_font'), so no font files are needed.
+
+use crate::backends::stroke_font;
+use crate::core::color::Color;
+use crate::core::format::{ClipHandle, RenderBackend};
+use crate::core::geometry::{
+    expand_tabs, get_size_for_str, get_width_of_char, get_width_of_line, Point,
+};
+use crate::core::style::{LineStyleKind, StyleAttr};
+use crate::std_shapes::render::path_to_bezier_points;
+use crate::std_shapes::shapes::LineEndKind;
+use std::collections::HashMap;
+use tiny_skia::{
+    FillRule, GradientStop, LineCap, LineJoin, LinearGradient, Mask, Paint,
+    Path, PathBuilder, Pixmap, Rect, SpreadMode, Stroke, StrokeDash, Transform,
+};
+
+/// The number of line segments that each bezier segment of an edge is
+/// flattened into.
+const CURVE_STEPS: usize = 16;
+
+/// \returns the color \p color in the format of the rasterizer.
+fn skia_color(color: &Color) -> tiny_skia::Color {
+    let rgb = u32::from_str_radix(&color.to_web_color_rgb()[1..], 16).unwrap();
+    let alpha = (color.alpha() * 255.).round() as u8;
+    let channel = |shift: u32| ((rgb >> shift) & 0xff) as u8;
+    tiny_skia::Color::from_rgba8(channel(16), channel(8), channel(0), alpha)
+}
+
+/// \returns a paint that fills with the solid color \p color.
+fn solid_paint(color: &Color) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color(skia_color(color));
+    paint.anti_alias = true;
+    paint
+}
+
+/// \returns the stroke of a line with the width \p width in the style
+/// \p style. The dash patterns match the patterns of the SVG backend.
+fn make_stroke(width: usize, style: LineStyleKind, scale: usize) -> Stroke {
+    let mut stroke = Stroke {
+        width: width as f32,
+        ..Stroke::default()
+    };
+    let pattern = match style {
+        LineStyleKind::Dashed => Option::Some((6., 4.)),
+        LineStyleKind::Dotted => Option::Some((1., 4.)),
+        LineStyleKind::Normal | LineStyleKind::None => Option::None,
+    };
+    if let Option::Some((dash, gap)) = pattern {
+        let scale = scale as f32;
+        stroke.dash = StrokeDash::new(vec![dash * scale, gap * scale], 0.);
+    }
+    stroke
+}
+
+/// \returns a path of the rectangle at \p xy with the size \p size, and with
+/// corners that are rounded by the radius \p rounded.
+fn rect_path(xy: Point, size: Point, rounded: usize) -> Option<Path> {
+    let (x, y) = (xy.x as f32, xy.y as f32);
+    let (w, h) = (size.x as f32, size.y as f32);
+    if rounded == 0 {
+        return Option::Some(PathBuilder::from_rect(Rect::from_xywh(
+            x, y, w, h,
+        )?));
+    }
+    let r = (rounded as f32).min(w / 2.).min(h / 2.);
+    let mut pb = PathBuilder::new();
+    pb.move_to(x + r, y);
+    pb.line_to(x + w - r, y);
+    pb.quad_to(x + w, y, x + w, y + r);
+    pb.line_to(x + w, y + h - r);
+    pb.quad_to(x + w, y + h, x + w - r, y + h);
+    pb.line_to(x + r, y + h);
+    pb.quad_to(x, y + h, x, y + h - r);
+    pb.line_to(x, y + r);
+    pb.quad_to(x, y, x + r, y);
+    pb.close();
+    pb.finish()
+}
+
+/// \returns a path through the points \p points, which is closed if
+/// \p close is set.
+fn polyline_path(points: &[Point], close: bool) -> Option<Path> {
+    let mut pb = PathBuilder::new();
+    pb.move_to(points[0].x as f32, points[0].y as f32);
+    for p in points.iter().skip(1) {
+        pb.line_to(p.x as f32, p.y as f32);
+    }
+    if close {
+        pb.close();
+    }
+    pb.finish()
+}
+
+/// \returns the point at \p t along the cubic bezier curve \p c.
+fn bezier_point(c: &[Point], t: f64) -> Point {
+    let s = 1. - t;
+    c[0].scale(s * s * s)
+        .add(c[1].scale(3. * s * s * t))
+        .add(c[2].scale(3. * s * t * t))
+        .add(c[3].scale(t * t * t))
+}
+
+/// \returns the cubic bezier curve \p points, in the format that
+/// 'path_to_bezier_points' returns, flattened into a list of points.
+fn flatten_curve(points: &[Point]) -> Vec<Point> {
+    let mut res = vec![points[0]];
+    for i in (0..points.len() - 1).step_by(3) {
+        let curve = &points[i..i + 4];
+        for step in 1..=CURVE_STEPS {
+            res.push(bezier_point(curve, step as f64 / CURVE_STEPS as f64));
+        }
+    }
+    res
+}
+
+/// \returns the width and the outline of the arrow head \p kind, pointing to
+/// the right, like the markers of the SVG backend. The height of the heads
+/// is 7. Dots don't have an outline.
+fn head_outline(kind: LineEndKind) -> (f64, Vec<(f64, f64)>) {
+    match kind {
+        LineEndKind::None | LineEndKind::Dot | LineEndKind::OpenDot => {
+            (7., Vec::new())
+        }
+        LineEndKind::Arrow | LineEndKind::OpenArrow | LineEndKind::Vee => {
+            (10., vec![(0., 0.), (10., 3.5), (0., 7.)])
+        }
+        LineEndKind::Diamond | LineEndKind::OpenDiamond => {
+            (14., vec![(0., 3.5), (7., 0.), (14., 3.5), (7., 7.)])
+        }
+        LineEndKind::Box | LineEndKind::OpenBox => {
+            (7., vec![(0., 0.), (7., 0.), (7., 7.), (0., 7.)])
+        }
+        LineEndKind::Inv | LineEndKind::OpenInv => {
+            (10., vec![(0., 3.5), (10., 0.), (10., 7.)])
+        }
+        LineEndKind::Tee => (3., vec![(0., 0.), (3., 0.), (3., 7.), (0., 7.)]),
+    }
+}
+
+/// A shape that was drawn, and is rasterized when the drawing is finalized.
+#[derive(Debug, Clone)]
+struct Command {
+    path: Path,
+    paint: Paint<'static>,
+    // Draw the outline of the path with this stroke, or fill the path.
+    stroke: Option<Stroke>,
+    clip: Option<ClipHandle>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RasterWriter {
+    commands: Vec<Command>,
+    view_size: Point,
+    // The shapes of the clip regions.
+    clip_regions: Vec<Path>,
+    // The number of pixels per point.
+    scale: f64,
+    background: Color,
+}
+
+impl RasterWriter {
+    pub fn new() -> RasterWriter {
+        RasterWriter {
+            commands: Vec::new(),
+            view_size: Point::zero(),
+            clip_regions: Vec::new(),
+            scale: 1.,
+            background: Color::fast("white"),
+        }
+    }
+}
+
+impl Default for RasterWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RasterWriter {
+    // Grow the image to include the point \p point plus some offset \p size.
+    fn grow_window(&mut self, point: Point, size: Point) {
+        self.view_size.x = self.view_size.x.max(point.x + size.x + 5.);
+        self.view_size.y = self.view_size.y.max(point.y + size.y + 5.);
+    }
+
+    fn fill(
+        &mut self,
+        path: Option<Path>,
+        color: &Color,
+        clip: Option<ClipHandle>,
+    ) {
+        if let Option::Some(path) = path {
+            if color.alpha() > 0. {
+                let paint = solid_paint(color);
+                let stroke = Option::None;
+                self.commands.push(Command {
+                    path,
+                    paint,
+                    stroke,
+                    clip,
+                });
+            }
+        }
+    }
+
+    fn stroke(&mut self, path: Option<Path>, paint: Paint<'static>, s: Stroke) {
+        if let Option::Some(path) = path {
+            if s.width > 0. {
+                let stroke = Option::Some(s);
+                let clip = Option::None;
+                self.commands.push(Command {
+                    path,
+                    paint,
+                    stroke,
+                    clip,
+                });
+            }
+        }
+    }
+
+    /// Fill the path \p path with the style \p look, and draw its outline.
+    fn draw_shape(
+        &mut self,
+        path: Option<Path>,
+        look: &StyleAttr,
+        clip: Option<ClipHandle>,
+    ) {
+        if let Option::Some(fill) = look.fill_color {
+            self.fill(path.clone(), &fill, clip);
+        }
+        self.draw_outline(path, look);
+    }
+
+    /// Draw the outline of the path \p path with the style \p look.
+    fn draw_outline(&mut self, path: Option<Path>, look: &StyleAttr) {
+        if look.line_color.alpha() == 0. {
+            return;
+        }
+        let width = look.line_width;
+        let stroke = make_stroke(width, look.line_style, width.max(1));
+        self.stroke(path, solid_paint(&look.line_color), stroke);
+    }
+
+    /// Draw the arrow head \p kind at the point \p tip, where the line that
+    /// arrives from the direction \p from ends.
+    fn draw_head(
+        &mut self,
+        kind: LineEndKind,
+        tip: Point,
+        from: Point,
+        look: &StyleAttr,
+    ) {
+        if let LineEndKind::None = kind {
+            return;
+        }
+        let dir = tip.sub(from);
+        let len = dir.length();
+        if len == 0. {
+            return;
+        }
+        // Heads grow with the width of the line, like SVG markers.
+        let scale = look.arrow_size * look.line_width.max(1) as f64;
+        let dir = dir.scale(1. / len);
+        let normal = Point::new(-dir.y, dir.x);
+        let (width, outline) = head_outline(kind);
+        let to_canvas = |x: f64, y: f64| {
+            tip.add(dir.scale((x - width) * scale))
+                .add(normal.scale((y - 3.5) * scale))
+        };
+
+        let path = match kind {
+            LineEndKind::Dot | LineEndKind::OpenDot => {
+                let center = to_canvas(3.5, 3.5);
+                let r = (3.5 * scale) as f32;
+                PathBuilder::from_circle(center.x as f32, center.y as f32, r)
+            }
+            _ => {
+                let points: Vec<Point> =
+                    outline.iter().map(|p| to_canvas(p.0, p.1)).collect();
+                let close = !matches!(kind, LineEndKind::Vee);
+                polyline_path(&points, close)
+            }
+        };
+        let color = look.line_color;
+        match kind {
+            LineEndKind::OpenArrow
+            | LineEndKind::OpenDiamond
+            | LineEndKind::OpenBox
+            | LineEndKind::OpenInv
+            | LineEndKind::OpenDot
+            | LineEndKind::Vee => {
+                let width = look.line_width.max(1);
+                let stroke = make_stroke(width, LineStyleKind::Normal, 1);
+                self.stroke(path, solid_paint(&color), stroke);
+            }
+            _ => self.fill(path, &color, Option::None),
+        }
+    }
+
+    /// Draw the image at \p scale pixels per point. The default is one
+    /// pixel per point.
+    pub fn set_scale(&mut self, scale: f64) {
+        assert!(scale > 0., "Invalid scale");
+        self.scale = scale;
+    }
+
+    /// Fill the image with the color \p color before drawing. The default is
+    /// white.
+    pub fn set_background(&mut self, color: Color) {
+        self.background = color;
+    }
+
+    /// \returns the size of the drawing, in points.
+    pub fn size(&self) -> Point {
+        self.view_size
+    }
+
+    /// Rasterize the drawing.
+    /// \returns the pixels of the image.
+    pub fn rasterize(&self) -> Pixmap {
+        let width = (self.view_size.x * self.scale).ceil().max(1.);
+        let height = (self.view_size.y * self.scale).ceil().max(1.);
+        let mut pixmap = Pixmap::new(width as u32, height as u32).unwrap();
+        pixmap.fill(skia_color(&self.background));
+
+        let scale = self.scale as f32;
+        let transform = Transform::from_scale(scale, scale);
+        let mut masks: HashMap<ClipHandle, Mask> = HashMap::new();
+        for cmd in &self.commands {
+            let mut mask = Option::None;
+            if let Option::Some(clip) = cmd.clip {
+                let mask_entry = masks.entry(clip).or_insert_with(|| {
+                    let mut mask =
+                        Mask::new(pixmap.width(), pixmap.height()).unwrap();
+                    let path = &self.clip_regions[clip];
+                    mask.fill_path(path, FillRule::Winding, true, transform);
+                    mask
+                });
+                mask = Option::Some(&*mask_entry);
+            }
+            match &cmd.stroke {
+                Option::Some(stroke) => {
+                    pixmap.stroke_path(
+                        &cmd.path, &cmd.paint, stroke, transform, mask,
+                    );
+                }
+                Option::None => {
+                    pixmap.fill_path(
+                        &cmd.path,
+                        &cmd.paint,
+                        FillRule::Winding,
+                        transform,
+                        mask,
+                    );
+                }
+            }
+        }
+        pixmap
+    }
+
+    /// \returns the content of the PNG file.
+    pub fn finalize(&self) -> Vec<u8> {
+        self.rasterize().encode_png().unwrap()
+    }
+}
+
+impl RenderBackend for RasterWriter {
+    fn draw_rect(
+        &mut self,
+        xy: Point,
+        size: Point,
+        look: &StyleAttr,
+        _properties: Option<String>,
+        clip: Option<ClipHandle>,
+    ) {
+        self.grow_window(xy, size);
+        self.draw_shape(rect_path(xy, size, look.rounded), look, clip);
+    }
+
+    fn draw_line(
+        &mut self,
+        start: Point,
+        stop: Point,
+        look: &StyleAttr,
+        _properties: Option<String>,
+    ) {
+        self.draw_outline(polyline_path(&[start, stop], false), look);
+    }
+
+    fn draw_circle(
+        &mut self,
+        xy: Point,
+        size: Point,
+        look: &StyleAttr,
+        _properties: Option<String>,
+    ) {
+        self.grow_window(xy, size);
+        let top_left = xy.sub(size.scale(0.5));
+        let oval = Rect::from_xywh(
+            top_left.x as f32,
+            top_left.y as f32,
+            size.x as f32,
+            size.y as f32,
+        );
+        self.draw_shape(oval.and_then(PathBuilder::from_oval), look, None);
+    }
+
+    fn draw_polygon(
+        &mut self,
+        points: &[Point],
+        look: &StyleAttr,
+        _properties: Option<String>,
+    ) {
+        for p in points {
+            self.grow_window(*p, Point::zero());
+        }
+        self.draw_shape(polyline_path(points, true), look, None);
+    }
+
+    fn draw_text(&mut self, xy: Point, text: &str, look: &StyleAttr) {
+        let mut size = get_size_for_str(text, look.font_size);
+        if look.vertical_text {
+            size = Point::new(size.y, size.x);
+        }
+        self.grow_window(xy, size.scale(0.5));
+
+        // The lines of text are 10 units tall, and are centered around the
+        // point, like in the SVG backend.
+        let font_size = look.font_size as f64;
+        let unit = font_size / 10.;
+        let lines: Vec<String> = text.lines().map(expand_tabs).collect();
+        let columns = |line: &String| get_width_of_line(line) as f64;
+        let widest = lines.iter().map(columns).fold(0., f64::max);
+        let mut pb = PathBuilder::new();
+        for (i, line) in lines.iter().enumerate() {
+            let middle = (lines.len() - 1) as f64 / 2.;
+            let center_y = xy.y + (i as f64 - middle) * font_size;
+            // Monospace lines are aligned to the left, to keep the
+            // indentation.
+            let width = if look.monospace {
+                widest
+            } else {
+                columns(line)
+            };
+            let mut x = xy.x - width * stroke_font::ADVANCE * unit / 2.;
+            for ch in line.chars() {
+                let cell = get_width_of_char(ch) as f64 * stroke_font::ADVANCE;
+                // The middle of the capital letters is at y=3.
+                let origin = Point::new(x + (cell - 4.) / 2. * unit, center_y);
+                for stroke in stroke_font::glyph(ch) {
+                    let to_canvas = |p: (f64, f64)| {
+                        let p =
+                            origin.add(Point::new(p.0, p.1 - 3.).scale(unit));
+                        (p.x as f32, p.y as f32)
+                    };
+                    let (x0, y0) = to_canvas(stroke[0]);
+                    pb.move_to(x0, y0);
+                    for p in stroke.iter().skip(1) {
+                        let (x1, y1) = to_canvas(*p);
+                        pb.line_to(x1, y1);
+                    }
+                }
+                x += cell * unit;
+            }
+        }
+
+        let mut path = pb.finish();
+        if look.vertical_text {
+            let (x, y) = (xy.x as f32, xy.y as f32);
+            let rotate = Transform::from_rotate_at(-90., x, y);
+            path = path.and_then(|p| p.transform(rotate));
+        }
+        let color = look.font_color.unwrap_or_else(|| Color::fast("black"));
+        let stroke = Stroke {
+            width: unit.max(1.) as f32,
+            line_cap: LineCap::Round,
+            line_join: LineJoin::Round,
+            ..Stroke::default()
+        };
+        if color.alpha() > 0. {
+            self.stroke(path, solid_paint(&color), stroke);
+        }
+    }
+
+    fn draw_arrow(
+        &mut self,
+        // The first element is the start point and its control point, and
+        // the rest are the entry control points and the following points.
+        path: &[(Point, Point)],
+        line_style: LineStyleKind,
+        head: (LineEndKind, LineEndKind),
+        look: &StyleAttr,
+        properties: Option<String>,
+        text: &str,
+    ) {
+        let points = path_to_bezier_points(path);
+        self.draw_curve_arrow(
+            &points, line_style, head, look, properties, text,
+        );
+    }
+
+    fn draw_curve_arrow(
+        &mut self,
+        points: &[Point],
+        line_style: LineStyleKind,
+        head: (LineEndKind, LineEndKind),
+        look: &StyleAttr,
+        _properties: Option<String>,
+        _text: &str,
+    ) {
+        for point in points {
+            self.grow_window(*point, Point::zero());
+        }
+        let points = flatten_curve(points);
+
+        let mut paint = solid_paint(&look.line_color);
+        if let Option::Some(head_color) = look.gradient_color {
+            let to_skia =
+                |p: Point| tiny_skia::Point::from_xy(p.x as _, p.y as _);
+            let stops = vec![
+                GradientStop::new(0., skia_color(&look.line_color)),
+                GradientStop::new(1., skia_color(&head_color)),
+            ];
+            let gradient = LinearGradient::new(
+                to_skia(points[0]),
+                to_skia(points[points.len() - 1]),
+                stops,
+                SpreadMode::Pad,
+                Transform::identity(),
+            );
+            // There is no gradient if the ends of the edge meet.
+            if let Option::Some(shader) = gradient {
+                paint.shader = shader;
+            }
+        }
+        let stroke = make_stroke(look.line_width, line_style, 1);
+        self.stroke(polyline_path(&points, false), paint, stroke);
+
+        let n = points.len();
+        self.draw_head(head.0, points[0], points[1], look);
+        self.draw_head(head.1, points[n - 1], points[n - 2], look);
+    }
+
+    fn create_clip(
+        &mut self,
+        xy: Point,
+        size: Point,
+        rounded_px: usize,
+    ) -> ClipHandle {
+        let handle = self.clip_regions.len();
+        let path = rect_path(xy, size, rounded_px);
+        // Empty clip regions hide the shapes.
+        let empty = Rect::from_xywh(0., 0., 0., 0.).map(PathBuilder::from_rect);
+        self.clip_regions.push(path.or(empty).unwrap());
+        handle
+    }
+}
+
+#[test]
+fn test_raster_backend() {
+    use crate::gv::{DotParser, GraphBuilder};
+
+    let dot = "digraph { a [shape=box, style=filled, fillcolor=red]; \
+        a -> b [style=dashed, arrowhead=odot]; }";
+    let ast = DotParser::new(dot).process().unwrap();
+    let mut gb = GraphBuilder::new();
+    gb.visit_graph(&ast);
+    let mut vg = gb.get();
+    let mut raster = RasterWriter::new();
+    vg.do_it(false, false, false, &mut raster);
+
+    let png = raster.finalize();
+    assert!(png.starts_with(b"\x89PNG\r\n\x1a\n"));
+    let image = Pixmap::decode_png(&png).unwrap();
+    assert_eq!(image.width() as f64, raster.size().x.ceil());
+    assert_eq!(image.height() as f64, raster.size().y.ceil());
+
+    // The box is filled with red, and the background is white. The label
+    // is drawn in the middle of the box, so look above it.
+    use crate::adt::dag::NodeHandle;
+    let bb = vg.pos(NodeHandle::new(0)).bbox(false);
+    let pixel = |p: Point| image.pixel(p.x as u32, p.y as u32).unwrap();
+    let red = pixel(Point::new((bb.0.x + bb.1.x) / 2., bb.0.y + 4.));
+    assert_eq!((red.red(), red.green(), red.blue()), (255, 0, 0));
+    let corner = pixel(Point::new(1., 1.));
+    assert_eq!(
+        (corner.red(), corner.green(), corner.blue()),
+        (255, 255, 255)
+    );
+
+    // Twice the scale doubles the size of the image.
+    raster.set_scale(2.);
+    let image = raster.rasterize();
+    assert_eq!(image.width() as f64, (raster.size().x * 2.).ceil());
+}
+
+#[test]
+fn test_raster_text() {
+    use crate::adt::dag::NodeHandle;
+    use crate::gv::{DotParser, GraphBuilder};
+
+    // \returns the number of pixels inside of the box of the first node
+    // that are not white.
+    let count_ink = |dot: &str| {
+        let ast = DotParser::new(dot).process().unwrap();
+        let mut gb = GraphBuilder::new();
+        gb.visit_graph(&ast);
+        let mut vg = gb.get();
+        let mut raster = RasterWriter::new();
+        vg.do_it(false, false, false, &mut raster);
+        let image = raster.rasterize();
+        // Skip the outline of the box.
+        let bb = vg.pos(NodeHandle::new(0)).bbox(false);
+        let (x0, y0) = (bb.0.x as u32 + 3, bb.0.y as u32 + 3);
+        let (x1, y1) = (bb.1.x as u32 - 3, bb.1.y as u32 - 3);
+        let mut count = 0;
+        for x in x0..x1 {
+            for y in y0..y1 {
+                let p = image.pixel(x, y).unwrap();
+                if (p.red(), p.green(), p.blue()) != (255, 255, 255) {
+                    count += 1;
+                }
+            }
+        }
+        count
+    };
+    assert_eq!(count_ink("digraph { a [shape=box, label=\" \"]; }"), 0);
+    assert!(count_ink("digraph { a [shape=box, label=\"Hello\"]; }") > 20);
+    assert!(count_ink("digraph { a [shape=box, label=\"\u{263a}\"]; }") > 0);
+}
@@ -0,0 +1,150 @@
+//! A simple stroke font, that the raster backend uses to draw text without
+//! font files. Each glyph is a list of polylines on a grid that is 4 units
+//! wide. The capital letters and the digits go from the top of the grid
+//! (y=0) to the baseline (y=6), the small letters start at y=2, and the
+//! descenders reach y=8.
+
+/// The distance between the starts of adjacent columns of text, in units.
+pub const ADVANCE: f64 = 6.;
+
+/// The glyph of characters that are not in the font: a box.
+const MISSING: &str = "0040460600";
+
+/// The glyphs of the printable ASCII characters, starting with the space.
+/// The polylines are separated by spaces, and each polyline is a list of
+/// points, written as pairs of digits (the x and the y of the point).
+const GLYPHS: [&str; 95] = [
+    "",                                    // ' '
+    "2024 2526",                           // '!'
+    "1012 3032",                           // '"'
+    "1115 3135 0242 0444",                 // '#'
+    "413010010213334445361605 2027",       // '$'
+    "0640 0011 3546",                      // '%'
+    "4612112030310405162644",              // '&'
+    "2022",                                // '''
+    "30111536",                            // '('
+    "10313516",                            // ')'
+    "2125 0244 0442",                      // '*'
+    "2125 0343",                           // '+'
+    "252617",                              // ','
+    "0343",                                // '-'
+    "2526",                                // '.'
+    "0640",                                // '/'
+    "103041453616050110 4105",             // '0'
+    "112026 1636",                         // '1'
+    "01103041420646",                      // '2'
+    "01103041423313 334445361605",         // '3'
+    "300444 3036",                         // '4'
+    "400002324345361605",                  // '5'
+    "413010010516364544331304",            // '6'
+    "004016",                              // '7'
+    "103041423313020110 1304051636454433", // '8'
+    "423313020110304145361605",            // '9'
+    "2223 2526",                           // ':'
+    "2223 252617",                         // ';'
+    "400346",                              // '<'
+    "0242 0444",                           // '='
+    "004306",                              // '>'
+    "011030414223 2526",                   // '?'
+    "34321214344541301001051636",          // '@'
+    "062046 1333",                         // 'A'
+    "06003041423303 3344453606",           // 'B'
+    "4130100105163645",                    // 'C'
+    "06002042442606",                      // 'D'
+    "40000646 0333",                       // 'E'
+    "400006 0333",                         // 'F'
+    "41301001051636454323",                // 'G'
+    "0006 4046 0343",                      // 'H'
+    "1030 2026 1636",                      // 'I'
+    "4045361605",                          // 'J'
+    "0006 4003 1246",                      // 'K'
+    "000646",                              // 'L'
+    "0600234046",                          // 'M'
+    "06004640",                            // 'N'
+    "103041453616050110",                  // 'O'
+    "06003041423303",                      // 'P'
+    "103041453616050110 2446",             // 'Q'
+    "06003041423303 2346",                 // 'R'
+    "413010010213334445361605",            // 'S'
+    "0040 2026",                           // 'T'
+    "000516364540",                        // 'U'
+    "002640",                              // 'V'
+    "0016233640",                          // 'W'
+    "0046 4006",                           // 'X'
+    "002340 2326",                         // 'Y'
+    "00400646",                            // 'Z'
+    "30101636",                            // '['
+    "0046",                                // '\'
+    "10303616",                            // ']'
+    "032043",                              // '^'
+    "0747",                                // '_'
+    "1021",                                // '`'
+    "12324346 441405163645",               // 'a'
+    "0006 0312324345361605",               // 'b'
+    "4332120305163645",                    // 'c'
+    "4046 4332120305163645",               // 'd'
+    "04444332120305163645",                // 'e'
+    "4130201116 0232",                     // 'f'
+    "4332120304153544 4247381807",         // 'g'
+    "0006 0312324346",                     // 'h'
+    "2226 2021",                           // 'i'
+    "3237281807 3031",                     // 'j'
+    "0006 4204 2446",                      // 'k'
+    "10202536",                            // 'l'
+    "0206 03122326 23324346",              // 'm'
+    "0206 0312324346",                     // 'n'
+    "123243453616050312",                  // 'o'
+    "0208 0312324345361605",               // 'p'
+    "4248 4332120305163645",               // 'q'
+    "0206 04223243",                       // 'r'
+    "43321203143445361605",                // 's'
+    "10152636 0232",                       // 't'
+    "0205163645 4246",                     // 'u'
+    "022642",                              // 'v'
+    "0216233642",                          // 'w'
+    "0246 4206",                           // 'x'
+    "0225 4218",                           // 'y'
+    "02420646",                            // 'z'
+    "30212213242536",                      // '{'
+    "2027",                                // '|'
+    "10212233242516",                      // '}'
+    "04132433",                            // '~'
+];
+
+/// \returns the polylines of the glyph of the character \p ch, in grid
+/// units. Characters that are not in the font are drawn as boxes.
+pub fn glyph(ch: char) -> Vec<Vec<(f64, f64)>> {
+    let code = ch as usize;
+    let desc = if (32..127).contains(&code) {
+        GLYPHS[code - 32]
+    } else {
+        MISSING
+    };
+    desc.split_whitespace()
+        .map(|line| {
+            line.as_bytes()
+                .chunks(2)
+                .map(|p| ((p[0] - b'0') as f64, (p[1] - b'0') as f64))
+                .collect()
+        })
+        .collect()
+}
+
+#[test]
+fn test_stroke_font() {
+    for code in 32..127u8 {
+        let ch = code as char;
+        let desc = GLYPHS[code as usize - 32];
+        for line in desc.split_whitespace() {
+            assert!(line.len() >= 4 && line.len() % 2 == 0, "{}", ch);
+        }
+        for line in glyph(ch) {
+            for (x, y) in line {
+                assert!(x <= 4. && y <= 8., "{}", ch);
+            }
+        }
+    }
+    assert!(glyph(' ').is_empty());
+    assert_eq!(glyph('\u{263a}'), glyph('\u{1}'));
+    assert_eq!(glyph('\u{263a}').len(), 1);
+}
@@ -2,26 +2,132 @@
 
 use crate::core::color::Color;
 use crate::core::format::{ClipHandle, RenderBackend};
-use crate::core::geometry::Point;
-use crate::core::style::StyleAttr;
-use std::collections::HashMap;
+use crate::core::geometry::{
+    expand_tabs, get_size_for_str, get_width_of_line, Point,
+};
+use crate::core::style::{LineCap, LineJoin, LineStyleKind, StyleAttr};
+use crate::std_shapes::shapes::LineEndKind;
+use crate::topo::layout::VisualGraph;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
 
 static SVG_HEADER: &str =
     r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>"#;
 
-static SVG_DEFS: &str = r#"<defs>
-<marker id="startarrow" markerWidth="10" markerHeight="7"
-refX="0" refY="3.5" orient="auto">
-<polygon points="10 0, 10 7, 0 3.5" fill="context-stroke" />
-</marker>
-<marker id="endarrow" markerWidth="10" markerHeight="7"
-refX="10" refY="3.5" orient="auto">
-<polygon points="0 0, 10 3.5, 0 7" fill="context-stroke" />
-</marker>
+static SVG_FOOTER: &str = "</svg>";
 
-</defs>"#;
+/// \returns the svg attributes that fill a shape with the color \p color.
+/// Translucent colors use the 'fill-opacity' attribute, which is supported by
+/// more renderers than colors with an alpha channel.
+fn fill_attributes(color: &Color) -> String {
+    if color.is_opaque() {
+        return format!("fill=\"{}\"", color.to_web_color());
+    }
+    format!(
+        "fill=\"{}\" fill-opacity=\"{:.3}\"",
+        color.to_web_color_rgb(),
+        color.alpha()
+    )
+}
 
-static SVG_FOOTER: &str = "</svg>";
+/// \returns the svg attributes of a gradient stop with the color \p color.
+fn stop_attributes(color: &Color) -> String {
+    if color.is_opaque() {
+        return format!("stop-color=\"{}\"", color.to_web_color());
+    }
+    format!(
+        "stop-color=\"{}\" stop-opacity=\"{:.3}\"",
+        color.to_web_color_rgb(),
+        color.alpha()
+    )
+}
+
+/// \returns the svg attributes that draw the outline of a shape with the
+/// color \p color, like 'fill_attributes'.
+fn stroke_attributes(color: &Color) -> String {
+    if color.is_opaque() {
+        return format!("stroke=\"{}\"", color.to_web_color());
+    }
+    format!(
+        "stroke=\"{}\" stroke-opacity=\"{:.3}\"",
+        color.to_web_color_rgb(),
+        color.alpha()
+    )
+}
+
+/// \returns the svg attribute that draws a line in the style \p style with
+/// dashes or dots, with the pattern scaled by \p scale, or an empty string
+/// for solid lines.
+fn dash_array(style: LineStyleKind, scale: usize) -> String {
+    let (dash, gap) = match style {
+        LineStyleKind::Dashed => (6, 4),
+        LineStyleKind::Dotted => (1, 4),
+        LineStyleKind::Normal | LineStyleKind::None => return String::new(),
+    };
+    format!(" stroke-dasharray=\"{},{}\"", dash * scale, gap * scale)
+}
+
+/// \returns the svg attribute that draws the outline of a shape in the style
+/// \p look with dashes or dots, or an empty string for solid lines. The
+/// patterns are scaled by the line width, unlike the patterns of edges, which
+/// keep the same size for all of the line widths.
+fn dash_attributes(look: &StyleAttr) -> String {
+    dash_array(look.line_style, look.line_width.max(1))
+}
+
+/// \returns the svg attributes that set the shape of the ends and the corners
+/// of the lines in the style \p look, or an empty string for the defaults of
+/// SVG. Edges, if \p is_edge is set, have round ends and corners by default.
+fn cap_and_join_attributes(look: &StyleAttr, is_edge: bool) -> String {
+    let mut res = String::new();
+    let cap = match is_edge {
+        true => look.line_cap.or(Option::Some(LineCap::Round)),
+        false => look.line_cap,
+    };
+    if let Option::Some(cap) = cap {
+        let name = match cap {
+            LineCap::Butt => "butt",
+            LineCap::Round => "round",
+            LineCap::Square => "square",
+        };
+        res.push_str(&format!(" stroke-linecap=\"{}\"", name));
+    }
+    let join = match is_edge {
+        true => look.line_join.or(Option::Some(LineJoin::Round)),
+        false => look.line_join,
+    };
+    if let Option::Some(join) = join {
+        let name = match join {
+            LineJoin::Miter => "miter",
+            LineJoin::Round => "round",
+            LineJoin::Bevel => "bevel",
+        };
+        res.push_str(&format!(" stroke-linejoin=\"{}\"", name));
+    }
+    res
+}
+
+/// \returns the svg attributes that set the color of text in the style
+/// \p look, or an empty string for the default color.
+fn text_color_attributes(look: &StyleAttr) -> String {
+    if let Option::Some(color) = &look.font_color {
+        return format!(" {}", fill_attributes(color));
+    }
+    String::new()
+}
+
+/// \returns \p x with the dashes that follow a dash separated by spaces,
+/// because comments can't contain the sequence "--".
+fn escape_comment(x: &str) -> String {
+    let mut res = String::new();
+    for c in x.chars() {
+        if c == '-' && res.ends_with('-') {
+            res.push(' ');
+        }
+        res.push(c);
+    }
+    res
+}
 
 fn escape_string(x: &str) -> String {
     let mut res = String::new();
@@ -50,15 +156,26 @@ fn escape_string(x: &str) -> String {
     res
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SVGWriter {
     content: String,
     view_size: Point,
     counter: usize,
     // Maps font sizes to their class name and class impl.
-    font_style_map: HashMap<usize, (String, String)>,
+    font_style_map: HashMap<(usize, bool), (String, String)>,
     // A list of clip regions to generate.
     clip_regions: Vec<String>,
+    // Maps the names of the arrow head markers to their impl.
+    markers: BTreeMap<String, String>,
+    // A list of the gradients that the edges use.
+    gradients: Vec<String>,
+    // A prefix for the ids and the class names in the document.
+    id_prefix: String,
+    // Stretch the text to the width that the layout reserved for it.
+    fit_text: bool,
+    // The top-left corner and the size of the visible part of the drawing,
+    // if only a part of the drawing is visible.
+    viewport: Option<(Point, Point)>,
 }
 
 impl SVGWriter {
@@ -69,6 +186,11 @@ impl SVGWriter {
             counter: 0,
             font_style_map: HashMap::new(),
             clip_regions: Vec::new(),
+            markers: BTreeMap::new(),
+            gradients: Vec::new(),
+            id_prefix: String::new(),
+            fit_text: false,
+            viewport: Option::None,
         }
     }
 }
@@ -94,20 +216,222 @@ impl SVGWriter {
 
     // Gets or creates a font 'class' for the parameters. Returns the class
     // name.
-    fn get_or_create_font_style(&mut self, font_size: usize) -> String {
-        if let Option::Some(x) = self.font_style_map.get(&font_size) {
+    fn get_or_create_font_style(
+        &mut self,
+        font_size: usize,
+        monospace: bool,
+    ) -> String {
+        let key = (font_size, monospace);
+        if let Option::Some(x) = self.font_style_map.get(&key) {
             return x.0.clone();
         }
-        let class_name = format!("a{}", font_size);
-        let class_impl = format!(
-            ".a{} {{ font-size: {}px; font-family: Times, serif; }}",
-            font_size, font_size
-        );
+        let class_name;
+        let class_impl;
+        if monospace {
+            class_name = format!("{}m{}", self.id_prefix, font_size);
+            class_impl = format!(
+                ".{} {{ font-size: {}px; font-family: Courier, monospace; \
+                white-space: pre; }}",
+                class_name, font_size
+            );
+        } else {
+            class_name = format!("{}a{}", self.id_prefix, font_size);
+            class_impl = format!(
+                ".{} {{ font-size: {}px; font-family: Times, serif; }}",
+                class_name, font_size
+            );
+        }
         let impl_ = (class_name.clone(), class_impl);
-        self.font_style_map.insert(font_size, impl_);
+        self.font_style_map.insert(key, impl_);
         class_name
     }
 
+    // Gets or creates a marker for the arrow head \p kind, at the start or at
+    // the end of the line, scaled by \p scale. Returns the marker attribute
+    // for the path.
+    fn get_or_create_marker(
+        &mut self,
+        kind: LineEndKind,
+        start: bool,
+        scale: f64,
+    ) -> String {
+        // The name, the width, and the outline of the head, pointing to the
+        // right. Open heads are not filled, and the vee head is not closed.
+        let triangle: &[(f64, f64)] = &[(0., 0.), (10., 3.5), (0., 7.)];
+        let diamond: &[(f64, f64)] =
+            &[(0., 3.5), (7., 0.), (14., 3.5), (7., 7.)];
+        let square: &[(f64, f64)] = &[(0., 0.), (7., 0.), (7., 7.), (0., 7.)];
+        let inv: &[(f64, f64)] = &[(0., 3.5), (10., 0.), (10., 7.)];
+        let bar: &[(f64, f64)] = &[(0., 0.), (3., 0.), (3., 7.), (0., 7.)];
+        // Dots are drawn as circles, and don't have an outline.
+        let none: &[(f64, f64)] = &[];
+        let (name, width, outline) = match kind {
+            LineEndKind::None => return String::new(),
+            LineEndKind::Arrow => ("arrow", 10., triangle),
+            LineEndKind::OpenArrow => ("openarrow", 10., triangle),
+            LineEndKind::Diamond => ("diamond", 14., diamond),
+            LineEndKind::OpenDiamond => ("opendiamond", 14., diamond),
+            LineEndKind::Vee => ("vee", 10., triangle),
+            LineEndKind::Dot => ("dot", 7., none),
+            LineEndKind::OpenDot => ("odot", 7., none),
+            LineEndKind::Box => ("box", 7., square),
+            LineEndKind::OpenBox => ("obox", 7., square),
+            LineEndKind::Inv => ("inv", 10., inv),
+            LineEndKind::OpenInv => ("oinv", 10., inv),
+            LineEndKind::Tee => ("tee", 3., bar),
+        };
+        let (mut id, attr) = if start {
+            (format!("{}start{}", self.id_prefix, name), "marker-start")
+        } else {
+            (format!("{}end{}", self.id_prefix, name), "marker-end")
+        };
+        // Heads of different sizes need their own markers.
+        if scale != 1. {
+            id = format!("{}-{}", id, scale);
+        }
+
+        if !self.markers.contains_key(&id) {
+            // Start markers point to the left.
+            let points: Vec<String> = outline
+                .iter()
+                .map(|p| {
+                    let x = if start { width - p.0 } else { p.0 };
+                    format!("{} {}", x * scale, p.1 * scale)
+                })
+                .collect();
+            let open = "fill=\"none\" stroke=\"context-stroke\"";
+            let filled = "fill=\"context-stroke\"";
+            let points = format!("points=\"{}\"", points.join(", "));
+            // Dots touch the end of the line.
+            let r = 3.5 * scale;
+            let circle = format!("cx=\"{}\" cy=\"{}\" r=\"{}\"", r, r, r);
+            let (elem, shape, fill) = match kind {
+                LineEndKind::OpenArrow
+                | LineEndKind::OpenDiamond
+                | LineEndKind::OpenBox
+                | LineEndKind::OpenInv => ("polygon", points, open),
+                LineEndKind::Vee => ("polyline", points, open),
+                LineEndKind::Dot => ("circle", circle, filled),
+                LineEndKind::OpenDot => ("circle", circle, open),
+                _ => ("polygon", points, filled),
+            };
+            let marker = format!(
+                "<marker id=\"{}\" markerWidth=\"{}\" markerHeight=\"{}\"\n\
+                refX=\"{}\" refY=\"{}\" orient=\"auto\" overflow=\"visible\">\n\
+                <{} {} {} />\n</marker>\n",
+                id,
+                width * scale,
+                7. * scale,
+                if start { 0. } else { width * scale },
+                3.5 * scale,
+                elem,
+                shape,
+                fill
+            );
+            self.markers.insert(id.clone(), marker);
+        }
+        format!("{}=\"url(#{})\"", attr, id)
+    }
+
+    /// Add the path element of an arrow with the svg path data \p d, which
+    /// goes from \p ends.0 to \p ends.1. The other parameters are like the
+    /// parameters of 'draw_arrow'.
+    #[allow(clippy::too_many_arguments)]
+    fn add_arrow_path(
+        &mut self,
+        d: &str,
+        ends: (Point, Point),
+        line_style: LineStyleKind,
+        head: (LineEndKind, LineEndKind),
+        look: &StyleAttr,
+        properties: Option<String>,
+        text: &str,
+    ) {
+        let dash = dash_array(line_style, 1);
+        let start = self.get_or_create_marker(head.0, true, look.arrow_size);
+        let end = self.get_or_create_marker(head.1, false, look.arrow_size);
+
+        let stroke_width = look.line_width;
+        let stroke_color = look.line_color;
+        let mut stroke = stroke_attributes(&stroke_color);
+        if let Option::Some(head_color) = look.gradient_color {
+            stroke = self.create_gradient(ends, stroke_color, head_color);
+        }
+        let props = properties.unwrap_or_default();
+        let line = format!(
+            "<g {props}>\n
+            <path id=\"{}arrow{}\" d=\"{}\" \
+            {} stroke-width=\"{}\"{}{} {} {} 
+            fill=\"transparent\" />\n
+            </g>\n",
+            self.id_prefix,
+            self.counter,
+            d,
+            stroke,
+            stroke_width,
+            dash,
+            cap_and_join_attributes(look, true),
+            start,
+            end
+        );
+        self.content.push_str(&line);
+
+        let font_class =
+            self.get_or_create_font_style(look.font_size, look.monospace);
+        let line = format!(
+            "<text><textPath href=\"#{}arrow{}\" startOffset=\"50%\" \
+            text-anchor=\"middle\" class=\"{}\"{}>{}</textPath></text>",
+            self.id_prefix,
+            self.counter,
+            font_class,
+            text_color_attributes(look),
+            escape_string(text)
+        );
+        self.content.push_str(&line);
+        self.counter += 1;
+    }
+
+    // Creates a gradient from the color \p tail at the start of the path
+    // \p ends.0 to the color \p head at the end of the path \p ends.1.
+    // Returns the stroke attribute for the path.
+    fn create_gradient(
+        &mut self,
+        ends: (Point, Point),
+        tail: Color,
+        head: Color,
+    ) -> String {
+        let id = format!("{}gradient{}", self.id_prefix, self.gradients.len());
+        let (from, to) = ends;
+        let gradient = format!(
+            "<linearGradient id=\"{}\" gradientUnits=\"userSpaceOnUse\" \
+            x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\">\n\
+            <stop offset=\"0\" {} />\n<stop offset=\"1\" {} />\n\
+            </linearGradient>\n",
+            id,
+            from.x,
+            from.y,
+            to.x,
+            to.y,
+            stop_attributes(&tail),
+            stop_attributes(&head)
+        );
+        self.gradients.push(gradient);
+        format!("stroke=\"url(#{})\"", id)
+    }
+
+    fn emit_svg_defs(&self) -> String {
+        let mut content = String::new();
+        content.push_str("<defs>\n");
+        for marker in self.markers.values() {
+            content.push_str(marker);
+        }
+        for gradient in self.gradients.iter() {
+            content.push_str(gradient);
+        }
+        content.push_str("</defs>\n");
+        content
+    }
+
     fn emit_svg_font_styles(&self) -> String {
         let mut content = String::new();
         content.push_str("<style>\n");
@@ -123,26 +447,104 @@ impl SVGWriter {
         content
     }
 
+    /// Start the ids of markers, clip regions and paths, and the names of
+    /// the font classes, with \p prefix. Drawings that are embedded in the
+    /// same page need different prefixes, to keep their ids unique. This
+    /// needs to be called before anything is drawn.
+    pub fn set_id_prefix(&mut self, prefix: &str) {
+        assert!(self.content.is_empty(), "Set the prefix before drawing");
+        self.id_prefix = prefix.to_string();
+    }
+
+    /// Emit the lines of text with the width that the layout measured for
+    /// them. The layout estimates the size of text, and the fonts of the
+    /// viewer may be wider or narrower. When \p enable is set the viewer
+    /// squeezes or stretches the text to fit the space that was reserved.
+    pub fn set_fit_text(&mut self, enable: bool) {
+        self.fit_text = enable;
+    }
+
+    /// Only show the part of the drawing that starts at the top-left corner
+    /// \p origin and has the size \p size. The size of the SVG document is
+    /// the size of the visible part.
+    pub fn set_viewport(&mut self, origin: Point, size: Point) {
+        self.viewport = Option::Some((origin, size));
+    }
+
+    /// \returns the size of the drawing.
+    pub fn size(&self) -> Point {
+        self.view_size
+    }
+
+    /// \returns the drawing as a group element that can be embedded in
+    /// another SVG document, without the XML prolog and the root element.
+    /// The definitions and the styles are inside the group. See
+    /// 'set_id_prefix' for embedding more than one drawing.
+    pub fn finalize_fragment(&self) -> String {
+        let mut content = String::new();
+        content.push_str("<g>\n");
+        content.push_str(&self.emit_svg_defs());
+        content.push_str(&self.emit_svg_font_styles());
+        content.push_str(&self.content);
+        content.push_str("</g>\n");
+        content
+    }
+
+    /// \returns the content of the SVG file.
     pub fn finalize(&self) -> String {
-        let mut result = String::new();
-        result.push_str(SVG_HEADER);
-
-        let svg_line = format!(
-            "<svg width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\
-            \" xmlns=\"http://www.w3.org/2000/svg\">\n",
-            self.view_size.x,
-            self.view_size.y,
-            self.view_size.x,
-            self.view_size.y
-        );
-        result.push_str(&svg_line);
-        result.push_str(SVG_DEFS);
-        result.push_str(&self.emit_svg_font_styles());
-        result.push_str(&self.content);
-        result.push_str(SVG_FOOTER);
-        result
+        let mut result = Vec::new();
+        self.finalize_to(&mut result).unwrap();
+        String::from_utf8(result).unwrap()
+    }
+
+    /// Write the content of the SVG file to \p writer. Unlike 'finalize',
+    /// this method does not build a second copy of the drawing in memory,
+    /// which matters for very large graphs.
+    pub fn finalize_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(SVG_HEADER.as_bytes())?;
+        let (origin, size) =
+            self.viewport.unwrap_or((Point::zero(), self.view_size));
+        writeln!(
+            writer,
+            "<svg width=\"{}\" height=\"{}\" viewBox=\"{} {} {} {}\
+            \" xmlns=\"http://www.w3.org/2000/svg\">",
+            size.x, size.y, origin.x, origin.y, size.x, size.y
+        )?;
+        writer.write_all(self.emit_svg_defs().as_bytes())?;
+        writer.write_all(self.emit_svg_font_styles().as_bytes())?;
+        writer.write_all(self.content.as_bytes())?;
+        writer.write_all(SVG_FOOTER.as_bytes())
+    }
+}
+impl VisualGraph {
+    /// Render the graph, and split the drawing into a grid of pages of the
+    /// size \p page_size, for printing large graphs. This method needs to be
+    /// called after the graph was laid out (see 'do_it' and 'layout').
+    /// \returns one document per page, by rows, from the top-left corner.
+    pub fn render_pages(&self, page_size: Point) -> Vec<SVGWriter> {
+        assert!(page_size.x > 0. && page_size.y > 0., "Invalid page size");
+        let mut svg = SVGWriter::new();
+        self.render(false, &mut svg);
+
+        let size = svg.size();
+        let cols = (size.x / page_size.x).ceil().max(1.) as usize;
+        let rows = (size.y / page_size.y).ceil().max(1.) as usize;
+        let mut pages = Vec::new();
+        for row in 0..rows {
+            for col in 0..cols {
+                let mut page = svg.clone();
+                let origin = Point::new(
+                    col as f64 * page_size.x,
+                    row as f64 * page_size.y,
+                );
+                page.set_viewport(origin, page_size);
+                pages.push(page);
+            }
+        }
+        pages
     }
 }
+
 impl RenderBackend for SVGWriter {
     fn draw_rect(
         &mut self,
@@ -156,7 +558,8 @@ impl RenderBackend for SVGWriter {
 
         let mut clip_option = String::new();
         if let Option::Some(clip_id) = clip {
-            clip_option = format!("clip-path=\"url(#C{})\"", clip_id);
+            clip_option =
+                format!("clip-path=\"url(#{}C{})\"", self.id_prefix, clip_id);
         }
         let props = properties.unwrap_or_default();
         let fill_color = look.fill_color.unwrap_or_else(Color::transparent);
@@ -165,16 +568,18 @@ impl RenderBackend for SVGWriter {
         let rounded_px = look.rounded;
         let line1 = format!(
             "<g {props}>\n
-            <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"{}\" 
-            stroke-width=\"{}\" stroke=\"{}\" rx=\"{}\" {} />\n
+            <rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {} 
+            stroke-width=\"{}\" {}{}{} rx=\"{}\" {} />\n
             </g>\n",
             xy.x,
             xy.y,
             size.x,
             size.y,
-            fill_color.to_web_color(),
+            fill_attributes(&fill_color),
             stroke_width,
-            stroke_color.to_web_color(),
+            stroke_attributes(&stroke_color),
+            dash_attributes(look),
+            cap_and_join_attributes(look, false),
             rounded_px,
             clip_option
         );
@@ -195,16 +600,45 @@ impl RenderBackend for SVGWriter {
         let props = properties.unwrap_or_default();
         let line1 = format!(
             "<g {props}>\n
-            <ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\" fill=\"{}\" 
-            stroke-width=\"{}\" stroke=\"{}\"/>\n
+            <ellipse cx=\"{}\" cy=\"{}\" rx=\"{}\" ry=\"{}\" {} 
+            stroke-width=\"{}\" {}{}{}/>\n
             </g>\n",
             xy.x,
             xy.y,
             size.x / 2.,
             size.y / 2.,
-            fill_color.to_web_color(),
+            fill_attributes(&fill_color),
             stroke_width,
-            stroke_color.to_web_color()
+            stroke_attributes(&stroke_color),
+            dash_attributes(look),
+            cap_and_join_attributes(look, false)
+        );
+        self.content.push_str(&line1);
+    }
+
+    fn draw_polygon(
+        &mut self,
+        points: &[Point],
+        look: &StyleAttr,
+        properties: Option<String>,
+    ) {
+        for p in points {
+            self.grow_window(*p, Point::zero());
+        }
+        let fill_color = look.fill_color.unwrap_or_else(Color::transparent);
+        let props = properties.unwrap_or_default();
+        let points: Vec<String> =
+            points.iter().map(|p| format!("{},{}", p.x, p.y)).collect();
+        let line1 = format!(
+            "<g {props}>\n
+            <polygon points=\"{}\" {} stroke-width=\"{}\" {}{}{}/>\n
+            </g>\n",
+            points.join(" "),
+            fill_attributes(&fill_color),
+            look.line_width,
+            stroke_attributes(&look.line_color),
+            dash_attributes(look),
+            cap_and_join_attributes(look, false)
         );
         self.content.push_str(&line1);
     }
@@ -212,13 +646,47 @@ impl RenderBackend for SVGWriter {
     fn draw_text(&mut self, xy: Point, text: &str, look: &StyleAttr) {
         let len = text.len();
 
-        let font_class = self.get_or_create_font_style(look.font_size);
+        let font_class =
+            self.get_or_create_font_style(look.font_size, look.monospace);
+
+        let mut lines: Vec<String> =
+            text.lines().map(|x| x.to_string()).collect();
+        let mut attrs = text_color_attributes(look);
+        if look.monospace {
+            // Keep the indentation of the lines. Pad the lines to the same
+            // width, so that the centered lines are aligned to the left.
+            lines = lines.iter().map(|x| expand_tabs(x)).collect();
+            let width = lines.iter().map(|x| get_width_of_line(x)).max();
+            let width = width.unwrap_or(0);
+            for line in lines.iter_mut() {
+                let pad = width - get_width_of_line(line);
+                line.push_str(&" ".repeat(pad));
+            }
+            attrs.push_str(" xml:space=\"preserve\"");
+        }
+        if look.vertical_text {
+            attrs.push_str(&format!(
+                " transform=\"rotate(-90 {} {})\"",
+                xy.x, xy.y
+            ));
+        }
 
         let mut content = String::new();
-        let cnt = 1 + text.lines().count();
+        let cnt = 1 + lines.len();
         let size_y = (cnt * look.font_size) as f64;
-        for line in text.lines() {
-            content.push_str(&format!("<tspan x = \"{}\" dy=\"1.0em\">", xy.x));
+        for line in lines.iter() {
+            let mut fit = String::new();
+            if self.fit_text && !line.is_empty() {
+                let width = get_size_for_str(line, look.font_size).x;
+                fit = format!(
+                    " textLength=\"{}\" lengthAdjust=\"spacingAndGlyphs\"",
+                    width
+                );
+            }
+            content.push_str(&format!(
+                "<tspan x = \"{}\" dy=\"1.0em\"{}>",
+                xy.x, fit
+            ));
             content.push_str(&escape_string(line));
             content.push_str("</tspan>");
         }
@@ -226,10 +694,11 @@ impl RenderBackend for SVGWriter {
         self.grow_window(xy, Point::new(10., len as f64 * 10.));
         let line = format!(
             "<text dominant-baseline=\"middle\" text-anchor=\"middle\" 
-            x=\"{}\" y=\"{}\" class=\"{}\">{}</text>",
+            x=\"{}\" y=\"{}\" class=\"{}\"{}>{}</text>",
             xy.x,
             xy.y - size_y / 2.,
             font_class,
+            attrs,
             &content
         );
 
@@ -242,8 +711,8 @@ impl RenderBackend for SVGWriter {
         // from the first point, and the rest of the vectors are "entry" vectors
         // into the following points.
         path: &[(Point, Point)],
-        dashed: bool,
-        head: (bool, bool),
+        line_style: LineStyleKind,
+        head: (LineEndKind, LineEndKind),
         look: &StyleAttr,
         properties: Option<String>,
         text: &str,
@@ -256,74 +725,83 @@ impl RenderBackend for SVGWriter {
             self.grow_window(point.1, Point::zero());
         }
 
-        let dash = if dashed {
-            &"stroke-dasharray=\"5,5\""
-        } else {
-            &""
-        };
-        let start = if head.0 {
-            "marker-start=\"url(#startarrow)\""
-        } else {
-            ""
-        };
-        let end = if head.1 {
-            "marker-end=\"url(#endarrow)\""
-        } else {
-            ""
-        };
-
         let mut path_builder = String::new();
 
-        // Handle the "exit vector" from the first point.
-        path_builder.push_str(&format!(
-            "M {} {} C {} {}, {} {}, {} {} ",
-            path[0].0.x,
-            path[0].0.y,
-            path[0].1.x,
-            path[0].1.y,
-            path[1].0.x,
-            path[1].0.y,
-            path[1].1.x,
-            path[1].1.y
-        ));
-
-        // Handle the "entry vector" from the rest of the points.
-        for point in path.iter().skip(2) {
+        // Paths with control points at the ends of the segments are made of
+        // straight lines.
+        if path.iter().all(|(a, b)| a == b) {
+            path_builder
+                .push_str(&format!("M {} {} ", path[0].0.x, path[0].0.y));
+            for point in path.iter().skip(1) {
+                path_builder
+                    .push_str(&format!("L {} {} ", point.0.x, point.0.y));
+            }
+        } else {
+            // Handle the "exit vector" from the first point.
             path_builder.push_str(&format!(
-                "S {} {}, {} {} ",
-                point.0.x, point.0.y, point.1.x, point.1.y
+                "M {} {} C {} {}, {} {}, {} {} ",
+                path[0].0.x,
+                path[0].0.y,
+                path[0].1.x,
+                path[0].1.y,
+                path[1].0.x,
+                path[1].0.y,
+                path[1].1.x,
+                path[1].1.y
             ));
+
+            // Handle the "entry vector" from the rest of the points.
+            for point in path.iter().skip(2) {
+                path_builder.push_str(&format!(
+                    "S {} {}, {} {} ",
+                    point.0.x, point.0.y, point.1.x, point.1.y
+                ));
+            }
         }
 
-        let stroke_width = look.line_width;
-        let stroke_color = look.line_color;
-        let props = properties.unwrap_or_default();
-        let line = format!(
-            "<g {props}>\n
-            <path id=\"arrow{}\" d=\"{}\" \
-            stroke=\"{}\" stroke-width=\"{}\" {} {} {} 
-            fill=\"transparent\" />\n
-            </g>\n",
-            self.counter,
-            path_builder.as_str(),
-            stroke_color.to_web_color(),
-            stroke_width,
-            dash,
-            start,
-            end
+        let ends = (path[0].0, path[path.len() - 1].1);
+        self.add_arrow_path(
+            &path_builder,
+            ends,
+            line_style,
+            head,
+            look,
+            properties,
+            text,
         );
-        self.content.push_str(&line);
+    }
 
-        let font_class = self.get_or_create_font_style(look.font_size);
-        let line = format!(
-            "<text><textPath href=\"#arrow{}\" startOffset=\"50%\" \
-            text-anchor=\"middle\" class=\"{}\">{}</textPath></text>",
-            self.counter,
-            font_class,
-            escape_string(text)
+    fn draw_curve_arrow(
+        &mut self,
+        points: &[Point],
+        line_style: LineStyleKind,
+        head: (LineEndKind, LineEndKind),
+        look: &StyleAttr,
+        properties: Option<String>,
+        text: &str,
+    ) {
+        for point in points {
+            self.grow_window(*point, Point::zero());
+        }
+        // Every segment has both of its control points.
+        let mut path_builder = format!("M {} {} ", points[0].x, points[0].y);
+        for seg in points[1..].chunks(3) {
+            path_builder.push_str(&format!(
+                "C {} {}, {} {}, {} {} ",
+                seg[0].x, seg[0].y, seg[1].x, seg[1].y, seg[2].x, seg[2].y
+            ));
+        }
+
+        let ends = (points[0], points[points.len() - 1]);
+        self.add_arrow_path(
+            &path_builder,
+            ends,
+            line_style,
+            head,
+            look,
+            properties,
+            text,
         );
-        self.content.push_str(&line);
-        self.counter += 1;
     }
 
     fn draw_line(
@@ -339,18 +817,45 @@ impl RenderBackend for SVGWriter {
         let line1 = format!(
             "<g {props}>\n
              <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke-width=\"{}\"
-             stroke=\"{}\" />\n
+             {}{}{} />\n
              </g>\n",
             start.x,
             start.y,
             stop.x,
             stop.y,
             stroke_width,
-            stroke_color.to_web_color()
+            stroke_attributes(&stroke_color),
+            dash_attributes(look),
+            cap_and_join_attributes(look, false)
         );
         self.content.push_str(&line1);
     }
 
+    fn draw_comment(&mut self, text: &str) {
+        let line = format!("<!-- {} -->\n", escape_comment(text));
+        self.content.push_str(&line);
+    }
+
+    fn begin_layer(&mut self, name: &str) {
+        // Keep the characters that are valid in class names.
+        let name: String = name
+            .chars()
+            .map(|c| {
+                if c.is_alphanumeric() || c == '-' || c == '_' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let line = format!("<g class=\"{}layer-{}\">\n", self.id_prefix, name);
+        self.content.push_str(&line);
+    }
+
+    fn end_layer(&mut self) {
+        self.content.push_str("</g>\n");
+    }
+
     fn create_clip(
         &mut self,
         xy: Point,
@@ -360,10 +865,10 @@ impl RenderBackend for SVGWriter {
         let handle = self.clip_regions.len();
 
         let clip_code = format!(
-            "<clipPath id=\"C{}\"><rect x=\"{}\" y=\"{}\" \
+            "<clipPath id=\"{}C{}\"><rect x=\"{}\" y=\"{}\" \
             width=\"{}\" height=\"{}\" rx=\"{}\" /> \
             </clipPath>",
-            handle, xy.x, xy.y, size.x, size.y, rounded_px
+            self.id_prefix, handle, xy.x, xy.y, size.x, size.y, rounded_px
         );
 
         self.clip_regions.push(clip_code);
@@ -0,0 +1,303 @@
+//! TikZ rendering backend, for including drawings in LaTeX documents. The
+//! drawing is a 'tikzpicture' environment, where one unit is one point. The
+//! y axis of TikZ points up, so the y coordinates are negated.
+
+use crate::core::color::Color;
+use crate::core::format::{ClipHandle, RenderBackend};
+use crate::core::geometry::Point;
+use crate::core::style::{LineStyleKind, StyleAttr};
+use crate::std_shapes::render::path_to_bezier_points;
+use crate::std_shapes::shapes::LineEndKind;
+
+/// \returns the point \p p in the TikZ coordinates.
+fn coord(p: Point) -> String {
+    format!("({},{})", p.x, -p.y)
+}
+
+/// \returns the color \p color in the xcolor format.
+fn tikz_color(color: &Color) -> String {
+    let rgb = u32::from_str_radix(&color.to_web_color_rgb()[1..], 16).unwrap();
+    format!(
+        "{{rgb,255:red,{};green,{};blue,{}}}",
+        rgb >> 16,
+        (rgb >> 8) & 0xff,
+        rgb & 0xff
+    )
+}
+
+/// \returns the TikZ options that draw the outline of a shape with the
+/// style \p look.
+fn draw_options(look: &StyleAttr) -> Vec<String> {
+    let mut options = Vec::new();
+    if look.line_width == 0 || look.line_color.alpha() == 0. {
+        options.push("draw=none".to_string());
+        return options;
+    }
+    options.push(format!("draw={}", tikz_color(&look.line_color)));
+    if !look.line_color.is_opaque() {
+        options.push(format!("draw opacity={:.3}", look.line_color.alpha()));
+    }
+    options.push(format!("line width={}pt", look.line_width));
+    match look.line_style {
+        LineStyleKind::Dashed => options.push("dashed".to_string()),
+        LineStyleKind::Dotted => options.push("dotted".to_string()),
+        LineStyleKind::Normal | LineStyleKind::None => {}
+    }
+    options
+}
+
+/// \returns the TikZ options that fill a shape with the style \p look, and
+/// draw its outline.
+fn shape_options(look: &StyleAttr) -> String {
+    let mut options = draw_options(look);
+    if let Option::Some(fill) = look.fill_color {
+        if fill.alpha() > 0. {
+            options.push(format!("fill={}", tikz_color(&fill)));
+            if !fill.is_opaque() {
+                options.push(format!("fill opacity={:.3}", fill.alpha()));
+            }
+        }
+    }
+    options.join(", ")
+}
+
+/// \returns the name of the TikZ arrow tip for the arrow head \p kind. Only
+/// the tips that don't need a TikZ library are used.
+fn arrow_tip(kind: LineEndKind) -> &'static str {
+    match kind {
+        LineEndKind::None => "",
+        LineEndKind::OpenArrow | LineEndKind::OpenDiamond => "to",
+        LineEndKind::Vee | LineEndKind::OpenDot => "to",
+        LineEndKind::OpenBox | LineEndKind::OpenInv => "to",
+        LineEndKind::Arrow | LineEndKind::Diamond | LineEndKind::Dot => "latex",
+        LineEndKind::Box | LineEndKind::Inv => "latex",
+        LineEndKind::Tee => "|",
+    }
+}
+
+/// Escape the characters of \p x that have a special meaning in LaTeX.
+fn escape_latex(x: &str) -> String {
+    let mut res = String::new();
+    for c in x.chars() {
+        match c {
+            '\\' => res.push_str("\\textbackslash{}"),
+            '{' | '}' | '#' | '$' | '%' | '&' | '_' => {
+                res.push('\\');
+                res.push(c);
+            }
+            '^' => res.push_str("\\^{}"),
+            '~' => res.push_str("\\~{}"),
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+/// \returns the lines of \p text as the content of a TikZ node.
+fn node_text(text: &str) -> String {
+    let lines: Vec<String> = text.lines().map(escape_latex).collect();
+    lines.join("\\\\")
+}
+
+/// \returns the TikZ options of text with the style \p look.
+fn text_options(look: &StyleAttr) -> String {
+    let size = look.font_size;
+    let mut font = format!("\\fontsize{{{}}}{{{}}}\\selectfont", size, size);
+    if look.monospace {
+        font.push_str("\\ttfamily");
+    }
+    let mut options = vec![format!("font={}", font), "align=center".into()];
+    if let Option::Some(color) = look.font_color {
+        options.push(format!("text={}", tikz_color(&color)));
+    }
+    if look.vertical_text {
+        options.push("rotate=90".to_string());
+    }
+    options.join(", ")
+}
+
+#[derive(Debug, Clone)]
+pub struct TikZWriter {
+    content: String,
+    // The number of clip regions that were created.
+    clip_count: usize,
+}
+
+impl TikZWriter {
+    pub fn new() -> TikZWriter {
+        TikZWriter {
+            content: String::new(),
+            clip_count: 0,
+        }
+    }
+
+    /// \returns the drawing as a 'tikzpicture' environment.
+    pub fn finalize(&self) -> String {
+        let mut result = String::new();
+        result.push_str("\\begin{tikzpicture}[x=1pt, y=1pt]\n");
+        result.push_str(&self.content);
+        result.push_str("\\end{tikzpicture}\n");
+        result
+    }
+}
+
+impl Default for TikZWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl RenderBackend for TikZWriter {
+    fn draw_rect(
+        &mut self,
+        xy: Point,
+        size: Point,
+        look: &StyleAttr,
+        _properties: Option<String>,
+        _clip: Option<ClipHandle>,
+    ) {
+        let mut options = shape_options(look);
+        if look.rounded > 0 {
+            options.push_str(&format!(", rounded corners={}pt", look.rounded));
+        }
+        let line = format!(
+            "\\draw[{}] {} rectangle {};\n",
+            options,
+            coord(xy),
+            coord(xy.add(size))
+        );
+        self.content.push_str(&line);
+    }
+
+    fn draw_line(
+        &mut self,
+        start: Point,
+        stop: Point,
+        look: &StyleAttr,
+        _properties: Option<String>,
+    ) {
+        let line = format!(
+            "\\draw[{}] {} -- {};\n",
+            draw_options(look).join(", "),
+            coord(start),
+            coord(stop)
+        );
+        self.content.push_str(&line);
+    }
+
+    fn draw_circle(
+        &mut self,
+        xy: Point,
+        size: Point,
+        look: &StyleAttr,
+        _properties: Option<String>,
+    ) {
+        let line = format!(
+            "\\draw[{}] {} ellipse [x radius={}, y radius={}];\n",
+            shape_options(look),
+            coord(xy),
+            size.x / 2.,
+            size.y / 2.
+        );
+        self.content.push_str(&line);
+    }
+
+    fn draw_polygon(
+        &mut self,
+        points: &[Point],
+        look: &StyleAttr,
+        _properties: Option<String>,
+    ) {
+        let points: Vec<String> = points.iter().map(|p| coord(*p)).collect();
+        let line = format!(
+            "\\draw[{}] {} -- cycle;\n",
+            shape_options(look),
+            points.join(" -- ")
+        );
+        self.content.push_str(&line);
+    }
+
+    fn draw_text(&mut self, xy: Point, text: &str, look: &StyleAttr) {
+        if text.is_empty() {
+            return;
+        }
+        let line = format!(
+            "\\node[{}] at {} {{{}}};\n",
+            text_options(look),
+            coord(xy),
+            node_text(text)
+        );
+        self.content.push_str(&line);
+    }
+
+    fn draw_arrow(
+        &mut self,
+        // The first element is the start point and its control point, and
+        // the rest are the entry control points and the following points.
+        path: &[(Point, Point)],
+        line_style: LineStyleKind,
+        head: (LineEndKind, LineEndKind),
+        look: &StyleAttr,
+        properties: Option<String>,
+        text: &str,
+    ) {
+        let points = path_to_bezier_points(path);
+        self.draw_curve_arrow(
+            &points, line_style, head, look, properties, text,
+        );
+    }
+
+    fn draw_curve_arrow(
+        &mut self,
+        points: &[Point],
+        line_style: LineStyleKind,
+        head: (LineEndKind, LineEndKind),
+        look: &StyleAttr,
+        _properties: Option<String>,
+        text: &str,
+    ) {
+        let mut look = look.clone();
+        look.line_style = line_style;
+        let mut options = draw_options(&look);
+        options.push(format!("{}-{}", arrow_tip(head.0), arrow_tip(head.1)));
+
+        let mut curve = coord(points[0]);
+        for seg in points[1..].chunks(3) {
+            curve.push_str(&format!(
+                " .. controls {} and {} .. {}",
+                coord(seg[0]),
+                coord(seg[1]),
+                coord(seg[2])
+            ));
+        }
+
+        let mut label = String::new();
+        if !text.is_empty() {
+            label = format!(
+                " node[midway, sloped, above, {}] {{{}}}",
+                text_options(&look),
+                node_text(text)
+            );
+        }
+        let line =
+            format!("\\draw[{}] {}{};\n", options.join(", "), curve, label);
+        self.content.push_str(&line);
+    }
+
+    fn draw_comment(&mut self, text: &str) {
+        for line in text.lines() {
+            self.content.push_str(&format!("% {}\n", line));
+        }
+    }
+
+    fn create_clip(
+        &mut self,
+        _xy: Point,
+        _size: Point,
+        _rounded_px: usize,
+    ) -> ClipHandle {
+        // The shapes are not clipped.
+        self.clip_count += 1;
+        self.clip_count - 1
+    }
+}
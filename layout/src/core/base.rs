@@ -24,6 +24,46 @@ impl Direction {
     }
 }
 
+/// The corner, or the middle of the top or the bottom side, of the drawing
+/// that a floating element is attached to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    TopCenter,
+    BottomCenter,
+}
+
+impl Anchor {
+    pub fn is_top(&self) -> bool {
+        matches!(self, Anchor::TopLeft | Anchor::TopRight | Anchor::TopCenter)
+    }
+    pub fn is_left(&self) -> bool {
+        matches!(self, Anchor::TopLeft | Anchor::BottomLeft)
+    }
+    pub fn is_center(&self) -> bool {
+        matches!(self, Anchor::TopCenter | Anchor::BottomCenter)
+    }
+}
+
+/// The way that the edges are drawn between the nodes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeRouting {
+    /// Curves that pass smoothly through the ranks.
+    Spline,
+    /// Elbow connectors, like in org charts. The edges that leave the same
+    /// node share the horizontal segment.
+    OrgChart,
+    /// Lines made of horizontal and vertical segments, that turn in the gaps
+    /// between the ranks (the GraphViz 'splines=ortho' attribute).
+    Ortho,
+    /// The edges are not drawn, but they still place the nodes in ranks
+    /// (the GraphViz 'splines=none' attribute).
+    None,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum Orientation {
     TopToBottom,
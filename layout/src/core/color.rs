@@ -151,6 +151,14 @@ static KNOWN_COLORS: [(&str, u32); 148] = [
     ("yellowgreen", 0x9acd32),
 ];
 
+/// A qualitative palette of distinguishable colors, for coloring categories
+/// (based on 'Tableau 10'). The colors are dark enough for lines, and light
+/// enough for fills with black text.
+static PALETTE: [u32; 12] = [
+    0x4e79a7, 0xf28e2b, 0xe15759, 0x76b7b2, 0x59a14f, 0xedc948, 0xb07aa1,
+    0xff9da7, 0x9c755f, 0xbab0ac, 0x8cd17d, 0xd4a6c8,
+];
+
 #[derive(Debug, Clone, Copy)]
 pub struct Color {
     // Color in the format RGBA
@@ -162,6 +170,16 @@ impl Color {
         Self { color: hex }
     }
 
+    /// \returns the color \p idx from the built-in palette. The palette
+    /// repeats itself after 'palette_size()' colors.
+    pub fn from_palette(idx: usize) -> Self {
+        Color::new((PALETTE[idx % PALETTE.len()] << 8) + 0xff)
+    }
+
+    pub fn palette_size() -> usize {
+        PALETTE.len()
+    }
+
     pub fn transparent() -> Self {
         Color::new(0x00000000)
     }
@@ -183,28 +201,107 @@ impl Color {
             }
         }
         // Parse the web format. Example: #edebe9.
-        if name.starts_with('#') {
-            let name = name.trim_start_matches('#');
-            if let Result::Ok(color) = u32::from_str_radix(name, 16) {
-                if name.len() <= 7 {
-                    return Some(Color::new((color << 8) + 0xff));
-                } else {
-                    return Some(Color::new(color));
+        if let Option::Some(hex) = name.strip_prefix('#') {
+            return Self::from_hex(hex);
+        }
+        Self::from_hsv(name)
+    }
+
+    /// Parse the hex digits \p hex of a color in the formats RGB, RRGGBB
+    /// and RRGGBBAA.
+    fn from_hex(hex: &str) -> Option<Color> {
+        if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            return None;
+        }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        match hex.len() {
+            3 => {
+                // Repeat each digit: #abc is #aabbcc.
+                let mut color = 0;
+                for shift in [8, 4, 0] {
+                    color = (color << 8) + ((value >> shift) & 0xf) * 0x11;
                 }
+                Some(Color::new((color << 8) + 0xff))
             }
+            6 => Some(Color::new((value << 8) + 0xff)),
+            8 => Some(Color::new(value)),
+            _ => None,
+        }
+    }
+
+    /// Parse a color in the GraphViz HSV format, which is made of three
+    /// numbers between 0 and 1 (the hue, the saturation and the value),
+    /// separated by spaces or commas. Example: "0.5 1 1".
+    fn from_hsv(name: &str) -> Option<Color> {
+        let parts: Vec<f64> = name
+            .split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|x| !x.is_empty())
+            .map(|x| x.parse::<f64>())
+            .collect::<Result<_, _>>()
+            .ok()?;
+        if parts.len() != 3 || parts.iter().any(|x| !(0.0..=1.).contains(x)) {
+            return None;
         }
-        None
+        let (h, s, v) = (parts[0], parts[1], parts[2]);
+        let sector = (h * 6.).floor() % 6.;
+        let f = h * 6. - (h * 6.).floor();
+        let (p, q, t) =
+            (v * (1. - s), v * (1. - f * s), v * (1. - (1. - f) * s));
+        let (r, g, b) = match sector as u32 {
+            0 => (v, t, p),
+            1 => (q, v, p),
+            2 => (p, v, t),
+            3 => (p, q, v),
+            4 => (t, p, v),
+            _ => (v, p, q),
+        };
+        let channel = |x: f64| (x * 255.).round() as u32;
+        let rgb = (channel(r) << 16) + (channel(g) << 8) + channel(b);
+        Some(Color::new((rgb << 8) + 0xff))
     }
 
+    /// \returns the color in the web format. Translucent colors are in the
+    /// 'rgba' format.
     pub fn to_web_color(&self) -> String {
-        format!("#{:08x}", self.color)
+        if self.is_opaque() {
+            return format!("#{:08x}", self.color);
+        }
+        let channel = |shift: u32| (self.color >> shift) & 0xff;
+        format!(
+            "rgba({}, {}, {}, {:.3})",
+            channel(24),
+            channel(16),
+            channel(8),
+            self.alpha()
+        )
+    }
+
+    /// \returns the color in the web format, without the alpha channel.
+    pub fn to_web_color_rgb(&self) -> String {
+        format!("#{:06x}", self.color >> 8)
+    }
+
+    /// \returns the alpha channel of the color, between 0 and 1.
+    pub fn alpha(&self) -> f64 {
+        (self.color & 0xff) as f64 / 255.
+    }
+
+    /// \returns the color, with its alpha channel scaled by \p opacity, which
+    /// is between 0 and 1.
+    pub fn with_opacity(&self, opacity: f64) -> Color {
+        let alpha = (self.alpha() * opacity.clamp(0., 1.) * 255.).round();
+        Color::new((self.color & !0xff) | alpha as u32)
+    }
+
+    pub fn is_opaque(&self) -> bool {
+        self.color & 0xff == 0xff
     }
 }
 
 #[test]
 fn test_color() {
     let color = Color::new(0x56_ff_00_7f);
-    assert_eq!(color.to_web_color(), "#56ff007f");
+    assert_eq!(color.to_web_color(), "rgba(86, 255, 0, 0.498)");
 
     let color = Color::from_name("coral");
     assert_eq!(color.unwrap().to_web_color(), "#ff7f50ff");
@@ -212,5 +309,51 @@ fn test_color() {
     let color = Color::from_name("#112233");
     assert_eq!(color.unwrap().to_web_color(), "#112233ff");
     let color = Color::from_name("#112233FA");
-    assert_eq!(color.unwrap().to_web_color(), "#112233fa");
+    assert_eq!(color.unwrap().to_web_color(), "rgba(17, 34, 51, 0.980)");
+    assert_eq!(color.unwrap().to_web_color_rgb(), "#112233");
+    assert!(!color.unwrap().is_opaque());
+    assert_eq!(Color::new(0x11223380).alpha(), 128. / 255.);
+
+    let color = Color::new(0x112233ff).with_opacity(0.5);
+    assert_eq!(color.to_web_color(), "rgba(17, 34, 51, 0.502)");
+    let color = Color::new(0x11223380).with_opacity(0.5);
+    assert_eq!(color.to_web_color(), "rgba(17, 34, 51, 0.251)");
+}
+
+#[test]
+fn test_parse_color() {
+    let web = |name: &str| Color::from_name(name).map(|c| c.to_web_color());
+    assert_eq!(web("#abc"), Some("#aabbccff".to_string()));
+    assert_eq!(web("#aabbcc"), Some("#aabbccff".to_string()));
+    assert_eq!(web("#ffcc00"), Some("#ffcc00ff".to_string()));
+    assert_eq!(web("#aabbcc80"), Some("rgba(170, 187, 204, 0.502)".into()));
+    assert_eq!(Color::from_name("#aabbcc80").unwrap().alpha(), 128. / 255.);
+    assert_eq!(web("0.5 1 1"), Some("#00ffffff".to_string()));
+    assert_eq!(web("0,1,1"), Some("#ff0000ff".to_string()));
+    assert_eq!(web("0.333 0 1"), Some("#ffffffff".to_string()));
+    assert_eq!(web("0.5 0.5 0.5"), Some("#408080ff".to_string()));
+
+    // Invalid colors.
+    assert!(web("#ab").is_none());
+    assert!(web("#aabbc").is_none());
+    assert!(web("#+abc").is_none());
+    assert!(web("#gghhii").is_none());
+    assert!(web("0.5 1").is_none());
+    assert!(web("2 1 1").is_none());
+    assert!(web("redd").is_none());
+}
+
+#[test]
+fn test_palette() {
+    let n = Color::palette_size();
+    let colors: Vec<String> = (0..n)
+        .map(|i| Color::from_palette(i).to_web_color())
+        .collect();
+    for i in 0..n {
+        for j in i + 1..n {
+            assert_ne!(colors[i], colors[j]);
+        }
+    }
+    // The palette repeats itself.
+    assert_eq!(Color::from_palette(n).to_web_color(), colors[0]);
 }
@@ -2,8 +2,10 @@
 
 use super::{
     geometry::{Point, Position},
-    style::StyleAttr,
+    style::{LineStyleKind, StyleAttr},
 };
+use crate::std_shapes::render::bezier_points_to_path;
+use crate::std_shapes::shapes::LineEndKind;
 
 /// This is the trait that all elements that can be arranged need to implement.
 pub trait Visible {
@@ -88,20 +90,68 @@ pub trait RenderBackend {
         properties: Option<String>,
     );
 
+    /// Draw a closed polygon with the vertices \p points. The default
+    /// implementation draws the outline of the polygon with 'draw_line', and
+    /// doesn't fill it.
+    fn draw_polygon(
+        &mut self,
+        points: &[Point],
+        look: &StyleAttr,
+        properties: Option<String>,
+    ) {
+        for (i, from) in points.iter().enumerate() {
+            let to = points[(i + 1) % points.len()];
+            self.draw_line(*from, to, look, properties.clone());
+        }
+    }
+
     /// Draw a labe.
     fn draw_text(&mut self, xy: Point, text: &str, look: &StyleAttr);
 
     /// Draw an arrow, with a label, with the style parameters in \p look.
+    /// The parameter \p head is the kind of the (start, end) arrow heads, and
+    /// \p line_style is the dash pattern of the line.
     fn draw_arrow(
         &mut self,
         path: &[(Point, Point)],
-        dashed: bool,
-        head: (bool, bool),
+        line_style: LineStyleKind,
+        head: (LineEndKind, LineEndKind),
         look: &StyleAttr,
         properties: Option<String>,
         text: &str,
     );
 
+    /// Draw an arrow along the cubic bezier curve \p points, which is the
+    /// start point followed by triplets of (control, control, end) points,
+    /// like 'path_to_bezier_points' returns. Unlike the path of 'draw_arrow',
+    /// every segment has both of its control points. The other parameters are
+    /// like the parameters of 'draw_arrow'. The default implementation calls
+    /// 'draw_arrow', which uses the reflection of the previous control point
+    /// as the first control point of the segments after the first one.
+    fn draw_curve_arrow(
+        &mut self,
+        points: &[Point],
+        line_style: LineStyleKind,
+        head: (LineEndKind, LineEndKind),
+        look: &StyleAttr,
+        properties: Option<String>,
+        text: &str,
+    ) {
+        let path = bezier_points_to_path(points);
+        self.draw_arrow(&path, line_style, head, look, properties, text);
+    }
+
+    /// Emit the comment \p text to the output, before the next shape. Formats
+    /// that don't have comments ignore them.
+    fn draw_comment(&mut self, _text: &str) {}
+
+    /// Start a group of shapes that belong to the layer \p name. Viewers can
+    /// show and hide the layers. Formats that don't have layers ignore them.
+    fn begin_layer(&mut self, _name: &str) {}
+
+    /// End the group that 'begin_layer' started.
+    fn end_layer(&mut self) {}
+
     /// Generate a clip region that shapes can use to create complex shapes.
     fn create_clip(
         &mut self,
@@ -2,6 +2,9 @@
 //! interaction. This includes things like intersection of shapes and length
 //! of vectors.
 
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
 // Stores a 2D coordinate, or a vector.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Point {
@@ -92,7 +95,6 @@ pub fn get_connection_point_for_circle(
     from: Point,
     force: f64,
 ) -> (Point, Point) {
-    let loc = loc;
     let dx = from.x - loc.x;
     let dy = from.y - loc.y;
 
@@ -280,11 +282,69 @@ pub fn pad_shape_scalar(size: Point, s: f64) -> Point {
     Point::new(size.x + s, size.y + s)
 }
 
+/// The number of columns between tab stops.
+const TAB_STOP: usize = 8;
+
+/// The ranges of the code points that take two columns (the East Asian wide
+/// and fullwidth characters, such as CJK ideographs, kana and hangul).
+const WIDE_CHARS: [(u32, u32); 15] = [
+    (0x1100, 0x115f),
+    (0x2e80, 0x303e),
+    (0x3041, 0x33ff),
+    (0x3400, 0x4dbf),
+    (0x4e00, 0x9fff),
+    (0xa000, 0xa4cf),
+    (0xac00, 0xd7a3),
+    (0xf900, 0xfaff),
+    (0xfe30, 0xfe4f),
+    (0xff00, 0xff60),
+    (0xffe0, 0xffe6),
+    (0x1f300, 0x1f64f),
+    (0x1f900, 0x1f9ff),
+    (0x20000, 0x2fffd),
+    (0x30000, 0x3fffd),
+];
+
+/// \returns the number of columns that the character \p ch occupies.
+pub fn get_width_of_char(ch: char) -> usize {
+    let c = ch as u32;
+    if WIDE_CHARS.iter().any(|(lo, hi)| *lo <= c && c <= *hi) {
+        return 2;
+    }
+    1
+}
+
+/// \returns \p line with the tabs replaced by spaces, up to the next tab stop.
+pub fn expand_tabs(line: &str) -> String {
+    let mut result = String::new();
+    let mut column = 0;
+    for ch in line.chars() {
+        if ch == '\t' {
+            let spaces = TAB_STOP - column % TAB_STOP;
+            result.push_str(&" ".repeat(spaces));
+            column += spaces;
+        } else {
+            result.push(ch);
+            column += get_width_of_char(ch);
+        }
+    }
+    result
+}
+
+/// \returns the number of columns that \p line occupies. Wide characters take
+/// two columns.
+pub fn get_width_of_line(line: &str) -> usize {
+    if line.contains('\t') {
+        return get_width_of_line(&expand_tabs(line));
+    }
+    line.chars().map(get_width_of_char).sum()
+}
+
 /// Estimate the bounding box of some rendered text.
 pub fn get_size_for_str(label: &str, font_size: usize) -> Point {
     // Find the longest line.
     let max_line_len = if !label.is_empty() {
-        label.lines().map(|x| x.chars().count()).max().unwrap()
+        label.lines().map(get_width_of_line).max().unwrap()
     } else {
         0
     };
@@ -292,6 +352,74 @@ pub fn get_size_for_str(label: &str, font_size: usize) -> Point {
     Point::new(ts.0 as f64, ts.1 as f64).scale(font_size as f64)
 }
 
+/// \returns \p label, where the lines that are wider than \p max_width are cut
+/// and end with an ellipsis. The width is measured like in 'get_size_for_str'.
+pub fn truncate_label(label: &str, max_width: f64, font_size: usize) -> String {
+    let max_columns = ((max_width / font_size as f64) as usize).max(1);
+    if label.lines().all(|x| get_width_of_line(x) <= max_columns) {
+        return label.to_string();
+    }
+    let lines: Vec<String> = label
+        .lines()
+        .map(|line| {
+            if get_width_of_line(line) <= max_columns {
+                return line.to_string();
+            }
+            let mut truncated = String::new();
+            let mut width = 0;
+            for ch in expand_tabs(line).chars() {
+                width += get_width_of_char(ch);
+                if width > max_columns - 1 {
+                    break;
+                }
+                truncated.push(ch);
+            }
+            truncated.push('\u{2026}');
+            truncated
+        })
+        .collect();
+    lines.join("\n")
+}
+
+/// Caches the estimated size of text, keyed by the text and the font size.
+/// Clones of the cache share the same storage, so a single cache can be
+/// shared by many graphs, and by many layouts of the same graph.
+#[derive(Debug, Clone, Default)]
+pub struct TextSizeCache {
+    sizes: Arc<Mutex<HashMap<(String, usize), Point>>>,
+}
+
+impl TextSizeCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// \returns the size of \p label, like 'get_size_for_str'.
+    pub fn get_size_for_str(&self, label: &str, font_size: usize) -> Point {
+        let key = (label.to_string(), font_size);
+        let mut sizes = self.sizes.lock().unwrap();
+        if let Option::Some(sz) = sizes.get(&key) {
+            return *sz;
+        }
+        let sz = get_size_for_str(label, font_size);
+        sizes.insert(key, sz);
+        sz
+    }
+
+    /// \returns the number of cached entries.
+    pub fn len(&self) -> usize {
+        self.sizes.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.sizes.lock().unwrap().clear();
+    }
+}
+
 /// \return true if \p x is in the inclusive range P.x .. P.y.
 pub fn in_range(range: (f64, f64), x: f64) -> bool {
     x >= range.0 && x <= range.1
@@ -384,6 +512,38 @@ impl Position {
     pub fn bottom(&self, with_halo: bool) -> f64 {
         self.bbox(with_halo).1.y
     }
+    /// Returns the top-left corner of the box.
+    pub fn top_left(&self, with_halo: bool) -> Point {
+        self.bbox(with_halo).0
+    }
+    /// Returns the top-right corner of the box.
+    pub fn top_right(&self, with_halo: bool) -> Point {
+        Point::new(self.right(with_halo), self.top(with_halo))
+    }
+    /// Returns the bottom-left corner of the box.
+    pub fn bottom_left(&self, with_halo: bool) -> Point {
+        Point::new(self.left(with_halo), self.bottom(with_halo))
+    }
+    /// Returns the bottom-right corner of the box.
+    pub fn bottom_right(&self, with_halo: bool) -> Point {
+        self.bbox(with_halo).1
+    }
+    /// Returns the midpoint of the top side of the box.
+    pub fn north(&self, with_halo: bool) -> Point {
+        Point::new(self.middle.x, self.top(with_halo))
+    }
+    /// Returns the midpoint of the bottom side of the box.
+    pub fn south(&self, with_halo: bool) -> Point {
+        Point::new(self.middle.x, self.bottom(with_halo))
+    }
+    /// Returns the midpoint of the right side of the box.
+    pub fn east(&self, with_halo: bool) -> Point {
+        Point::new(self.right(with_halo), self.middle.y)
+    }
+    /// Returns the midpoint of the left side of the box.
+    pub fn west(&self, with_halo: bool) -> Point {
+        Point::new(self.left(with_halo), self.middle.y)
+    }
     // Returns the bounding box of the shape.
     // Include the size of the halo, if \p with_halo is set.
     pub fn bbox(&self, with_halo: bool) -> (Point, Point) {
@@ -420,6 +580,10 @@ impl Position {
         self.size = size;
     }
 
+    pub fn set_halo(&mut self, halo: Point) {
+        self.halo = halo;
+    }
+
     /// Update the center point for the shape. This is expressed as the delta
     /// from the center of mass (middle-point).
     pub fn set_new_center_point(&mut self, center: Point) {
@@ -578,3 +742,66 @@ fn segment_rect_intersection_test() {
     assert!(!segment_rect_intersection((v1.0, v1.1), (v1.2, v1.3)));
     assert!(!segment_rect_intersection((v2.0, v2.1), (v2.2, v2.3)));
 }
+
+#[test]
+fn position_corners_test() {
+    let pos = Position::new(
+        Point::new(100., 50.),
+        Point::new(40., 20.),
+        Point::zero(),
+        Point::splat(10.),
+    );
+    assert_eq!(pos.top_left(false), Point::new(80., 40.));
+    assert_eq!(pos.top_right(false), Point::new(120., 40.));
+    assert_eq!(pos.bottom_left(false), Point::new(80., 60.));
+    assert_eq!(pos.bottom_right(false), Point::new(120., 60.));
+    assert_eq!(pos.north(false), Point::new(100., 40.));
+    assert_eq!(pos.south(false), Point::new(100., 60.));
+    assert_eq!(pos.east(false), Point::new(120., 50.));
+    assert_eq!(pos.west(false), Point::new(80., 50.));
+    // The halo is applied symmetrically to the sides.
+    assert_eq!(pos.top_left(true), Point::new(75., 35.));
+    assert_eq!(pos.bottom_right(true), Point::new(125., 65.));
+}
+
+#[test]
+fn test_text_size_cache() {
+    let cache = TextSizeCache::new();
+    let shared = cache.clone();
+    assert!(cache.is_empty());
+    let sz = cache.get_size_for_str("hello\nworld!", 10);
+    assert_eq!(sz, get_size_for_str("hello\nworld!", 10));
+    cache.get_size_for_str("hello\nworld!", 10);
+    cache.get_size_for_str("hello\nworld!", 12);
+    // The clone shares the storage of the original cache.
+    assert_eq!(shared.len(), 2);
+    shared.clear();
+    assert!(cache.is_empty());
+}
+
+#[test]
+fn test_truncate_label() {
+    // At font size 10, a width of 60 fits six characters.
+    assert_eq!(truncate_label("short", 60., 10), "short");
+    assert_eq!(truncate_label("a_long_name", 60., 10), "a_lon\u{2026}");
+    assert_eq!(
+        truncate_label("ok\nvery long", 60., 10),
+        "ok\nvery \u{2026}"
+    );
+    let truncated = truncate_label("\tindented", 60., 10);
+    assert_eq!(truncated, "     \u{2026}");
+    assert!(get_size_for_str(&truncated, 10).x <= 60.);
+}
+
+#[test]
+fn test_wide_characters() {
+    // CJK characters take twice the width of ASCII characters.
+    let ascii = get_size_for_str("abcd", 10);
+    let cjk = get_size_for_str("漢字かな", 10);
+    assert_eq!(cjk.x, ascii.x * 2.);
+    assert_eq!(cjk.y, ascii.y);
+    assert_eq!(get_width_of_line("한국어 ok"), 9);
+    // Wide characters are cut at the column limit.
+    assert_eq!(truncate_label("漢字かな", 60., 10), "漢字\u{2026}");
+    assert_eq!(truncate_label("漢字", 60., 10), "漢字");
+}
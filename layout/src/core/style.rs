@@ -10,6 +10,22 @@ pub enum LineStyleKind {
     None,
 }
 
+/// The shape of the ends of lines.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineCap {
+    Butt,
+    Round,
+    Square,
+}
+
+/// The shape of the corners where the segments of lines meet.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LineJoin {
+    Miter,
+    Round,
+    Bevel,
+}
+
 #[derive(Clone, Debug)]
 pub struct StyleAttr {
     pub line_color: Color,
@@ -17,6 +33,35 @@ pub struct StyleAttr {
     pub fill_color: Option<Color>,
     pub rounded: usize,
     pub font_size: usize,
+    // Render the text in a fixed-width font, and keep its whitespace.
+    pub monospace: bool,
+    // The color of the text. The default is black.
+    pub font_color: Option<Color>,
+    // Rotate the text by 90 degrees, to read from the bottom up.
+    pub vertical_text: bool,
+    // The scale of the arrow heads of edges. The heads also grow with the
+    // width of the line.
+    pub arrow_size: f64,
+    // The dash pattern of the outline of shapes. The dashes grow with the
+    // width of the line.
+    pub line_style: LineStyleKind,
+    // Edges fade from the line color at the tail to this color at the head.
+    pub gradient_color: Option<Color>,
+    // The width and the color of the lines between the fields of records.
+    // By default, the lines are half as wide as the outline, and have the
+    // color of the outline.
+    pub separator_width: Option<usize>,
+    pub separator_color: Option<Color>,
+    // The number of nested outlines of the shape. The default depends on
+    // the shape, and zero hides the outline.
+    pub peripheries: Option<usize>,
+    // Draw a drop shadow behind the shape.
+    pub shadow: bool,
+    // The shape of the ends and the corners of the lines. By default, edges
+    // have round ends and corners, and the outlines of shapes use the
+    // defaults of the backend.
+    pub line_cap: Option<LineCap>,
+    pub line_join: Option<LineJoin>,
 }
 
 impl StyleAttr {
@@ -33,6 +78,18 @@ impl StyleAttr {
             fill_color,
             rounded,
             font_size,
+            monospace: false,
+            font_color: Option::None,
+            vertical_text: false,
+            arrow_size: 1.,
+            line_style: LineStyleKind::Normal,
+            gradient_color: Option::None,
+            separator_width: Option::None,
+            separator_color: Option::None,
+            peripheries: Option::None,
+            shadow: false,
+            line_cap: Option::None,
+            line_join: Option::None,
         }
     }
 
@@ -1,17 +1,20 @@
 //! A graph builder that converts parsed AST trees to graphs.
 
-use super::record::record_builder;
+use super::record::{record_builder, unescape_label};
 use crate::adt::dag::NodeHandle;
 use crate::adt::map::ScopedMap;
-use crate::core::base::Orientation;
+use crate::core::base::{Anchor, EdgeRouting, Orientation};
 use crate::core::color::Color;
+use crate::core::geometry::{truncate_label, Point, TextSizeCache};
 use crate::core::style::*;
 use crate::gv::parser::ast;
-use crate::std_shapes::render::get_shape_size;
+use crate::std_shapes::render::{get_shape_size, get_shape_size_with_cache};
 use crate::std_shapes::shapes::ShapeKind;
 use crate::std_shapes::shapes::*;
 use crate::topo::layout::VisualGraph;
 use std::collections::HashMap;
+use std::ops::Bound;
+use std::ops::RangeBounds;
 
 type PropertyList = HashMap<String, String>;
 
@@ -19,7 +22,7 @@ type PropertyList = HashMap<String, String>;
 // AST into the VisualGraph data-structure that we use for layout and rendering
 // of the graph.
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct EdgeDesc {
     from: String,
     to: String,
@@ -46,6 +49,18 @@ pub struct GraphBuilder {
     global_attr: ScopedMap<String, String>,
     node_attr: ScopedMap<String, String>,
     edge_attr: ScopedMap<String, String>,
+    // An optional cache of text sizes that is shared across builds.
+    text_cache: Option<TextSizeCache>,
+    // True if the graph is a 'digraph'.
+    directed: bool,
+    // Labels that are wider than this width are truncated.
+    max_label_width: Option<f64>,
+    // The names of the nodes in the order in which they are mentioned.
+    mentions: Vec<String>,
+    // The lists of the nodes of subgraphs with the attribute 'rank=same'.
+    same_ranks: Vec<Vec<String>>,
+    // The nodes and the attributes of the subgraphs that are clusters.
+    clusters: Vec<(Vec<String>, PropertyList)>,
 }
 impl Default for GraphBuilder {
     fn default() -> Self {
@@ -63,16 +78,61 @@ impl GraphBuilder {
             global_attr: ScopedMap::new(),
             node_attr: ScopedMap::new(),
             edge_attr: ScopedMap::new(),
+            text_cache: Option::None,
+            directed: true,
+            max_label_width: Option::None,
+            mentions: Vec::new(),
+            same_ranks: Vec::new(),
+            clusters: Vec::new(),
         }
     }
+
+    /// Use the text size cache \p cache when measuring the shapes. The cache
+    /// is passed to the built graphs, and can be shared across many builds.
+    pub fn set_text_cache(&mut self, cache: TextSizeCache) {
+        self.text_cache = Option::Some(cache);
+    }
+
+    /// Truncate the labels of nodes and edges that are wider than \p width,
+    /// and end them with an ellipsis. This keeps the size of nodes bounded
+    /// in graphs with very long names. Record labels are not truncated.
+    pub fn set_max_label_width(&mut self, width: f64) {
+        self.max_label_width = Option::Some(width);
+    }
     pub fn visit_graph(&mut self, graph: &ast::Graph) {
+        self.directed = graph.kind == ast::GraphKind::Digraph;
         self.global_attr.push();
         self.node_attr.push();
         self.edge_attr.push();
+        let first_mention = self.mentions.len();
+        // Clusters are recorded before the clusters that are nested in them.
+        let first_cluster = self.clusters.len();
         for stmt in &graph.list.list {
             self.visit_stmt(stmt);
         }
 
+        let is_subgraph = self.global_attr.len() > 1;
+        let mut names: Vec<String> = Vec::new();
+        for name in &self.mentions[first_mention..] {
+            if !names.contains(name) {
+                names.push(name.clone());
+            }
+        }
+
+        // Record the nodes of subgraphs whose name starts with 'cluster',
+        // which are drawn inside of a box.
+        if is_subgraph && graph.name.starts_with("cluster") {
+            let attrs = self.global_attr.flatten();
+            self.clusters.insert(first_cluster, (names.clone(), attrs));
+        }
+
+        // Record the nodes that are mentioned in subgraphs that place their
+        // nodes in the same rank.
+        let rank = self.global_attr.get(&"rank".to_string());
+        if is_subgraph && rank.is_some_and(|x| x == "same") {
+            self.same_ranks.push(names);
+        }
+
         // TODO: we dump the property list when we close the scope. This is not
         // correct for sub graphs.
         self.global_state = self.global_attr.flatten();
@@ -105,12 +165,12 @@ impl GraphBuilder {
             self.edge_attr.insert(&att.0, &att.1);
         }
 
-        self.init_node_with_name(&e.from.name, false);
+        self.init_node_with_name(&e.from.name);
 
         let mut prev = &e.from.name;
         for dest in &e.to {
             let curr = &dest.0.name;
-            self.init_node_with_name(curr, false);
+            self.init_node_with_name(curr);
 
             let has_arrow = matches!(dest.1, ast::ArrowKind::Arrow);
             let prop_list = self.edge_attr.flatten();
@@ -129,33 +189,26 @@ impl GraphBuilder {
         self.edge_attr.pop();
     }
 
-    // If \p overwrite is set then we are declaring a node. This means that
-    // we need to update the properties that already exist.
-    fn init_node_with_name(&mut self, name: &str, overwrite: bool) {
-        let node_attr = self.node_attr.flatten();
-
-        if let Option::Some(prop_list) = self.nodes.get_mut(name) {
-            if !overwrite {
-                return;
-            }
-            for p in node_attr {
-                prop_list.insert(p.0, p.1);
-            }
-        } else {
+    // Create the node \p name, if this is its first mention. New nodes get
+    // the default attributes that are in scope at this point, and later
+    // changes to the defaults don't apply to them.
+    fn init_node_with_name(&mut self, name: &str) -> &mut PropertyList {
+        self.mentions.push(name.to_string());
+        if !self.nodes.contains_key(name) {
             self.node_order.push(name.to_string());
-            self.nodes.insert(name.to_string(), node_attr);
+            self.nodes
+                .insert(name.to_string(), self.node_attr.flatten());
         }
+        self.nodes.get_mut(name).unwrap()
     }
 
     fn visit_node(&mut self, n: &ast::NodeStmt) {
-        self.node_attr.push();
-
+        // Declaring a node that already exists only updates the attributes
+        // that the declaration lists.
+        let prop_list = self.init_node_with_name(&n.id.name);
         for att in n.list.iter() {
-            self.node_attr.insert(&att.0, &att.1);
+            prop_list.insert(att.0.clone(), att.1.clone());
         }
-
-        self.init_node_with_name(&n.id.name, true);
-        self.node_attr.pop();
     }
 
     fn visit_att(&mut self, att: &ast::AttrStmt) {
@@ -178,17 +231,124 @@ impl GraphBuilder {
         }
     }
 
+    /// Build the graph. Problems in the input, such as unsupported shapes,
+    /// are reported as warnings in the log. See 'get_with_warnings'.
+    /// Panics if the graph can't be built. See 'try_get'.
     pub fn get(&self) -> VisualGraph {
-        let mut dir = Orientation::TopToBottom;
+        self.try_get().unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Build the graph, like 'get'.
+    /// \returns the graph, or an error if the graph can't be built, for
+    /// example if an edge refers to a node that does not exist.
+    pub fn try_get(&self) -> Result<VisualGraph, String> {
+        let (vg, _warnings) = self.try_get_with_warnings()?;
+        #[cfg(feature = "log")]
+        for warning in _warnings.iter() {
+            log::warn!("{}", warning);
+        }
+        Result::Ok(vg)
+    }
 
+    /// Build the graph.
+    /// \returns the graph and the list of problems in the input, such as
+    /// unsupported shapes or invalid attribute values. These are handled by
+    /// falling back to a default.
+    /// Panics if the graph can't be built. See 'try_get_with_warnings'.
+    pub fn get_with_warnings(&self) -> (VisualGraph, Vec<String>) {
+        self.try_get_with_warnings()
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Build the graph, like 'get_with_warnings'.
+    /// \returns the graph and the list of warnings, or an error if the graph
+    /// can't be built.
+    pub fn try_get_with_warnings(
+        &self,
+    ) -> Result<(VisualGraph, Vec<String>), String> {
+        let mut warnings = Vec::new();
         // Set the graph orientation based on the 'rankdir' property.
-        if let Option::Some(rd) = self.global_state.get("rankdir") {
-            if rd == "LR" {
-                dir = Orientation::LeftToRight;
+        let (dir, reversed) = self
+            .global_state
+            .get("rankdir")
+            .map_or((Orientation::TopToBottom, false), |x| {
+                Self::parse_rankdir(x)
+            });
+
+        let mut vg = VisualGraph::new(dir);
+        vg.set_reversed(reversed);
+        vg.set_directed(self.directed);
+
+        // The separation between nodes and ranks is measured in inches.
+        if let Option::Some(x) = Self::get_number(
+            &self.global_state,
+            "nodesep",
+            0.0..,
+            &mut warnings,
+        ) {
+            vg.set_node_sep(x * 72.);
+        }
+        if let Option::Some(x) = Self::get_number(
+            &self.global_state,
+            "ranksep",
+            0.0..,
+            &mut warnings,
+        ) {
+            vg.set_rank_sep(x * 72.);
+        }
+
+        // The clearance between edges and nodes is measured in points.
+        if let Option::Some(x) =
+            Self::get_number(&self.global_state, "esep", 0.0.., &mut warnings)
+        {
+            vg.set_edge_sep(x);
+        }
+
+        if let Option::Some(comment) = self.global_state.get("comment") {
+            vg.set_comment(comment);
+        }
+
+        match self.global_state.get("splines").map(|x| x.as_str()) {
+            Option::Some("ortho") => vg.set_edge_routing(EdgeRouting::Ortho),
+            Option::Some("none" | "false") => {
+                vg.set_edge_routing(EdgeRouting::None)
             }
+            _ => {}
         }
 
-        let mut vg = VisualGraph::new(dir);
+        if let Option::Some(order) = self.global_state.get("outputorder") {
+            vg.set_edges_first(order == "edgesfirst");
+        }
+
+        // Numeric 'ratio' values set the aspect ratio of the drawing. The
+        // keywords scale the drawing to the 'size' attribute, which is not
+        // supported, and GraphViz ignores them when there is no size.
+        let keywords = ["fill", "compress", "expand", "auto"];
+        let ratio = self.global_state.get("ratio").map(|x| x.trim());
+        if !ratio.is_some_and(|x| keywords.contains(&x)) {
+            let positive = (Bound::Excluded(0.), Bound::Unbounded);
+            if let Option::Some(x) = Self::get_number(
+                &self.global_state,
+                "ratio",
+                positive,
+                &mut warnings,
+            ) {
+                vg.set_ratio(x);
+            }
+        }
+        if let Option::Some(cache) = &self.text_cache {
+            vg.set_text_cache(cache.clone());
+        }
+
+        // When the graph uses layers, the elements that are not assigned to a
+        // layer are drawn in the default layer.
+        let has_layers = self.global_state.contains_key("layers")
+            || self.nodes.values().any(|p| p.contains_key("layer"))
+            || self.edges.iter().any(|e| e.props.contains_key("layer"));
+        let get_layer = |lst: &PropertyList| {
+            let layer = lst.get(&"layer".to_string()).map(|x| x.as_str());
+            has_layers.then(|| layer.unwrap_or("default").to_string())
+        };
 
         // Keeps track of the newly created nodes and indexes them by name.
         let mut node_map: HashMap<String, NodeHandle> = HashMap::new();
@@ -197,28 +357,209 @@ impl GraphBuilder {
 
         // Create and register all of the nodes.
         for node_name in self.node_order.iter() {
-            let node_prop = self.nodes.get(node_name).unwrap();
+            let node_prop = match self.nodes.get(node_name) {
+                Option::Some(prop) => prop,
+                Option::None => return Self::unknown_node(node_name),
+            };
 
-            let shape =
-                Self::get_shape_from_attributes(dir, node_prop, node_name);
+            let mut shape = Self::get_shape_from_attributes(
+                dir,
+                node_prop,
+                node_name,
+                self.text_cache.as_ref(),
+                self.max_label_width,
+                &mut warnings,
+            );
+            shape.layer = get_layer(node_prop);
             let handle = vg.add_node(shape);
+            vg.set_node_name(handle, node_name);
             node_map.insert(node_name.to_string(), handle);
         }
 
+        let top = Self::set_node_positions(
+            &mut vg,
+            &self.global_state,
+            &self.node_order,
+            &self.nodes,
+            &node_map,
+            &mut warnings,
+        );
+
+        // Place the nodes of 'rank=same' subgraphs in the same rank. With
+        // 'newrank=true' the constraint applies to all of the nodes in the
+        // subgraph. Otherwise, like in GraphViz, where clusters are ranked on
+        // their own, it only aligns the nodes that are in the same cluster.
+        let newrank = self
+            .global_state
+            .get("newrank")
+            .is_some_and(|x| x == "true");
+        // Returns the index of the innermost cluster that the node is in.
+        // Clusters are recorded before the clusters that are nested in them.
+        let cluster_of = |name: &String| {
+            self.clusters
+                .iter()
+                .rposition(|(names, _)| names.contains(name))
+        };
+        for names in &self.same_ranks {
+            let mut sets: Vec<(Option<usize>, Vec<NodeHandle>)> = Vec::new();
+            for name in names {
+                let handle = match node_map.get(name) {
+                    Option::Some(handle) => *handle,
+                    Option::None => return Self::unknown_node(name),
+                };
+                let cluster = match newrank {
+                    true => Option::None,
+                    false => cluster_of(name),
+                };
+                match sets.iter_mut().find(|s| s.0 == cluster) {
+                    Option::Some(set) => set.1.push(handle),
+                    Option::None => sets.push((cluster, vec![handle])),
+                }
+            }
+            for (_, set) in sets {
+                vg.set_same_rank(&set);
+            }
+        }
+
+        for (names, attrs) in &self.clusters {
+            let mut nodes = Vec::new();
+            for name in names {
+                match node_map.get(name) {
+                    Option::Some(handle) => nodes.push(*handle),
+                    Option::None => return Self::unknown_node(name),
+                }
+            }
+            let (label, look) =
+                Self::get_cluster_style_from_attributes(attrs, &mut warnings);
+            let cluster = vg.add_cluster(&nodes, &label, look);
+            if let Option::Some(rd) = attrs.get(&"rankdir".to_string()) {
+                let (dir, reversed) = Self::parse_rankdir(rd);
+                vg.set_cluster_rankdir(cluster, dir, reversed);
+            }
+        }
+
         // Create and register all of the edges.
         for edge_prop in &self.edges {
-            let shape = Self::get_arrow_from_attributes(
+            let mut shape = Self::get_arrow_from_attributes(
                 &edge_prop.props,
                 edge_prop.is_directed,
                 edge_prop.from_port.clone(),
                 edge_prop.to_port.clone(),
+                self.max_label_width,
+                &mut warnings,
             );
-            let from = node_map.get(&edge_prop.from).unwrap();
-            let to = node_map.get(&edge_prop.to).unwrap();
+            let from = match node_map.get(&edge_prop.from) {
+                Option::Some(handle) => handle,
+                Option::None => return Self::unknown_node(&edge_prop.from),
+            };
+            let to = match node_map.get(&edge_prop.to) {
+                Option::Some(handle) => handle,
+                Option::None => return Self::unknown_node(&edge_prop.to),
+            };
+            shape.layer = get_layer(&edge_prop.props);
+            if let Option::Some(top) = top {
+                shape.spline =
+                    Self::get_spline(&edge_prop.props, top, &mut warnings);
+            }
             vg.add_edge(shape, *from, *to);
         }
 
-        vg
+        if let Option::Some((title, anchor)) =
+            Self::get_title_from_attributes(&self.global_state, &mut warnings)
+        {
+            vg.add_floating(title, anchor);
+        }
+
+        Result::Ok((vg, warnings))
+    }
+
+    /// \returns the error for a reference to the node \p name, which was
+    /// never created.
+    fn unknown_node<T>(name: &str) -> Result<T, String> {
+        Result::Err(format!("Unknown node \"{}\"", name))
+    }
+
+    /// \returns the orientation of the GraphViz 'rankdir' value \p rd, and
+    /// whether the orientation is reversed.
+    fn parse_rankdir(rd: &str) -> (Orientation, bool) {
+        let dir = if rd == "LR" || rd == "RL" {
+            Orientation::LeftToRight
+        } else {
+            Orientation::TopToBottom
+        };
+        (dir, rd == "BT" || rd == "RL")
+    }
+
+    /// \returns the label and the style of the box of a cluster with the
+    /// attributes \p lst. The box is filled with the 'bgcolor', or with the
+    /// 'fillcolor' if the cluster is filled.
+    fn get_cluster_style_from_attributes(
+        lst: &PropertyList,
+        warnings: &mut Vec<String>,
+    ) -> (String, StyleAttr) {
+        let label = lst
+            .get(&"label".to_string())
+            .map_or(String::new(), |x| unescape_label(x));
+        let color = lst
+            .get(&"pencolor".to_string())
+            .or_else(|| lst.get(&"color".to_string()))
+            .map_or("black".to_string(), |x| Self::normalize_color(x.clone()));
+        let filled = lst
+            .get(&"style".to_string())
+            .is_some_and(|x| x.split(',').any(|s| s.trim() == "filled"));
+        let mut fill = lst.get(&"bgcolor".to_string());
+        if filled {
+            fill = lst.get(&"fillcolor".to_string()).or(fill);
+        }
+        let fill = fill.map(|x| Color::fast(&Self::normalize_color(x.clone())));
+        let line_width = Self::get_number(lst, "penwidth", 0.0.., warnings)
+            .map_or(1, |x| x.round() as usize);
+        let font_size = Self::get_number(lst, "fontsize", 1.0.., warnings)
+            .map_or(14, |x| x.round() as usize);
+        let mut look =
+            StyleAttr::new(Color::fast(&color), line_width, fill, 5, font_size);
+        if let Option::Some(x) = lst.get(&"fontcolor".to_string()) {
+            let color = Self::normalize_color(x.clone());
+            look.font_color = Option::Some(Color::fast(&color));
+        }
+        (label, look)
+    }
+
+    /// \returns the title of the graph (the graph 'label' attribute) and the
+    /// side of the drawing that it is placed at, if the graph has a title. The
+    /// font of the title is set by the attributes of the graph, and not by the
+    /// attributes of the nodes.
+    fn get_title_from_attributes(
+        lst: &PropertyList,
+        warnings: &mut Vec<String>,
+    ) -> Option<(Element, Anchor)> {
+        let label = &unescape_label(lst.get(&"label".to_string())?);
+        let top = lst.get(&"labelloc".to_string()).is_some_and(|x| x == "t");
+        let just = lst.get(&"labeljust".to_string()).map(|x| x.as_str());
+        let anchor = match (top, just) {
+            (true, Option::Some("l")) => Anchor::TopLeft,
+            (true, Option::Some("r")) => Anchor::TopRight,
+            (true, _) => Anchor::TopCenter,
+            (false, Option::Some("l")) => Anchor::BottomLeft,
+            (false, Option::Some("r")) => Anchor::BottomRight,
+            (false, _) => Anchor::BottomCenter,
+        };
+
+        let font_size = Self::get_number(lst, "fontsize", 1.0.., warnings)
+            .map_or(14, |x| x.round() as usize);
+        let mut look =
+            StyleAttr::new(Color::transparent(), 0, None, 0, font_size);
+        look.monospace =
+            Self::is_monospace_font(lst.get(&"fontname".to_string()));
+        if let Option::Some(x) = lst.get(&"fontcolor".to_string()) {
+            let color = Self::normalize_color(x.clone());
+            look.font_color = Option::Some(Color::fast(&color));
+        }
+
+        let dir = Orientation::TopToBottom;
+        let shape = ShapeKind::new_box(label);
+        let size = get_shape_size(dir, &shape, font_size, false);
+        Option::Some((Element::create(shape, look, dir, size), anchor))
     }
 
     fn get_arrow_from_attributes(
@@ -226,55 +567,423 @@ impl GraphBuilder {
         has_arrow: bool,
         from_port: Option<String>,
         to_port: Option<String>,
+        max_label_width: Option<f64>,
+        warnings: &mut Vec<String>,
     ) -> Arrow {
         let mut line_width = 1;
         let mut font_size: usize = 14;
-        let start = LineEndKind::None;
-        let end = if has_arrow {
-            LineEndKind::Arrow
-        } else {
-            LineEndKind::None
-        };
+        let mut start = LineEndKind::None;
+        let mut end = LineEndKind::None;
         let mut label = String::from("");
         let mut color = String::from("black");
         let mut line_style = LineStyleKind::Normal;
 
         if let Option::Some(val) = lst.get(&"label".to_string()) {
-            label = val.clone();
+            label = unescape_label(val);
         }
 
+        // Ports in the 'node:port' syntax take precedence over the port
+        // attributes.
+        let from_port =
+            from_port.or_else(|| lst.get(&"tailport".to_string()).cloned());
+        let to_port =
+            to_port.or_else(|| lst.get(&"headport".to_string()).cloned());
+
+        // The 'dir' attribute selects the ends of the edge that have heads.
+        // Directed edges point forward by default.
+        let default_dir = if has_arrow { "forward" } else { "none" };
+        let dir = lst
+            .get(&"dir".to_string())
+            .map_or(default_dir, |d| d.as_str());
+        let (has_head, has_tail) = match dir {
+            "forward" => (true, false),
+            "back" => (false, true),
+            "both" => (true, true),
+            "none" => (false, false),
+            _ => {
+                warnings
+                    .push(format!("Unsupported edge direction \"{}\"", dir));
+                (has_arrow, false)
+            }
+        };
+        if has_head {
+            end = Self::get_arrow_end(lst, "arrowhead", warnings);
+        }
+        if has_tail {
+            start = Self::get_arrow_end(lst, "arrowtail", warnings);
+        }
+
+        let positive = (Bound::Excluded(0.), Bound::Unbounded);
+        let arrow_size = Self::get_number(lst, "arrowsize", positive, warnings)
+            .unwrap_or(1.);
+
+        let mut bold = false;
+        let mut gradient = false;
         if let Option::Some(stl) = lst.get(&"style".to_string()) {
-            if stl == "dashed" {
-                line_style = LineStyleKind::Dashed;
+            for stl in stl.split(',') {
+                match stl.trim() {
+                    "dashed" => line_style = LineStyleKind::Dashed,
+                    "dotted" => line_style = LineStyleKind::Dotted,
+                    "bold" => bold = true,
+                    "gradient" => gradient = true,
+                    _ => {}
+                }
             }
         }
 
+        // Gradient edges fade from the first color in the color list, at the
+        // tail, to the second color, at the head. Otherwise, every color in
+        // the list is drawn as a separate stroke.
+        let mut head_color = Option::None;
+        let mut colors = Vec::new();
         if let Option::Some(x) = lst.get(&"color".to_string()) {
             color = x.clone();
             color = Self::normalize_color(color);
+            if gradient {
+                head_color = x.split(':').nth(1).map(|c| {
+                    Color::fast(&Self::normalize_color(c.to_string()))
+                });
+            } else if x.contains(':') {
+                colors = x
+                    .split(':')
+                    .filter(|c| !c.is_empty())
+                    .map(|c| Color::fast(&Self::normalize_color(c.to_string())))
+                    .collect();
+            }
         }
 
-        if let Option::Some(pw) = lst.get(&"penwidth".to_string()) {
-            if let Result::Ok(x) = pw.parse::<usize>() {
-                line_width = x;
-            } else {
-                #[cfg(feature = "log")]
-                log::info!("Can't parse integer \"{}\"", pw);
+        // Bold edges are thicker, unless the width is set explicitly.
+        if bold {
+            line_width *= 2;
+        }
+
+        if let Option::Some(x) =
+            Self::get_number(lst, "penwidth", 0.0.., warnings)
+        {
+            line_width = x.round() as usize;
+        }
+
+        if let Option::Some(x) =
+            Self::get_number(lst, "fontsize", 1.0.., warnings)
+        {
+            font_size = x.round() as usize;
+        }
+
+        // The length of edges is measured in inches.
+        let len =
+            Self::get_number(lst, "len", 0.0.., warnings).map(|x| x * 72.);
+
+        if let Option::Some(width) = max_label_width {
+            label = truncate_label(&label, width, font_size);
+        }
+
+        let color = Color::fast(&color);
+        let mut look = StyleAttr::new(color, line_width, None, 0, font_size);
+        look.monospace =
+            Self::is_monospace_font(lst.get(&"fontname".to_string()));
+        look.arrow_size = arrow_size;
+        look.gradient_color = head_color;
+        let mut arrow = Arrow::new(
+            start, end, line_style, &label, &look, &from_port, &to_port,
+        );
+        arrow.len = len;
+        arrow.colors = colors;
+        if let Option::Some(x) =
+            Self::get_number(lst, "weight", 0.0.., warnings)
+        {
+            arrow.weight = x;
+        }
+        arrow.constraint = lst
+            .get(&"constraint".to_string())
+            .is_none_or(|x| x != "false");
+
+        // The head and tail labels have their own font attributes.
+        arrow.head_label =
+            lst.get(&"headlabel".to_string()).map(|x| unescape_label(x));
+        arrow.tail_label =
+            lst.get(&"taillabel".to_string()).map(|x| unescape_label(x));
+        if let Option::Some(x) =
+            Self::get_number(lst, "labelfontsize", 1.0.., warnings)
+        {
+            arrow.label_look.font_size = x.round() as usize;
+        }
+        if let Option::Some(x) = lst.get(&"labelfontcolor".to_string()) {
+            let color = Self::normalize_color(x.clone());
+            arrow.label_look.font_color = Option::Some(Color::fast(&color));
+        }
+        if let Option::Some(name) = lst.get(&"labelfontname".to_string()) {
+            arrow.label_look.monospace =
+                Self::is_monospace_font(Option::Some(name));
+        }
+        Self::apply_opacity(lst, &mut arrow.look, warnings);
+        Self::apply_opacity(lst, &mut arrow.label_look, warnings);
+        arrow.comment = lst.get(&"comment".to_string()).cloned();
+        arrow
+    }
+
+    /// \returns the kind of the head that the attribute \p attr selects, or
+    /// the default arrow head if the attribute is missing or unsupported.
+    fn get_arrow_end(
+        lst: &PropertyList,
+        attr: &str,
+        warnings: &mut Vec<String>,
+    ) -> LineEndKind {
+        let name = match lst.get(&attr.to_string()) {
+            Option::Some(name) => name,
+            Option::None => return LineEndKind::Arrow,
+        };
+        Self::get_arrow_head_kind(name).unwrap_or_else(|| {
+            warnings.push(format!("Unsupported arrow head \"{}\"", name));
+            LineEndKind::Arrow
+        })
+    }
+
+    /// Convert the GraphViz arrow shape name \p name to an arrow head kind.
+    /// The prefix 'o' selects the open (unfilled) variant of the shape.
+    /// \returns None if the shape is not supported.
+    fn get_arrow_head_kind(name: &str) -> Option<LineEndKind> {
+        // Handle the names that are aliases of other shapes.
+        let name = match name {
+            "empty" => "onormal",
+            "open" => "vee",
+            _ => name,
+        };
+        let (open, shape) = match name.strip_prefix('o') {
+            Option::Some(rest) if !rest.is_empty() => (true, rest),
+            _ => (false, name),
+        };
+        let kind = match (shape, open) {
+            ("none", _) => LineEndKind::None,
+            ("normal", false) => LineEndKind::Arrow,
+            ("normal", true) => LineEndKind::OpenArrow,
+            ("diamond", false) => LineEndKind::Diamond,
+            ("diamond", true) => LineEndKind::OpenDiamond,
+            // The vee head is never filled.
+            ("vee", _) => LineEndKind::Vee,
+            ("dot", false) => LineEndKind::Dot,
+            ("dot", true) => LineEndKind::OpenDot,
+            ("box", false) => LineEndKind::Box,
+            ("box", true) => LineEndKind::OpenBox,
+            ("inv", false) => LineEndKind::Inv,
+            ("inv", true) => LineEndKind::OpenInv,
+            ("tee", false) => LineEndKind::Tee,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    /// \returns True if the font \p name is a fixed-width font, such as
+    /// "Courier" or "DejaVu Sans Mono".
+    fn is_monospace_font(name: Option<&String>) -> bool {
+        if let Option::Some(name) = name {
+            let name = name.to_lowercase();
+            return name.contains("courier") || name.contains("mono");
+        }
+        false
+    }
+
+    /// Parse the numeric attribute \p attr of the property list \p lst.
+    /// Values that are not numbers, or that are outside of \p range, are
+    /// ignored and reported in \p warnings.
+    /// \returns the value, or None if the attribute is missing or ignored.
+    fn get_number<R: RangeBounds<f64>>(
+        lst: &PropertyList,
+        attr: &str,
+        range: R,
+        warnings: &mut Vec<String>,
+    ) -> Option<f64> {
+        let val = lst.get(&attr.to_string())?;
+        match val.trim().parse::<f64>() {
+            Result::Ok(x) if x.is_finite() && range.contains(&x) => {
+                Option::Some(x)
+            }
+            Result::Ok(_) => {
+                warnings
+                    .push(format!("{}={} is out of range; ignored", attr, val));
+                Option::None
+            }
+            Result::Err(_) => {
+                warnings.push(format!(
+                    "{}=\"{}\" is not a number; ignored",
+                    attr, val
+                ));
+                Option::None
             }
         }
+    }
+
+    /// \returns the list of numbers in the attribute \p attr, such as the
+    /// point "1,2", if the attribute has \p count numbers. GraphViz marks
+    /// points that don't move with a '!' suffix, which is ignored.
+    fn get_numbers(
+        lst: &PropertyList,
+        attr: &str,
+        count: usize,
+        warnings: &mut Vec<String>,
+    ) -> Option<Vec<f64>> {
+        let val = lst.get(&attr.to_string())?;
+        let numbers: Vec<f64> = val
+            .trim()
+            .trim_end_matches('!')
+            .split(',')
+            .filter_map(|x| x.trim().parse::<f64>().ok())
+            .filter(|x| x.is_finite())
+            .collect();
+        if numbers.len() != count || val.split(',').count() != count {
+            warnings.push(format!(
+                "{}=\"{}\" is not a list of {} numbers; ignored",
+                attr, val, count
+            ));
+            return Option::None;
+        }
+        Option::Some(numbers)
+    }
 
-        if let Option::Some(fx) = lst.get(&"fontsize".to_string()) {
-            if let Result::Ok(x) = fx.parse::<usize>() {
-                font_size = x;
+    /// Place the nodes at the positions in their 'pos' attribute, if all of
+    /// the nodes have one. Otherwise, only the nodes whose position ends with
+    /// a '!' keep their position, and the other nodes are laid out around
+    /// them. The positions are in points, and the y axis points up, like in
+    /// GraphViz, so the drawing is flipped at the top of the bounding box of
+    /// the graph (the 'bb' attribute), or at the top of the highest node.
+    /// \returns the y coordinate that the drawing is flipped at, if the nodes
+    /// were placed.
+    fn set_node_positions(
+        vg: &mut VisualGraph,
+        graph: &PropertyList,
+        node_order: &[String],
+        nodes: &HashMap<String, PropertyList>,
+        node_map: &HashMap<String, NodeHandle>,
+        warnings: &mut Vec<String>,
+    ) -> Option<f64> {
+        let mut positions = Vec::new();
+        let mut pinned = Vec::new();
+        for name in node_order {
+            if let Option::Some(pt) =
+                Self::get_numbers(&nodes[name], "pos", 2, warnings)
+            {
+                positions.push((node_map[name], Point::new(pt[0], pt[1])));
+                let val = &nodes[name][&"pos".to_string()];
+                pinned.push(val.trim().ends_with('!'));
+            }
+        }
+        if positions.is_empty() {
+            return Option::None;
+        }
+        let all = positions.len() == node_order.len();
+        if !all {
+            if pinned.contains(&false) {
+                warnings.push(
+                    "Some of the nodes don't have a position; ignoring 'pos'"
+                        .to_string(),
+                );
+            }
+            let mut pin = pinned.iter();
+            positions.retain(|_| *pin.next().unwrap());
+            if positions.is_empty() {
+                return Option::None;
+            }
+        }
+
+        let top = match Self::get_numbers(graph, "bb", 4, warnings) {
+            Option::Some(bb) => bb[3],
+            Option::None => positions
+                .iter()
+                .map(|(node, pt)| pt.y + vg.pos(*node).size(false).y / 2.)
+                .fold(f64::MIN, f64::max),
+        };
+        for (node, pt) in positions {
+            let center = Point::new(pt.x, top - pt.y);
+            if all {
+                vg.set_node_position(node, center);
             } else {
-                #[cfg(feature = "log")]
-                log::info!("Can't parse integer \"{}\"", fx);
+                vg.element_mut(node).pinned = Option::Some(center);
             }
         }
+        all.then_some(top)
+    }
 
-        let color = Color::fast(&color);
-        let look = StyleAttr::new(color, line_width, None, 0, font_size);
-        Arrow::new(start, end, line_style, &label, &look, &from_port, &to_port)
+    /// \returns the points of the curve in the 'pos' attribute of an edge, if
+    /// the attribute is a valid GraphViz spline, such as "e,10,10 0,0 1,1
+    /// 2,2 3,3". The optional start ('s') and end ('e') points are the tips
+    /// of the arrow heads, which are drawn from the ends of the curve, so
+    /// straight segments from the ends of the curve to these points are added
+    /// to the curve. The y axis is flipped at \p top, like the positions of
+    /// the nodes.
+    fn get_spline(
+        lst: &PropertyList,
+        top: f64,
+        warnings: &mut Vec<String>,
+    ) -> Option<Vec<Point>> {
+        let val = lst.get(&"pos".to_string())?;
+        let parse = |s: &str| {
+            let (x, y) = s.split_once(',')?;
+            let x = x.trim().parse::<f64>().ok()?;
+            let y = y.trim().parse::<f64>().ok()?;
+            Option::Some(Point::new(x, top - y))
+        };
+
+        let mut start = Option::None;
+        let mut end = Option::None;
+        let mut points = Vec::new();
+        let mut valid = true;
+        for token in val.split_whitespace() {
+            let pt = if let Option::Some(rest) = token.strip_prefix("s,") {
+                parse(rest).map(|p| start = Option::Some(p))
+            } else if let Option::Some(rest) = token.strip_prefix("e,") {
+                parse(rest).map(|p| end = Option::Some(p))
+            } else {
+                parse(token).map(|p| points.push(p))
+            };
+            valid &= pt.is_some();
+        }
+        if !valid || points.len() < 4 || points.len() % 3 != 1 {
+            warnings.push(format!("pos=\"{}\" is not a spline; ignored", val));
+            return Option::None;
+        }
+
+        // Straight segments have their control points at their ends.
+        if let Option::Some(start) = start {
+            points.splice(0..0, [start, start, points[0]]);
+        }
+        if let Option::Some(end) = end {
+            let last = points[points.len() - 1];
+            points.extend([last, end, end]);
+        }
+        Option::Some(points)
+    }
+
+    /// \returns the polygon that the 'sides', 'skew' and 'distortion'
+    /// attributes describe.
+    fn get_polygon_from_attributes(
+        lst: &PropertyList,
+        warnings: &mut Vec<String>,
+    ) -> PolygonDef {
+        let sides = Self::get_number(lst, "sides", 3.0..=120., warnings)
+            .map_or(4, |x| x.round() as usize);
+        let skew = Self::get_number(lst, "skew", .., warnings);
+        let distortion = Self::get_number(lst, "distortion", .., warnings);
+        PolygonDef::new(sides, skew.unwrap_or(0.), distortion.unwrap_or(0.))
+    }
+
+    /// Fade the lines, the fill and the text of the style \p look by the
+    /// 'opacity' attribute, which is between 0 (invisible) and 1 (opaque).
+    fn apply_opacity(
+        lst: &PropertyList,
+        look: &mut StyleAttr,
+        warnings: &mut Vec<String>,
+    ) {
+        let opacity = match Self::get_number(lst, "opacity", 0.0..=1., warnings)
+        {
+            Option::Some(x) => x,
+            Option::None => return,
+        };
+        look.line_color = look.line_color.with_opacity(opacity);
+        look.fill_color = look.fill_color.map(|c| c.with_opacity(opacity));
+        look.gradient_color =
+            look.gradient_color.map(|c| c.with_opacity(opacity));
+        let font_color =
+            look.font_color.unwrap_or_else(|| Color::fast("black"));
+        look.font_color = Option::Some(font_color.with_opacity(opacity));
     }
 
     /// Convert the color to some color that we can handle.
@@ -293,6 +1002,9 @@ impl GraphBuilder {
         dir: Orientation,
         lst: &PropertyList,
         default_name: &str,
+        cache: Option<&TextSizeCache>,
+        max_label_width: Option<f64>,
+        warnings: &mut Vec<String>,
     ) -> Element {
         let mut label = default_name.to_string();
         let mut edge_color = String::from("black");
@@ -301,11 +1013,15 @@ impl GraphBuilder {
         let mut line_width: usize = 1;
         let mut make_xy_same = false;
         let mut rounded_corder_value = 0;
+        let mut peripheries = Option::None;
 
         if let Option::Some(val) = lst.get(&"label".to_string()) {
             label = val.clone();
         }
 
+        // Records handle the escapes in the label when they parse it.
+        let record_label = label.clone();
+        let label = unescape_label(&label);
         let mut shape = ShapeKind::Circle(label.clone());
 
         // Set the shape.
@@ -315,18 +1031,54 @@ impl GraphBuilder {
                     shape = ShapeKind::Box(label);
                     make_xy_same = false;
                 }
+                // Boxes without a border, which show only the label.
+                "none" | "plaintext" => {
+                    shape = ShapeKind::Box(label);
+                    peripheries = Option::Some(0);
+                }
                 "doublecircle" => {
                     shape = ShapeKind::DoubleCircle(label);
                     make_xy_same = true;
                 }
+                "point" => {
+                    shape = ShapeKind::Point;
+                }
                 "record" => {
-                    shape = record_builder(&label);
+                    shape = record_builder(&record_label);
                 }
                 "Mrecord" => {
                     rounded_corder_value = 15;
-                    shape = record_builder(&label);
+                    shape = record_builder(&record_label);
+                }
+                "circle" | "ellipse" | "oval" => {
+                    shape = ShapeKind::Circle(label)
+                }
+                "polygon" => {
+                    let poly = Self::get_polygon_from_attributes(lst, warnings);
+                    shape = ShapeKind::Polygon(label, poly);
+                }
+                "triangle" | "pentagon" | "hexagon" | "septagon"
+                | "octagon" | "parallelogram" | "trapezium"
+                | "invtrapezium" => {
+                    let poly = match &val[..] {
+                        "triangle" => PolygonDef::new(3, 0., 0.),
+                        "pentagon" => PolygonDef::new(5, 0., 0.),
+                        "hexagon" => PolygonDef::new(6, 0., 0.),
+                        "septagon" => PolygonDef::new(7, 0., 0.),
+                        "octagon" => PolygonDef::new(8, 0., 0.),
+                        "parallelogram" => PolygonDef::new(4, 0.6, 0.),
+                        "trapezium" => PolygonDef::new(4, 0., -0.4),
+                        _ => PolygonDef::new(4, 0., 0.4),
+                    };
+                    shape = ShapeKind::Polygon(label, poly);
+                }
+                _ => {
+                    warnings.push(format!(
+                        "Unsupported shape \"{}\" in node \"{}\"; using a circle",
+                        val, default_name
+                    ));
+                    shape = ShapeKind::Circle(label);
                 }
-                _ => shape = ShapeKind::Circle(label),
             }
         }
 
@@ -335,47 +1087,121 @@ impl GraphBuilder {
             edge_color = Self::normalize_color(edge_color);
         }
 
+        let mut line_style = LineStyleKind::Normal;
+        let mut shadow = false;
         if let Option::Some(style) = lst.get(&"style".to_string()) {
-            if style == "filled" && !lst.contains_key("fillcolor") {
-                fill_color = "lightgray".to_string();
+            for style in style.split(',') {
+                match style.trim() {
+                    "filled" if !lst.contains_key("fillcolor") => {
+                        fill_color = "lightgray".to_string();
+                    }
+                    "dashed" => line_style = LineStyleKind::Dashed,
+                    "dotted" => line_style = LineStyleKind::Dotted,
+                    "shadow" => shadow = true,
+                    _ => {}
+                }
             }
         }
 
+        // Points are filled with the line color by default.
+        if matches!(shape, ShapeKind::Point) {
+            fill_color = edge_color.clone();
+        }
+
         if let Option::Some(x) = lst.get(&"fillcolor".to_string()) {
             fill_color = x.clone();
             fill_color = Self::normalize_color(fill_color);
         }
 
-        if let Option::Some(fx) = lst.get(&"fontsize".to_string()) {
-            if let Result::Ok(x) = fx.parse::<usize>() {
-                font_size = x;
+        if let Option::Some(x) =
+            Self::get_number(lst, "fontsize", 1.0.., warnings)
+        {
+            font_size = x.round() as usize;
+        }
+
+        // The width of points is their size, in inches.
+        let mut point_size = Option::None;
+        if let Option::Some(x) = Self::get_number(lst, "width", 0.0.., warnings)
+        {
+            if matches!(shape, ShapeKind::Point) {
+                point_size = Option::Some(x * 72.);
             } else {
-                #[cfg(feature = "log")]
-                log::info!("Can't parse integer \"{}\"", fx);
+                line_width = x.round() as usize;
             }
         }
+        if let Option::Some(x) =
+            Self::get_number(lst, "penwidth", 0.0.., warnings)
+        {
+            line_width = x.round() as usize;
+        }
 
-        if let Option::Some(pw) = lst.get(&"width".to_string()) {
-            if let Result::Ok(x) = pw.parse::<usize>() {
-                line_width = x;
-            } else {
-                #[cfg(feature = "log")]
-                log::info!("Can't parse integer \"{}\"", pw);
+        if let Option::Some(width) = max_label_width {
+            match &mut shape {
+                ShapeKind::Box(text)
+                | ShapeKind::Circle(text)
+                | ShapeKind::DoubleCircle(text)
+                | ShapeKind::Polygon(text, _) => {
+                    *text = truncate_label(text, width, font_size);
+                }
+                _ => {}
             }
         }
 
-        // We flip the orientation before we create the shape. In graphs that
-        // grow top down the records grow to the left.
-        let dir = dir.flip();
+        // We pick the orientation before we create the shape.
+        let dir = shape.orientation_in_graph(dir);
 
-        let sz = get_shape_size(dir, &shape, font_size, make_xy_same);
-        let look = StyleAttr::new(
+        let monospace =
+            Self::is_monospace_font(lst.get(&"fontname".to_string()));
+        let mut sz = get_shape_size_with_cache(
+            dir,
+            &shape,
+            font_size,
+            make_xy_same,
+            cache,
+        );
+        if let Option::Some(size) = point_size {
+            sz = Point::splat(size);
+        }
+        let mut look = StyleAttr::new(
             Color::fast(&edge_color),
             line_width,
             Option::Some(Color::fast(&fill_color)),
             rounded_corder_value,
             font_size,
         );
-        Element::create(shape, look, dir, sz)
+        look.monospace = monospace;
+        look.line_style = line_style;
+        look.shadow = shadow;
+        look.peripheries = peripheries;
+        if let Option::Some(x) =
+            Self::get_number(lst, "peripheries", 0.0.., warnings)
+        {
+            look.peripheries = Option::Some(x.round() as usize);
+        }
+        Self::apply_opacity(lst, &mut look, warnings);
+        let mut elem = Element::create(shape, look, dir, sz);
+        if let Option::Some(group) = lst.get(&"group".to_string()) {
+            elem.group = Option::Some(group.clone());
+        }
+        elem.comment = lst.get(&"comment".to_string()).cloned();
+        elem
     }
 }
+
+#[test]
+fn test_edge_to_missing_node() {
+    use super::DotParser;
+
+    let graph = DotParser::new("digraph { a -> b; }").process().unwrap();
+    let mut gb = GraphBuilder::new();
+    gb.visit_graph(&graph);
+    assert!(gb.try_get().is_ok());
+
+    // Add an edge to a node that was never declared.
+    let mut edge = gb.edges[0].clone();
+    edge.to = "c".to_string();
+    gb.edges.push(edge);
+    let err = gb.try_get().unwrap_err();
+    assert_eq!(err, "Unknown node \"c\"");
+    assert!(gb.try_get_with_warnings().is_err());
+}
@@ -0,0 +1,172 @@
+//! A compact JSON input format that can be used as an alternative to DOT.
+//! The JSON document is converted into the same AST that the DOT parser
+//! produces, so the graph goes through the regular GraphBuilder path and is
+//! laid out exactly like the equivalent DOT file.
+//!
+//! The format looks like this:
+//!
+//! ```txt
+//! {
+//!   "directed": true,
+//!   "graph": { "rankdir": "LR" },
+//!   "nodes": [ { "id": "a", "label": "Hello", "shape": "box" } ],
+//!   "edges": [ { "from": "a", "to": "b", "label": "x" } ]
+//! }
+//! ```
+//!
+//! Every key other than "id" (for nodes) and "from"/"to" (for edges) is
+//! treated as a regular GraphViz attribute.
+
+use super::parser::ast;
+use super::GraphBuilder;
+use crate::topo::layout::VisualGraph;
+use serde_json::{Map, Value};
+
+/// Convert a JSON attribute value into the string form that the DOT parser
+/// would have produced.
+fn value_to_string(key: &str, val: &Value) -> Result<String, String> {
+    match val {
+        Value::String(s) => Result::Ok(s.clone()),
+        Value::Number(n) => Result::Ok(n.to_string()),
+        Value::Bool(b) => Result::Ok(b.to_string()),
+        _ => {
+            Result::Err(format!("Unsupported value for attribute \"{}\"", key))
+        }
+    }
+}
+
+/// Collect all of the attributes in \p obj, skipping the keys in \p skip.
+fn to_attribute_list(
+    obj: &Map<String, Value>,
+    skip: &[&str],
+) -> Result<ast::AttributeList, String> {
+    let mut list = ast::AttributeList::new();
+    for (key, val) in obj.iter() {
+        if skip.contains(&key.as_str()) {
+            continue;
+        }
+        list.add_attr(key, &value_to_string(key, val)?);
+    }
+    Result::Ok(list)
+}
+
+/// \returns the string field \p key of the object \p obj.
+fn get_str<'a>(
+    obj: &'a Map<String, Value>,
+    key: &str,
+) -> Result<&'a str, String> {
+    match obj.get(key) {
+        Option::Some(Value::String(s)) => Result::Ok(s),
+        _ => Result::Err(format!("Expected a string field \"{}\"", key)),
+    }
+}
+
+/// \returns the array \p key of the object \p obj, or an empty array.
+fn get_array<'a>(
+    obj: &'a Map<String, Value>,
+    key: &str,
+) -> Result<&'a [Value], String> {
+    match obj.get(key) {
+        Option::None => Result::Ok(&[]),
+        Option::Some(Value::Array(arr)) => Result::Ok(arr),
+        _ => Result::Err(format!("Expected \"{}\" to be an array", key)),
+    }
+}
+
+fn as_object<'a>(
+    val: &'a Value,
+    what: &str,
+) -> Result<&'a Map<String, Value>, String> {
+    match val {
+        Value::Object(obj) => Result::Ok(obj),
+        _ => Result::Err(format!("Expected {} to be an object", what)),
+    }
+}
+
+/// Parse the JSON document \p input and convert it to a GraphViz AST.
+pub fn parse_json(input: &str) -> Result<ast::Graph, String> {
+    let root: Value = match serde_json::from_str(input) {
+        Result::Ok(v) => v,
+        Result::Err(err) => return Result::Err(err.to_string()),
+    };
+    let root = as_object(&root, "the document")?;
+
+    let directed = !matches!(root.get("directed"), Some(Value::Bool(false)));
+    let arrow = if directed {
+        ast::ArrowKind::Arrow
+    } else {
+        ast::ArrowKind::Line
+    };
+
+    let mut graph = ast::Graph::new("");
+    if !directed {
+        graph.kind = ast::GraphKind::Graph;
+    }
+
+    if let Option::Some(attrs) = root.get("graph") {
+        let list = to_attribute_list(as_object(attrs, "\"graph\"")?, &[])?;
+        let stmt = ast::AttrStmt::new(ast::AttrStmtTarget::Graph, list);
+        graph.list.list.push(ast::Stmt::Attribute(stmt));
+    }
+
+    for node in get_array(root, "nodes")? {
+        let node = as_object(node, "a node")?;
+        let id = ast::NodeId::new(get_str(node, "id")?, &None);
+        let list = to_attribute_list(node, &["id"])?;
+        let stmt = ast::NodeStmt::new_with_list(id, list);
+        graph.list.list.push(ast::Stmt::Node(stmt));
+    }
+
+    for edge in get_array(root, "edges")? {
+        let edge = as_object(edge, "an edge")?;
+        let from = ast::NodeId::new(get_str(edge, "from")?, &None);
+        let to = ast::NodeId::new(get_str(edge, "to")?, &None);
+        let mut stmt = ast::EdgeStmt::new(from);
+        stmt.insert(to, arrow.clone());
+        stmt.list = to_attribute_list(edge, &["from", "to"])?;
+        graph.list.list.push(ast::Stmt::Edge(stmt));
+    }
+
+    Result::Ok(graph)
+}
+
+/// Parse the JSON document \p input and build a VisualGraph from it.
+pub fn json_to_graph(input: &str) -> Result<VisualGraph, String> {
+    let graph = parse_json(input)?;
+    let mut gb = GraphBuilder::new();
+    gb.visit_graph(&graph);
+    gb.try_get()
+}
+
+#[test]
+fn test_json_matches_dot() {
+    use crate::backends::svg::SVGWriter;
+    use crate::gv::DotParser;
+
+    let json = r#"{
+        "graph": {"rankdir": "LR"},
+        "nodes": [{"id": "a", "shape": "box", "label": "hello"}, {"id": "b"}],
+        "edges": [{"from": "a", "to": "b", "label": "x", "penwidth": 2},
+                  {"from": "b", "to": "c"}]
+    }"#;
+    let dot = r#"digraph {
+        rankdir=LR; a [shape=box, label="hello"]; b;
+        a -> b [label="x", penwidth=2]; b -> c;
+    }"#;
+
+    let mut vg0 = json_to_graph(json).unwrap();
+    let mut svg0 = SVGWriter::new();
+    vg0.do_it(false, false, false, &mut svg0);
+
+    let ast = DotParser::new(dot).process().unwrap();
+    let mut gb = GraphBuilder::new();
+    gb.visit_graph(&ast);
+    let mut vg1 = gb.get();
+    let mut svg1 = SVGWriter::new();
+    vg1.do_it(false, false, false, &mut svg1);
+
+    assert_eq!(vg0.num_nodes(), vg1.num_nodes());
+    assert_eq!(svg0.finalize(), svg1.finalize());
+
+    assert!(json_to_graph("{\"nodes\": [{\"label\": \"x\"}]}").is_err());
+}
@@ -2,11 +2,46 @@
 //! file format (parsing, building a compatible graph, etc.)
 
 pub mod builder;
+#[cfg(feature = "serde_json")]
+pub mod json;
 pub mod parser;
 pub mod record;
+pub mod writer;
 
 pub use builder::GraphBuilder;
 pub use parser::lexer::Lexer;
 pub use parser::lexer::Token;
 pub use parser::printer::dump_ast;
+pub use parser::printer::to_dot;
+#[cfg(feature = "serde")]
+pub use parser::printer::to_json;
 pub use parser::DotParser;
+pub use parser::ParseError;
+
+use crate::core::geometry::Point;
+use std::collections::HashMap;
+
+/// Parse the DOT program \p dot, and lay out the graph without rendering it.
+/// \returns a map from the name of each node to the center and the size of
+/// the node, or the parser error.
+pub fn layout_dot(
+    dot: &str,
+) -> Result<HashMap<String, (Point, Point)>, String> {
+    let graph = DotParser::new(dot).process().map_err(|e| e.to_string())?;
+    let mut gb = GraphBuilder::new();
+    gb.visit_graph(&graph);
+    let mut vg = gb.try_get()?;
+    if vg.num_nodes() == 0 {
+        return Result::Ok(HashMap::new());
+    }
+    vg.layout(false, false);
+
+    let mut res = HashMap::new();
+    for node in vg.iter_nodes() {
+        if let Option::Some(name) = vg.name_of(node) {
+            let pos = vg.pos(node);
+            res.insert(name.to_string(), (pos.center(), pos.size(false)));
+        }
+    }
+    Result::Ok(res)
+}
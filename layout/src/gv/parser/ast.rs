@@ -1,7 +1,9 @@
-//! An AST that represents the GraphViz file format.
+//! An AST that represents the GraphViz file format. With the 'serde' feature,
+//! the AST can be serialized, for example to JSON with 'to_json'.
 
 // "first : <f0>"
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct NodeId {
     pub name: String,
     pub port: Option<String>,
@@ -17,6 +19,8 @@ impl NodeId {
 
 // [a=b; c=d; ... ]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct AttributeList {
     pub list: Vec<(String, String)>,
 }
@@ -29,7 +33,7 @@ impl AttributeList {
         self.list.push((from.to_string(), to.to_string()));
     }
 
-    pub fn iter(&self) -> std::slice::Iter<(String, String)> {
+    pub fn iter(&self) -> std::slice::Iter<'_, (String, String)> {
         self.list.iter()
     }
 }
@@ -42,6 +46,7 @@ impl Default for AttributeList {
 
 // (graph | node | edge)
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum AttrStmtTarget {
     Graph,
     Node,
@@ -49,6 +54,7 @@ pub enum AttrStmtTarget {
 }
 // (graph | node | edge) [ ... ]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct AttrStmt {
     pub target: AttrStmtTarget,
     pub list: AttributeList,
@@ -62,6 +68,7 @@ impl AttrStmt {
 
 // node-name [ ... ]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct NodeStmt {
     pub id: NodeId,
     pub list: AttributeList,
@@ -81,6 +88,7 @@ impl NodeStmt {
 
 // (-> | -- )
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub enum ArrowKind {
     Arrow,
     Line,
@@ -88,6 +96,7 @@ pub enum ArrowKind {
 
 // a -> b -> c [...]
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct EdgeStmt {
     pub from: NodeId,
     pub to: Vec<(NodeId, ArrowKind)>,
@@ -109,6 +118,8 @@ impl EdgeStmt {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(tag = "stmt"))]
 pub enum Stmt {
     Edge(EdgeStmt),
     Node(NodeStmt),
@@ -118,6 +129,8 @@ pub enum Stmt {
 
 // { ... }
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+#[cfg_attr(feature = "serde", serde(transparent))]
 pub struct StmtList {
     pub list: Vec<Stmt>,
 }
@@ -134,9 +147,20 @@ impl Default for StmtList {
     }
 }
 
+// graph | digraph
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub enum GraphKind {
+    Graph,
+    Digraph,
+}
+
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
 pub struct Graph {
     pub name: String,
+    /// The kind of the graph. Subgraphs have the kind of the root graph.
+    pub kind: GraphKind,
     pub list: StmtList,
 }
 
@@ -144,6 +168,7 @@ impl Graph {
     pub fn new(name: &str) -> Self {
         Self {
             name: name.to_string(),
+            kind: GraphKind::Digraph,
             list: StmtList::new(),
         }
     }
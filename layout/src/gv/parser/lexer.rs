@@ -28,6 +28,8 @@ pub struct Lexer {
     input: Vec<char>,
     pub pos: usize,
     pub ch: char,
+    // The index of the first character of the last token.
+    pub tok_pos: usize,
 }
 
 impl Lexer {
@@ -41,6 +43,7 @@ impl Lexer {
             input,
             pos: 0,
             ch: '\0',
+            tok_pos: 0,
         };
         l.read_char();
         l
@@ -77,6 +80,22 @@ impl Lexer {
         }
     }
 
+    /// \returns the line and the column of the character at index \p pos.
+    /// Both start at 1.
+    pub fn line_and_column(&self, pos: usize) -> (usize, usize) {
+        let mut line = 1;
+        let mut col = 1;
+        for ch in self.input.iter().take(pos) {
+            if *ch == '\n' {
+                line += 1;
+                col = 1;
+            } else {
+                col += 1;
+            }
+        }
+        (line, col)
+    }
+
     pub fn has_next(&self) -> bool {
         self.pos < self.input.len()
     }
@@ -161,6 +180,39 @@ impl Lexer {
         result
     }
 
+    /// \returns the character after the current character, without
+    /// consuming it.
+    fn peek_char(&self) -> char {
+        self.input.get(self.pos).copied().unwrap_or('\0')
+    }
+
+    /// Reads an unquoted numeric attribute value. Graphviz files often omit
+    /// the quotes around numbers with units, such as '0.5in', and around
+    /// comma-separated lists of numbers, such as '1.2,3.4'. A comma only
+    /// continues the value if a number follows it immediately.
+    pub fn read_numeric_value(&mut self) -> String {
+        let mut result = self.read_number();
+        loop {
+            // Read the units.
+            while self.ch.is_ascii_alphabetic() {
+                result.push(self.ch);
+                self.read_char();
+            }
+            let next = self.peek_char();
+            let is_number = next.is_ascii_digit() || next == '.' || next == '-';
+            if self.ch != ',' || !is_number {
+                return result;
+            }
+            result.push(',');
+            self.read_char();
+            if self.ch == '-' {
+                result.push('-');
+                self.read_char();
+            }
+            result.push_str(&self.read_number());
+        }
+    }
+
     pub fn read_string(&mut self) -> Token {
         let mut result = String::new();
         self.read_char();
@@ -172,6 +224,12 @@ impl Lexer {
                 self.ch = match self.ch {
                     'n' => '\n',
                     'l' => '\n',
+                    // Keep the escapes of the characters that have a meaning
+                    // in record labels. See 'record::unescape_label'.
+                    '|' | '{' | '}' | '<' | '>' => {
+                        result.push('\\');
+                        self.ch
+                    }
                     _ => self.ch,
                 }
             } else if self.ch == '\0' {
@@ -187,6 +245,12 @@ impl Lexer {
     pub fn next_token(&mut self) -> Token {
         let tok: Token;
         while self.skip_comment() || self.skip_whitespace() {}
+        // 'pos' points one char after the current char, unless we are at the
+        // end of the file.
+        self.tok_pos = match self.ch {
+            '\0' => self.input.len(),
+            _ => self.pos - 1,
+        };
         match self.ch {
             '=' => {
                 tok = Token::Equal;
@@ -227,10 +291,11 @@ impl Lexer {
                     _ => {
                         if self.ch.is_ascii_digit() {
                             let mut num = String::new();
-                            let res = self.read_number();
+                            let res = self.read_numeric_value();
                             num.push('-');
                             num.push_str(&res[..]);
-                            tok = Token::Identifier(num);
+                            // The number ends at the current character.
+                            return Token::Identifier(num);
                         } else {
                             tok = Token::Error(self.pos);
                         }
@@ -268,7 +333,7 @@ impl Lexer {
                     }
                 }
                 if self.ch.is_ascii_digit() {
-                    let num = self.read_number();
+                    let num = self.read_numeric_value();
                     return Token::Identifier(num);
                 }
 
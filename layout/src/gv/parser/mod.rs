@@ -2,10 +2,15 @@
 
 pub mod ast;
 pub mod lexer;
+#[allow(clippy::module_inception)]
 pub mod parser;
 pub mod printer;
 
 pub use lexer::Lexer;
 pub use lexer::Token;
 pub use parser::DotParser;
+pub use parser::ParseError;
 pub use printer::dump_ast;
+pub use printer::to_dot;
+#[cfg(feature = "serde")]
+pub use printer::to_json;
@@ -6,19 +6,37 @@ use super::lexer::Token;
 pub struct DotParser {
     lexer: Lexer,
     tok: Token,
+    // The kind of the root graph, which the subgraphs inherit.
+    kind: ast::GraphKind,
 }
 
-/// Creates an error from the string \p str.
-fn to_error<T>(str: &str) -> Result<T, String> {
-    Result::Err(str.to_string())
+/// An error in the input of the parser, and the place where it was found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub message: String,
+    /// The index of the character (not the byte) in the input.
+    pub pos: usize,
+    /// The line of the error, starting at 1.
+    pub line: usize,
+    /// The column of the error, in characters, starting at 1.
+    pub col: usize,
 }
 
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.message)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
 impl DotParser {
     pub fn new(input: &str) -> Self {
         let chars: Vec<char> = input.chars().collect();
         Self {
             lexer: Lexer::new(chars),
             tok: Token::Colon,
+            kind: ast::GraphKind::Digraph,
         }
     }
 
@@ -26,6 +44,18 @@ impl DotParser {
         self.lexer.print_error();
     }
 
+    /// Creates an error with the message \p str at the current token.
+    fn to_error<T>(&self, str: &str) -> Result<T, ParseError> {
+        let pos = self.lexer.tok_pos;
+        let (line, col) = self.lexer.line_and_column(pos);
+        Result::Err(ParseError {
+            message: str.to_string(),
+            pos,
+            line,
+            col,
+        })
+    }
+
     pub fn lex(&mut self) {
         match self.tok {
             Token::Error(_) => {
@@ -46,8 +76,9 @@ impl DotParser {
     pub fn parse_graph(
         &mut self,
         is_subgraph: bool,
-    ) -> Result<ast::Graph, String> {
+    ) -> Result<ast::Graph, ParseError> {
         let mut graph = ast::Graph::new("");
+        graph.kind = self.kind;
 
         // Handle the subgraph structure.
         if is_subgraph {
@@ -55,7 +86,7 @@ impl DotParser {
             if let Token::SubgraphKW = self.tok.clone() {
                 self.lex();
             } else {
-                return to_error("Expected 'subgraph'");
+                return self.to_error("Expected 'subgraph'");
             }
 
             // Consume the optional graph name.
@@ -67,7 +98,7 @@ impl DotParser {
             if let Token::OpenBrace = self.tok.clone() {
                 self.lex();
             } else {
-                return to_error("Expected '{'");
+                return self.to_error("Expected '{'");
             }
             graph.list = self.parse_stmt_list()?;
             return Result::Ok(graph);
@@ -80,19 +111,23 @@ impl DotParser {
 
         match self.tok {
             Token::GraphKW => {
+                self.kind = ast::GraphKind::Graph;
                 self.lex();
             }
             Token::DigraphKW => {
+                self.kind = ast::GraphKind::Digraph;
                 self.lex();
             }
             Token::SubgraphKW => {
                 self.lex();
             }
             _ => {
-                return to_error("Expected (graph|digraph)");
+                return self.to_error("Expected (graph|digraph)");
             }
         }
 
+        graph.kind = self.kind;
+
         // Consume the optional graph name.
         if let Token::Identifier(name) = self.tok.clone() {
             graph.name = name;
@@ -102,13 +137,13 @@ impl DotParser {
         if let Token::OpenBrace = self.tok.clone() {
             self.lex();
         } else {
-            return to_error("Expected '{'");
+            return self.to_error("Expected '{'");
         }
         graph.list = self.parse_stmt_list()?;
         Result::Ok(graph)
     }
     // stmt_list : [ stmt [ ';' ] stmt_list ]
-    pub fn parse_stmt_list(&mut self) -> Result<ast::StmtList, String> {
+    pub fn parse_stmt_list(&mut self) -> Result<ast::StmtList, ParseError> {
         let mut lst = ast::StmtList::new();
 
         loop {
@@ -127,7 +162,7 @@ impl DotParser {
         }
     }
     // stmt : node_stmt | edge_stmt | attr_stmt | ID '=' ID | subgraph
-    pub fn parse_stmt(&mut self) -> Result<ast::Stmt, String> {
+    pub fn parse_stmt(&mut self) -> Result<ast::Stmt, ParseError> {
         match self.tok {
             Token::Identifier(_) => {
                 let id0 = self.parse_node_id()?;
@@ -166,7 +201,7 @@ impl DotParser {
                         let ns = ast::Stmt::Node(ns);
                         Result::Ok(ns)
                     }
-                    _ => to_error("Unsupported token"),
+                    _ => self.to_error("Unsupported token"),
                 }
             }
             Token::SubgraphKW => {
@@ -198,21 +233,24 @@ impl DotParser {
                 // Handle anonymous scopes:
                 self.lex();
                 let mut graph = ast::Graph::new("anonymous");
+                graph.kind = self.kind;
                 graph.list = self.parse_stmt_list()?;
                 Result::Ok(ast::Stmt::SubGraph(graph))
             }
 
-            _ => to_error("Unknown token"),
+            _ => self.to_error("Unknown token"),
         }
     }
     //attr_list : '[' [ a_list ] ']' [ attr_list ]
-    pub fn parse_attr_list(&mut self) -> Result<ast::AttributeList, String> {
+    pub fn parse_attr_list(
+        &mut self,
+    ) -> Result<ast::AttributeList, ParseError> {
         let mut lst = ast::AttributeList::new();
 
         if let Token::OpenBracket = self.tok.clone() {
             self.lex();
         } else {
-            return to_error("Expected '['");
+            return self.to_error("Expected '['");
         }
 
         while !matches!(self.tok, Token::CloseBracket) {
@@ -223,14 +261,14 @@ impl DotParser {
                 // Consume the property name.
                 self.lex();
             } else {
-                return to_error("Expected property name");
+                return self.to_error("Expected property name");
             }
 
             if let Token::Equal = self.tok.clone() {
                 // Consume the '='.
                 self.lex();
             } else {
-                return to_error("Expected '='");
+                return self.to_error("Expected '='");
             }
 
             if let Token::Identifier(value) = self.tok.clone() {
@@ -238,7 +276,7 @@ impl DotParser {
                 // Consume the value name.
                 self.lex();
             } else {
-                return to_error("Expected value after assignment");
+                return self.to_error("Expected value after assignment");
             }
 
             // Skip semicolon.
@@ -253,7 +291,7 @@ impl DotParser {
         if let Token::CloseBracket = self.tok.clone() {
             self.lex();
         } else {
-            return to_error("Expected ']'");
+            return self.to_error("Expected ']'");
         }
         Result::Ok(lst)
     }
@@ -266,24 +304,24 @@ impl DotParser {
     pub fn parse_attribute_stmt(
         &mut self,
         id: ast::NodeId,
-    ) -> Result<ast::AttrStmt, String> {
+    ) -> Result<ast::AttrStmt, ParseError> {
         let mut lst = ast::AttributeList::new();
 
         if id.port.is_some() {
-            return to_error("Can't assign into a port");
+            return self.to_error("Can't assign into a port");
         }
 
         if let Token::Equal = self.tok.clone() {
             self.lex();
         } else {
-            return to_error("Expected '='");
+            return self.to_error("Expected '='");
         }
 
         if let Token::Identifier(val) = self.tok.clone() {
             lst.add_attr(&id.name, &val);
             self.lex();
         } else {
-            return to_error("Expected identifier.");
+            return self.to_error("Expected identifier.");
         }
 
         Result::Ok(ast::AttrStmt::new(ast::AttrStmtTarget::Graph, lst))
@@ -293,7 +331,7 @@ impl DotParser {
     pub fn parse_edge_stmt(
         &mut self,
         id: ast::NodeId,
-    ) -> Result<ast::EdgeStmt, String> {
+    ) -> Result<ast::EdgeStmt, ParseError> {
         let mut es = ast::EdgeStmt::new(id);
 
         while self.is_edge_token() {
@@ -301,7 +339,7 @@ impl DotParser {
                 Token::ArrowLine => ast::ArrowKind::Line,
                 Token::ArrowRight => ast::ArrowKind::Arrow,
                 _ => {
-                    return to_error("Expected '->' or '--' ");
+                    return self.to_error("Expected '->' or '--' ");
                 }
             };
             // Consume the arrow.
@@ -318,14 +356,14 @@ impl DotParser {
     }
 
     //node_id : ID [ port ]
-    pub fn parse_node_id(&mut self) -> Result<ast::NodeId, String> {
+    pub fn parse_node_id(&mut self) -> Result<ast::NodeId, ParseError> {
         let node_name: String;
         if let Token::Identifier(name) = self.tok.clone() {
             node_name = name;
             // Consume the value name.
             self.lex();
         } else {
-            return to_error("port");
+            return self.to_error("port");
         }
 
         if let Token::Colon = self.tok.clone() {
@@ -336,7 +374,7 @@ impl DotParser {
                 self.lex();
                 return Result::Ok(ast::NodeId::new(&node_name, &Some(port)));
             } else {
-                return to_error("Expected a port name");
+                return self.to_error("Expected a port name");
             }
         }
         Result::Ok(ast::NodeId::new(&node_name, &None))
@@ -344,12 +382,12 @@ impl DotParser {
 
     /// Parses dot files, as specified here:
     /// <https://graphviz.org/doc/info/lang.html>
-    pub fn process(&mut self) -> Result<ast::Graph, String> {
+    pub fn process(&mut self) -> Result<ast::Graph, ParseError> {
         self.lex();
         let result = self.parse_graph(false)?;
         if let Token::EOF = self.tok {
             return Result::Ok(result);
         }
-        to_error("Unexpected content at the end of the file.")
+        self.to_error("Unexpected content at the end of the file.")
     }
 }
@@ -88,3 +88,225 @@ fn print_graph(graph: &ast::Graph, indent: usize) {
 pub fn dump_ast(graph: &ast::Graph) {
     print_graph(graph, 0);
 }
+
+const DOT_KEYWORDS: [&str; 6] =
+    ["graph", "digraph", "subgraph", "node", "edge", "strict"];
+
+/// \returns True if \p s can be written without quotes, according to the
+/// rules of the lexer.
+fn is_plain_id(s: &str) -> bool {
+    if DOT_KEYWORDS.contains(&s) {
+        return false;
+    }
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        Some(_) => {
+            let digits = s.strip_prefix('-').unwrap_or(s);
+            !digits.is_empty()
+                && digits.starts_with(|c: char| c.is_ascii_digit())
+                && digits.chars().all(|c| c.is_ascii_digit() || c == '.')
+                && digits.matches('.').count() <= 1
+        }
+        None => false,
+    }
+}
+
+fn quote_id(s: &str) -> String {
+    if is_plain_id(s) {
+        return s.to_string();
+    }
+    let mut result = String::from("\"");
+    for ch in s.chars() {
+        match ch {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            _ => result.push(ch),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn node_id_to_dot(n: &ast::NodeId) -> String {
+    if let Option::Some(port) = &n.port {
+        format!("{}:{}", quote_id(&n.name), quote_id(port))
+    } else {
+        quote_id(&n.name)
+    }
+}
+
+/// Append the attribute list \p ll to \p out, in the order of the source.
+fn attribute_list_to_dot(ll: &ast::AttributeList, out: &mut String) {
+    if ll.list.is_empty() {
+        return;
+    }
+    let attrs: Vec<String> = ll
+        .iter()
+        .map(|(k, v)| format!("{}={}", quote_id(k), quote_id(v)))
+        .collect();
+    out.push_str(&format!(" [{}]", attrs.join(", ")));
+}
+
+fn stmt_to_dot(stmt: &ast::Stmt, indent: usize, out: &mut String) {
+    out.push_str(&" ".repeat(indent));
+    match stmt {
+        ast::Stmt::Edge(e) => {
+            out.push_str(&node_id_to_dot(&e.from));
+            for (dest, kind) in &e.to {
+                match kind {
+                    ast::ArrowKind::Arrow => out.push_str(" -> "),
+                    ast::ArrowKind::Line => out.push_str(" -- "),
+                }
+                out.push_str(&node_id_to_dot(dest));
+            }
+            attribute_list_to_dot(&e.list, out);
+        }
+        ast::Stmt::Node(n) => {
+            out.push_str(&node_id_to_dot(&n.id));
+            attribute_list_to_dot(&n.list, out);
+        }
+        ast::Stmt::Attribute(a) => {
+            out.push_str(match a.target {
+                ast::AttrStmtTarget::Graph => "graph",
+                ast::AttrStmtTarget::Node => "node",
+                ast::AttrStmtTarget::Edge => "edge",
+            });
+            // Attribute statements must have a list, even an empty one.
+            if a.list.list.is_empty() {
+                out.push_str(" []");
+            }
+            attribute_list_to_dot(&a.list, out);
+        }
+        ast::Stmt::SubGraph(g) => {
+            out.push_str("subgraph ");
+            graph_body_to_dot(g, indent, out);
+            return;
+        }
+    }
+    out.push_str(";\n");
+}
+
+fn graph_body_to_dot(graph: &ast::Graph, indent: usize, out: &mut String) {
+    if !graph.name.is_empty() {
+        out.push_str(&quote_id(&graph.name));
+        out.push(' ');
+    }
+    out.push_str("{\n");
+    for stmt in &graph.list.list {
+        stmt_to_dot(stmt, indent + 4, out);
+    }
+    out.push_str(&" ".repeat(indent));
+    out.push_str("}\n");
+}
+
+/// Format the graph \p graph in the DOT format. The statements and the
+/// attribute lists are printed in the order in which they were parsed, so
+/// formatting a file does not reorder it. Comments are not preserved.
+pub fn to_dot(graph: &ast::Graph) -> String {
+    let mut out = String::new();
+    match graph.kind {
+        ast::GraphKind::Graph => out.push_str("graph "),
+        ast::GraphKind::Digraph => out.push_str("digraph "),
+    }
+    graph_body_to_dot(graph, 0, &mut out);
+    out
+}
+
+/// Serialize the graph \p graph to JSON, for tools that want to analyze the
+/// structure of a DOT file without parsing it. This is the syntax tree, before
+/// any layout: the statements and the attributes are in the order of the
+/// source, and attribute values are the strings that the parser read.
+#[cfg(feature = "serde")]
+pub fn to_json(graph: &ast::Graph) -> String {
+    serde_json::to_string_pretty(graph).unwrap()
+}
+
+#[test]
+fn test_to_dot_keeps_declared_order() {
+    use super::DotParser;
+
+    let program = "digraph G { rankdir=LR; c -> a [label=\"x \\\"y\\\"\", \
+                   color=red]; node [shape=box]; edge []; b:p1 -> \"c d\"; \
+                   subgraph s { z; y [width=2, label=\"node\"] } }";
+    let ast = DotParser::new(program).process().unwrap();
+    let expected = r#"digraph G {
+    graph [rankdir=LR];
+    c -> a [label="x \"y\"", color=red];
+    node [shape=box];
+    edge [];
+    b:p1 -> "c d";
+    subgraph s {
+        z;
+        y [width=2, label="node"];
+    }
+}
+"#;
+    let dot = to_dot(&ast);
+    assert_eq!(dot, expected);
+
+    // Formatting the output again does not change it.
+    let ast = DotParser::new(&dot).process().unwrap();
+    assert_eq!(to_dot(&ast), expected);
+
+    let ast = DotParser::new("graph { a -- b; }").process().unwrap();
+    assert_eq!(to_dot(&ast), "graph {\n    a -- b;\n}\n");
+
+    // The kind of the graph comes from the source, and not from the edges.
+    let ast = DotParser::new("graph { a; }").process().unwrap();
+    assert_eq!(to_dot(&ast), "graph {\n    a;\n}\n");
+    let ast = DotParser::new("digraph { { a -- b; } }").process().unwrap();
+    assert!(to_dot(&ast).starts_with("digraph {"));
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_to_json() {
+    use super::DotParser;
+
+    let program = "digraph G { rankdir=LR; a:p -> b -- c [color=red]; \
+                   subgraph s { node [shape=box]; d [label=\"x\"]; } }";
+    let ast = DotParser::new(program).process().unwrap();
+    let val: serde_json::Value = serde_json::from_str(&to_json(&ast)).unwrap();
+    let expected = serde_json::json!({
+        "name": "G",
+        "kind": "Digraph",
+        "list": [
+            {
+                "stmt": "Attribute",
+                "target": "Graph",
+                "list": [["rankdir", "LR"]]
+            },
+            {
+                "stmt": "Edge",
+                "from": { "name": "a", "port": "p" },
+                "to": [
+                    [{ "name": "b", "port": null }, "Arrow"],
+                    [{ "name": "c", "port": null }, "Line"]
+                ],
+                "list": [["color", "red"]]
+            },
+            {
+                "stmt": "SubGraph",
+                "name": "s",
+                "kind": "Digraph",
+                "list": [
+                    {
+                        "stmt": "Attribute",
+                        "target": "Node",
+                        "list": [["shape", "box"]]
+                    },
+                    {
+                        "stmt": "Node",
+                        "id": { "name": "d", "port": null },
+                        "list": [["label", "x"]]
+                    }
+                ]
+            }
+        ]
+    });
+    assert_eq!(val, expected);
+}
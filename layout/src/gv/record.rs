@@ -36,6 +36,26 @@ struct RecordParserFrame {
     arr: Vec<RecordDef>,
 }
 
+/// \returns true if the character \p ch has a meaning in record labels, and
+/// can be escaped with a backslash.
+fn is_record_char(ch: char) -> bool {
+    matches!(ch, '|' | '{' | '}' | '<' | '>')
+}
+
+/// \returns the label \p label after removing the backslashes that escape
+/// the characters that have a meaning in record labels, like "\|".
+pub fn unescape_label(label: &str) -> String {
+    let mut res = String::new();
+    let mut chars = label.chars().peekable();
+    while let Option::Some(ch) = chars.next() {
+        if ch == '\\' && chars.peek().is_some_and(|c| is_record_char(*c)) {
+            continue;
+        }
+        res.push(ch);
+    }
+    res
+}
+
 impl RecordParserFrame {
     pub fn new() -> Self {
         Self {
@@ -45,19 +65,24 @@ impl RecordParserFrame {
     }
 
     /// Split a label such as "<f0> XXX" into the port part "f0" and the text
-    /// part "XXX".
+    /// part "XXX", and remove the escapes from both parts.
     fn split_label_to_text_and_port(str: &str) -> (String, Option<String>) {
         let str = str.trim();
         if str.starts_with('<') {
-            if let Option::Some(idx) = str.find('>') {
-                let port = &str[1..idx];
-                return (
-                    str[idx + 1..].trim().to_string(),
-                    Option::Some(port.to_string()),
-                );
+            // Find the first '>' that is not escaped.
+            let mut escaped = false;
+            for (idx, ch) in str.char_indices() {
+                if ch == '>' && !escaped {
+                    let port = unescape_label(&str[1..idx]);
+                    return (
+                        unescape_label(str[idx + 1..].trim()),
+                        Option::Some(port),
+                    );
+                }
+                escaped = ch == '\\' && !escaped;
             }
         }
-        (str.to_string(), Option::None)
+        (unescape_label(str), Option::None)
     }
 
     pub fn finalize_label(&mut self) {
@@ -92,6 +117,15 @@ impl RecordParser {
             let ch = self.input[self.pos];
 
             match ch {
+                '\\' if self.pos + 1 < self.input.len()
+                    && is_record_char(self.input[self.pos + 1]) =>
+                {
+                    // Keep the escaped character in the label. The escape is
+                    // removed when the label is finalized.
+                    frame.label.push(ch);
+                    frame.label.push(self.input[self.pos + 1]);
+                    self.pos += 2;
+                }
                 '{' => {
                     self.pos += 1;
                     // Finalize the label.
@@ -131,6 +165,39 @@ pub fn parse_record_string(label: &str) -> RecordDef {
     RecordParser::new(label).parse()
 }
 
+/// \returns the record label that 'parse_record_string' parses into \p rec.
+/// The characters that have a meaning in record labels are escaped.
+pub fn record_to_string(rec: &RecordDef) -> String {
+    fn escape(s: &str) -> String {
+        let mut res = String::new();
+        for ch in s.chars() {
+            if is_record_char(ch) {
+                res.push('\\');
+            }
+            res.push(ch);
+        }
+        res
+    }
+    fn visit(rec: &RecordDef, nested: bool) -> String {
+        match rec {
+            RecordDef::Text(label, Option::Some(port)) => {
+                format!("<{}> {}", escape(port), escape(label))
+            }
+            RecordDef::Text(label, Option::None) => escape(label),
+            RecordDef::Array(arr) => {
+                let fields: Vec<String> =
+                    arr.iter().map(|x| visit(x, true)).collect();
+                if nested {
+                    format!("{{{}}}", fields.join("|"))
+                } else {
+                    fields.join("|")
+                }
+            }
+        }
+    }
+    visit(rec, false)
+}
+
 // Construct a record from a description string.
 pub fn record_builder(label: &str) -> ShapeKind {
     let res = parse_record_string(label);
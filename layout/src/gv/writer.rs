@@ -0,0 +1,275 @@
+//! Writes a VisualGraph in the DOT format, for graphs that are built with the
+//! API instead of parsed, so that they can be opened with other GraphViz
+//! tools. The attributes of the nodes and the edges are reconstructed from
+//! their shapes and styles, and only the attributes that differ from the
+//! defaults of the GraphBuilder are written.
+
+use super::parser::ast;
+use super::parser::printer;
+use super::record::record_to_string;
+use crate::adt::dag::NodeHandle;
+use crate::core::base::Orientation;
+use crate::core::color::Color;
+use crate::core::style::{LineStyleKind, StyleAttr};
+use crate::std_shapes::shapes::{Arrow, Element, LineEndKind, ShapeKind};
+use crate::topo::layout::VisualGraph;
+
+/// \returns the GraphViz name of the arrow head \p kind. This is the inverse
+/// of the arrow head names that the GraphBuilder accepts.
+fn arrow_head_name(kind: LineEndKind) -> &'static str {
+    match kind {
+        LineEndKind::None => "none",
+        LineEndKind::Arrow => "normal",
+        LineEndKind::OpenArrow => "onormal",
+        LineEndKind::Diamond => "diamond",
+        LineEndKind::OpenDiamond => "odiamond",
+        LineEndKind::Vee => "vee",
+        LineEndKind::Dot => "dot",
+        LineEndKind::OpenDot => "odot",
+        LineEndKind::Box => "box",
+        LineEndKind::OpenBox => "obox",
+        LineEndKind::Inv => "inv",
+        LineEndKind::OpenInv => "oinv",
+        LineEndKind::Tee => "tee",
+    }
+}
+
+/// \returns the name of the node \p node in the output. Nodes that were not
+/// given a name are named after their index.
+fn node_name(vg: &VisualGraph, node: NodeHandle) -> String {
+    match vg.name_of(node) {
+        Option::Some(name) => name.to_string(),
+        Option::None => format!("n{}", node.get_index()),
+    }
+}
+
+fn is_color(color: Color, name: &str) -> bool {
+    color.to_web_color() == Color::fast(name).to_web_color()
+}
+
+/// Add the attributes of the style \p look, and of the line style
+/// \p line_style, that are shared by nodes and edges to \p list. The names of
+/// the styles are added to \p styles.
+fn add_style_attributes(
+    look: &StyleAttr,
+    line_style: LineStyleKind,
+    styles: &mut Vec<&str>,
+    list: &mut ast::AttributeList,
+) {
+    if look.line_width != 1 {
+        list.add_attr("penwidth", &look.line_width.to_string());
+    }
+    if look.font_size != 14 {
+        list.add_attr("fontsize", &look.font_size.to_string());
+    }
+    if look.monospace {
+        list.add_attr("fontname", "Courier");
+    }
+    match line_style {
+        LineStyleKind::Normal => {}
+        LineStyleKind::Dashed => styles.push("dashed"),
+        LineStyleKind::Dotted => styles.push("dotted"),
+        LineStyleKind::None => styles.push("invis"),
+    }
+}
+
+fn node_attributes(name: &str, elem: &Element) -> ast::AttributeList {
+    let mut list = ast::AttributeList::new();
+    let mut styles = Vec::new();
+    let label = match &elem.shape {
+        ShapeKind::None | ShapeKind::Connector(_) => Option::None,
+        ShapeKind::Box(label) => {
+            list.add_attr("shape", "box");
+            Option::Some(label.clone())
+        }
+        ShapeKind::Circle(label) => Option::Some(label.clone()),
+        ShapeKind::DoubleCircle(label) => {
+            list.add_attr("shape", "doublecircle");
+            Option::Some(label.clone())
+        }
+        ShapeKind::Polygon(label, poly) => {
+            list.add_attr("shape", "polygon");
+            list.add_attr("sides", &poly.sides.to_string());
+            if poly.skew != 0. {
+                list.add_attr("skew", &poly.skew.to_string());
+            }
+            if poly.distortion != 0. {
+                list.add_attr("distortion", &poly.distortion.to_string());
+            }
+            Option::Some(label.clone())
+        }
+        ShapeKind::Point => {
+            list.add_attr("shape", "point");
+            Option::None
+        }
+        ShapeKind::Record(rec) => {
+            let shape = if elem.look.rounded > 0 {
+                "Mrecord"
+            } else {
+                "record"
+            };
+            list.add_attr("shape", shape);
+            Option::Some(record_to_string(rec))
+        }
+    };
+    // Nodes are labeled with their name by default.
+    if let Option::Some(label) = label {
+        if label != name {
+            list.add_attr("label", &label);
+        }
+    }
+
+    let look = &elem.look;
+    if !is_color(look.line_color, "black") {
+        list.add_attr("color", &look.line_color.to_web_color());
+    }
+    if let Option::Some(fill) = look.fill_color {
+        if !is_color(fill, "white") && !matches!(elem.shape, ShapeKind::Point) {
+            list.add_attr("fillcolor", &fill.to_web_color());
+            styles.push("filled");
+        }
+    }
+    if look.shadow {
+        styles.push("shadow");
+    }
+    add_style_attributes(look, look.line_style, &mut styles, &mut list);
+    if !styles.is_empty() {
+        list.add_attr("style", &styles.join(","));
+    }
+    if let Option::Some(peripheries) = look.peripheries {
+        list.add_attr("peripheries", &peripheries.to_string());
+    }
+    if let Option::Some(group) = &elem.group {
+        list.add_attr("group", group);
+    }
+    if let Option::Some(comment) = &elem.comment {
+        list.add_attr("comment", comment);
+    }
+    list
+}
+
+fn edge_attributes(arrow: &Arrow, directed: bool) -> ast::AttributeList {
+    let mut list = ast::AttributeList::new();
+    let mut styles = Vec::new();
+    if !arrow.text.is_empty() {
+        list.add_attr("label", &arrow.text);
+    }
+
+    let look = &arrow.look;
+    if arrow.colors.len() > 1 {
+        let colors: Vec<String> =
+            arrow.colors.iter().map(|c| c.to_web_color()).collect();
+        list.add_attr("color", &colors.join(":"));
+    } else if let Option::Some(head_color) = look.gradient_color {
+        let colors = format!(
+            "{}:{}",
+            look.line_color.to_web_color(),
+            head_color.to_web_color()
+        );
+        list.add_attr("color", &colors);
+        styles.push("gradient");
+    } else if !is_color(look.line_color, "black") {
+        list.add_attr("color", &look.line_color.to_web_color());
+    }
+    add_style_attributes(look, arrow.line_style, &mut styles, &mut list);
+    if !styles.is_empty() {
+        list.add_attr("style", &styles.join(","));
+    }
+
+    // Directed edges have a head by default, and undirected edges have none.
+    if directed {
+        let has_tail = !matches!(arrow.start, LineEndKind::None);
+        let has_head = !matches!(arrow.end, LineEndKind::None);
+        match (has_head, has_tail) {
+            (true, false) => {}
+            (false, true) => list.add_attr("dir", "back"),
+            (true, true) => list.add_attr("dir", "both"),
+            (false, false) => list.add_attr("dir", "none"),
+        }
+        if has_head && !matches!(arrow.end, LineEndKind::Arrow) {
+            list.add_attr("arrowhead", arrow_head_name(arrow.end));
+        }
+        if has_tail && !matches!(arrow.start, LineEndKind::Arrow) {
+            list.add_attr("arrowtail", arrow_head_name(arrow.start));
+        }
+    }
+    if look.arrow_size != 1. {
+        list.add_attr("arrowsize", &look.arrow_size.to_string());
+    }
+
+    if let Option::Some(label) = &arrow.head_label {
+        list.add_attr("headlabel", label);
+    }
+    if let Option::Some(label) = &arrow.tail_label {
+        list.add_attr("taillabel", label);
+    }
+    if let Option::Some(len) = arrow.len {
+        list.add_attr("len", &(len / 72.).to_string());
+    }
+    if arrow.weight != 1. {
+        list.add_attr("weight", &arrow.weight.to_string());
+    }
+    if !arrow.constraint {
+        list.add_attr("constraint", "false");
+    }
+    if let Option::Some(comment) = &arrow.comment {
+        list.add_attr("comment", comment);
+    }
+    list
+}
+
+/// Format the graph \p vg in the DOT format. This is meant for graphs before
+/// layout: the edges are written between their original end points, and the
+/// connector nodes that the layout adds are not written. The graph is
+/// undirected if none of the edges has an arrow head.
+pub fn to_dot(vg: &VisualGraph) -> String {
+    let mut graph = ast::Graph::new("");
+
+    let rankdir = match (vg.orientation(), vg.is_reversed()) {
+        (Orientation::TopToBottom, false) => Option::None,
+        (Orientation::TopToBottom, true) => Option::Some("BT"),
+        (Orientation::LeftToRight, false) => Option::Some("LR"),
+        (Orientation::LeftToRight, true) => Option::Some("RL"),
+    };
+    if let Option::Some(rankdir) = rankdir {
+        let mut list = ast::AttributeList::new();
+        list.add_attr("rankdir", rankdir);
+        let stmt = ast::AttrStmt::new(ast::AttrStmtTarget::Graph, list);
+        graph.list.list.push(ast::Stmt::Attribute(stmt));
+    }
+
+    for node in vg.iter_nodes() {
+        if vg.is_connector(node) {
+            continue;
+        }
+        let name = node_name(vg, node);
+        let list = node_attributes(&name, vg.element(node));
+        let id = ast::NodeId::new(&name, &Option::None);
+        let stmt = ast::NodeStmt::new_with_list(id, list);
+        graph.list.list.push(ast::Stmt::Node(stmt));
+    }
+
+    let directed = vg.edge_list().iter().any(|(arrow, _)| {
+        !matches!(arrow.start, LineEndKind::None)
+            || !matches!(arrow.end, LineEndKind::None)
+    });
+    let kind = if directed {
+        ast::ArrowKind::Arrow
+    } else {
+        ast::ArrowKind::Line
+    };
+    if !directed && !vg.edge_list().is_empty() {
+        graph.kind = ast::GraphKind::Graph;
+    }
+    for (arrow, chain) in vg.edge_list() {
+        let (from, to) = (chain[0], chain[chain.len() - 1]);
+        let from = ast::NodeId::new(&node_name(vg, from), &arrow.src_port);
+        let to = ast::NodeId::new(&node_name(vg, to), &arrow.dst_port);
+        let mut stmt = ast::EdgeStmt::new(from);
+        stmt.insert(to, kind.clone());
+        stmt.list = edge_attributes(arrow, directed);
+        graph.list.list.push(ast::Stmt::Edge(stmt));
+    }
+
+    printer::to_dot(&graph)
+}
@@ -1,28 +1,42 @@
 //! Implements the drawing of elements and arrows on the backing canvas.
 
 use crate::core::base::Orientation;
+use crate::core::color::Color;
 use crate::core::format::{ClipHandle, RenderBackend, Renderable, Visible};
 use crate::core::geometry::*;
 use crate::core::style::{LineStyleKind, StyleAttr};
 use crate::std_shapes::shapes::*;
 
+/// Return the size of \p text, using the cache \p cache if one is provided.
+fn get_text_size(
+    text: &str,
+    font_size: usize,
+    cache: Option<&TextSizeCache>,
+) -> Point {
+    match cache {
+        Option::Some(cache) => cache.get_size_for_str(text, font_size),
+        Option::None => get_size_for_str(text, font_size),
+    }
+}
+
 /// Return the height and width of the record, depending on the geometry and
 /// internal text.
 fn get_record_size(
     rec: &RecordDef,
     dir: Orientation,
     font_size: usize,
+    cache: Option<&TextSizeCache>,
 ) -> Point {
     match rec {
         RecordDef::Text(label, _) => pad_shape_scalar(
-            get_size_for_str(label, font_size),
+            get_text_size(label, font_size, cache),
             BOX_SHAPE_PADDING,
         ),
         RecordDef::Array(arr) => {
             let mut x: f64 = 0.;
             let mut y: f64 = 0.;
             for elem in arr {
-                let ret = get_record_size(elem, dir.flip(), font_size);
+                let ret = get_record_size(elem, dir.flip(), font_size, cache);
                 if dir.is_left_right() {
                     x += ret.x;
                     y = y.max(ret.y);
@@ -38,6 +52,72 @@ fn get_record_size(
 
 const BOX_SHAPE_PADDING: f64 = 10.;
 const CIRCLE_SHAPE_PADDING: f64 = 20.;
+// The gap between the rings of a double circle, relative to the node size.
+const DOUBLE_CIRCLE_GAP_RATIO: f64 = 0.1;
+const DOUBLE_CIRCLE_MIN_GAP: f64 = 6.;
+/// The distance between shapes and their shadows, and the opacity of the
+/// shadows.
+const SHADOW_OFFSET: f64 = 4.;
+const SHADOW_OPACITY: f64 = 0.3;
+// The default size of a point shape (0.05 inch).
+pub const POINT_SHAPE_SIZE: f64 = 3.6;
+
+/// Return the size of the inner ring of a double circle with the size \p size.
+/// The gap between the rings grows with the size of the node, so that large
+/// nodes don't get a thin ring.
+pub fn get_double_circle_inner_size(size: Point) -> Point {
+    get_periphery_size(size, 1)
+}
+
+/// Return the size of the outline number \p index (counting from the outside)
+/// of a shape with the size \p size and a few peripheries. The outlines are
+/// placed at the same gap as the rings of double circles.
+pub fn get_periphery_size(size: Point, index: usize) -> Point {
+    let gap = size.x.min(size.y) * DOUBLE_CIRCLE_GAP_RATIO;
+    let gap = gap.max(DOUBLE_CIRCLE_MIN_GAP) * index as f64;
+    size.sub(Point::splat(gap))
+}
+
+/// Return the vertices of the polygon \p poly that fills the box with the
+/// center \p center and size \p size. The vertices of a regular polygon are
+/// placed on a circle, with a flat bottom side. The skew shifts each vertex
+/// horizontally in proportion to its height, and the distortion scales the
+/// width of the polygon in proportion to the height, like in GraphViz.
+pub fn get_polygon_vertices(
+    poly: &PolygonDef,
+    center: Point,
+    size: Point,
+) -> Vec<Point> {
+    use std::f64::consts::PI;
+    let count = poly.sides.max(3);
+    let sides = count as f64;
+    // Compute the vertices in a y-up system, where 'v' is the height.
+    let mut points = Vec::new();
+    for i in 0..count {
+        let angle = -PI / 2. + PI / sides + 2. * PI * i as f64 / sides;
+        let (v, x) = angle.sin_cos();
+        let x = x * (1. + poly.distortion * v) + poly.skew * v;
+        points.push(Point::new(x, v));
+    }
+
+    // Stretch the polygon to fill the box.
+    let mut min = Point::splat(f64::INFINITY);
+    let mut max = Point::splat(f64::NEG_INFINITY);
+    for p in points.iter() {
+        min = Point::new(min.x.min(p.x), min.y.min(p.y));
+        max = Point::new(max.x.max(p.x), max.y.max(p.y));
+    }
+    let mid = min.add(max).scale(0.5);
+    let half = max.sub(min).scale(0.5);
+    points
+        .iter()
+        .map(|p| {
+            let x = (p.x - mid.x) / half.x * size.x / 2.;
+            let y = (p.y - mid.y) / half.y * size.y / 2.;
+            Point::new(center.x + x, center.y - y)
+        })
+        .collect()
+}
 
 /// Return the size of the shape. If \p make_xy_same is set then make the
 /// X and the Y of the shape the same. This will turn ellipses into circles and
@@ -48,24 +128,50 @@ pub fn get_shape_size(
     s: &ShapeKind,
     font: usize,
     make_xy_same: bool,
+) -> Point {
+    get_shape_size_with_cache(dir, s, font, make_xy_same, None)
+}
+
+/// Return the size of the shape, like 'get_shape_size'. The text sizes are
+/// looked up in (and added to) the cache \p cache, if one is provided.
+pub fn get_shape_size_with_cache(
+    dir: Orientation,
+    s: &ShapeKind,
+    font: usize,
+    make_xy_same: bool,
+    cache: Option<&TextSizeCache>,
 ) -> Point {
     let mut res = match s {
-        ShapeKind::Box(text) => {
-            pad_shape_scalar(get_size_for_str(text, font), BOX_SHAPE_PADDING)
-        }
-        ShapeKind::Circle(text) => {
-            pad_shape_scalar(get_size_for_str(text, font), CIRCLE_SHAPE_PADDING)
-        }
-        ShapeKind::DoubleCircle(text) => {
-            pad_shape_scalar(get_size_for_str(text, font), CIRCLE_SHAPE_PADDING)
-        }
-        ShapeKind::Record(sr) => {
-            pad_shape_scalar(get_record_size(sr, dir, font), BOX_SHAPE_PADDING)
+        ShapeKind::Box(text) => pad_shape_scalar(
+            get_text_size(text, font, cache),
+            BOX_SHAPE_PADDING,
+        ),
+        ShapeKind::Circle(text) => pad_shape_scalar(
+            get_text_size(text, font, cache),
+            CIRCLE_SHAPE_PADDING,
+        ),
+        ShapeKind::DoubleCircle(text) => pad_shape_scalar(
+            get_text_size(text, font, cache),
+            CIRCLE_SHAPE_PADDING,
+        ),
+        ShapeKind::Polygon(text, poly) => {
+            // Make room for the label in the narrow parts of the polygon.
+            let size = pad_shape_scalar(
+                get_text_size(text, font, cache),
+                CIRCLE_SHAPE_PADDING,
+            );
+            let stretch = 1. + poly.skew.abs() + poly.distortion.abs();
+            Point::new(size.x * stretch, size.y)
         }
+        ShapeKind::Point => Point::splat(POINT_SHAPE_SIZE),
+        ShapeKind::Record(sr) => pad_shape_scalar(
+            get_record_size(sr, dir, font, cache),
+            BOX_SHAPE_PADDING,
+        ),
         ShapeKind::Connector(text) => {
             if let Option::Some(text) = text {
                 pad_shape_scalar(
-                    get_size_for_str(text, font),
+                    get_text_size(text, font, cache),
                     BOX_SHAPE_PADDING,
                 )
             } else {
@@ -80,6 +186,32 @@ pub fn get_shape_size(
     res
 }
 
+/// Return the size of the shape, like 'get_shape_size_with_cache', for the
+/// text style \p look. The text of shapes with vertical text is rotated, so
+/// the width and the height of the shape are swapped. Records are not
+/// affected by the rotation.
+pub fn get_shape_size_with_style(
+    dir: Orientation,
+    s: &ShapeKind,
+    look: &StyleAttr,
+    make_xy_same: bool,
+    cache: Option<&TextSizeCache>,
+) -> Point {
+    let size =
+        get_shape_size_with_cache(dir, s, look.font_size, make_xy_same, cache);
+    match s {
+        ShapeKind::Box(_)
+        | ShapeKind::Circle(_)
+        | ShapeKind::DoubleCircle(_)
+        | ShapeKind::Polygon(..)
+            if look.vertical_text =>
+        {
+            size.transpose()
+        }
+        _ => size,
+    }
+}
+
 // Returns the innermost shape that the record describes, or the location and
 // size of the outer shape.
 fn get_record_port_location(
@@ -89,6 +221,7 @@ fn get_record_port_location(
     size: Point,
     look: &StyleAttr,
     port_name: &str,
+    cache: Option<&TextSizeCache>,
 ) -> (Point, Point) {
     struct Locator {
         port_name: String,
@@ -119,22 +252,46 @@ fn get_record_port_location(
         loc,
         size,
     };
-    visit_record(rec, dir, loc, size, look, &mut visitor);
+    visit_record(rec, dir, loc, size, look, cache, &mut visitor);
     (visitor.loc, visitor.size)
 }
 
+/// \returns the direction of the compass point \p port, such as "n" or "sw",
+/// or None if the port is not a compass point. The y axis points down. The
+/// compass points "c" and "_" let the edge attach anywhere, like no port.
+fn get_compass_direction(port: &str) -> Option<Point> {
+    let (x, y) = match port {
+        "n" => (0., -1.),
+        "ne" => (1., -1.),
+        "e" => (1., 0.),
+        "se" => (1., 1.),
+        "s" => (0., 1.),
+        "sw" => (-1., 1.),
+        "w" => (-1., 0.),
+        "nw" => (-1., -1.),
+        _ => return None,
+    };
+    Some(Point::new(x, y))
+}
+
 fn render_record(
     rec: &RecordDef,
     dir: Orientation,
     loc: Point,
     size: Point,
     look: &StyleAttr,
+    cache: Option<&TextSizeCache>,
     canvas: &mut dyn RenderBackend,
 ) {
     struct Renderer<'a> {
         look: StyleAttr,
         clip_handle: Option<ClipHandle>,
         canvas: &'a mut dyn RenderBackend,
+        // The corners of the record, if the outer frame is hidden. Then the
+        // fields are drawn as the lines between them.
+        frameless: Option<(Point, Point)>,
+        // The lines that were drawn, because neighboring fields share sides.
+        lines: Vec<(Point, Point)>,
     }
 
     // A reference to the clip region.
@@ -146,8 +303,44 @@ fn render_record(
         clip_handle = Option::Some(ch);
     }
 
+    impl<'a> Renderer<'a> {
+        /// Draw the sides of the field at \p loc with the size \p size that
+        /// are not on the outline of the record \p frame.
+        fn draw_separators(
+            &mut self,
+            loc: Point,
+            size: Point,
+            frame: (Point, Point),
+        ) {
+            let (tl, br) = (loc.sub(size.scale(0.5)), loc.add(size.scale(0.5)));
+            let (tr, bl) = (Point::new(br.x, tl.y), Point::new(tl.x, br.y));
+            let on_frame = |a: f64, b: f64, c: f64| {
+                (a - b).abs() < 0.01 || (a - c).abs() < 0.01
+            };
+            let sides = [
+                (tl, tr, on_frame(tl.y, frame.0.y, frame.1.y)),
+                (bl, br, on_frame(bl.y, frame.0.y, frame.1.y)),
+                (tl, bl, on_frame(tl.x, frame.0.x, frame.1.x)),
+                (tr, br, on_frame(tr.x, frame.0.x, frame.1.x)),
+            ];
+            for (from, to, hidden) in sides {
+                let drawn = self.lines.iter().any(|l| {
+                    l.0.sub(from).length() < 0.01 && l.1.sub(to).length() < 0.01
+                });
+                if !hidden && !drawn {
+                    self.canvas.draw_line(from, to, &self.look, Option::None);
+                    self.lines.push((from, to));
+                }
+            }
+        }
+    }
+
     impl<'a> RecordVisitor for Renderer<'a> {
         fn handle_box(&mut self, loc: Point, size: Point) {
+            if let Option::Some(frame) = self.frameless {
+                self.draw_separators(loc, size, frame);
+                return;
+            }
             self.canvas.draw_rect(
                 Point::new(loc.x - size.x / 2., loc.y - size.y / 2.),
                 Point::new(size.x, size.y),
@@ -167,24 +360,37 @@ fn render_record(
         }
     }
 
+    // Records with 'peripheries=0' have no outer frame. Only the background
+    // and the lines between the fields are drawn.
+    let frameless = look.peripheries == Option::Some(0);
+    let xy = Point::new(loc.x - size.x / 2., loc.y - size.y / 2.);
+    if frameless {
+        let mut look = look.clone();
+        look.line_width = 0;
+        canvas.draw_rect(xy, size, &look, Option::None, Option::None);
+    }
+
     let mut visitor = Renderer {
         look: look.clone(),
         clip_handle,
         canvas,
+        frameless: frameless.then(|| (xy, xy.add(size))),
+        lines: Vec::new(),
     };
-    // Make the internal record boxes square and not round.
+    // Make the internal record boxes square and not round, and draw the
+    // lines between the fields with the separator style.
     visitor.look.rounded = 0;
-    visit_record(rec, dir, loc, size, look, &mut visitor);
+    visitor.look.line_width =
+        look.separator_width.unwrap_or((look.line_width / 2).max(1));
+    visitor.look.line_color = look.separator_color.unwrap_or(look.line_color);
+    visit_record(rec, dir, loc, size, look, cache, &mut visitor);
 
+    if frameless {
+        return;
+    }
     let mut look = look.clone();
     look.fill_color = Option::None;
-    canvas.draw_rect(
-        Point::new(loc.x - size.x / 2., loc.y - size.y / 2.),
-        Point::new(size.x, size.y),
-        &look,
-        Option::None,
-        Option::None,
-    );
+    canvas.draw_rect(xy, size, &look, Option::None, Option::None);
 }
 
 pub trait RecordVisitor {
@@ -204,6 +410,7 @@ fn visit_record(
     loc: Point,
     size: Point,
     look: &StyleAttr,
+    cache: Option<&TextSizeCache>,
     visitor: &mut dyn RecordVisitor,
 ) {
     visitor.handle_box(loc, size);
@@ -218,7 +425,7 @@ fn visit_record(
             // Figure out the recursive size of each element, and the largest
             // element.
             for elem in arr {
-                let sz = get_record_size(elem, dir, look.font_size);
+                let sz = get_record_size(elem, dir, look.font_size, cache);
                 sizes.push(sz);
                 sum = Point::new(sum.x + sz.x, sum.y + sz.y);
                 mx = Point::new(mx.x.max(sz.x), mx.y.max(sz.y));
@@ -246,6 +453,7 @@ fn visit_record(
                         loc2,
                         sizes[i],
                         look,
+                        cache,
                         visitor,
                     );
                     startx += sizes[i].x;
@@ -263,6 +471,7 @@ fn visit_record(
                         loc2,
                         sizes[i],
                         look,
+                        cache,
                         visitor,
                     );
                     starty += sizes[i].y;
@@ -272,8 +481,60 @@ fn visit_record(
     }
 }
 
+impl Element {
+    /// Draw the outlines of the element with \p draw, which is called with
+    /// the offset of the shape from its place, the size of each outline, the
+    /// style and the properties. The number of outlines is taken from the
+    /// style, or is \p default. Without outlines the shape is drawn once
+    /// without a border. The shadow of the shape is drawn first, if the style
+    /// asks for one.
+    fn render_peripheries<F>(
+        &self,
+        canvas: &mut dyn RenderBackend,
+        default: usize,
+        draw: F,
+    ) where
+        F: Fn(&mut dyn RenderBackend, Point, Point, &StyleAttr, Option<String>),
+    {
+        let size = self.pos.size(false);
+        if self.look.shadow {
+            let color = Color::fast("black").with_opacity(SHADOW_OPACITY);
+            let mut look = self.look.clone();
+            look.line_width = 0;
+            look.line_color = Color::transparent();
+            look.fill_color = Option::Some(color);
+            let offset = Point::splat(SHADOW_OFFSET);
+            draw(canvas, offset, size, &look, Option::None);
+        }
+        let count = self.look.peripheries.unwrap_or(default);
+        let none = Point::zero();
+        if count == 0 {
+            let mut look = self.look.clone();
+            look.line_width = 0;
+            draw(canvas, none, size, &look, self.properties.clone());
+            return;
+        }
+        for i in 0..count {
+            let inner = get_periphery_size(size, i);
+            if inner.x <= 0. || inner.y <= 0. {
+                break;
+            }
+            // Only the outer outline carries the properties of the element.
+            let props = if i == 0 {
+                self.properties.clone()
+            } else {
+                Option::None
+            };
+            draw(canvas, none, inner, &self.look, props);
+        }
+    }
+}
+
 impl Renderable for Element {
     fn render(&self, debug: bool, canvas: &mut dyn RenderBackend) {
+        if let Option::Some(comment) = &self.comment {
+            canvas.draw_comment(comment);
+        }
         if debug {
             // Draw the pink bounding box.
             let debug_look = StyleAttr::debug0();
@@ -287,6 +548,21 @@ impl Renderable for Element {
             );
         }
 
+        // A custom renderer replaces the standard shape drawing.
+        if let Option::Some(custom) = &self.renderer {
+            let bb = self.pos.bbox(false);
+            (custom.0)(canvas, bb.0, self.pos.size(false), &self.look);
+            if debug {
+                canvas.draw_circle(
+                    self.pos.center(),
+                    Point::new(6., 6.),
+                    &StyleAttr::debug2(),
+                    Option::None,
+                );
+            }
+            return;
+        }
+
         match &self.shape {
             ShapeKind::None => {}
             ShapeKind::Record(rec) => {
@@ -296,42 +572,70 @@ impl Renderable for Element {
                     self.pos.center(),
                     self.pos.size(false),
                     &self.look,
+                    self.text_cache.as_ref(),
                     canvas,
                 );
             }
             ShapeKind::Box(text) => {
-                canvas.draw_rect(
-                    self.pos.bbox(false).0,
-                    self.pos.size(false),
-                    &self.look,
-                    self.properties.clone(),
-                    Option::None,
+                self.render_peripheries(
+                    canvas,
+                    1,
+                    |canvas, d, size, look, p| {
+                        let xy = self.pos.middle().add(d).sub(size.scale(0.5));
+                        canvas.draw_rect(xy, size, look, p, Option::None);
+                    },
                 );
                 canvas.draw_text(self.pos.center(), text.as_str(), &self.look);
             }
             ShapeKind::Circle(text) => {
-                canvas.draw_circle(
-                    self.pos.center(),
-                    self.pos.size(false),
-                    &self.look,
-                    self.properties.clone(),
+                self.render_peripheries(
+                    canvas,
+                    1,
+                    |canvas, d, size, look, p| {
+                        canvas.draw_circle(
+                            self.pos.center().add(d),
+                            size,
+                            look,
+                            p,
+                        );
+                    },
                 );
                 canvas.draw_text(self.pos.center(), text.as_str(), &self.look);
             }
             ShapeKind::DoubleCircle(text) => {
+                self.render_peripheries(
+                    canvas,
+                    2,
+                    |canvas, d, size, look, p| {
+                        canvas.draw_circle(
+                            self.pos.center().add(d),
+                            size,
+                            look,
+                            p,
+                        );
+                    },
+                );
+                canvas.draw_text(self.pos.center(), text.as_str(), &self.look);
+            }
+            ShapeKind::Polygon(text, poly) => {
+                self.render_peripheries(
+                    canvas,
+                    1,
+                    |canvas, d, size, look, p| {
+                        let center = self.pos.center().add(d);
+                        let points = get_polygon_vertices(poly, center, size);
+                        canvas.draw_polygon(&points, look, p);
+                    },
+                );
+                canvas.draw_text(self.pos.center(), text.as_str(), &self.look);
+            }
+            ShapeKind::Point => {
                 canvas.draw_circle(
                     self.pos.center(),
                     self.pos.size(false),
                     &self.look,
                     self.properties.clone(),
                 );
-                canvas.draw_circle(
-                    self.pos.center(),
-                    self.pos.size(false).sub(Point::splat(15.)),
-                    &self.look,
-                    Option::None,
-                );
-                canvas.draw_text(self.pos.center(), text.as_str(), &self.look);
             }
             ShapeKind::Connector(label) => {
                 if debug {
@@ -372,6 +676,33 @@ impl Renderable for Element {
         force: f64,
         port: &Option<String>,
     ) -> (Point, Point) {
+        // Edges that name a compass point attach to that side of the shape,
+        // and leave the shape in the direction of the side. The fields of
+        // records may have ports with the same names, which come first.
+        let is_field = match (&self.shape, port) {
+            (ShapeKind::Record(rec), Option::Some(port)) => rec.has_port(port),
+            _ => false,
+        };
+        if let (false, Option::Some(dir)) =
+            (is_field, port.as_deref().and_then(get_compass_direction))
+        {
+            let half = self.pos.size(false).scale(0.5);
+            // The diagonal points of round shapes are on the outline, and
+            // not on the corners of the bounding box.
+            let round = match &self.shape {
+                ShapeKind::Circle(_)
+                | ShapeKind::DoubleCircle(_)
+                | ShapeKind::Point => normalize_scale_vector(dir, 1.),
+                _ => dir,
+            };
+            let loc = self
+                .pos
+                .center()
+                .add(Point::new(round.x * half.x, round.y * half.y));
+            let exit = normalize_scale_vector(dir, force);
+            return (loc, loc.add(exit));
+        }
+
         match &self.shape {
             ShapeKind::None => (Point::zero(), Point::zero()),
             ShapeKind::Record(rec) => {
@@ -386,6 +717,7 @@ impl Renderable for Element {
                         size,
                         &self.look,
                         port_name,
+                        self.text_cache.as_ref(),
                     );
                     loc = r.0;
                     size = r.1;
@@ -393,7 +725,7 @@ impl Renderable for Element {
 
                 get_connection_point_for_box(loc, size, from, force)
             }
-            ShapeKind::Box(_) => {
+            ShapeKind::Box(_) | ShapeKind::Polygon(..) => {
                 let loc = self.pos.center();
                 let size = self.pos.size(false);
                 get_connection_point_for_box(loc, size, from, force)
@@ -403,7 +735,7 @@ impl Renderable for Element {
                 let size = self.pos.size(false);
                 get_connection_point_for_circle(loc, size, from, force)
             }
-            ShapeKind::DoubleCircle(_) => {
+            ShapeKind::DoubleCircle(_) | ShapeKind::Point => {
                 let loc = self.pos.center();
                 let size = self.pos.size(false);
                 get_connection_point_for_circle(loc, size, from, force)
@@ -430,6 +762,41 @@ impl Renderable for Element {
     }
 }
 
+/// The magnitude of the direction vectors that shape the edge curves.
+pub const ARROW_FORCE: f64 = 30.;
+
+/// Convert the path that generate_curve_for_elements returns into a flat list
+/// of cubic bezier points: the start point followed by triplets of (control,
+/// control, end) points. The path is in the format of the SVG 'M C S ...'
+/// commands, where the first control point of each 'S' segment is the
+/// reflection of the previous control point.
+pub fn path_to_bezier_points(path: &[(Point, Point)]) -> Vec<Point> {
+    let mut res = vec![path[0].0, path[0].1, path[1].0, path[1].1];
+    for seg in path.iter().skip(2) {
+        let prev_end = res[res.len() - 1];
+        let prev_ctrl = res[res.len() - 2];
+        res.push(prev_end.scale(2.).sub(prev_ctrl));
+        res.push(seg.0);
+        res.push(seg.1);
+    }
+    res
+}
+
+/// Convert the list of cubic bezier points \p points, in the format that
+/// 'path_to_bezier_points' returns, into a path in the format of
+/// 'generate_curve_for_elements'. The path can't represent the first control
+/// point of the segments after the first segment, and uses the reflection of
+/// the previous control point instead. This is exact for smooth curves
+/// where the control points around each joint are at the same distance.
+pub fn bezier_points_to_path(points: &[Point]) -> Vec<(Point, Point)> {
+    assert!(points.len() >= 4 && points.len() % 3 == 1, "Invalid curve");
+    let mut path = vec![(points[0], points[1]), (points[2], points[3])];
+    for seg in points[4..].chunks(3) {
+        path.push((seg[1], seg[2]));
+    }
+    path
+}
+
 pub fn generate_curve_for_elements(
     elements: &[Element],
     arrow: &Arrow,
@@ -470,16 +837,179 @@ pub fn generate_curve_for_elements(
     path
 }
 
+/// Generate an elbow connector that passes through the elements in
+/// \p elements. The line leaves the first element from its bottom, crosses
+/// over at the position \p bus along the rank axis, and enters the next
+/// element from its top. If \p lr is set then the ranks are columns, and the
+/// line leaves from the right side instead. Ports are ignored. The path is in
+/// the format of 'generate_curve_for_elements'.
+pub fn generate_elbow_for_elements(
+    elements: &[Element],
+    bus: f64,
+    lr: bool,
+) -> Vec<(Point, Point)> {
+    // Work in the coordinates of a top-to-bottom graph.
+    let flip = |p: Point| if lr { p.transpose() } else { p };
+    let center = |e: &Element| flip(e.position().center()).x;
+    let top = |e: &Element| flip(e.position().bbox(false).0).y;
+    let bottom = |e: &Element| flip(e.position().bbox(false).1).y;
+
+    let mut points =
+        vec![Point::new(center(&elements[0]), bottom(&elements[0]))];
+    let mut bus = bus;
+    for i in 1..elements.len() {
+        let x = center(&elements[i]);
+        let prev = points[points.len() - 1];
+        points.push(Point::new(prev.x, bus));
+        points.push(Point::new(x, bus));
+        if i == elements.len() - 1 {
+            points.push(Point::new(x, top(&elements[i])));
+        } else {
+            // Pass through the connector, and turn again halfway to the
+            // next element.
+            points.push(Point::new(x, bottom(&elements[i])));
+            bus = (bottom(&elements[i]) + top(&elements[i + 1])) / 2.;
+        }
+    }
+
+    straight_path(points, lr)
+}
+
+/// \returns the path of straight segments between the points \p points, in
+/// the format of 'generate_curve_for_elements'. The points are in the
+/// coordinates of a top-to-bottom graph, and are transposed if \p lr is set.
+/// Repeated points, and points in the middle of straight runs, are removed.
+fn straight_path(mut points: Vec<Point>, lr: bool) -> Vec<(Point, Point)> {
+    let flip = |p: Point| if lr { p.transpose() } else { p };
+    // Merge the straight runs through the connectors.
+    let same = |a: f64, b: f64| (a - b).abs() < 0.001;
+    points.dedup_by(|a, b| same(a.x, b.x) && same(a.y, b.y));
+    let mut i = 1;
+    while i + 1 < points.len() {
+        let (a, b, c) = (points[i - 1], points[i], points[i + 1]);
+        let vertical = same(a.x, b.x) && same(b.x, c.x);
+        let horizontal = same(a.y, b.y) && same(b.y, c.y);
+        if vertical || horizontal {
+            points.remove(i);
+        } else {
+            i += 1;
+        }
+    }
+
+    // Straight segments are curves with control points at their ends.
+    points.iter().map(|p| (flip(*p), flip(*p))).collect()
+}
+
+/// Generate a path of horizontal and vertical segments that passes through
+/// the elements in \p elements. Between the elements i and i+1 the line
+/// crosses over at the position \p buses[i] along the rank axis. The line
+/// leaves and enters the elements from their side that faces the bus, so
+/// buses above the elements connect elements in the same rank. If \p lr is
+/// set then the ranks are columns. Ports are ignored. The path is in the
+/// format of 'generate_curve_for_elements'.
+pub fn generate_ortho_for_elements(
+    elements: &[Element],
+    buses: &[f64],
+    lr: bool,
+) -> Vec<(Point, Point)> {
+    assert_eq!(
+        buses.len() + 1,
+        elements.len(),
+        "Expected a bus per segment"
+    );
+    // Work in the coordinates of a top-to-bottom graph.
+    let flip = |p: Point| if lr { p.transpose() } else { p };
+    let center = |e: &Element| flip(e.position().center());
+    let side = |e: &Element, bus: f64| {
+        let bb = e.position().bbox(false);
+        if bus > center(e).y {
+            flip(bb.1).y
+        } else {
+            flip(bb.0).y
+        }
+    };
+
+    let mut points = Vec::new();
+    for (i, bus) in buses.iter().enumerate() {
+        let (from, to) = (&elements[i], &elements[i + 1]);
+        let (x0, x1) = (center(from).x, center(to).x);
+        points.push(Point::new(x0, side(from, *bus)));
+        points.push(Point::new(x0, *bus));
+        points.push(Point::new(x1, *bus));
+        points.push(Point::new(x1, side(to, *bus)));
+    }
+    straight_path(points, lr)
+}
+
+/// Generate a path between the two elements in \p elements, which are in the
+/// same rank. If the elements are \p adjacent in the rank then the path is a
+/// straight line between their sides. Otherwise, the path is an arc that
+/// leaves and enters the elements from the side of the previous rank, to
+/// pass over the elements between them. If \p lr is set then the ranks are
+/// columns. Ports are ignored. The path is in the format of
+/// 'generate_curve_for_elements'.
+pub fn generate_flat_edge_for_elements(
+    elements: &[Element],
+    adjacent: bool,
+    lr: bool,
+) -> Vec<(Point, Point)> {
+    assert_eq!(elements.len(), 2, "Expected an edge between two elements");
+    // Work in the coordinates of a top-to-bottom graph.
+    let flip = |p: Point| if lr { p.transpose() } else { p };
+    let from = &elements[0].position();
+    let to = &elements[1].position();
+    let (c0, c1) = (flip(from.center()), flip(to.center()));
+    let dir = if c0.x < c1.x { 1. } else { -1. };
+
+    if adjacent {
+        let half = |p: &Position| flip(p.size(false)).x / 2.;
+        let start = Point::new(c0.x + dir * half(from), c0.y);
+        let end = Point::new(c1.x - dir * half(to), c1.y);
+        let third = end.sub(start).scale(1. / 3.);
+        return vec![
+            (flip(start), flip(start.add(third))),
+            (flip(end.sub(third)), flip(end)),
+        ];
+    }
+
+    let top = |p: &Position| flip(p.bbox(false).0).y;
+    let start = Point::new(c0.x, top(from));
+    let end = Point::new(c1.x, top(to));
+    let rise = Point::new(0., -(c1.x - c0.x).abs() / 4.);
+    vec![
+        (flip(start), flip(start.add(rise))),
+        (flip(end.add(rise)), flip(end)),
+    ]
+}
+
 pub fn render_arrow(
     canvas: &mut dyn RenderBackend,
     debug: bool,
     elements: &[Element],
     arrow: &Arrow,
 ) {
-    let path = generate_curve_for_elements(elements, arrow, 30.);
+    let path = generate_curve_for_elements(elements, arrow, ARROW_FORCE);
+    render_arrow_path(canvas, debug, &path, arrow);
+}
 
+/// The distance between head and tail labels and the end of the edge.
+const PORT_LABEL_DISTANCE: f64 = 20.;
+/// The angle between head and tail labels and the edge, in degrees.
+const PORT_LABEL_ANGLE: f64 = -25.;
+
+/// Render the arrow \p arrow along the path \p path, which was generated by
+/// 'generate_curve_for_elements'.
+pub fn render_arrow_path(
+    canvas: &mut dyn RenderBackend,
+    debug: bool,
+    path: &[(Point, Point)],
+    arrow: &Arrow,
+) {
+    if let Option::Some(comment) = &arrow.comment {
+        canvas.draw_comment(comment);
+    }
     if debug {
-        for seg in &path {
+        for seg in path {
             canvas.draw_line(seg.0, seg.1, &StyleAttr::debug2(), Option::None);
             canvas.draw_circle(
                 seg.0,
@@ -496,24 +1026,196 @@ pub fn render_arrow(
         }
     }
 
-    let dash = match arrow.line_style {
-        LineStyleKind::None => {
-            return;
+    if let LineStyleKind::None = arrow.line_style {
+        return;
+    }
+
+    // Draws a stroke of the edge, moved sideways by 'offset'. The curves
+    // that the user provided are drawn with all of their control points.
+    let mut draw = |offset: f64, look: &StyleAttr, text: &str| {
+        let head = (arrow.start, arrow.end);
+        let props = arrow.properties.clone();
+        if let Option::Some(points) = &arrow.spline {
+            let points = offset_bezier_points(points, offset);
+            canvas.draw_curve_arrow(
+                &points,
+                arrow.line_style,
+                head,
+                look,
+                props,
+                text,
+            );
+        } else {
+            let path = offset_path(path, offset);
+            canvas.draw_arrow(&path, arrow.line_style, head, look, props, text);
         }
-        LineStyleKind::Normal => false,
-        LineStyleKind::Dashed => true,
-        LineStyleKind::Dotted => true,
     };
 
-    let start = matches!(arrow.start, LineEndKind::Arrow);
-    let end = matches!(arrow.end, LineEndKind::Arrow);
+    if arrow.colors.len() > 1 {
+        // Draw one stroke per color, side by side. Only the first stroke has
+        // the label.
+        let spacing = arrow.look.line_width.max(1) as f64 * 2.;
+        let middle = (arrow.colors.len() - 1) as f64 / 2.;
+        for (i, color) in arrow.colors.iter().enumerate() {
+            let mut look = arrow.look.clone();
+            look.line_color = *color;
+            look.gradient_color = Option::None;
+            let offset = (i as f64 - middle) * spacing;
+            draw(offset, &look, if i == 0 { &arrow.text } else { "" });
+        }
+    } else {
+        draw(0., &arrow.look, &arrow.text);
+    }
+
+    // Draw the head and tail labels next to the end points of the path.
+    let (tail, head) = get_end_label_locations(path);
+    let ends = [(&arrow.tail_label, tail), (&arrow.head_label, head)];
+    for (label, loc) in ends {
+        if let Option::Some(label) = label {
+            canvas.draw_text(loc, label, &arrow.label_look);
+        }
+    }
+}
 
-    canvas.draw_arrow(
-        &path,
-        dash,
-        (start, end),
-        &arrow.look,
-        arrow.properties.clone(),
-        &arrow.text,
-    );
+/// \returns the path \p path, in the format of 'generate_curve_for_elements',
+/// moved sideways by \p offset. Every point moves perpendicular to the
+/// direction of the curve at the point.
+fn offset_path(path: &[(Point, Point)], offset: f64) -> Vec<(Point, Point)> {
+    // Paths of straight segments have their control points at their points.
+    if path.iter().all(|seg| seg.1.sub(seg.0).length() < 0.001) {
+        let points: Vec<Point> = path.iter().map(|seg| seg.0).collect();
+        let points = offset_polyline(&points, offset);
+        return points.iter().map(|p| (*p, *p)).collect();
+    }
+    let first = path[0].0;
+    let last = path[path.len() - 1].1;
+    path.iter()
+        .map(|seg| {
+            // The first pair goes from a point to its control point, and the
+            // rest go from a control point to the point, so both follow the
+            // direction of the curve.
+            let mut dir = seg.1.sub(seg.0);
+            if dir.length() < 0.001 {
+                dir = last.sub(first);
+            }
+            let len = dir.length().max(0.001);
+            let normal = Point::new(-dir.y, dir.x).scale(offset / len);
+            (seg.0.add(normal), seg.1.add(normal))
+        })
+        .collect()
+}
+
+/// \returns the cubic bezier points \p points, in the format of
+/// 'path_to_bezier_points', moved sideways by \p offset. The ends of every
+/// segment move with their control points, like in 'offset_path'.
+fn offset_bezier_points(points: &[Point], offset: f64) -> Vec<Point> {
+    let mut res: Vec<Point> = Vec::new();
+    for seg in points.windows(4).step_by(3) {
+        let path = offset_path(&[(seg[0], seg[1]), (seg[2], seg[3])], offset);
+        // The point between the segments moves with the next segment.
+        res.pop();
+        res.extend([path[0].0, path[0].1, path[1].0, path[1].1]);
+    }
+    res
+}
+
+/// \returns the line through the points \p points moved sideways by
+/// \p offset. Every segment moves perpendicular to its own direction, and the
+/// segments meet at mitered corners.
+fn offset_polyline(points: &[Point], offset: f64) -> Vec<Point> {
+    // Returns the unit normal of the segment from 'a' to 'b', if it has one.
+    let normal = |a: Point, b: Point| {
+        let dir = b.sub(a);
+        let len = dir.length();
+        (len > 0.001).then(|| Point::new(-dir.y / len, dir.x / len))
+    };
+    (0..points.len())
+        .map(|i| {
+            let p = points[i];
+            let before = points[..i].iter().rev().find_map(|q| normal(*q, p));
+            let after = points[i + 1..].iter().find_map(|q| normal(p, *q));
+            let dir = match (before, after) {
+                (Option::Some(a), Option::Some(b)) => {
+                    // The corner of the two moved segments.
+                    let cos = a.x * b.x + a.y * b.y;
+                    if cos < -0.999 {
+                        a
+                    } else {
+                        a.add(b).scale(1. / (1. + cos))
+                    }
+                }
+                (Option::Some(a), Option::None) => a,
+                (Option::None, Option::Some(b)) => b,
+                (Option::None, Option::None) => Point::zero(),
+            };
+            p.add(dir.scale(offset))
+        })
+        .collect()
+}
+
+/// \returns the locations of the tail and the head labels of an edge with the
+/// path \p path, which was generated by 'generate_curve_for_elements'. The
+/// labels are placed near the line, rotated away from the edge.
+pub fn get_end_label_locations(path: &[(Point, Point)]) -> (Point, Point) {
+    let place = |end: Point, ctrl: Point| {
+        let dir = ctrl.sub(end);
+        let len = dir.length().max(1.);
+        let offset = dir
+            .scale(PORT_LABEL_DISTANCE / len)
+            .rotate(PORT_LABEL_ANGLE.to_radians());
+        end.add(offset)
+    };
+    let last = path[path.len() - 1];
+    (place(path[0].0, path[0].1), place(last.1, last.0))
+}
+
+#[test]
+fn test_double_circle_gap() {
+    let small = Point::splat(30.);
+    let large = Point::splat(300.);
+    let small_gap = small.sub(get_double_circle_inner_size(small)).x;
+    let large_gap = large.sub(get_double_circle_inner_size(large)).x;
+    assert_eq!(small_gap, DOUBLE_CIRCLE_MIN_GAP);
+    assert_eq!(large_gap, 30.);
+}
+
+#[test]
+fn test_polygon_skew() {
+    let round = |points: Vec<Point>| -> Vec<(f64, f64)> {
+        let r = |x: f64| (x * 100.).round() / 100.;
+        points.iter().map(|p| (r(p.x), r(p.y))).collect()
+    };
+    let size = Point::splat(100.);
+
+    // A square fills the box. The vertices start at the bottom right.
+    let square = PolygonDef::new(4, 0., 0.);
+    let points = round(get_polygon_vertices(&square, Point::zero(), size));
+    let expected = [(50., 50.), (50., -50.), (-50., -50.), (-50., 50.)];
+    assert_eq!(points, expected);
+
+    // The skew moves the top side right and the bottom side left.
+    let skewed = PolygonDef::new(4, 0.5, 0.);
+    let points = round(get_polygon_vertices(&skewed, Point::zero(), size));
+    let expected = [(16.67, 50.), (50., -50.), (-16.67, -50.), (-50., 50.)];
+    assert_eq!(points, expected);
+
+    // A negative distortion makes the bottom side wider than the top side.
+    let trapezium = PolygonDef::new(4, 0., -0.5);
+    let points = round(get_polygon_vertices(&trapezium, Point::zero(), size));
+    let expected = [(50., 50.), (23.88, -50.), (-23.88, -50.), (-50., 50.)];
+    assert_eq!(points, expected);
+}
+
+#[test]
+fn test_bezier_points_round_trip() {
+    let path = vec![
+        (Point::new(0., 0.), Point::new(0., 10.)),
+        (Point::new(5., 20.), Point::new(10., 30.)),
+        (Point::new(20., 40.), Point::new(30., 40.)),
+    ];
+    let points = path_to_bezier_points(&path);
+    assert_eq!(points.len(), 7);
+    // The reflected control point is restored by the conversion.
+    assert_eq!(points[4], Point::new(15., 40.));
+    assert_eq!(bezier_points_to_path(&points), path);
 }
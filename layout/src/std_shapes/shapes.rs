@@ -4,18 +4,38 @@
 //! This includes things like font size, and color.
 
 use crate::core::base::Orientation;
-use crate::core::format::Visible;
-use crate::core::geometry::{Point, Position};
+use crate::core::color::Color;
+use crate::core::format::{RenderBackend, Visible};
+use crate::core::geometry::{Point, Position, TextSizeCache};
 use crate::core::style::{LineStyleKind, StyleAttr};
-use crate::std_shapes::render::get_shape_size;
+use crate::std_shapes::render::get_shape_size_with_cache;
+use std::fmt;
+use std::sync::Arc;
 
 const PADDING: f64 = 60.;
 const CONN_PADDING: f64 = 10.;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum LineEndKind {
     None,
     Arrow,
+    // An arrow head that is not filled.
+    OpenArrow,
+    Diamond,
+    OpenDiamond,
+    // An open arrow head that is drawn with two lines.
+    Vee,
+    // A circle at the end of the line, and a circle that is not filled.
+    Dot,
+    OpenDot,
+    // A square at the end of the line, and a square that is not filled.
+    Box,
+    OpenBox,
+    // An arrow head that points back at the line, and its open variant.
+    Inv,
+    OpenInv,
+    // A bar across the end of the line.
+    Tee,
 }
 
 #[derive(Debug, Clone)]
@@ -33,6 +53,37 @@ impl RecordDef {
     pub fn new_text_with_port(s: &str, p: &str) -> Self {
         RecordDef::Text(s.to_string(), Some(p.to_string()))
     }
+
+    /// \returns true if one of the fields of the record has the port \p port.
+    pub fn has_port(&self, port: &str) -> bool {
+        match self {
+            RecordDef::Text(_, p) => p.as_deref() == Some(port),
+            RecordDef::Array(arr) => arr.iter().any(|x| x.has_port(port)),
+        }
+    }
+}
+
+/// The parameters of a polygon shape, like the GraphViz polygon attributes.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PolygonDef {
+    /// The number of sides of the polygon (at least 3).
+    pub sides: usize,
+    /// Shifts the top of the polygon to the right, and the bottom to the
+    /// left. Positive values slant the polygon like a parallelogram.
+    pub skew: f64,
+    /// Makes the top of the polygon wider than the bottom, for positive
+    /// values, which turns boxes into trapezoids.
+    pub distortion: f64,
+}
+
+impl PolygonDef {
+    pub fn new(sides: usize, skew: f64, distortion: f64) -> Self {
+        Self {
+            sides: sides.max(3),
+            skew,
+            distortion,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -41,6 +92,9 @@ pub enum ShapeKind {
     Box(String),
     Circle(String),
     DoubleCircle(String),
+    Polygon(String, PolygonDef),
+    // A small filled circle without a label.
+    Point,
     Record(RecordDef),
     Connector(Option<String>),
 }
@@ -55,6 +109,12 @@ impl ShapeKind {
     pub fn new_double_circle(s: &str) -> Self {
         ShapeKind::DoubleCircle(s.to_string())
     }
+    pub fn new_polygon(s: &str, def: PolygonDef) -> Self {
+        ShapeKind::Polygon(s.to_string(), def)
+    }
+    pub fn new_point() -> Self {
+        ShapeKind::Point
+    }
     pub fn new_record(r: &RecordDef) -> Self {
         ShapeKind::Record(r.clone())
     }
@@ -64,6 +124,34 @@ impl ShapeKind {
         }
         ShapeKind::Connector(Some(s.to_string()))
     }
+
+    /// \returns the orientation of the shape in a graph with the orientation
+    /// \p dir. We flip the orientation of shapes, because in graphs that grow
+    /// top down the shapes grow to the left. The fields of records are always
+    /// placed side by side, and every level of '{}' in the label flips the
+    /// direction, so records read the same in all of the graph directions.
+    pub fn orientation_in_graph(&self, dir: Orientation) -> Orientation {
+        match self {
+            ShapeKind::Record(_) => Orientation::LeftToRight,
+            _ => dir.flip(),
+        }
+    }
+}
+
+/// A function that draws a node instead of the standard shape drawing. The
+/// function is called with the backend, the top-left corner of the shape,
+/// the size of the shape, and the style of the element.
+pub type CustomRenderFn =
+    dyn Fn(&mut dyn RenderBackend, Point, Point, &StyleAttr) + Send + Sync;
+
+/// A user-supplied renderer for a node. See 'Element::set_renderer'.
+#[derive(Clone)]
+pub struct CustomRenderer(pub Arc<CustomRenderFn>);
+
+impl fmt::Debug for CustomRenderer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CustomRenderer")
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -73,6 +161,20 @@ pub struct Element {
     pub look: StyleAttr,
     pub orientation: Orientation,
     pub properties: Option<String>,
+    /// Nodes in the same group prefer to be vertically aligned.
+    pub group: Option<String>,
+    /// Draws the element in place of the standard shape, if set.
+    pub renderer: Option<CustomRenderer>,
+    /// A comment that is passed to the output (the 'comment' attribute).
+    pub comment: Option<String>,
+    /// The name of the layer that the element is drawn in, if any.
+    pub layer: Option<String>,
+    /// The center that the node is pinned at, like the GraphViz 'pos'
+    /// attribute with a '!'. The other nodes are laid out around it.
+    pub pinned: Option<Point>,
+    /// The text size cache that the element was resized with, which is used
+    /// again to measure the fields of records when they are drawn.
+    pub(crate) text_cache: Option<TextSizeCache>,
 }
 
 impl Element {
@@ -93,6 +195,12 @@ impl Element {
                 Point::splat(PADDING),
             ),
             properties: Option::None,
+            group: Option::None,
+            renderer: Option::None,
+            comment: Option::None,
+            layer: Option::None,
+            pinned: Option::None,
+            text_cache: Option::None,
         }
     }
 
@@ -123,6 +231,12 @@ impl Element {
                 Point::splat(CONN_PADDING),
             ),
             properties: Option::None,
+            group: Option::None,
+            renderer: Option::None,
+            comment: Option::None,
+            layer: Option::None,
+            pinned: Option::None,
+            text_cache: Option::None,
         }
     }
 
@@ -130,6 +244,19 @@ impl Element {
         Self::create_connector("", &StyleAttr::simple(), dir)
     }
 
+    /// Draw the element with \p renderer instead of the standard shape. The
+    /// size, the position and the edge connection points of the element
+    /// are still computed from its shape.
+    pub fn set_renderer<F>(&mut self, renderer: F)
+    where
+        F: Fn(&mut dyn RenderBackend, Point, Point, &StyleAttr)
+            + Send
+            + Sync
+            + 'static,
+    {
+        self.renderer = Option::Some(CustomRenderer(Arc::new(renderer)));
+    }
+
     // Make the center of the shape point to \p to.
     pub fn move_to(&mut self, to: Point) {
         self.pos.move_to(to)
@@ -146,6 +273,33 @@ pub struct Arrow {
     pub properties: Option<String>,
     pub src_port: Option<String>,
     pub dst_port: Option<String>,
+    // The preferred length of the edge in points (the 'len' attr, which is
+    // measured in inches). The ranking converts it to a number of ranks.
+    pub len: Option<f64>,
+    // The importance of the edge (the 'weight' attr). Heavier edges are
+    // kept straighter.
+    pub weight: f64,
+    // Set if the edge affects the ranking of the nodes. When cleared (the
+    // 'constraint=false' attr), the edge is drawn between the nodes wherever
+    // the ranking places them.
+    pub constraint: bool,
+    // The labels that are drawn next to the head and the tail of the edge.
+    pub head_label: Option<String>,
+    pub tail_label: Option<String>,
+    // The style of the head and tail labels.
+    pub label_look: StyleAttr,
+    // A comment that is passed to the output (the 'comment' attribute).
+    pub comment: Option<String>,
+    // The name of the layer that the edge is drawn in, if any.
+    pub layer: Option<String>,
+    // The points of the curve of the edge, in the format of
+    // 'path_to_bezier_points', if the path of the edge is not computed by
+    // the layout (the 'pos' attribute).
+    pub spline: Option<Vec<Point>>,
+    // The colors of the parallel strokes that draw the edge (a color list,
+    // such as 'red:blue'). Empty if the edge is a single stroke in the line
+    // color of the style.
+    pub colors: Vec<Color>,
 }
 
 impl Default for Arrow {
@@ -159,21 +313,55 @@ impl Default for Arrow {
             properties: Option::None,
             src_port: Option::None,
             dst_port: Option::None,
+            len: Option::None,
+            weight: 1.,
+            constraint: true,
+            head_label: Option::None,
+            tail_label: Option::None,
+            label_look: StyleAttr::simple(),
+            comment: Option::None,
+            layer: Option::None,
+            spline: Option::None,
+            colors: Vec::new(),
         }
     }
 }
 
 impl Arrow {
     pub fn reverse(&self) -> Arrow {
+        // Gradients go from the tail of the edge to the head.
+        let mut look = self.look.clone();
+        if let Option::Some(color) = look.gradient_color {
+            look.gradient_color = Option::Some(look.line_color);
+            look.line_color = color;
+        }
+        let spline = self.spline.as_ref().map(|points| {
+            let mut points = points.clone();
+            points.reverse();
+            points
+        });
+        // Keep every color on the same side of the reversed path.
+        let mut colors = self.colors.clone();
+        colors.reverse();
         Arrow {
             start: self.end,
             end: self.start,
             line_style: self.line_style,
             text: self.text.clone(),
-            look: self.look.clone(),
+            look,
             properties: self.properties.clone(),
             src_port: self.dst_port.clone(),
             dst_port: self.src_port.clone(),
+            len: self.len,
+            weight: self.weight,
+            constraint: self.constraint,
+            head_label: self.tail_label.clone(),
+            tail_label: self.head_label.clone(),
+            label_look: self.label_look.clone(),
+            comment: self.comment.clone(),
+            layer: self.layer.clone(),
+            spline,
+            colors,
         }
     }
 
@@ -195,9 +383,20 @@ impl Arrow {
             properties: Option::None,
             src_port: src_port.clone(),
             dst_port: dst_port.clone(),
+            len: Option::None,
+            weight: 1.,
+            constraint: true,
+            head_label: Option::None,
+            tail_label: Option::None,
+            label_look: look.clone(),
+            comment: Option::None,
+            layer: Option::None,
+            spline: Option::None,
+            colors: Vec::new(),
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn with_properties(
         start: LineEndKind,
         end: LineEndKind,
@@ -217,6 +416,16 @@ impl Arrow {
             properties: Option::Some(properties.into()),
             src_port: src_port.clone(),
             dst_port: dst_port.clone(),
+            len: Option::None,
+            weight: 1.,
+            constraint: true,
+            head_label: Option::None,
+            tail_label: Option::None,
+            label_look: look.clone(),
+            comment: Option::None,
+            layer: Option::None,
+            spline: Option::None,
+            colors: Vec::new(),
         }
     }
 
@@ -272,12 +481,22 @@ impl Visible for Element {
     }
 
     fn resize(&mut self) {
+        self.resize_with_cache(None);
+    }
+}
+
+impl Element {
+    /// Resize the element, like 'resize', and use the text size cache
+    /// \p cache if one is provided. The element keeps the cache for drawing.
+    pub fn resize_with_cache(&mut self, cache: Option<&TextSizeCache>) {
+        self.text_cache = cache.cloned();
         if let ShapeKind::Connector(_) = self.shape.clone() {
-            let size = get_shape_size(
+            let size = get_shape_size_with_cache(
                 self.orientation,
                 &self.shape,
                 self.look.font_size,
                 false,
+                cache,
             );
             self.pos.set_size(size);
             match self.orientation {
@@ -8,30 +8,82 @@
 extern crate log;
 
 use crate::adt::dag::*;
-use crate::core::base::Orientation;
+use crate::core::base::{Anchor, EdgeRouting, Orientation};
+use crate::core::color::Color;
 use crate::core::format::RenderBackend;
 use crate::core::format::Renderable;
 use crate::core::format::Visible;
-use crate::core::geometry::Position;
+use crate::core::geometry::{
+    do_boxes_intersect, get_size_for_str, Point, Position, TextSizeCache,
+};
+use crate::core::style::StyleAttr;
 use crate::std_shapes::render::*;
 use crate::std_shapes::shapes::*;
+use crate::topo::optimizer::count_crossed_edges;
 use crate::topo::optimizer::EdgeCrossOptimizer;
 use crate::topo::optimizer::RankOptimizer;
+use crate::topo::timings::{PhaseTimings, Timer};
+use std::collections::HashMap;
+use std::hash::Hash;
 use std::mem::swap;
 use std::vec;
 
 use super::placer::Placer;
 
+/// A summary of the size and the quality of the layout of a graph. See
+/// 'VisualGraph::stats'.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GraphStats {
+    // The number of nodes, without the connectors.
+    pub num_nodes: usize,
+    pub num_edges: usize,
+    pub num_ranks: usize,
+    // The number of nodes and connectors in the widest rank.
+    pub max_rank_width: usize,
+    // The number of connector nodes that the lowering of edges created.
+    pub num_connectors: usize,
+    // The number of pairs of edges that cross between adjacent ranks.
+    pub num_crossings: usize,
+    // The sum of the lengths of all of the edges. See 'edge_lengths'.
+    pub total_edge_length: f64,
+    // The top-left and the bottom-right corners of the drawing.
+    pub bounds: (Point, Point),
+}
+
+/// A group of nodes that is drawn inside of a box with a label, like the
+/// GraphViz clusters. The layout keeps the nodes of the cluster next to each
+/// other in every rank. See 'VisualGraph::add_cluster'.
+#[derive(Debug, Clone)]
+pub struct Cluster {
+    pub nodes: Vec<NodeHandle>,
+    pub label: String,
+    // The style of the box. The fill color is the background of the cluster.
+    pub look: StyleAttr,
+    // The direction of the edges inside of the cluster (the orientation, and
+    // whether it is reversed), if it overrides the direction of the graph.
+    pub rankdir: Option<(Orientation, bool)>,
+}
+
+/// The space between the nodes of a cluster and its box.
+pub(crate) const CLUSTER_MARGIN: f64 = 10.;
+
 #[derive(Debug)]
 pub struct VisualGraph {
     // Holds all of the elements in the graph.
     nodes: Vec<Element>,
     // The arrows and the list of elements that they visits.
     edges: Vec<(Arrow, Vec<NodeHandle>)>,
-    // Contains a list of self-edges. We use this as a temporary storage during
-    // lowering. This list should be removes by the time we start the layout
-    // process.
-    self_edges: Vec<(Arrow, NodeHandle)>,
+    // For each entry in 'edges', the index of the edge in the order in which
+    // the edges were added, and true if lowering reversed the edge.
+    edge_origins: Vec<(usize, bool)>,
+    // Contains a list of self-edges, and their original index. We use this
+    // as a temporary storage during lowering. This list should be removes by
+    // the time we start the layout process.
+    self_edges: Vec<(Arrow, NodeHandle, usize)>,
+    // Contains a list of the edges that don't constrain the ranking, their
+    // ends and their original index. Like the self edges, they are kept
+    // out of the dag during the lowering.
+    free_edges: Vec<(Arrow, NodeHandle, NodeHandle, usize)>,
     // Representing the connections between the nodes. Used to keep the graph
     // a dag by detecting reverse edges. Used to create 'levels', and decide
     // which node moves/controls which node. After lowering, the graph should
@@ -39,6 +91,52 @@ pub struct VisualGraph {
     pub dag: DAG,
     // Sets the graph orientation (L-to-R, or T-to-B).
     orientation: Orientation,
+    // True if the edges of the graph are directed (a 'digraph').
+    directed: bool,
+    // Place the ranks in reverse order (B-to-T, or R-to-L).
+    reversed: bool,
+    // An optional cache of text sizes that is shared across layouts.
+    text_cache: Option<TextSizeCache>,
+    // Order the edge attachment points by the position of the edge targets.
+    sort_edge_attachments: bool,
+    // The requested ratio between the height and the width of the drawing.
+    ratio: Option<f64>,
+    // Draw the edges before the nodes, so that nodes cover the edges.
+    edges_first: bool,
+    // Elements that don't participate in the layout, such as legends, and the
+    // corner of the drawing that they are placed at.
+    floating: Vec<(Element, Anchor)>,
+    // The color of the background bands that are drawn behind every other
+    // rank, if any.
+    rank_bands: Option<Color>,
+    // The way that the edges are drawn.
+    edge_routing: EdgeRouting,
+    // Scales the length of the tangents of the edge curves. Low values make
+    // the curves hug their points, and high values make them bow.
+    edge_tension: f64,
+    // A comment that is passed to the output (the 'comment' attribute).
+    comment: Option<String>,
+    // The space around the labels of edges, if it's not the default.
+    connector_padding: Option<f64>,
+    // The extra space between adjacent nodes in the same rank.
+    node_sep: f64,
+    // The extra space between adjacent ranks.
+    rank_sep: f64,
+    // The minimal clearance between the edges and the nodes that they pass.
+    edge_sep: f64,
+    // The minimal distance between the centers of adjacent connectors.
+    connector_sep: f64,
+    // Maps the names of the nodes (such as the names in the DOT file) to the
+    // nodes, and the nodes back to their names.
+    name_to_node: HashMap<String, NodeHandle>,
+    node_to_name: HashMap<NodeHandle, String>,
+    // The positions of the centers of nodes that the user placed.
+    fixed_positions: HashMap<NodeHandle, Point>,
+    // The groups of nodes that are drawn inside of boxes.
+    clusters: Vec<Cluster>,
+    // The time that the phases of the layout took (with the 'timings'
+    // feature).
+    timings: PhaseTimings,
 }
 
 impl VisualGraph {
@@ -46,16 +144,700 @@ impl VisualGraph {
         VisualGraph {
             nodes: Vec::new(),
             edges: Vec::new(),
+            edge_origins: Vec::new(),
             self_edges: Vec::new(),
+            free_edges: Vec::new(),
             dag: DAG::new(),
             orientation,
+            directed: true,
+            reversed: false,
+            text_cache: Option::None,
+            sort_edge_attachments: false,
+            ratio: Option::None,
+            edges_first: false,
+            floating: Vec::new(),
+            rank_bands: Option::None,
+            edge_routing: EdgeRouting::Spline,
+            edge_tension: 1.,
+            comment: Option::None,
+            connector_padding: Option::None,
+            node_sep: 0.,
+            rank_sep: 0.,
+            edge_sep: 0.,
+            connector_sep: 0.,
+            name_to_node: HashMap::new(),
+            node_to_name: HashMap::new(),
+            fixed_positions: HashMap::new(),
+            clusters: Vec::new(),
+            timings: PhaseTimings::new(),
+        }
+    }
+
+    /// Remove all of the nodes, the edges and the floating elements, to reuse
+    /// the graph for another drawing. The options of the graph, such as the
+    /// orientation and the text cache, are kept.
+    pub fn clear(&mut self) {
+        self.nodes.clear();
+        self.edges.clear();
+        self.edge_origins.clear();
+        self.self_edges.clear();
+        self.free_edges.clear();
+        self.floating.clear();
+        self.dag.clear();
+        self.name_to_node.clear();
+        self.node_to_name.clear();
+        self.fixed_positions.clear();
+        self.clusters.clear();
+        self.timings.clear();
+    }
+
+    /// \returns the time that the phases of the layout and the rendering
+    /// took. The phases are "lower", "rank", "cross", "place" and "render".
+    /// The timings are only recorded with the 'timings' feature.
+    pub fn phase_timings(&self) -> &PhaseTimings {
+        &self.timings
+    }
+
+    /// Give the node \p node the name \p name, which can be used to find the
+    /// node after the layout. See 'handle_for_name' and 'name_of'. A node
+    /// that had the name before loses it.
+    pub fn set_node_name(&mut self, node: NodeHandle, name: &str) {
+        assert!(node.get_index() < self.nodes.len(), "Invalid handle");
+        if let Option::Some(old) = self.node_to_name.remove(&node) {
+            self.name_to_node.remove(&old);
+        }
+        if let Option::Some(prev) =
+            self.name_to_node.insert(name.to_string(), node)
+        {
+            self.node_to_name.remove(&prev);
+        }
+        self.node_to_name.insert(node, name.to_string());
+    }
+
+    /// \returns the node with the name \p name, if any.
+    pub fn handle_for_name(&self, name: &str) -> Option<NodeHandle> {
+        self.name_to_node.get(name).copied()
+    }
+
+    /// \returns the name of the node \p node, if it has one.
+    pub fn name_of(&self, node: NodeHandle) -> Option<&str> {
+        self.node_to_name.get(&node).map(|x| x.as_str())
+    }
+
+    /// Add an element that does not participate in the layout, such as a
+    /// legend. The element is placed next to the corner \p anchor of the
+    /// drawing after the layout, and is not connected to any edges.
+    pub fn add_floating(&mut self, elem: Element, anchor: Anchor) {
+        self.floating.push((elem, anchor));
+    }
+
+    /// \returns the elements that were added with 'add_floating'.
+    pub fn floating_elements(&self) -> &[(Element, Anchor)] {
+        &self.floating
+    }
+
+    /// Fill the nodes with colors from the built-in palette. The function
+    /// \p category returns the category of each node, and nodes in the same
+    /// category get the same color. Connectors are not colored.
+    pub fn assign_palette_by<K: Eq + Hash, F: FnMut(&Element) -> K>(
+        &mut self,
+        mut category: F,
+    ) {
+        let mut colors: HashMap<K, Color> = HashMap::new();
+        for elem in self.nodes.iter_mut() {
+            if elem.is_connector() {
+                continue;
+            }
+            let next = colors.len();
+            let color = *colors
+                .entry(category(elem))
+                .or_insert_with(|| Color::from_palette(next));
+            elem.look.fill_color = Option::Some(color);
         }
     }
 
+    /// Color the edges with colors from the built-in palette. The function
+    /// \p category returns the category of each edge, and edges in the same
+    /// category get the same color.
+    pub fn assign_edge_palette_by<K: Eq + Hash, F: FnMut(&Arrow) -> K>(
+        &mut self,
+        mut category: F,
+    ) {
+        let mut colors: HashMap<K, Color> = HashMap::new();
+        for edge in self.edges.iter_mut() {
+            let next = colors.len();
+            let color = *colors
+                .entry(category(&edge.0))
+                .or_insert_with(|| Color::from_palette(next));
+            edge.0.look.line_color = color;
+        }
+    }
+
+    /// Draw the nodes \p nodes and the edges \p edges with the line color, the
+    /// line width and the fill color (if any) of \p style, for example to
+    /// show a path through the graph. The edges are numbered in the order in
+    /// which they were added. The other attributes, such as the font, don't
+    /// change. This can be called before or after the layout.
+    pub fn highlight(
+        &mut self,
+        nodes: &[NodeHandle],
+        edges: &[usize],
+        style: StyleAttr,
+    ) {
+        for node in nodes {
+            let look = &mut self.element_mut(*node).look;
+            look.line_color = style.line_color;
+            look.line_width = style.line_width;
+            if style.fill_color.is_some() {
+                look.fill_color = style.fill_color;
+            }
+        }
+        for (edge, (idx, _)) in self.edges.iter_mut().zip(&self.edge_origins) {
+            if edges.contains(idx) {
+                edge.0.look.line_color = style.line_color;
+                edge.0.look.line_width = style.line_width;
+                edge.0.look.gradient_color = Option::None;
+            }
+        }
+    }
+
+    /// When \p enable is set the edges are drawn before the nodes, so edges
+    /// that cross translucent nodes show through their fill (the GraphViz
+    /// 'outputorder=edgesfirst' attribute).
+    pub fn set_edges_first(&mut self, enable: bool) {
+        self.edges_first = enable;
+    }
+
+    /// Draw a background band with the color \p color behind every other
+    /// rank, which makes wide graphs easier to read. None disables the bands.
+    pub fn set_rank_bands(&mut self, color: Option<Color>) {
+        self.rank_bands = color;
+    }
+
+    /// \returns the rectangle (top-left, bottom-right) that each rank of the
+    /// laid out graph occupies. The rectangles span the whole drawing, and
+    /// the gaps between the ranks are split between neighboring ranks.
+    pub fn rank_bands(&self) -> Vec<(Point, Point)> {
+        let lr = self.orientation.is_left_right();
+        // Work in the coordinates of a top-to-bottom graph.
+        let flip = |p: Point| if lr { p.transpose() } else { p };
+        let bb = self.bounding_box();
+        let (start, end) = (flip(bb.0), flip(bb.1));
+
+        // Find the extent of each rank along the rank axis. Empty ranks, that
+        // are left above pinned nodes, have no height.
+        let mut extents: Vec<(f64, f64)> = Vec::new();
+        for level in 0..self.dag.num_levels() {
+            let prev = extents.last().map_or(start.y, |e| e.1);
+            let mut extent = (f64::INFINITY, f64::NEG_INFINITY);
+            for node in self.dag.row(level) {
+                let bb = self.pos(*node).bbox(false);
+                extent.0 = extent.0.min(flip(bb.0).y);
+                extent.1 = extent.1.max(flip(bb.1).y);
+            }
+            if self.dag.row(level).is_empty() {
+                extent = (prev, prev);
+            }
+            extents.push(extent);
+        }
+
+        let mut bands = Vec::new();
+        for (i, extent) in extents.iter().enumerate() {
+            let mut top = extent.0;
+            let mut bottom = extent.1;
+            if i > 0 {
+                top = (extents[i - 1].1 + top) / 2.;
+            }
+            if i + 1 < extents.len() {
+                bottom = (bottom + extents[i + 1].0) / 2.;
+            }
+            let top_left = flip(Point::new(start.x, top));
+            let bottom_right = flip(Point::new(end.x, bottom));
+            bands.push((top_left, bottom_right));
+        }
+        bands
+    }
+
+    /// Draw the nodes \p nodes inside of a box with the label \p label and
+    /// the style \p look, and keep the nodes next to each other in every
+    /// rank. Clusters may contain other clusters, and the box of a cluster
+    /// surrounds the boxes of the clusters inside of it. The nodes are only
+    /// grouped within each rank, so nodes that are not in the cluster can
+    /// still be placed inside of the box, between the ranks of the cluster.
+    /// \returns the index of the cluster.
+    pub fn add_cluster(
+        &mut self,
+        nodes: &[NodeHandle],
+        label: &str,
+        look: StyleAttr,
+    ) -> usize {
+        self.clusters.push(Cluster {
+            nodes: nodes.to_vec(),
+            label: label.to_string(),
+            look,
+            rankdir: Option::None,
+        });
+        self.clusters.len() - 1
+    }
+
+    /// Lay out the edges between the nodes of the cluster \p cluster in the
+    /// direction \p orientation, which is reversed if \p reversed is set,
+    /// like a cluster with its own GraphViz 'rankdir' attribute. This is only
+    /// supported for directions across the ranks of the graph: the nodes of
+    /// the cluster are placed in one rank, in the order of the edges between
+    /// them, and the edges between them don't affect the ranking. A direction
+    /// with the orientation of the graph is ignored.
+    pub fn set_cluster_rankdir(
+        &mut self,
+        cluster: usize,
+        orientation: Orientation,
+        reversed: bool,
+    ) {
+        self.clusters[cluster].rankdir = Option::Some((orientation, reversed));
+    }
+
+    pub fn clusters(&self) -> &[Cluster] {
+        &self.clusters
+    }
+
+    /// \returns the indices of the clusters, where the clusters that may be
+    /// nested in other clusters come before them: the small clusters, and
+    /// the clusters that were added last.
+    fn clusters_inside_out(&self) -> Vec<usize> {
+        let mut order: Vec<usize> = (0..self.clusters.len()).collect();
+        order.sort_by_key(|i| {
+            (self.clusters[*i].nodes.len(), std::cmp::Reverse(*i))
+        });
+        order
+    }
+
+    /// \returns the rectangle (top-left, bottom-right) of the box of each
+    /// cluster, in the order of 'clusters'. The boxes surround the nodes of
+    /// the cluster, the connectors of the edges inside of the cluster, the
+    /// boxes of the nested clusters and the label at the top of the box.
+    pub fn cluster_boxes(&self) -> Vec<(Point, Point)> {
+        let mut boxes =
+            vec![(Point::zero(), Point::zero()); self.clusters.len()];
+        let order = self.clusters_inside_out();
+        for (i, idx) in order.iter().enumerate() {
+            let cluster = &self.clusters[*idx];
+            if cluster.nodes.is_empty() {
+                continue;
+            }
+            let mut top_left = Point::splat(f64::INFINITY);
+            let mut bottom_right = Point::splat(f64::NEG_INFINITY);
+            let mut add = |bb: (Point, Point)| {
+                top_left =
+                    Point::new(top_left.x.min(bb.0.x), top_left.y.min(bb.0.y));
+                bottom_right = Point::new(
+                    bottom_right.x.max(bb.1.x),
+                    bottom_right.y.max(bb.1.y),
+                );
+            };
+            for node in &cluster.nodes {
+                add(self.pos(*node).bbox(false));
+            }
+            for inner in &order[..i] {
+                let nodes = &self.clusters[*inner].nodes;
+                if !nodes.is_empty()
+                    && nodes.iter().all(|n| cluster.nodes.contains(n))
+                {
+                    add(boxes[*inner]);
+                }
+            }
+            let margin = Point::splat(CLUSTER_MARGIN);
+            let mut top_left = top_left.sub(margin);
+            let mut bottom_right = bottom_right.add(margin);
+            // Make room for the label at the top of the box.
+            if !cluster.label.is_empty() {
+                let size =
+                    get_size_for_str(&cluster.label, cluster.look.font_size);
+                top_left.y -= size.y;
+                let width = bottom_right.x - top_left.x;
+                let missing = size.x + CLUSTER_MARGIN * 2. - width;
+                if missing > 0. {
+                    top_left.x -= missing / 2.;
+                    bottom_right.x += missing / 2.;
+                }
+            }
+            boxes[*idx] = (top_left, bottom_right);
+        }
+        boxes
+    }
+
+    /// Request that the drawing is stretched until the ratio between its
+    /// height and its width is \p ratio (the GraphViz 'ratio' attribute).
+    /// The layout is only stretched, and never compressed. The keywords of
+    /// the attribute, such as 'fill' and 'compress', fit the drawing to its
+    /// 'size', which is not supported.
+    pub fn set_ratio(&mut self, ratio: f64) {
+        assert!(ratio.is_finite() && ratio > 0., "Invalid ratio");
+        self.ratio = Option::Some(ratio);
+    }
+
+    /// Set the comment that the drawing of the graph starts with.
+    pub fn set_comment(&mut self, comment: &str) {
+        self.comment = Option::Some(comment.to_string());
+    }
+
+    pub fn ratio(&self) -> Option<f64> {
+        self.ratio
+    }
+
+    /// \returns the bounding box of all of the nodes in the graph.
+    pub fn bounding_box(&self) -> (Point, Point) {
+        let mut top_left = Point::splat(f64::INFINITY);
+        let mut bottom_right = Point::splat(f64::NEG_INFINITY);
+        for node in self.iter_nodes() {
+            let bb = self.pos(node).bbox(false);
+            top_left =
+                Point::new(top_left.x.min(bb.0.x), top_left.y.min(bb.0.y));
+            bottom_right = Point::new(
+                bottom_right.x.max(bb.1.x),
+                bottom_right.y.max(bb.1.y),
+            );
+        }
+        (top_left, bottom_right)
+    }
+
+    /// \returns the bounding box of the whole drawing: the nodes with their
+    /// halos, the floating elements, and the points and the control points
+    /// of the edges. This needs to be called after the layout. The box of an
+    /// empty drawing is empty, at the origin.
+    pub fn drawing_bounding_box(&self) -> (Point, Point) {
+        let mut boxes: Vec<(Point, Point)> = Vec::new();
+        for node in self.iter_nodes() {
+            boxes.push(self.pos(node).bbox(true));
+        }
+        for (elem, _) in &self.floating {
+            boxes.push(elem.position().bbox(true));
+        }
+        boxes.extend(self.cluster_boxes());
+        for path in self.edge_paths() {
+            for (a, b) in path {
+                boxes.push((a, a));
+                boxes.push((b, b));
+            }
+        }
+        if boxes.is_empty() {
+            return (Point::zero(), Point::zero());
+        }
+        let mut top_left = Point::splat(f64::INFINITY);
+        let mut bottom_right = Point::splat(f64::NEG_INFINITY);
+        for bb in boxes {
+            top_left =
+                Point::new(top_left.x.min(bb.0.x), top_left.y.min(bb.0.y));
+            bottom_right = Point::new(
+                bottom_right.x.max(bb.1.x),
+                bottom_right.y.max(bb.1.y),
+            );
+        }
+        (top_left, bottom_right)
+    }
+
+    /// \returns an iterator over the nodes and their positions, for reading
+    /// the result of the layout. The connectors of the edges are included.
+    pub fn node_positions(
+        &self,
+    ) -> impl Iterator<Item = (NodeHandle, Position)> + '_ {
+        self.iter_nodes().map(move |node| (node, self.pos(node)))
+    }
+
+    /// \returns the pairs of nodes whose bounding boxes intersect. This is
+    /// meant to be used after the layout of the graph to detect defects.
+    /// Connectors without a label are ignored.
+    pub fn overlapping_nodes(&self) -> Vec<(NodeHandle, NodeHandle)> {
+        let mut boxes: Vec<(NodeHandle, (Point, Point))> = Vec::new();
+        for node in self.iter_nodes() {
+            if let ShapeKind::Connector(None) = self.element(node).shape {
+                continue;
+            }
+            boxes.push((node, self.pos(node).bbox(false)));
+        }
+
+        // Sort the boxes by their left side, and only compare boxes that
+        // overlap on the x axis.
+        boxes.sort_by(|a, b| a.1 .0.x.partial_cmp(&b.1 .0.x).unwrap());
+        let mut res = Vec::new();
+        for i in 0..boxes.len() {
+            for j in i + 1..boxes.len() {
+                if boxes[j].1 .0.x >= boxes[i].1 .1.x {
+                    break;
+                }
+                if do_boxes_intersect(boxes[i].1, boxes[j].1) {
+                    res.push((boxes[i].0, boxes[j].0));
+                }
+            }
+        }
+        res
+    }
+
+    /// \returns a summary of the graph, such as the number of ranks and the
+    /// number of edge crossings. This is meant to be used after the layout of
+    /// the graph, for logging and for checking the quality of the layout.
+    pub fn stats(&self) -> GraphStats {
+        let num_connectors =
+            self.iter_nodes().filter(|n| self.is_connector(*n)).count();
+        let max_rank_width = self.dag.ranks().iter().map(|r| r.len()).max();
+        GraphStats {
+            num_nodes: self.num_nodes() - num_connectors,
+            num_edges: self.edges.len()
+                + self.self_edges.len()
+                + self.free_edges.len(),
+            num_ranks: self.dag.num_levels(),
+            max_rank_width: max_rank_width.unwrap_or(0),
+            num_connectors,
+            num_crossings: count_crossed_edges(&self.dag),
+            total_edge_length: self.edge_lengths().iter().sum(),
+            bounds: self.bounding_box(),
+        }
+    }
+
+    /// \returns the length of each edge, in the order in which the edges were
+    /// added. The length of an edge is the length of the control polygon of
+    /// its curve, which is the sum of the distances between the consecutive
+    /// points of the curve. This is meant to be used after the layout of the
+    /// graph, together with 'stats', to compare the quality of layouts.
+    pub fn edge_lengths(&self) -> Vec<f64> {
+        let mut lengths = vec![0.; self.edges.len()];
+        for ((arrow, lst), (idx, _)) in
+            self.edges.iter().zip(self.edge_origins.iter())
+        {
+            let elements: Vec<Element> =
+                lst.iter().map(|h| self.element(*h).clone()).collect();
+            let path = generate_curve_for_elements(
+                &elements,
+                arrow,
+                self.arrow_force(),
+            );
+            let points = path_to_bezier_points(&path);
+            lengths[*idx] =
+                points.windows(2).map(|w| w[0].distance_to(w[1])).sum();
+        }
+        lengths
+    }
+
+    /// Move the centers of all of the nodes away from the top-left corner of
+    /// the drawing. The distances are scaled by \p sx and \p sy. The size of
+    /// the nodes does not change.
+    pub fn stretch(&mut self, sx: f64, sy: f64) {
+        let origin = self.bounding_box().0;
+        for node in self.dag.iter() {
+            let offset = self.pos(node).center().sub(origin);
+            let center = origin.add(Point::new(offset.x * sx, offset.y * sy));
+            self.pos_mut(node).move_to(center);
+        }
+    }
+
+    /// Stretch the drawing along one of the axis until the ratio between the
+    /// height and the width of the drawing is \p ratio.
+    pub fn apply_ratio(&mut self, ratio: f64) {
+        if self.num_nodes() == 0 {
+            return;
+        }
+        let bb = self.bounding_box();
+        let size = bb.1.sub(bb.0);
+
+        // The span of the node centers, which is the part that can stretch.
+        let mut lo = Point::splat(f64::INFINITY);
+        let mut hi = Point::splat(f64::NEG_INFINITY);
+        for node in self.iter_nodes() {
+            let c = self.pos(node).center();
+            lo = Point::new(lo.x.min(c.x), lo.y.min(c.y));
+            hi = Point::new(hi.x.max(c.x), hi.y.max(c.y));
+        }
+        let span = hi.sub(lo);
+
+        if size.y / size.x < ratio {
+            // Stretch the height: size.y + span.y * (sy - 1) = ratio * size.x.
+            if span.y > 0. {
+                let sy = 1. + (ratio * size.x - size.y) / span.y;
+                self.stretch(1., sy);
+            }
+        } else if span.x > 0. {
+            // Stretch the width: size.x + span.x * (sx - 1) = size.y / ratio.
+            let sx = 1. + (size.y / ratio - size.x) / span.x;
+            self.stretch(sx, 1.);
+        }
+    }
+
+    /// When \p enable is set, the edges that connect to the same side of a
+    /// node are attached in the order of the position of their targets. This
+    /// reduces the tangling of edges near nodes with many edges.
+    pub fn set_sort_edge_attachments(&mut self, enable: bool) {
+        self.sort_edge_attachments = enable;
+    }
+
+    /// Place the node \p node at the rank \p level, and rank the other nodes
+    /// around it. Edges between two pinned nodes that point up the ranks are
+    /// reversed. Pinned nodes are not moved to stretch edges to their
+    /// preferred length (the 'len' attribute). If the chain of predecessors
+    /// of the node is longer than \p level then the node is placed at the
+    /// closest rank below \p level.
+    pub fn set_node_rank(&mut self, node: NodeHandle, level: usize) {
+        self.dag.pin_level(node, level);
+    }
+
+    /// Place the nodes \p nodes in the same rank, like the nodes of a
+    /// subgraph with the GraphViz 'rank=same' attribute. The nodes are moved
+    /// down to the lowest rank that any of them requires. The constraint is
+    /// ignored if there is a path between two of the nodes.
+    pub fn set_same_rank(&mut self, nodes: &[NodeHandle]) {
+        self.dag.add_same_level(nodes);
+    }
+
+    /// Place the center of the node \p node at \p center. If all of the nodes
+    /// in the graph have positions then the automatic placement is skipped,
+    /// and the nodes are placed at their positions, like the GraphViz 'pos'
+    /// attribute. Otherwise, the positions are ignored.
+    pub fn set_node_position(&mut self, node: NodeHandle, center: Point) {
+        assert!(node.get_index() < self.nodes.len(), "Invalid handle");
+        self.fixed_positions.insert(node, center);
+    }
+
+    /// \returns true if all of the nodes in the graph have positions. See
+    /// 'set_node_position'.
+    pub fn has_fixed_positions(&self) -> bool {
+        !self.nodes.is_empty()
+            && self
+                .iter_nodes()
+                .all(|n| self.fixed_positions.contains_key(&n))
+    }
+
+    /// Move the nodes to the positions that the user selected, and place the
+    /// connectors of each edge on the line between the ends of the edge. The
+    /// connectors of self edges move with their node.
+    fn apply_fixed_positions(&mut self) {
+        let mut moves: Vec<(NodeHandle, Point)> = Vec::new();
+        for (_, lst) in &self.edges {
+            let first = lst[0];
+            let last = lst[lst.len() - 1];
+            let from = self.fixed_positions[&first];
+            let to = self.fixed_positions[&last];
+            let inner = &lst[1..lst.len() - 1];
+            for (i, conn) in inner.iter().enumerate() {
+                let center = if first == last {
+                    let delta = from.sub(self.pos(first).center());
+                    self.pos(*conn).center().add(delta)
+                } else {
+                    let t = (i + 1) as f64 / (lst.len() - 1) as f64;
+                    from.scale(1. - t).add(to.scale(t))
+                };
+                moves.push((*conn, center));
+            }
+        }
+        for (node, center) in &self.fixed_positions {
+            moves.push((*node, *center));
+        }
+        for (node, center) in moves {
+            self.pos_mut(node).move_to(center);
+        }
+    }
+
+    /// Reserve \p padding around the labels of edges, which sets the distance
+    /// between the labels and the shapes around them. Larger padding pushes
+    /// the ranks apart. This needs to be called before the layout.
+    pub fn set_connector_padding(&mut self, padding: f64) {
+        assert!(padding >= 0., "Invalid padding");
+        self.connector_padding = Option::Some(padding);
+    }
+
+    /// Add \p sep to the space between adjacent nodes in the same rank (the
+    /// GraphViz 'nodesep' attribute). The space is added to the padding
+    /// around the shapes.
+    pub fn set_node_sep(&mut self, sep: f64) {
+        assert!(sep >= 0., "Invalid node separation");
+        self.node_sep = sep;
+    }
+
+    pub fn node_sep(&self) -> f64 {
+        self.node_sep
+    }
+
+    /// Add \p sep to the space between adjacent ranks (the GraphViz 'ranksep'
+    /// attribute). The space is added to the padding around the shapes.
+    pub fn set_rank_sep(&mut self, sep: f64) {
+        assert!(sep >= 0., "Invalid rank separation");
+        self.rank_sep = sep;
+    }
+
+    pub fn rank_sep(&self) -> f64 {
+        self.rank_sep
+    }
+
+    /// Keep the edges at least \p sep away from the boxes of the nodes that
+    /// they pass by (the GraphViz 'esep' attribute).
+    pub fn set_edge_sep(&mut self, sep: f64) {
+        assert!(sep >= 0., "Invalid edge separation");
+        self.edge_sep = sep;
+    }
+
+    pub fn edge_sep(&self) -> f64 {
+        self.edge_sep
+    }
+
+    /// Keep the centers of adjacent connectors in the same rank at least
+    /// \p sep apart, to separate the long edges that pass between the same
+    /// ranks. Unlike 'set_node_sep', this only affects the edges.
+    pub fn set_connector_sep(&mut self, sep: f64) {
+        assert!(sep >= 0., "Invalid connector separation");
+        self.connector_sep = sep;
+    }
+
+    pub fn connector_sep(&self) -> f64 {
+        self.connector_sep
+    }
+
+    /// Draw the edges in the style \p routing. The default is splines.
+    pub fn set_edge_routing(&mut self, routing: EdgeRouting) {
+        self.edge_routing = routing;
+    }
+
+    /// Set the tension of the edge curves to \p tension. The tangents of the
+    /// curves at the points that they pass are scaled by the tension, so
+    /// values below 1 make straighter edges, and values above 1 make curvier
+    /// edges. The default is 1.
+    pub fn set_edge_tension(&mut self, tension: f64) {
+        assert!(tension > 0., "Invalid tension");
+        self.edge_tension = tension;
+    }
+
+    /// \returns the length of the tangents of the edge curves.
+    pub(crate) fn arrow_force(&self) -> f64 {
+        ARROW_FORCE * self.edge_tension
+    }
+
+    /// Use the text size cache \p cache when measuring text during layout.
+    /// The cache can be shared by many graphs.
+    pub fn set_text_cache(&mut self, cache: TextSizeCache) {
+        self.text_cache = Option::Some(cache);
+    }
+
     pub fn orientation(&self) -> Orientation {
         self.orientation
     }
 
+    /// Place the ranks in the reverse order if \p reversed is set, which
+    /// draws the graph from the bottom to the top, or from the right to the
+    /// left (the GraphViz 'rankdir' values BT and RL).
+    pub fn set_reversed(&mut self, reversed: bool) {
+        self.reversed = reversed;
+    }
+
+    pub fn is_reversed(&self) -> bool {
+        self.reversed
+    }
+
+    /// Mark the edges of the graph as directed (a 'digraph') or undirected
+    /// (a 'graph'). This does not change the arrow heads of the edges.
+    pub fn set_directed(&mut self, directed: bool) {
+        self.directed = directed;
+    }
+
+    pub fn is_directed(&self) -> bool {
+        self.directed
+    }
+
     pub fn num_nodes(&self) -> usize {
         self.dag.len()
     }
@@ -81,7 +863,7 @@ impl VisualGraph {
     }
 
     pub fn is_connector(&self, n: NodeHandle) -> bool {
-        return self.element(n).is_connector();
+        self.element(n).is_connector()
     }
 
     pub fn transpose(&mut self) {
@@ -98,6 +880,45 @@ impl VisualGraph {
         &mut self.nodes[node.get_index()]
     }
 
+    /// \returns the edges of the graph, and the list of nodes that each edge
+    /// visits.
+    pub fn edge_list(&self) -> &[(Arrow, Vec<NodeHandle>)] {
+        &self.edges
+    }
+
+    /// \returns true if lowering reversed the edge at index \p idx in
+    /// 'edge_list'.
+    pub fn is_edge_reversed(&self, idx: usize) -> bool {
+        self.edge_origins[idx].1
+    }
+
+    /// \returns the list of nodes that each edge visits, in the order in
+    /// which the edges were added, and in the direction of the edge. After
+    /// the graph is lowered (see 'do_it'), the lists include the connector
+    /// nodes of long edges, edges with labels and self edges.
+    pub fn edge_chains(&self) -> Vec<Vec<NodeHandle>> {
+        let mut chains = vec![Vec::new(); self.edges.len()];
+        for ((_, lst), (idx, reversed)) in
+            self.edges.iter().zip(self.edge_origins.iter())
+        {
+            let mut lst = lst.clone();
+            if *reversed {
+                lst.reverse();
+            }
+            chains[*idx] = lst;
+        }
+        chains
+    }
+
+    /// \returns the group that both \p from and \p to belong to, if any.
+    fn common_group(&self, from: NodeHandle, to: NodeHandle) -> Option<String> {
+        let group = &self.element(from).group;
+        if group.is_some() && *group == self.element(to).group {
+            return group.clone();
+        }
+        None
+    }
+
     /// Add a node to the graph.
     /// \returns a handle to the node.
     pub fn add_node(&mut self, elem: Element) -> NodeHandle {
@@ -112,29 +933,443 @@ impl VisualGraph {
         assert!(from.get_index() < self.nodes.len(), "Invalid handle");
         assert!(to.get_index() < self.nodes.len(), "Invalid handle");
         let lst = vec![from, to];
+        self.edge_origins.push((self.edges.len(), false));
         self.edges.push((arrow, lst));
     }
 }
 
 // Render.
 impl VisualGraph {
-    fn render(&self, debug: bool, rb: &mut dyn RenderBackend) {
-        // Draw the nodes.
-        for node in &self.nodes {
+    pub(crate) fn render(&self, debug: bool, rb: &mut dyn RenderBackend) {
+        let visible = vec![true; self.nodes.len()];
+        self.render_visible(debug, rb, &visible);
+    }
+
+    /// Render only the nodes that match \p filter, and the edges whose ends
+    /// both match, into \p rb. The edges to the other nodes are dropped. This
+    /// needs to be called after the layout.
+    pub fn render_filtered<F: Fn(NodeHandle) -> bool>(
+        &self,
+        rb: &mut dyn RenderBackend,
+        filter: F,
+    ) {
+        let mut visible = vec![false; self.nodes.len()];
+        for node in self.iter_nodes() {
+            if !self.is_connector(node) {
+                visible[node.get_index()] = filter(node);
+            }
+        }
+        // The connectors are visible if their edge is visible.
+        for (_, lst) in &self.edges {
+            let first = lst[0].get_index();
+            let last = lst[lst.len() - 1].get_index();
+            if visible[first] && visible[last] {
+                for h in lst {
+                    visible[h.get_index()] = true;
+                }
+            }
+        }
+        self.render_visible(false, rb, &visible);
+    }
+
+    /// Render the graph into \p rb. Only the nodes that are marked in
+    /// \p visible, and the edges whose nodes are all visible are drawn.
+    fn render_visible(
+        &self,
+        debug: bool,
+        rb: &mut dyn RenderBackend,
+        visible: &[bool],
+    ) {
+        if let Option::Some(comment) = &self.comment {
+            rb.draw_comment(comment);
+        }
+        if let Option::Some(color) = self.rank_bands {
+            let look = StyleAttr::new(color, 0, Option::Some(color), 0, 0);
+            for band in self.rank_bands().iter().step_by(2) {
+                let size = band.1.sub(band.0);
+                rb.draw_rect(band.0, size, &look, Option::None, Option::None);
+            }
+        }
+        self.render_clusters(rb, visible);
+        if !self.edges_first {
+            self.render_nodes(debug, rb, visible);
+        }
+        self.render_edges(debug, rb, visible);
+        if self.edges_first {
+            self.render_nodes(debug, rb, visible);
+        }
+    }
+
+    /// Draw the boxes of the clusters that have visible nodes, with their
+    /// labels. The large clusters are drawn first, below the clusters that
+    /// are nested in them.
+    fn render_clusters(&self, rb: &mut dyn RenderBackend, visible: &[bool]) {
+        let boxes = self.cluster_boxes();
+        for idx in self.clusters_inside_out().into_iter().rev() {
+            let cluster = &self.clusters[idx];
+            if !cluster.nodes.iter().any(|n| visible[n.get_index()]) {
+                continue;
+            }
+            let (top_left, bottom_right) = boxes[idx];
+            let size = bottom_right.sub(top_left);
+            rb.draw_rect(
+                top_left,
+                size,
+                &cluster.look,
+                Option::None,
+                Option::None,
+            );
+            if !cluster.label.is_empty() {
+                let text =
+                    get_size_for_str(&cluster.label, cluster.look.font_size);
+                let loc = Point::new(
+                    top_left.x + size.x / 2.,
+                    top_left.y + CLUSTER_MARGIN / 2. + text.y / 2.,
+                );
+                rb.draw_text(loc, &cluster.label, &cluster.look);
+            }
+        }
+    }
+
+    fn render_nodes(
+        &self,
+        debug: bool,
+        rb: &mut dyn RenderBackend,
+        visible: &[bool],
+    ) {
+        for (node, _) in self.nodes.iter().zip(visible).filter(|x| *x.1) {
+            if let Option::Some(layer) = &node.layer {
+                rb.begin_layer(layer);
+                node.render(debug, rb);
+                rb.end_layer();
+                continue;
+            }
             node.render(debug, rb);
         }
+        for (elem, _) in &self.floating {
+            elem.render(debug, rb);
+        }
+    }
+
+    fn render_edges(
+        &self,
+        debug: bool,
+        rb: &mut dyn RenderBackend,
+        visible: &[bool],
+    ) {
+        if self.edge_routing == EdgeRouting::None {
+            return;
+        }
+        let paths = self.edge_paths();
 
         // Draw the arrows:
+        for (arrow, path) in self.edges.iter().zip(paths.iter()) {
+            if !arrow.1.iter().all(|h| visible[h.get_index()]) {
+                continue;
+            }
+            if let Option::Some(layer) = &arrow.0.layer {
+                rb.begin_layer(layer);
+                render_arrow_path(rb, debug, path, &arrow.0);
+                rb.end_layer();
+                continue;
+            }
+            render_arrow_path(rb, debug, path, &arrow.0);
+        }
+    }
+
+    /// \returns the paths of the edges, in the order of 'edge_list', in the
+    /// format of 'generate_curve_for_elements'.
+    fn edge_paths(&self) -> Vec<Vec<(Point, Point)>> {
+        // Generate the paths of the arrows.
+        let org_chart = self.edge_routing == EdgeRouting::OrgChart;
+        let ortho = self.edge_routing == EdgeRouting::Ortho;
+        let buses = self.compute_org_chart_buses();
+        let (levels, extents) = self.unreversed_rank_extents();
+        let lr = self.orientation.is_left_right();
+        let mut paths = Vec::new();
         for arrow in &self.edges {
             let mut elements = Vec::new();
             for h in &arrow.1 {
                 elements.push(self.nodes[h.get_index()].clone());
             }
-            render_arrow(rb, debug, &elements[..], &arrow.0);
+            // The flat edges and the elbows are generated in the coordinates
+            // of a graph that isn't reversed, and are mirrored back.
+            let unreversed: Vec<Element> = arrow
+                .1
+                .iter()
+                .map(|h| self.unreversed_element(*h))
+                .collect();
+            let mirror = |path: Vec<(Point, Point)>| {
+                path.iter()
+                    .map(|(a, b)| (self.unreversed(*a), self.unreversed(*b)))
+                    .collect()
+            };
+            // Edges between nodes in the same rank are drawn above the rank.
+            let flat = self.get_flat_edge(&arrow.1);
+            let is_self_edge = arrow.1[0] == arrow.1[arrow.1.len() - 1];
+            if ortho && flat != Option::Some(true) && !is_self_edge {
+                let buses = self.ortho_buses(&arrow.1, &levels, &extents);
+                paths.push(mirror(generate_ortho_for_elements(
+                    &unreversed,
+                    &buses,
+                    lr,
+                )));
+                continue;
+            }
+            if let Option::Some(adjacent) = flat {
+                let mut path =
+                    generate_flat_edge_for_elements(&unreversed, adjacent, lr);
+                // Straight segments have control points at their ends.
+                if ortho {
+                    path = vec![(path[0].0, path[0].0), (path[1].1, path[1].1)];
+                }
+                paths.push(mirror(path));
+                continue;
+            }
+            // Self edges are drawn as curves in all styles.
+            if org_chart && !is_self_edge {
+                let bus = buses[arrow.1[0].get_index()];
+                paths.push(mirror(generate_elbow_for_elements(
+                    &unreversed,
+                    bus,
+                    lr,
+                )));
+                continue;
+            }
+            paths.push(generate_curve_for_elements(
+                &elements[..],
+                &arrow.0,
+                self.arrow_force(),
+            ));
+        }
+
+        if self.sort_edge_attachments && !org_chart && !ortho {
+            self.sort_attachment_points(&mut paths);
+        }
+
+        // Use the paths that the user provided.
+        for (arrow, path) in self.edges.iter().zip(paths.iter_mut()) {
+            if let Option::Some(spline) = &arrow.0.spline {
+                *path = bezier_points_to_path(spline);
+            }
+        }
+        paths
+    }
+
+    /// \returns None if the edge that visits the nodes \p lst is not an edge
+    /// between two different nodes in the same rank. Otherwise, \returns true
+    /// if the nodes are next to each other in the rank.
+    fn get_flat_edge(&self, lst: &[NodeHandle]) -> Option<bool> {
+        if lst.len() != 2 || lst[0] == lst[1] {
+            return Option::None;
+        }
+        for row in self.dag.ranks() {
+            let a = row.iter().position(|x| *x == lst[0]);
+            let b = row.iter().position(|x| *x == lst[1]);
+            match (a, b) {
+                (Option::Some(a), Option::Some(b)) => {
+                    return Option::Some(a.abs_diff(b) == 1);
+                }
+                (Option::None, Option::None) => {}
+                _ => return Option::None,
+            }
+        }
+        Option::None
+    }
+
+    /// \returns the position along the rank axis of the horizontal segment
+    /// that the edges that leave each node share, in the org chart routing.
+    /// The segment is halfway between the node and the closest next node.
+    /// The positions are in the coordinates of a graph that isn't reversed.
+    fn compute_org_chart_buses(&self) -> Vec<f64> {
+        let lr = self.orientation.is_left_right();
+        let start = |p: Point| if lr { p.x } else { p.y };
+        let mut next_top = vec![f64::INFINITY; self.nodes.len()];
+        for (_, lst) in &self.edges {
+            if lst[0] == lst[lst.len() - 1] || self.get_flat_edge(lst).is_some()
+            {
+                continue;
+            }
+            let first = lst[0].get_index();
+            let next = self.unreversed_element(lst[1]);
+            let top = start(next.position().bbox(false).0);
+            next_top[first] = next_top[first].min(top);
+        }
+        let mut buses = Vec::new();
+        for (i, top) in next_top.iter().enumerate() {
+            let node = self.unreversed_element(NodeHandle::new(i));
+            let bottom = start(node.position().bbox(false).1);
+            buses.push((bottom + top) / 2.);
+        }
+        buses
+    }
+
+    /// \returns the rank of each node, and the start and the end of each rank
+    /// along the rank axis, in the coordinates of a top-to-bottom graph that
+    /// isn't reversed. Empty ranks have no height.
+    fn unreversed_rank_extents(&self) -> (Vec<usize>, Vec<(f64, f64)>) {
+        let lr = self.orientation.is_left_right();
+        let start = |p: Point| if lr { p.x } else { p.y };
+        let mut levels = vec![0; self.nodes.len()];
+        let mut extents: Vec<(f64, f64)> = Vec::new();
+        for level in 0..self.dag.num_levels() {
+            let prev = extents.last().map_or(0., |e| e.1);
+            let mut extent = (f64::INFINITY, f64::NEG_INFINITY);
+            for node in self.dag.row(level) {
+                levels[node.get_index()] = level;
+                let bb = self.unreversed_element(*node).position().bbox(false);
+                extent.0 = extent.0.min(start(bb.0));
+                extent.1 = extent.1.max(start(bb.1));
+            }
+            if self.dag.row(level).is_empty() {
+                extent = (prev, prev);
+            }
+            extents.push(extent);
+        }
+        (levels, extents)
+    }
+
+    /// \returns the buses of the orthogonal edge that visits the nodes
+    /// \p lst, in the format of 'generate_ortho_for_elements'. The line
+    /// crosses over in the gap below the higher rank of each segment, or
+    /// above the rank of edges in the same rank. The edges from different
+    /// nodes in a rank cross over at different places in the gap, so their
+    /// horizontal segments don't overlap. \p levels and \p extents are
+    /// computed by 'unreversed_rank_extents'.
+    fn ortho_buses(
+        &self,
+        lst: &[NodeHandle],
+        levels: &[usize],
+        extents: &[(f64, f64)],
+    ) -> Vec<f64> {
+        // The distance of the buses of edges in the same rank from the rank.
+        const FLAT_EDGE_RISE: f64 = 10.;
+        let mut buses = Vec::new();
+        for seg in lst.windows(2) {
+            let (from, to) =
+                (levels[seg[0].get_index()], levels[seg[1].get_index()]);
+            if from == to {
+                buses.push(extents[from].0 - FLAT_EDGE_RISE);
+                continue;
+            }
+            // Spread the buses of the edges from the nodes of the row, in the
+            // order of the nodes.
+            let upper = from.min(to);
+            let row = self.dag.row(from);
+            let idx = row.iter().position(|n| *n == seg[0]).unwrap_or(0);
+            let frac = (idx + 1) as f64 / (row.len() + 1) as f64;
+            let (top, bottom) = (extents[upper].1, extents[upper + 1].0);
+            buses.push(top + (bottom - top) * frac);
+        }
+        buses
+    }
+
+    /// Reorder the attachment points of the edges in \p paths, such that the
+    /// edges that leave the same side of a node are attached in the order of
+    /// the position of the next node on the edge. The paths are in the format
+    /// of 'generate_curve_for_elements'.
+    fn sort_attachment_points(&self, paths: &mut [Vec<(Point, Point)>]) {
+        let lr = self.orientation.is_left_right();
+        // The coordinate along the rank, and across the rank.
+        let rank_axis = |p: Point| if lr { p.x } else { p.y };
+        let cross_axis = |p: Point| if lr { p.y } else { p.x };
+
+        // Returns the (attachment point, control point) of the edge end.
+        let get_end = |path: &[(Point, Point)], start: bool| {
+            if start {
+                path[0]
+            } else {
+                let last = path[path.len() - 1];
+                (last.1, last.0)
+            }
+        };
+
+        // For each node, collect the edge ends that attach to the node, in the
+        // format (edge index, is start, location of the next node).
+        let mut ends: Vec<Vec<(usize, bool, Point)>> =
+            vec![Vec::new(); self.nodes.len()];
+        for (i, (arrow, lst)) in self.edges.iter().enumerate() {
+            let first = lst[0];
+            let last = lst[lst.len() - 1];
+            // Don't move self edges, edges between nodes in the same rank,
+            // and edges that connect to ports.
+            let rank_of = |n: NodeHandle| rank_axis(self.pos(n).center());
+            if first == last || (rank_of(first) - rank_of(lst[1])).abs() < 0.001
+            {
+                continue;
+            }
+            if arrow.src_port.is_none() {
+                let next = self.pos(lst[1]).center();
+                ends[first.get_index()].push((i, true, next));
+            }
+            if arrow.dst_port.is_none() {
+                let next = self.pos(lst[lst.len() - 2]).center();
+                ends[last.get_index()].push((i, false, next));
+            }
+        }
+
+        for (node, list) in ends.iter().enumerate() {
+            let center = self.nodes[node].position().center();
+            for before in [true, false] {
+                let mut side: Vec<(usize, bool, Point)> = list
+                    .iter()
+                    .filter(|e| (rank_axis(e.2) < rank_axis(center)) == before)
+                    .cloned()
+                    .collect();
+                if side.len() < 2 {
+                    continue;
+                }
+
+                // Sort the edges by the location of their next node, and the
+                // attachment points by their location on the node.
+                side.sort_by(|a, b| {
+                    cross_axis(a.2).partial_cmp(&cross_axis(b.2)).unwrap()
+                });
+                let mut points: Vec<Point> =
+                    side.iter().map(|e| get_end(&paths[e.0], e.1).0).collect();
+                points.sort_by(|a, b| {
+                    cross_axis(*a).partial_cmp(&cross_axis(*b)).unwrap()
+                });
+
+                // Move the edges to their new attachment points, and keep the
+                // direction of the control points.
+                for (e, pt) in side.iter().zip(points.iter()) {
+                    let (loc, control) = get_end(&paths[e.0], e.1);
+                    let control = pt.add(control.sub(loc));
+                    let path = &mut paths[e.0];
+                    if e.1 {
+                        path[0] = (*pt, control);
+                    } else {
+                        let last = path.len() - 1;
+                        path[last] = (control, *pt);
+                    }
+                }
+            }
         }
     }
 }
 
+/// \returns the distance to move each of the consecutive intervals
+/// \p extents, where some of the intervals need to move by the distance in
+/// \p shifts. The other intervals move with the previous interval that needs
+/// to move (or with the first one), and then the intervals move forward as
+/// needed to keep their order and at least half of the gaps between them.
+fn spread_shifts(shifts: &[Option<f64>], extents: &[(f64, f64)]) -> Vec<f64> {
+    let mut last = shifts.iter().find_map(|x| *x).unwrap_or(0.);
+    let mut res: Vec<f64> = Vec::new();
+    for (i, shift) in shifts.iter().enumerate() {
+        last = shift.unwrap_or(last);
+        let mut current = last;
+        if i > 0 {
+            let gap = (extents[i].0 - extents[i - 1].1).max(0.);
+            let start = extents[i - 1].1 + res[i - 1] + gap / 2.;
+            current = current.max(start - extents[i].0);
+        }
+        res.push(current);
+    }
+    res
+}
+
 impl VisualGraph {
     pub fn do_it(
         &mut self,
@@ -143,20 +1378,293 @@ impl VisualGraph {
         disable_layout: bool,
         rb: &mut dyn RenderBackend,
     ) {
-        self.lower(disable_opt);
-        Placer::new(self).layout(disable_layout);
+        self.layout(disable_opt, disable_layout);
+        let timer = Timer::start();
         self.render(debug_mode, rb);
+        self.timings.record("render", timer);
+    }
+
+    /// Lower the graph and place all of the nodes, without rendering the
+    /// graph. This is the first part of 'do_it', for users that only need
+    /// the positions of the nodes. The nodes can be queried with 'pos'.
+    pub fn layout(&mut self, disable_opt: bool, disable_layout: bool) {
+        // Check before the lowering adds connectors without positions.
+        let fixed = self.has_fixed_positions();
+        self.lower(disable_opt);
+        let timer = Timer::start();
+        Placer::new(self).layout(disable_layout || fixed);
+        if fixed {
+            self.apply_fixed_positions();
+        } else {
+            if self.reversed {
+                self.reverse_ranks();
+            }
+            self.apply_pinned_positions();
+        }
+        self.timings.record("place", timer);
+        self.make_room_for_clusters();
+        self.place_floating_elements();
+    }
+
+    /// \returns the nodes that are pinned, and the centers that they are
+    /// pinned at. See 'Element::pinned'.
+    fn pinned_nodes(&self) -> Vec<(NodeHandle, Point)> {
+        self.iter_nodes()
+            .filter_map(|n| self.element(n).pinned.map(|p| (n, p)))
+            .collect()
+    }
+
+    /// Place the pinned nodes in ranks in the order of their centers along
+    /// the rank axis. Nodes that are pinned at the same position along the
+    /// axis share a rank, and nodes that are pinned further along the axis
+    /// are placed in later ranks.
+    fn rank_pinned_nodes(&mut self) {
+        let lr = self.orientation.is_left_right();
+        let sign = if self.reversed { -1. } else { 1. };
+        let mut pins: Vec<(f64, NodeHandle)> = self
+            .pinned_nodes()
+            .iter()
+            .map(|(node, p)| (sign * if lr { p.x } else { p.y }, *node))
+            .collect();
+        pins.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+
+        let mut level = 0;
+        let mut same: Vec<NodeHandle> = Vec::new();
+        for (i, (pos, node)) in pins.iter().enumerate() {
+            if i > 0 && pos - pins[i - 1].0 > 0.001 {
+                self.dag.add_same_level(&same);
+                same.clear();
+                level += 1;
+            }
+            self.dag.pin_level(*node, level);
+            same.push(*node);
+        }
+        self.dag.add_same_level(&same);
+    }
+
+    /// Move the laid out graph to place the pinned nodes at their centers.
+    /// The whole graph moves to place the first pinned node. The ranks of
+    /// the other pinned nodes, and the nodes in these ranks, move to place
+    /// them, and the nodes between them move along. The nodes keep their
+    /// order, and keep at least half of their distance from their neighbors,
+    /// so pins that don't leave enough room are not met. If the nodes end up
+    /// above or to the left of the drawing, the whole drawing moves back,
+    /// and the pins keep their distances from each other.
+    fn apply_pinned_positions(&mut self) {
+        let pins = self.pinned_nodes();
+        if let Option::Some((first, center)) = pins.first() {
+            let delta = center.sub(self.pos(*first).center());
+            for node in self.dag.iter() {
+                self.pos_mut(node).translate(delta);
+            }
+        } else {
+            return;
+        }
+        let pins: HashMap<NodeHandle, Point> = pins.into_iter().collect();
+
+        // Work in the coordinates of a top-to-bottom graph.
+        let lr = self.orientation.is_left_right();
+        let flip = |p: Point| if lr { p.transpose() } else { p };
+        // Returns the start and the end of the node along the x axis.
+        let extent = |vg: &Self, node: NodeHandle| {
+            let bb = vg.pos(node).bbox(false);
+            (flip(bb.0).x, flip(bb.1).x)
+        };
+        // Returns the distance from the center of the node to its pin.
+        let to_pin = |vg: &Self, node: NodeHandle| {
+            let center = vg.pos(node).center();
+            pins.get(&node).map(|pin| flip(pin.sub(center)))
+        };
+
+        // Move the ranks, in the order of their position along the y axis.
+        let mut rows: Vec<Vec<NodeHandle>> = Vec::new();
+        for level in 0..self.dag.num_levels() {
+            if !self.dag.row(level).is_empty() {
+                rows.push(self.dag.row(level).clone());
+            }
+        }
+        rows.sort_by(|a, b| {
+            let a = flip(self.pos(a[0]).center()).y;
+            let b = flip(self.pos(b[0]).center()).y;
+            a.partial_cmp(&b).unwrap()
+        });
+        let rank_extents: Vec<(f64, f64)> = rows
+            .iter()
+            .map(|row| {
+                let mut res = (f64::INFINITY, f64::NEG_INFINITY);
+                for node in row {
+                    let bb = self.pos(*node).bbox(false);
+                    res.0 = res.0.min(flip(bb.0).y);
+                    res.1 = res.1.max(flip(bb.1).y);
+                }
+                res
+            })
+            .collect();
+        let shifts: Vec<Option<f64>> = rows
+            .iter()
+            .map(|row| row.iter().find_map(|n| to_pin(self, *n)).map(|d| d.y))
+            .collect();
+        let shifts = spread_shifts(&shifts, &rank_extents);
+        for (row, shift) in rows.iter().zip(shifts) {
+            for node in row {
+                let delta = flip(Point::new(0., shift));
+                self.pos_mut(*node).translate(delta);
+            }
+        }
+
+        // Move the nodes in the ranks of the pinned nodes.
+        for row in rows {
+            let shifts: Vec<Option<f64>> =
+                row.iter().map(|n| to_pin(self, *n).map(|d| d.x)).collect();
+            if shifts.iter().all(|x| x.is_none()) {
+                continue;
+            }
+            let extents: Vec<(f64, f64)> =
+                row.iter().map(|n| extent(self, *n)).collect();
+            let shifts = spread_shifts(&shifts, &extents);
+            for (node, shift) in row.iter().zip(shifts) {
+                let delta = flip(Point::new(shift, 0.));
+                self.pos_mut(*node).translate(delta);
+            }
+        }
+
+        let mut min = Point::zero();
+        for node in self.dag.iter() {
+            let bb = self.pos(node).bbox(true);
+            min = Point::new(min.x.min(bb.0.x), min.y.min(bb.0.y));
+        }
+        if min.x < 0. || min.y < 0. {
+            for node in self.dag.iter() {
+                self.pos_mut(node).translate(min.scale(-1.));
+            }
+        }
+    }
+
+    /// Move the graph to make room for the boxes of the clusters, which may
+    /// extend beyond the nodes at the top and the left of the drawing.
+    fn make_room_for_clusters(&mut self) {
+        let mut min = Point::zero();
+        for bb in self.cluster_boxes() {
+            min = Point::new(min.x.min(bb.0.x), min.y.min(bb.0.y));
+        }
+        if min.x < 0. || min.y < 0. {
+            for node in self.dag.iter() {
+                self.pos_mut(node).translate(min.scale(-1.));
+            }
+        }
+    }
+
+    /// Mirror the laid out graph along the rank axis, to place the first rank
+    /// at the bottom (or at the right) of the drawing.
+    fn reverse_ranks(&mut self) {
+        if self.num_nodes() == 0 {
+            return;
+        }
+        let bb = self.bounding_box();
+        let sum = bb.0.add(bb.1);
+        for node in self.dag.iter() {
+            let middle = self.pos(node).middle();
+            let to = if self.orientation.is_left_right() {
+                Point::new(sum.x - middle.x, middle.y)
+            } else {
+                Point::new(middle.x, sum.y - middle.y)
+            };
+            self.pos_mut(node).translate(to.sub(middle));
+        }
+    }
+
+    /// \returns the point \p p mirrored along the rank axis if the graph is
+    /// reversed. This maps the drawing to the coordinates of a graph that
+    /// isn't reversed (up to a translation), and back.
+    fn unreversed(&self, p: Point) -> Point {
+        if !self.reversed {
+            return p;
+        }
+        if self.orientation.is_left_right() {
+            Point::new(-p.x, p.y)
+        } else {
+            Point::new(p.x, -p.y)
+        }
+    }
+
+    /// \returns a copy of the element \p node, that is moved to the
+    /// coordinates of a graph that isn't reversed.
+    fn unreversed_element(&self, node: NodeHandle) -> Element {
+        let mut elem = self.nodes[node.get_index()].clone();
+        let middle = elem.position().middle();
+        let delta = self.unreversed(middle).sub(middle);
+        elem.position_mut().translate(delta);
+        elem
+    }
+
+    /// Place the floating elements above and below the laid out graph, at
+    /// the corners of the drawing. The graph is moved down to make room for
+    /// the elements at the top.
+    fn place_floating_elements(&mut self) {
+        const GAP: f64 = 20.;
+        if self.floating.is_empty() || self.num_nodes() == 0 {
+            return;
+        }
+
+        // Elements that are attached to the same anchor are stacked away
+        // from the graph. Compute the height of each stack.
+        let mut stacks = [GAP; 6];
+        for (elem, anchor) in &self.floating {
+            stacks[*anchor as usize] += elem.pos.size(false).y;
+        }
+
+        // Make room for the elements at the top of the drawing.
+        let top_height = [Anchor::TopLeft, Anchor::TopRight, Anchor::TopCenter]
+            .iter()
+            .map(|anchor| stacks[*anchor as usize])
+            .fold(0., f64::max);
+        if self.floating.iter().any(|(_, anchor)| anchor.is_top()) {
+            for node in self.dag.iter() {
+                self.pos_mut(node).translate(Point::new(0., top_height));
+            }
+        }
+
+        // Place the elements outside of the boxes of the clusters.
+        let mut bb = self.bounding_box();
+        for cluster in self.cluster_boxes() {
+            bb.0 = Point::new(bb.0.x.min(cluster.0.x), bb.0.y.min(cluster.0.y));
+            bb.1 = Point::new(bb.1.x.max(cluster.1.x), bb.1.y.max(cluster.1.y));
+        }
+        let mut offsets = [GAP; 6];
+        for (elem, anchor) in self.floating.iter_mut() {
+            let size = elem.pos.size(false);
+            let x = if anchor.is_left() {
+                bb.0.x + size.x / 2.
+            } else if anchor.is_center() {
+                (bb.0.x + bb.1.x) / 2.
+            } else {
+                (bb.1.x - size.x / 2.).max(bb.0.x + size.x / 2.)
+            };
+            let offset = &mut offsets[*anchor as usize];
+            let y = if anchor.is_top() {
+                bb.0.y - *offset - size.y / 2.
+            } else {
+                bb.1.y + *offset + size.y / 2.
+            };
+            *offset += size.y;
+            elem.position_mut().move_to(Point::new(x, y));
+        }
     }
 
     fn lower(&mut self, disable_optimizations: bool) {
         #[cfg(feature = "log")]
         log::info!("Lowering a graph with {} nodes.", self.num_nodes());
+        let timer = Timer::start();
+        self.apply_cluster_rankdirs();
+        self.rank_pinned_nodes();
         self.to_valid_dag();
         self.split_text_edges();
+        self.timings.record("lower", timer);
         self.split_long_edges(disable_optimizations);
 
+        let cache = self.text_cache.clone();
         for elem in self.dag.iter() {
-            self.element_mut(elem).resize();
+            self.element_mut(elem).resize_with_cache(cache.as_ref());
         }
     }
 
@@ -164,14 +1672,16 @@ impl VisualGraph {
     /// This is the first step of graph canonicalization.
     pub fn to_valid_dag(&mut self) {
         let edges = self.edges.clone();
+        let origins = self.edge_origins.clone();
         self.edges.clear();
+        self.edge_origins.clear();
 
         // At this point the DAG should have all of the nodes, but none of the
         // edges. In here we construct the edges.
         assert_eq!(self.nodes.len(), self.dag.len(), "bad number of nodes");
 
         // For each edge.
-        for edge in edges {
+        for (edge, origin) in edges.into_iter().zip(origins) {
             let mut arrow = edge.0;
             let lst = edge.1;
             assert_eq!(lst.len(), 2);
@@ -179,23 +1689,51 @@ impl VisualGraph {
             let mut to = lst[1];
 
             if from == to {
-                self.self_edges.push((arrow, from));
+                self.self_edges.push((arrow, from, origin.0));
+                continue;
+            }
+
+            // Edges between nodes that share a rank can't constrain the
+            // ranking, and are drawn as flat edges.
+            if !arrow.constraint || self.dag.in_same_level_set(from, to) {
+                self.free_edges.push((arrow, from, to, origin.0));
                 continue;
             }
 
-            // Reverse back edges.
-            if self.dag.is_reachable(to, from) {
+            // Reverse back edges, and edges that point up from a pinned node
+            // to a node that is pinned above it, unless this creates a cycle.
+            let pinned_up = match (
+                self.dag.pinned_level(from),
+                self.dag.pinned_level(to),
+            ) {
+                (Some(a), Some(b)) => b < a,
+                _ => false,
+            };
+            let reversed = self.dag.is_reachable(to, from)
+                || (pinned_up && !self.dag.is_reachable(from, to));
+            if reversed {
                 swap(&mut from, &mut to);
                 arrow = arrow.reverse();
             }
 
             self.dag.add_edge(from, to);
             self.add_edge(arrow, from, to);
+            self.edge_origins.pop();
+            self.edge_origins.push((origin.0, reversed));
 
             self.dag.verify();
         }
     }
 
+    /// \returns the orientation of the connectors that are added to the
+    /// graph. This follows the orientation of the graph, and not the
+    /// orientation of the nodes that the edges leave, because records have
+    /// the same orientation in all of the graph directions.
+    fn connector_orientation(&self) -> Orientation {
+        ShapeKind::Connector(Option::None)
+            .orientation_in_graph(self.orientation)
+    }
+
     /// Convert all of the edges that contain text labels to edges that go
     /// through connectors.
     /// This is the second step of graph canonicalization.
@@ -218,8 +1756,14 @@ impl VisualGraph {
             let text = arrow.text.clone();
 
             // Create a new connection block.
-            let dir = self.element(from).orientation;
-            let conn = Element::create_connector(&text, &arrow.look, dir);
+            let dir = self.connector_orientation();
+            let mut conn = Element::create_connector(&text, &arrow.look, dir);
+            conn.group = self.common_group(from, to);
+            // The label is drawn in the layer of the edge.
+            conn.layer = arrow.layer.clone();
+            if let Option::Some(padding) = self.connector_padding {
+                conn.pos.set_halo(Point::splat(padding));
+            }
             let conn = self.add_node(conn);
 
             // Update the edge node list, and remove the text.
@@ -236,19 +1780,61 @@ impl VisualGraph {
         self.edges = edges;
     }
 
+    /// \returns the number of ranks that an edge from \p from to \p to spans
+    /// when its length is close to \p len points. Each rank adds the extent
+    /// of the nodes along the ranks, and the separation between the ranks.
+    fn length_in_ranks(
+        &self,
+        from: NodeHandle,
+        to: NodeHandle,
+        len: f64,
+    ) -> usize {
+        let extent = |node: NodeHandle| {
+            let size = self.pos(node).size(true);
+            if self.orientation.is_left_right() {
+                size.x
+            } else {
+                size.y
+            }
+        };
+        let pitch = (extent(from) + extent(to)) / 2. + self.rank_sep;
+        (len / pitch).round().max(1.) as usize
+    }
+
     pub fn split_long_edges(&mut self, disable_optimizations: bool) {
         // Assign optimal rank to nodes in the graph.
+        let timer = Timer::start();
         self.dag.recompute_node_ranks();
         self.dag.verify();
         if !disable_optimizations {
-            RankOptimizer::new(&mut self.dag).optimize();
+            let lengths: Vec<(NodeHandle, NodeHandle, usize)> = self
+                .edges
+                .iter()
+                .filter_map(|(arrow, lst)| {
+                    let (from, to) = (lst[0], lst[lst.len() - 1]);
+                    let len = arrow.len?;
+                    Option::Some((
+                        from,
+                        to,
+                        self.length_in_ranks(from, to, len),
+                    ))
+                })
+                .collect();
+            let mut opt = RankOptimizer::new(&mut self.dag);
+            for (from, to, ranks) in lengths {
+                opt.add_preferred_length(from, to, ranks);
+            }
+            opt.optimize();
         }
+        self.timings.record("rank", timer);
 
         let mut edges = self.edges.clone();
         self.edges.clear();
 
         for edge in edges.iter_mut() {
             let mut lst = edge.1.clone();
+            // Connectors inherit the group of the edge endpoints.
+            let group = self.common_group(lst[0], lst[lst.len() - 1]);
 
             // Points the 'to' edge in each pair in the graph. We start with
             // node '1', and compare to the previous node.
@@ -268,8 +1854,9 @@ impl VisualGraph {
                 }
 
                 // We need to add a new connector node.
-                let dir = self.element(prev).orientation;
-                let conn = Element::empty_connector(dir);
+                let dir = self.connector_orientation();
+                let mut conn = Element::empty_connector(dir);
+                conn.group = group.clone();
                 let conn = self.add_node(conn);
                 lst.insert(i, conn);
 
@@ -286,10 +1873,147 @@ impl VisualGraph {
         }
         self.edges = edges;
 
+        let timer = Timer::start();
         if !disable_optimizations {
             EdgeCrossOptimizer::new(&mut self.dag).optimize();
         }
-        self.expand_self_edges()
+        self.add_connectors_to_clusters();
+        self.group_clusters_in_rows();
+        self.timings.record("cross", timer);
+        self.expand_self_edges();
+        self.expand_free_edges();
+    }
+
+    /// \returns true if the cluster \p cluster places its nodes in one rank,
+    /// because it overrides the direction of the graph with a direction that
+    /// goes across the ranks.
+    fn is_cross_rank_cluster(&self, cluster: &Cluster) -> bool {
+        cluster.rankdir.is_some_and(|(orientation, _)| {
+            orientation.is_left_right() != self.orientation.is_left_right()
+        })
+    }
+
+    /// Place the nodes of the clusters that go across the ranks of the graph
+    /// in one rank, and make the edges between them free edges, that don't
+    /// constrain the ranking.
+    fn apply_cluster_rankdirs(&mut self) {
+        for i in 0..self.clusters.len() {
+            if !self.is_cross_rank_cluster(&self.clusters[i]) {
+                continue;
+            }
+            let nodes = self.clusters[i].nodes.clone();
+            for (arrow, lst) in self.edges.iter_mut() {
+                if nodes.contains(&lst[0]) && nodes.contains(&lst[1]) {
+                    arrow.constraint = false;
+                }
+            }
+            self.dag.add_same_level(&nodes);
+        }
+    }
+
+    /// Reorder the nodes of the cluster \p cluster in the row \p row, which
+    /// are next to each other, in the order of the edges between them. The
+    /// order is reversed if the direction of the cluster is reversed.
+    fn order_cross_rank_cluster(
+        &self,
+        cluster: &Cluster,
+        row: &mut [NodeHandle],
+    ) {
+        let slots: Vec<usize> = (0..row.len())
+            .filter(|i| cluster.nodes.contains(&row[*i]))
+            .collect();
+        let mut members: Vec<NodeHandle> =
+            slots.iter().map(|i| row[*i]).collect();
+        let edges: Vec<(NodeHandle, NodeHandle)> = self
+            .edges
+            .iter()
+            .map(|(_, lst)| (lst[0], lst[lst.len() - 1]))
+            .filter(|(a, b)| {
+                a != b && members.contains(a) && members.contains(b)
+            })
+            .collect();
+
+        // Place the nodes after their predecessors, and keep the order of the
+        // row between nodes that don't depend on each other. The remaining
+        // nodes are on cycles, and keep their order.
+        let mut order = Vec::new();
+        while !members.is_empty() {
+            let ready = members.iter().position(|n| {
+                edges.iter().all(|(a, b)| b != n || order.contains(a))
+            });
+            order.push(members.remove(ready.unwrap_or(0)));
+        }
+        if cluster.rankdir.is_some_and(|(_, reversed)| reversed) {
+            order.reverse();
+        }
+        for (slot, node) in slots.iter().zip(order) {
+            row[*slot] = node;
+        }
+    }
+
+    /// Add the connectors of the edges that go between the nodes of a
+    /// cluster to the cluster, to keep the edges inside of the box.
+    fn add_connectors_to_clusters(&mut self) {
+        for cluster in self.clusters.iter_mut() {
+            let mut connectors = Vec::new();
+            for (_, lst) in &self.edges {
+                let first = lst[0];
+                let last = lst[lst.len() - 1];
+                if cluster.nodes.contains(&first)
+                    && cluster.nodes.contains(&last)
+                {
+                    connectors.extend_from_slice(&lst[1..lst.len() - 1]);
+                }
+            }
+            cluster.nodes.extend(connectors);
+        }
+    }
+
+    /// Reorder the rows, to place the nodes of each cluster next to each
+    /// other. The nodes of a cluster move to the middle of the place that
+    /// they occupied in the row, and keep their order. Nested clusters are
+    /// grouped first, and move together with the clusters around them. This
+    /// only groups the nodes within each rank, and the placer keeps the other
+    /// nodes out of the columns that the cluster covers in its other ranks.
+    fn group_clusters_in_rows(&mut self) {
+        let order = self.clusters_inside_out();
+        for level in 0..self.dag.num_levels() {
+            for idx in &order {
+                let cluster = &self.clusters[*idx].nodes;
+                let row = self.dag.row(level);
+                let members: Vec<bool> =
+                    row.iter().map(|n| cluster.contains(n)).collect();
+                let count = members.iter().filter(|x| **x).count();
+                if count < 2 {
+                    continue;
+                }
+                let sum: usize = (0..row.len()).filter(|i| members[*i]).sum();
+                let middle = sum as f64 / count as f64;
+                let mut keys: Vec<(f64, bool, usize)> = (0..row.len())
+                    .map(|i| match members[i] {
+                        true => (middle, true, i),
+                        false => (i as f64, false, i),
+                    })
+                    .collect();
+                keys.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                let mut row: Vec<NodeHandle> =
+                    keys.iter().map(|k| row[k.2]).collect();
+                let cluster = &self.clusters[*idx];
+                if self.is_cross_rank_cluster(cluster) {
+                    self.order_cross_rank_cluster(cluster, &mut row);
+                }
+                *self.dag.row_mut(level) = row;
+            }
+        }
+    }
+
+    /// Add the saved edges that don't constrain the ranking back to the
+    /// graph. The edges go straight between their ends, without connectors.
+    fn expand_free_edges(&mut self) {
+        for (arrow, from, to, origin) in std::mem::take(&mut self.free_edges) {
+            self.edge_origins.push((origin, false));
+            self.edges.push((arrow, vec![from, to]));
+        }
     }
 
     /// Convert all of the saved self edges into proper edges in the graph.
@@ -300,10 +2024,11 @@ impl VisualGraph {
             let level = self.dag.level(node);
             let text = arrow.text.to_string();
             arrow.text = String::new();
-            let dir = self.element(node).orientation;
+            let dir = self.connector_orientation();
             let conn = Element::create_connector(&text, &arrow.look, dir);
             let conn = self.add_node(conn);
             self.dag.update_node_rank_level(conn, level, Some(node));
+            self.edge_origins.push((se.2, false));
             self.edges.push((arrow, vec![node, conn, node]));
         }
 
@@ -311,3 +2036,40 @@ impl VisualGraph {
         self.self_edges.clear();
     }
 }
+
+#[test]
+fn test_sort_attachment_points() {
+    use crate::core::style::StyleAttr;
+    let mut vg = VisualGraph::new(Orientation::TopToBottom);
+    let mut add = |name: &str, p: Point| {
+        let shape = ShapeKind::new_box(name);
+        let dir = Orientation::TopToBottom;
+        let sz = Point::new(100., 40.);
+        let mut elem = Element::create(shape, StyleAttr::simple(), dir, sz);
+        elem.position_mut().move_to(p);
+        vg.add_node(elem)
+    };
+    let a = add("a", Point::new(100., 0.));
+    let b = add("b", Point::new(0., 100.));
+    let c = add("c", Point::new(200., 100.));
+    vg.add_edge(Arrow::default(), a, b);
+    vg.add_edge(Arrow::default(), a, c);
+
+    // The edges leave 'a' in the opposite order of their targets.
+    let mut paths = vec![
+        vec![
+            (Point::new(110., 20.), Point::new(100., 50.)),
+            (Point::new(0., 50.), Point::new(0., 80.)),
+        ],
+        vec![
+            (Point::new(90., 20.), Point::new(100., 50.)),
+            (Point::new(200., 50.), Point::new(200., 80.)),
+        ],
+    ];
+    vg.sort_attachment_points(&mut paths);
+    assert_eq!(paths[0][0], (Point::new(90., 20.), Point::new(80., 50.)));
+    assert_eq!(paths[1][0], (Point::new(110., 20.), Point::new(120., 50.)));
+    // The ends of the edges attach to different nodes, and don't move.
+    assert_eq!(paths[0][1].1, Point::new(0., 80.));
+    assert_eq!(paths[1][1].1, Point::new(200., 80.));
+}
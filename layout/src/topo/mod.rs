@@ -3,3 +3,4 @@
 pub mod layout;
 pub mod optimizer;
 pub mod placer;
+pub mod timings;
@@ -7,6 +7,75 @@ use crate::adt::dag::NodeHandle;
 use crate::adt::dag::DAG;
 use crate::core::base::Direction;
 
+/// Given two nodes that may have connections in \p row, check how many of
+/// these edges intersect. Check both successors and predecessors.
+///               A   B
+///             /   \/ \
+///            /    /\  \
+///  Row: [][][][][][][][][][]
+fn num_crossing(
+    dag: &DAG,
+    a: NodeHandle,
+    b: NodeHandle,
+    row: &[NodeHandle],
+) -> usize {
+    let mut sum = 0;
+    // Record the number of edges that previously connected with node B.
+    let mut num_b = 0;
+
+    let a_edges1 = dag.successors(a);
+    let a_edges2 = dag.predecessors(a);
+    let b_edges1 = dag.successors(b);
+    let b_edges2 = dag.predecessors(b);
+
+    for node in row {
+        let is_a1 = a_edges1.iter().any(|x| x == node);
+        let is_a2 = a_edges2.iter().any(|x| x == node);
+        let is_b1 = b_edges1.iter().any(|x| x == node);
+        let is_b2 = b_edges2.iter().any(|x| x == node);
+        if is_a1 || is_a2 {
+            sum += num_b;
+        }
+        if is_b1 || is_b2 {
+            num_b += 1;
+        }
+    }
+    sum
+}
+
+fn count_crossing_in_rows(
+    dag: &DAG,
+    first: &[NodeHandle],
+    second: &[NodeHandle],
+) -> usize {
+    if first.len() < 2 {
+        return 0;
+    }
+    let mut sum = 0;
+    // Check for each pair of nodes a,b where b comes after a.
+    for i in 0..first.len() {
+        for j in i + 1..first.len() {
+            let a = first[i];
+            let b = first[j];
+            sum += num_crossing(dag, a, b, second);
+        }
+    }
+    sum
+}
+
+/// \returns the number of pairs of edges that cross each other between the
+/// ranks of \p dag.
+pub fn count_crossed_edges(dag: &DAG) -> usize {
+    let mut sum = 0;
+    // Compare each row to the row afterwards.
+    for row_idx in 1..dag.num_levels() {
+        let first_row = dag.row(row_idx - 1);
+        let second_row = dag.row(row_idx);
+        sum += count_crossing_in_rows(dag, first_row, second_row);
+    }
+    sum
+}
+
 /// This optimizations changes the order of nodes within a rank (ordering along
 /// the x-axis). The transformation tries to reduce the number of edges that
 /// cross each other.
@@ -19,42 +88,6 @@ impl<'a> EdgeCrossOptimizer<'a> {
         Self { dag }
     }
 
-    /// Given two nodes that may have connections in \p row, check how many of
-    /// these edges intersect. Check both successors and predecessors.
-    ///               A   B
-    ///             /   \/ \
-    ///            /    /\  \
-    ///  Row: [][][][][][][][][][]
-    fn num_crossing(
-        &self,
-        a: NodeHandle,
-        b: NodeHandle,
-        row: &[NodeHandle],
-    ) -> usize {
-        let mut sum = 0;
-        // Record the number of edges that previously connected with node B.
-        let mut num_b = 0;
-
-        let a_edges1 = self.dag.successors(a);
-        let a_edges2 = self.dag.predecessors(a);
-        let b_edges1 = self.dag.successors(b);
-        let b_edges2 = self.dag.predecessors(b);
-
-        for node in row {
-            let is_a1 = a_edges1.iter().any(|x| x == node);
-            let is_a2 = a_edges2.iter().any(|x| x == node);
-            let is_b1 = b_edges1.iter().any(|x| x == node);
-            let is_b2 = b_edges2.iter().any(|x| x == node);
-            if is_a1 || is_a2 {
-                sum += num_b;
-            }
-            if is_b1 || is_b2 {
-                num_b += 1;
-            }
-        }
-        sum
-    }
-
     // Shuffle the nodes in all of the ranks.
     pub fn perturb_rank(&mut self) {
         for i in 0..self.dag.num_levels() {
@@ -70,7 +103,10 @@ impl<'a> EdgeCrossOptimizer<'a> {
     pub fn rotate_rank(&mut self) {
         for i in 0..self.dag.num_levels() {
             let row = self.dag.row_mut(i);
-            row.rotate_left(1);
+            // Ranks may be empty when nodes are pinned to a rank.
+            if !row.is_empty() {
+                row.rotate_left(1);
+            }
         }
     }
 
@@ -79,7 +115,7 @@ impl<'a> EdgeCrossOptimizer<'a> {
         #[cfg(feature = "log")]
         log::info!("Optimizing edge crossing.");
         let mut best_rank = self.dag.ranks().clone();
-        let mut best_cnt = self.count_crossed_edges();
+        let mut best_cnt = count_crossed_edges(self.dag);
         #[cfg(feature = "log")]
         log::info!("Starting with {} crossings.", best_cnt);
         for i in 0..50 {
@@ -89,7 +125,7 @@ impl<'a> EdgeCrossOptimizer<'a> {
                 _ => Direction::Down,
             };
             self.swap_crossed_edges(dir);
-            let new_cnt = self.count_crossed_edges();
+            let new_cnt = count_crossed_edges(self.dag);
             if new_cnt < best_cnt {
                 #[cfg(feature = "log")]
                 log::info!("Found a rank with {} crossings.", new_cnt);
@@ -104,37 +140,6 @@ impl<'a> EdgeCrossOptimizer<'a> {
         *self.dag.ranks_mut() = best_rank;
     }
 
-    fn count_crossed_edges(&self) -> usize {
-        let mut sum = 0;
-        // Compare each row to the row afterwards.
-        for row_idx in 0..self.dag.num_levels() - 1 {
-            let first_row = self.dag.row(row_idx);
-            let second_row = self.dag.row(row_idx + 1);
-            sum += self.count_crossing_in_rows(first_row, second_row);
-        }
-        sum
-    }
-
-    fn count_crossing_in_rows(
-        &self,
-        first: &[NodeHandle],
-        second: &[NodeHandle],
-    ) -> usize {
-        if first.len() < 2 {
-            return 0;
-        }
-        let mut sum = 0;
-        // Check for each pair of nodes a,b where b comes after a.
-        for i in 0..first.len() {
-            for j in i + 1..first.len() {
-                let a = first[i];
-                let b = first[j];
-                sum += self.num_crossing(a, b, second);
-            }
-        }
-        sum
-    }
-
     /// Scan all of the node pairs in the module and count the number of crossed
     /// edges. If \p allow_swap is set then swap the edges if it reduces the
     /// number of crossing.
@@ -191,10 +196,10 @@ impl<'a> EdgeCrossOptimizer<'a> {
             let mut ba = 0;
             // Figure out if A crosses the edges of B, and vice versa, on both
             // the edges pointing up and down.
-            ab += self.num_crossing(a, b, &prev_row);
-            ba += self.num_crossing(b, a, &prev_row);
-            ab += self.num_crossing(a, b, &next_row);
-            ba += self.num_crossing(b, a, &next_row);
+            ab += num_crossing(self.dag, a, b, &prev_row);
+            ba += num_crossing(self.dag, b, a, &prev_row);
+            ab += num_crossing(self.dag, a, b, &next_row);
+            ba += num_crossing(self.dag, b, a, &next_row);
 
             // Swap the edges.
             if ab > ba {
@@ -212,18 +217,63 @@ impl<'a> EdgeCrossOptimizer<'a> {
 }
 
 /// This optimization sinks nodes in an attempt to shorten the length of edges
-/// that run through the graph.
+/// that run through the graph. Edges that have a preferred length are then
+/// stretched, as long as this does not push the nodes past their successors.
 #[derive(Debug)]
 pub struct RankOptimizer<'a> {
     dag: &'a mut DAG,
+    // A list of (from, to, len) edges that prefer to span 'len' ranks.
+    preferred_lengths: Vec<(NodeHandle, NodeHandle, usize)>,
 }
 
 impl<'a> RankOptimizer<'a> {
     pub fn new(dag: &'a mut DAG) -> Self {
-        Self { dag }
+        Self {
+            dag,
+            preferred_lengths: Vec::new(),
+        }
+    }
+
+    /// Ask the optimizer to place \p to \p len ranks below \p from.
+    /// This is a soft constraint.
+    pub fn add_preferred_length(
+        &mut self,
+        from: NodeHandle,
+        to: NodeHandle,
+        len: usize,
+    ) {
+        self.preferred_lengths.push((from, to, len));
+    }
+
+    /// Try to sink the destination of an edge that is shorter than its
+    /// preferred length. The node can't sink below its successors, and pinned
+    /// nodes and nodes that share a rank with other nodes don't move.
+    fn try_to_stretch_edge(
+        &mut self,
+        from: NodeHandle,
+        to: NodeHandle,
+        len: usize,
+    ) -> bool {
+        if self.dag.is_level_constrained(to) {
+            return false;
+        }
+        let curr_rank = self.dag.level(to);
+        let mut target = self.dag.level(from) + len;
+        for elem in self.dag.successors(to) {
+            target = target.min(self.dag.level(*elem) - 1);
+        }
+
+        if target > curr_rank {
+            self.dag.update_node_rank_level(to, target, None);
+            return true;
+        }
+        false
     }
 
     pub fn try_to_sink_node(&mut self, node: NodeHandle) -> bool {
+        if self.dag.is_level_constrained(node) {
+            return false;
+        }
         let backs = self.dag.predecessors(node);
         let fwds = self.dag.successors(node);
 
@@ -279,5 +329,18 @@ impl<'a> RankOptimizer<'a> {
 
         #[cfg(feature = "log")]
         log::info!("Sank {} nodes in {} iteration.", cnt, iter);
+
+        // Stretch the edges that prefer to be longer. We don't sink nodes
+        // after this step because that would shorten the edges again.
+        loop {
+            let mut changed = false;
+            for i in 0..self.preferred_lengths.len() {
+                let (from, to, len) = self.preferred_lengths[i];
+                changed |= self.try_to_stretch_edge(from, to, len);
+            }
+            if !changed {
+                break;
+            }
+        }
     }
 }
@@ -3,7 +3,7 @@
 
 use crate::adt::dag::NodeHandle;
 use crate::core::geometry::weighted_median;
-use crate::topo::layout::VisualGraph;
+use crate::topo::layout::{VisualGraph, CLUSTER_MARGIN};
 use std::collections::HashSet;
 
 use super::simple;
@@ -111,6 +111,16 @@ struct Scheduler<'a> {
     last_x_for_row: Vec<f64>,
     // The node placement order (left to right, or right to left).
     order: OrderLR,
+    // The extra space between adjacent nodes in a row.
+    node_sep: f64,
+    // The minimal space between the edges and the nodes next to them.
+    edge_sep: f64,
+    // For each row, saves the center of the last box, if it's a connector.
+    last_connector_for_row: Vec<Option<f64>>,
+    // The minimal distance between the centers of adjacent connectors.
+    connector_sep: f64,
+    // The indices of the clusters that each node is in.
+    clusters: Vec<Vec<usize>>,
 }
 
 impl<'a> Scheduler<'a> {
@@ -123,6 +133,12 @@ impl<'a> Scheduler<'a> {
             f64::INFINITY
         };
         let last_x_for_row = vec![v; vg.dag.num_levels()];
+        let mut clusters = vec![Vec::new(); vg.num_nodes()];
+        for (i, cluster) in vg.clusters().iter().enumerate() {
+            for node in &cluster.nodes {
+                clusters[node.get_index()].push(i);
+            }
+        }
         Self {
             vg,
             vl,
@@ -130,9 +146,35 @@ impl<'a> Scheduler<'a> {
             sched_idx: idx,
             last_x_for_row,
             order,
+            node_sep: vg.node_sep(),
+            edge_sep: vg.edge_sep(),
+            last_connector_for_row: vec![None; vg.dag.num_levels()],
+            connector_sep: vg.connector_sep(),
+            clusters,
         }
     }
 
+    /// \returns the extra space between the node \p node and the node that
+    /// was placed before it in the row \p level, which makes room for the
+    /// sides of the boxes of the clusters that are between the nodes.
+    fn cluster_gap(&self, node: NodeHandle, level: usize) -> f64 {
+        let row = self.vg.dag.row(level);
+        let placed = self.sched_idx[level];
+        if placed == 0 || placed > row.len() {
+            return 0.;
+        }
+        let prev = if self.order.is_left_to_right() {
+            row[placed - 1]
+        } else {
+            row[row.len() - placed]
+        };
+        let a = &self.clusters[node.get_index()];
+        let b = &self.clusters[prev.get_index()];
+        let sides = a.iter().filter(|c| !b.contains(c)).count()
+            + b.iter().filter(|c| !a.contains(c)).count();
+        sides as f64 * CLUSTER_MARGIN * 2.
+    }
+
     pub fn get_x_placement(&self) -> &Vec<f64> {
         &self.x_coordinates
     }
@@ -152,11 +194,7 @@ impl<'a> Scheduler<'a> {
                 let v = self.vl[i].clone();
                 // Place the nodes.
                 let x = self.first_schedule_x(&v);
-                if self.order.is_left_to_right() {
-                    self.place_vertical(&v, x);
-                } else {
-                    self.place_vertical(&v, x);
-                }
+                self.place_vertical(&v, x);
                 // Wipe the vertical.
                 self.vl[i].clear();
                 to_place -= 1;
@@ -172,17 +210,37 @@ impl<'a> Scheduler<'a> {
             let last = self.last_x_for_row[level];
             let pos = self.vg.pos(*elem);
 
-            let offset = if self.order.is_left_to_right() {
+            let mut offset = if self.order.is_left_to_right() {
                 pos.distance_to_left(true)
             } else {
                 pos.distance_to_right(true)
             };
+            // Edges pass through the center of connectors, and need to keep
+            // their distance from the previous box.
+            if self.vg.is_connector(*elem) {
+                offset = offset.max(self.edge_sep);
+            }
+            offset += self.cluster_gap(*elem, level);
 
             if self.order.is_left_to_right() {
                 last_offset_x = last_offset_x.max(last + offset);
             } else {
                 last_offset_x = last_offset_x.min(last - offset);
             }
+
+            // Keep the edges that pass through adjacent connectors apart.
+            if let (true, Some(prev)) = (
+                self.vg.is_connector(*elem),
+                self.last_connector_for_row[level],
+            ) {
+                if self.order.is_left_to_right() {
+                    last_offset_x =
+                        last_offset_x.max(prev + self.connector_sep);
+                } else {
+                    last_offset_x =
+                        last_offset_x.min(prev - self.connector_sep);
+                }
+            }
         }
         last_offset_x
     }
@@ -192,16 +250,26 @@ impl<'a> Scheduler<'a> {
         for elem in v {
             // Record the x coordinate for the vertical.
             self.x_coordinates[elem.get_index()] = center_x;
-            // Update the last x value for the row.
+            // Update the last x value for the row. The next node in the row
+            // starts after the separation.
             let level = self.vg.dag.level(*elem);
             let pos = self.vg.pos(*elem);
+            let conn = self.vg.is_connector(*elem);
             if self.order.is_left_to_right() {
-                let side_x = pos.distance_to_right(true);
+                let mut side_x = pos.distance_to_right(true) + self.node_sep;
+                if conn {
+                    side_x = side_x.max(self.edge_sep);
+                }
                 self.last_x_for_row[level] = center_x + side_x;
             } else {
-                let side_x = pos.distance_to_left(true);
+                let mut side_x = pos.distance_to_left(true) + self.node_sep;
+                if conn {
+                    side_x = side_x.max(self.edge_sep);
+                }
                 self.last_x_for_row[level] = center_x - side_x;
             }
+            self.last_connector_for_row[level] =
+                if conn { Some(center_x) } else { None };
             self.sched_idx[level] += 1;
         }
     }
@@ -421,8 +489,10 @@ impl<'a> BK<'a> {
 
             for node in r1 {
                 let node_x = medians[node.get_index()];
+                let group = &self.vg.element(node).group;
                 let mut best_idx: Option<usize> = None;
                 let mut best_delta = f64::INFINITY;
+                let mut best_in_group = false;
 
                 // Scan the predecessors:
                 for pred in self.vg.preds(node) {
@@ -439,19 +509,25 @@ impl<'a> BK<'a> {
                         continue;
                     }
 
-                    // Of the remaining edges, select the closest one.
+                    // Of the remaining edges, select the closest one. Prefer
+                    // predecessors that are in the same group as the node.
                     let delta = (self.vg.pos(*pred).center().x - node_x).abs();
-                    if delta < best_delta {
+                    let in_group = group.is_some()
+                        && *group == self.vg.element(*pred).group;
+                    if (in_group && !best_in_group)
+                        || (in_group == best_in_group && delta < best_delta)
+                    {
                         best_idx = Some(idx);
                         best_delta = delta;
+                        best_in_group = in_group;
                     }
                 }
 
                 // Mark the current node as aligned to the 'best' node on the
                 // previous line.
                 if let Some(idx) = best_idx {
-                    for i in 0..(idx + 1) {
-                        used[i] = true;
+                    for u in used.iter_mut().take(idx + 1) {
+                        *u = true;
                     }
                     align_info.add(node, r0[idx]);
                 }
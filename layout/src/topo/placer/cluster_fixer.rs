@@ -0,0 +1,143 @@
+//! This pass moves the nodes apart to keep the boxes of the clusters clear.
+//! The box of a cluster covers the columns of all of its nodes, in all of
+//! the ranks of the cluster, and the rows only keep the nodes of a cluster
+//! next to each other within each rank. This pass moves the nodes that are
+//! not in the cluster out of the box, and moves the boxes of the clusters
+//! that are not nested in each other apart.
+
+use super::EPSILON;
+use crate::adt::dag::NodeHandle;
+use crate::core::geometry::Point;
+use crate::topo::layout::{VisualGraph, CLUSTER_MARGIN};
+
+/// The nodes that must be kept out of the box of a cluster (a single node,
+/// or the nodes of another cluster), with the ranks and the columns that
+/// they cover.
+struct Span {
+    nodes: Vec<NodeHandle>,
+    ranks: (usize, usize),
+    left: f64,
+    right: f64,
+}
+
+impl Span {
+    /// \returns the span of the nodes \p nodes, with a margin of \p margin
+    /// on the sides.
+    fn new(vg: &VisualGraph, nodes: &[NodeHandle], margin: f64) -> Self {
+        let mut ranks = (usize::MAX, 0);
+        let mut left = f64::INFINITY;
+        let mut right = f64::NEG_INFINITY;
+        for node in nodes {
+            let level = vg.dag.level(*node);
+            ranks = (ranks.0.min(level), ranks.1.max(level));
+            let bb = vg.pos(*node).bbox(false);
+            left = left.min(bb.0.x - margin);
+            right = right.max(bb.1.x + margin);
+        }
+        Self {
+            nodes: nodes.to_vec(),
+            ranks,
+            left,
+            right,
+        }
+    }
+
+    fn shares_ranks(&self, other: &Span) -> bool {
+        self.ranks.0 <= other.ranks.1 && other.ranks.0 <= self.ranks.1
+    }
+
+    /// \returns true if this span comes before \p other in the rows. The
+    /// order of the nodes in a rank that both spans cover decides, and the
+    /// centers of the spans decide if there is no such rank.
+    fn is_left_of(&self, vg: &VisualGraph, other: &Span) -> bool {
+        for a in &self.nodes {
+            for b in &other.nodes {
+                if vg.dag.level(*a) == vg.dag.level(*b) {
+                    return vg.pos(*a).center().x < vg.pos(*b).center().x;
+                }
+            }
+        }
+        self.left + self.right < other.left + other.right
+    }
+}
+
+/// Move the span \p right, and the nodes that come after it in the rows,
+/// to the right by \p delta, to move it away from the span \p left. The
+/// order of the nodes in each row is kept.
+fn separate(vg: &mut VisualGraph, left: &Span, right: &Span, delta: f64) {
+    for level in 0..vg.dag.num_levels() {
+        let row = vg.dag.row(level).clone();
+        let first = if let Option::Some(i) =
+            row.iter().position(|n| right.nodes.contains(n))
+        {
+            i
+        } else if let Option::Some(i) =
+            row.iter().rposition(|n| left.nodes.contains(n))
+        {
+            i + 1
+        } else {
+            row.iter()
+                .filter(|n| vg.pos(**n).center().x < right.left)
+                .count()
+        };
+        for node in &row[first..] {
+            vg.pos_mut(*node).translate(Point::new(delta, 0.));
+        }
+    }
+}
+
+/// Find one node or cluster that overlaps the box of one of the clusters
+/// \p clusters, and move them apart. \returns true if anything moved.
+fn fix_one_overlap(vg: &mut VisualGraph, clusters: &[Vec<NodeHandle>]) -> bool {
+    for (i, cluster) in clusters.iter().enumerate() {
+        let boxed = Span::new(vg, cluster, CLUSTER_MARGIN);
+        let mut obstacles: Vec<Span> = vg
+            .dag
+            .iter()
+            .filter(|n| !cluster.contains(n))
+            .map(|n| Span::new(vg, &[n], 0.))
+            .collect();
+        for (j, other) in clusters.iter().enumerate() {
+            if i != j && other.iter().all(|n| !cluster.contains(n)) {
+                obstacles.push(Span::new(vg, other, CLUSTER_MARGIN));
+            }
+        }
+
+        for obstacle in obstacles {
+            if !obstacle.shares_ranks(&boxed) {
+                continue;
+            }
+            if obstacle.is_left_of(vg, &boxed) {
+                let delta = obstacle.right + CLUSTER_MARGIN - boxed.left;
+                if delta > EPSILON {
+                    separate(vg, &obstacle, &boxed, delta);
+                    return true;
+                }
+            } else {
+                let delta = boxed.right + CLUSTER_MARGIN - obstacle.left;
+                if delta > EPSILON {
+                    separate(vg, &boxed, &obstacle, delta);
+                    return true;
+                }
+            }
+        }
+    }
+    false
+}
+
+pub fn do_it(vg: &mut VisualGraph) {
+    let clusters: Vec<Vec<NodeHandle>> = vg
+        .clusters()
+        .iter()
+        .filter(|c| !c.nodes.is_empty())
+        .map(|c| c.nodes.clone())
+        .collect();
+
+    // Every move makes room for one node or cluster, and may push other
+    // nodes into the boxes. Give up on graphs where the moves don't settle.
+    for _ in 0..(clusters.len() + 1) * 8 {
+        if !fix_one_overlap(vg, &clusters) {
+            return;
+        }
+    }
+}
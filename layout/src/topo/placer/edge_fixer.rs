@@ -40,6 +40,7 @@ pub fn straighten_edge(vg: &mut VisualGraph) -> usize {
     let mut cnt = 0;
 
     let mut to_straighten: Vec<NodeHandle> = Vec::new();
+    let sep = vg.edge_sep();
 
     for row_idx in 1..vg.dag.num_levels() - 1 {
         let row = vg.dag.row(row_idx);
@@ -65,7 +66,7 @@ pub fn straighten_edge(vg: &mut VisualGraph) -> usize {
                     // outgoing edges that create a straight line intersect with
                     // any of the boxes in the current row.
                     for elem in row.iter() {
-                        let rect = vg.pos(*elem).bbox(false);
+                        let rect = inflate(vg.pos(*elem).bbox(false), sep);
                         if segment_rect_intersection(seg, rect) {
                             // The line intersects with some box. Move to the
                             // next candidate.
@@ -80,6 +81,21 @@ pub fn straighten_edge(vg: &mut VisualGraph) -> usize {
         }
     }
 
+    // Straighten the heavy edges first, because straightening an edge may
+    // block the edges next to it.
+    let mut weights = vec![0.; vg.num_nodes()];
+    for (arrow, lst) in vg.edge_list() {
+        // The connectors are the inner nodes of the edge.
+        for node in &lst[1..lst.len() - 1] {
+            weights[node.get_index()] = arrow.weight;
+        }
+    }
+    to_straighten.sort_by(|a, b| {
+        let wa = weights[a.get_index()];
+        let wb = weights[b.get_index()];
+        wb.partial_cmp(&wa).unwrap()
+    });
+
     // Straighten the edges by moving the center block.
     for elem in to_straighten {
         let pred = vg.dag.single_pred(elem).unwrap();
@@ -88,8 +104,13 @@ pub fn straighten_edge(vg: &mut VisualGraph) -> usize {
         let p2 = vg.pos(succ).center();
         let new_pos = p1.add(p2).scale(0.5);
 
+        // The whole box of the connector needs to fit between its neighbors,
+        // and not only its center.
         let bounds = compute_bounds_for_node(vg, elem);
-        if in_range(bounds, new_pos.x) {
+        let pos = vg.pos(elem);
+        let left = new_pos.x - pos.distance_to_left(true);
+        let right = new_pos.x + pos.distance_to_right(true);
+        if in_range(bounds, left) && in_range(bounds, right) {
             vg.pos_mut(elem).set_x(new_pos.x);
             cnt += 1;
         }
@@ -111,18 +132,21 @@ pub fn handle_disconnected_nodes(vg: &mut VisualGraph) -> usize {
                 continue;
             }
 
+            // Keep the node separation, which also keeps the ends of flat
+            // edges apart (these edges are not in the dag).
             let range = compute_bounds_for_node(vg, *elem);
+            let sep = vg.node_sep() + EPSILON;
 
             // Try to align to the left.
             if range.0.is_finite() {
-                vg.pos_mut(*elem).align_to_left(range.0 + EPSILON);
+                vg.pos_mut(*elem).align_to_left(range.0 + sep);
                 cnt += 1;
                 continue;
             }
 
             // Try to align to the right.
             if range.1.is_finite() {
-                vg.pos_mut(*elem).align_to_right(range.1 - EPSILON);
+                vg.pos_mut(*elem).align_to_right(range.1 - sep);
                 cnt += 1;
                 continue;
             }
@@ -179,6 +203,13 @@ pub fn align_self_edges(vg: &mut VisualGraph) -> usize {
 type Segment = (Point, Point);
 type Rect = (Point, Point);
 
+/// \returns the rect \p rect, grown by \p by in each direction. This is used
+/// for keeping the edges away from the boxes.
+fn inflate(rect: Rect, by: f64) -> Rect {
+    let d = Point::splat(by);
+    (rect.0.sub(d), rect.1.add(d))
+}
+
 fn is_intersecting_any(segs: &[Segment], rects: &[Rect]) -> bool {
     for seg in segs {
         for rec in rects {
@@ -195,6 +226,7 @@ pub fn adjust_crossing_edges(vg: &mut VisualGraph) -> usize {
     // A list of nodes to adjust, and the dy.
     let mut to_move: Vec<(NodeHandle, Point)> = Vec::new();
     let len = vg.dag.num_levels();
+    let sep = vg.edge_sep();
 
     let offsets = [
         Point::new(0., 15.),
@@ -254,17 +286,19 @@ pub fn adjust_crossing_edges(vg: &mut VisualGraph) -> usize {
                     let mut pos_all = Vec::new();
                     let mut bounds = Vec::new();
                     if i > 0 {
-                        bounds.push(vg.pos(row[i - 1]).bbox(false));
-                        pos_all.push(vg.pos(row[i - 1]).bbox(false));
+                        let rect = inflate(vg.pos(row[i - 1]).bbox(false), sep);
+                        bounds.push(rect);
+                        pos_all.push(rect);
                     }
                     if i < row.len() - 1 {
-                        bounds.push(vg.pos(row[i + 1]).bbox(false));
-                        pos_all.push(vg.pos(row[i + 1]).bbox(false));
+                        let rect = inflate(vg.pos(row[i + 1]).bbox(false), sep);
+                        bounds.push(rect);
+                        pos_all.push(rect);
                     }
 
                     for e in all.iter() {
                         if *e != pred && *e != succ {
-                            pos_all.push(vg.pos(*e).bbox(false));
+                            pos_all.push(inflate(vg.pos(*e).bbox(false), sep));
                         }
                     }
 
@@ -308,3 +342,57 @@ pub fn do_it(vg: &mut VisualGraph) {
     #[cfg(feature = "log")]
     log::info!("Adjusted crossing {} edges.", cnt);
 }
+
+#[test]
+fn test_straighten_heavy_edges_first() {
+    use crate::core::base::Orientation;
+    use crate::core::style::StyleAttr;
+    use crate::std_shapes::shapes::{Arrow, Element, ShapeKind};
+
+    // Two crossing edges pass through the connectors x and y in the middle
+    // rank. The straight lines of both edges meet at the same point, so only
+    // one of the edges can be straightened.
+    let build = |weights: (f64, f64)| {
+        let mut vg = VisualGraph::new(Orientation::TopToBottom);
+        let mut nodes = Vec::new();
+        for name in ["a", "b", "c", "d"] {
+            let shape = ShapeKind::new_box(name);
+            let look = StyleAttr::simple();
+            let size = Point::new(20., 20.);
+            let elem =
+                Element::create(shape, look, Orientation::TopToBottom, size);
+            nodes.push(vg.add_node(elem));
+        }
+        let mut light = Arrow::simple("x");
+        light.weight = weights.0;
+        let mut heavy = Arrow::simple("y");
+        heavy.weight = weights.1;
+        vg.add_edge(light, nodes[0], nodes[3]);
+        vg.add_edge(heavy, nodes[1], nodes[2]);
+        vg.to_valid_dag();
+        vg.split_text_edges();
+        vg.split_long_edges(true);
+
+        let x = vg.edge_list()[0].1[1];
+        let y = vg.edge_list()[1].1[1];
+        *vg.dag.row_mut(1) = vec![x, y];
+        let locations = [
+            (nodes[0], 0., 0.),
+            (nodes[1], 280., 0.),
+            (x, 100., 100.),
+            (y, 180., 100.),
+            (nodes[2], 0., 200.),
+            (nodes[3], 280., 200.),
+        ];
+        for (node, px, py) in locations {
+            vg.pos_mut(node).move_to(Point::new(px, py));
+        }
+        assert_eq!(straighten_edge(&mut vg), 1);
+        (vg.pos(x).center().x, vg.pos(y).center().x)
+    };
+
+    // The edges are straightened in the order of the rank by default.
+    assert_eq!(build((1., 1.)), (140., 180.));
+    // The heavy edge is straightened first, and blocks the light one.
+    assert_eq!(build((1., 5.)), (100., 140.));
+}
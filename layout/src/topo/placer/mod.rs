@@ -29,6 +29,7 @@ impl BlockKind {
 }
 
 mod bk;
+mod cluster_fixer;
 mod edge_fixer;
 mod move_between_rows;
 mod simple;
@@ -6,6 +6,7 @@ extern crate log;
 
 use crate::topo::layout::VisualGraph;
 use crate::topo::placer::bk::BK;
+use crate::topo::placer::cluster_fixer;
 use crate::topo::placer::edge_fixer;
 use crate::topo::placer::move_between_rows;
 use crate::topo::placer::simple;
@@ -62,9 +63,19 @@ impl<'a> Placer<'a> {
 
         edge_fixer::do_it(self.vg);
 
+        // Keep the nodes out of the boxes of the clusters that they are not
+        // in, in all of the ranks that the boxes cover.
+        cluster_fixer::do_it(self.vg);
+
+        verifier::do_it(self.vg);
+
         // Finalize left-to-right graphs.
         if need_transpose {
             self.vg.transpose();
         }
+
+        if let Option::Some(ratio) = self.vg.ratio() {
+            self.vg.apply_ratio(ratio);
+        }
     }
 }
@@ -24,6 +24,7 @@ pub fn align_to_left(vg: &mut VisualGraph) {
 /// Assign the initial Y coordinates.
 fn assign_y_coordinates(vg: &mut VisualGraph) {
     let mut lowest_point = 0.;
+    let rank_sep = vg.rank_sep();
     for i in 0..vg.dag.num_levels() {
         let current_row = vg.dag.row(i);
 
@@ -41,7 +42,7 @@ fn assign_y_coordinates(vg: &mut VisualGraph) {
             vg.pos_mut(*idx).align_to_top(new_center - height / 2.);
         }
 
-        lowest_point += max_height;
+        lowest_point += max_height + rank_sep;
     }
 }
 
@@ -0,0 +1,89 @@
+//! This module records the time that the phases of the layout take, such as
+//! the ranking, the crossing minimization and the placement. The timings are
+//! only collected when the 'timings' feature is enabled, and are meant to
+//! turn reports of slow layouts into measurements.
+
+use std::time::Duration;
+#[cfg(feature = "timings")]
+use std::time::Instant;
+
+/// The start point of a measured phase.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timer {
+    #[cfg(feature = "timings")]
+    start: Instant,
+}
+
+impl Timer {
+    pub fn start() -> Self {
+        Timer {
+            #[cfg(feature = "timings")]
+            start: Instant::now(),
+        }
+    }
+}
+
+/// The accumulated time of each phase, in the order in which the phases
+/// first ran.
+#[derive(Debug, Clone, Default)]
+pub struct PhaseTimings {
+    phases: Vec<(&'static str, Duration)>,
+}
+
+impl PhaseTimings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the time since \p timer started to the phase \p phase.
+    #[cfg_attr(not(feature = "timings"), allow(unused_variables))]
+    pub(crate) fn record(&mut self, phase: &'static str, timer: Timer) {
+        #[cfg(feature = "timings")]
+        self.add(phase, timer.start.elapsed());
+    }
+
+    /// Add \p time to the phase \p phase.
+    pub fn add(&mut self, phase: &'static str, time: Duration) {
+        if let Option::Some(entry) =
+            self.phases.iter_mut().find(|(name, _)| *name == phase)
+        {
+            entry.1 += time;
+            return;
+        }
+        self.phases.push((phase, time));
+    }
+
+    /// \returns the time that the phase \p phase took, if it was recorded.
+    pub fn get(&self, phase: &str) -> Option<Duration> {
+        self.phases
+            .iter()
+            .find(|(name, _)| *name == phase)
+            .map(|(_, time)| *time)
+    }
+
+    /// \returns the recorded phases and their times.
+    pub fn phases(&self) -> &[(&'static str, Duration)] {
+        &self.phases
+    }
+
+    pub fn clear(&mut self) {
+        self.phases.clear();
+    }
+}
+
+#[test]
+fn test_phase_timings() {
+    let mut timings = PhaseTimings::new();
+    timings.add("rank", Duration::from_millis(3));
+    timings.add("place", Duration::from_millis(5));
+    timings.add("rank", Duration::from_millis(4));
+    assert_eq!(timings.get("rank"), Some(Duration::from_millis(7)));
+    assert_eq!(timings.get("place"), Some(Duration::from_millis(5)));
+    assert_eq!(timings.get("render"), None);
+    assert_eq!(timings.phases().len(), 2);
+    assert_eq!(timings.phases()[0].0, "rank");
+
+    // Recording a phase only measures it with the 'timings' feature.
+    timings.record("render", Timer::start());
+    assert_eq!(timings.get("render").is_some(), cfg!(feature = "timings"));
+}
@@ -9,16 +9,23 @@ use clap::{Arg, ArgAction, Command};
 use gv::parser::DotParser;
 use gv::GraphBuilder;
 use layout::backends::svg::SVGWriter;
-use layout::core::utils::save_to_file;
+use layout::core::base::EdgeRouting;
+use layout::core::color::Color;
 use layout::gv;
 use layout::topo::layout::VisualGraph;
 use std::fs;
+use std::fs::File;
+use std::io::{BufWriter, Write};
 
 struct CLIOptions {
     disable_opt: bool,
     disable_layout: bool,
     output_path: String,
     debug_mode: bool,
+    sort_edges: bool,
+    org_chart: bool,
+    rank_bands: Option<Color>,
+    fit_text: bool,
 }
 
 impl CLIOptions {
@@ -28,21 +35,35 @@ impl CLIOptions {
             disable_layout: false,
             output_path: String::new(),
             debug_mode: false,
+            sort_edges: false,
+            org_chart: false,
+            rank_bands: Option::None,
+            fit_text: false,
         }
     }
 }
 
 fn generate_svg(graph: &mut VisualGraph, options: CLIOptions) {
     let mut svg = SVGWriter::new();
+    svg.set_fit_text(options.fit_text);
+    graph.set_sort_edge_attachments(options.sort_edges);
+    graph.set_rank_bands(options.rank_bands);
+    if options.org_chart {
+        graph.set_edge_routing(EdgeRouting::OrgChart);
+    }
     graph.do_it(
         options.debug_mode,
         options.disable_opt,
         options.disable_layout,
         &mut svg,
     );
-    let content = svg.finalize();
-
-    let res = save_to_file(&options.output_path, &content);
+    // Write the drawing directly to the file, without building a copy of
+    // the whole document in memory.
+    let res = File::create(&options.output_path).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        svg.finalize_to(&mut writer)?;
+        writer.flush()
+    });
     if let Result::Err(err) = res {
         log::error!("Could not write the file {}", options.output_path);
         log::error!("Error {}", err);
@@ -73,6 +94,37 @@ fn main() {
                 .help("Disable the graph optimizations")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("sort-edges")
+                .long("sort-edges")
+                .help("Attach edges to nodes in the order of their targets")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("org-chart")
+                .long("org-chart")
+                .help("Draw the edges as elbow connectors, like in org charts")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("rank-bands")
+                .long("rank-bands")
+                .help("Draw a background band behind every other rank")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("band-color")
+                .long("band-color")
+                .value_name("COLOR")
+                .help("The color of the rank bands")
+                .num_args(1),
+        )
+        .arg(
+            Arg::new("fit-text")
+                .long("fit-text")
+                .help("Stretch the text to the width that the layout measured")
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("a")
                 .short('a')
@@ -104,6 +156,18 @@ fn main() {
     cli.debug_mode = matches.get_flag("d");
     cli.disable_opt = matches.get_flag("no-optz");
     cli.disable_layout = matches.get_flag("no-layout");
+    cli.sort_edges = matches.get_flag("sort-edges");
+    cli.org_chart = matches.get_flag("org-chart");
+    cli.fit_text = matches.get_flag("fit-text");
+    if matches.get_flag("rank-bands") {
+        let name = matches
+            .get_one::<String>("band-color")
+            .map_or("#f2f2f2", |name| name.as_str());
+        cli.rank_bands = Color::from_name(name);
+        if cli.rank_bands.is_none() {
+            log::error!("Unknown band color {}", name);
+        }
+    }
     cli.output_path = matches
         .get_one::<String>("output")
         .cloned()
@@ -127,8 +191,10 @@ fn main() {
             }
             let mut gb = GraphBuilder::new();
             gb.visit_graph(&g);
-            let mut vg = gb.get();
-            generate_svg(&mut vg, cli);
+            match gb.try_get() {
+                Result::Ok(mut vg) => generate_svg(&mut vg, cli),
+                Result::Err(err) => log::error!("Error: {}", err),
+            }
         }
     }
 }
@@ -3,13 +3,33 @@ use layout::core::geometry::Point;
 #[cfg(test)]
 mod tests {
 
+    use layout::backends::svg::SVGWriter;
     use layout::core::geometry::weighted_median;
     use layout::gv::record::parse_record_string;
     use layout::gv::record::print_record;
     use layout::gv::DotParser;
+    use layout::gv::GraphBuilder;
     use layout::gv::Lexer;
     use layout::gv::Token;
     use layout::std_shapes::shapes::RecordDef;
+    use layout::topo::layout::VisualGraph;
+
+    /// Parse and build the graph in \p program.
+    fn build_graph(program: &str) -> VisualGraph {
+        let mut parser = DotParser::new(program);
+        let graph = parser.process().unwrap();
+        let mut gb = GraphBuilder::new();
+        gb.visit_graph(&graph);
+        gb.get()
+    }
+
+    /// Parse, build, layout and render the graph in \p program.
+    fn render_graph(program: &str) -> (VisualGraph, String) {
+        let mut vg = build_graph(program);
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+        (vg, svg.finalize())
+    }
 
     fn is_identifier(t: Token, target: &str) -> bool {
         match t {
@@ -94,6 +114,54 @@ mod tests {
         assert!(is_identifier(t0, "1.12"));
         assert!(matches!(t1, Token::EOF));
     }
+    #[test]
+    fn negative_number_before_punctuation() {
+        // The character after a negative number starts the next token.
+        let mut lexer = Lexer::from_string("[x=-0.2]");
+        assert!(matches!(lexer.next_token(), Token::OpenBracket));
+        assert!(is_identifier(lexer.next_token(), "x"));
+        assert!(matches!(lexer.next_token(), Token::Equal));
+        assert!(is_identifier(lexer.next_token(), "-0.2"));
+        assert!(matches!(lexer.next_token(), Token::CloseBracket));
+        assert!(matches!(lexer.next_token(), Token::EOF));
+    }
+
+    #[test]
+    fn unquoted_numeric_values() {
+        use layout::gv::parser::ast::Stmt;
+        let mut lexer = Lexer::from_string("0.5in 8,10 -1.2,3.4 1, 2");
+        assert!(is_identifier(lexer.next_token(), "0.5in"));
+        assert!(is_identifier(lexer.next_token(), "8,10"));
+        assert!(is_identifier(lexer.next_token(), "-1.2,3.4"));
+        // A comma that is followed by a space separates the values.
+        assert!(is_identifier(lexer.next_token(), "1"));
+        assert!(matches!(lexer.next_token(), Token::Comma));
+        assert!(is_identifier(lexer.next_token(), "2"));
+        assert!(matches!(lexer.next_token(), Token::EOF));
+
+        let program = "digraph { size=8,10; \
+            a [width=0.5, pos=1.2,3.4, height=0.5in]; }";
+        let graph = DotParser::new(program).process().unwrap();
+        let mut attrs = Vec::new();
+        for stmt in graph.list.list.iter() {
+            match stmt {
+                Stmt::Attribute(x) => attrs.extend(x.list.iter().cloned()),
+                Stmt::Node(x) => attrs.extend(x.list.iter().cloned()),
+                _ => {}
+            }
+        }
+        let expected = [
+            ("size", "8,10"),
+            ("width", "0.5"),
+            ("pos", "1.2,3.4"),
+            ("height", "0.5in"),
+        ];
+        let expected: Vec<(String, String)> = expected
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        assert_eq!(attrs, expected);
+    }
 
     #[test]
     fn simple_program() {
@@ -179,6 +247,22 @@ mod tests {
         panic!();
     }
 
+    #[test]
+    fn parse_error_location() {
+        let err = DotParser::new("graph { a = }").process().unwrap_err();
+        assert_eq!((err.pos, err.line, err.col), (12, 1, 13));
+        assert_eq!(err.message, "Expected identifier.");
+        assert_eq!(err.to_string(), "1:13: Expected identifier.");
+
+        // Lines and columns start at 1, and count characters.
+        let err = DotParser::new("graph {\n  \"é\" = }")
+            .process()
+            .unwrap_err();
+        assert_eq!((err.line, err.col), (2, 9));
+        let err = DotParser::new("graph { a -> b;").process().unwrap_err();
+        assert_eq!((err.pos, err.line, err.col), (15, 1, 16));
+    }
+
     #[test]
     fn parse_record0() {
         let desc = "hello&#92;nworld |{ b |{c|<here> d|e}| f}| g | h";
@@ -221,6 +305,2442 @@ mod tests {
         }
     }
 
+    #[test]
+    fn parse_record_escapes() {
+        let res = parse_record_string("a\\|b");
+        if let RecordDef::Array(arr) = res {
+            assert_eq!(arr.len(), 1, "expecting one field");
+            if let RecordDef::Text(label, port) = &arr[0] {
+                assert_eq!(label, "a|b");
+                assert!(port.is_none());
+            } else {
+                panic!();
+            }
+        } else {
+            panic!();
+        }
+
+        // Escaped braces and angle brackets are not structure or ports.
+        let res = parse_record_string("<p> \\{x\\}|\\<y\\>");
+        if let RecordDef::Array(arr) = res {
+            assert_eq!(arr.len(), 2, "expecting two fields");
+            if let RecordDef::Text(label, port) = &arr[0] {
+                assert_eq!(label, "{x}");
+                assert_eq!(port.as_deref(), Some("p"));
+            } else {
+                panic!();
+            }
+            if let RecordDef::Text(label, port) = &arr[1] {
+                assert_eq!(label, "<y>");
+                assert!(port.is_none());
+            } else {
+                panic!();
+            }
+        } else {
+            panic!();
+        }
+
+        // The escapes in a DOT string reach the record parser.
+        let (_, svg) = render_graph(
+            "digraph { a [shape=record, label=\"x\\|y|z\"]; \
+            b [label=\"p\\|q\"]; }",
+        );
+        assert!(svg.contains(">x|y<"));
+        assert!(svg.contains(">z<"));
+        assert!(svg.contains(">p|q<"));
+    }
+
+    #[test]
+    fn group_alignment() {
+        use layout::adt::dag::NodeHandle;
+        let delta = |program: &str| {
+            let (vg, _) = render_graph(program);
+            // The nodes are created in the order: x, c, a.
+            let c = vg.pos(NodeHandle::new(1)).center().x;
+            let a = vg.pos(NodeHandle::new(2)).center().x;
+            (a - c).abs()
+        };
+        let grouped =
+            delta("digraph { x -> c; a -> c; a [group=g]; c [group=g]; }");
+        let plain = delta("digraph { x -> c; a -> c; }");
+        assert!(grouped < 0.001);
+        assert!(grouped < plain);
+    }
+
+    #[test]
+    fn edge_len_stretches_ranks() {
+        use layout::adt::dag::NodeHandle;
+        let gap = |program: &str| {
+            let (vg, _) = render_graph(program);
+            // The nodes are created in the order: a, b, c.
+            let a = vg.dag.level(NodeHandle::new(0));
+            let c = vg.dag.level(NodeHandle::new(2));
+            c - a
+        };
+        assert_eq!(gap("digraph { a -> b; a -> c; }"), 1);
+        assert_eq!(gap("digraph { a -> b; a -> c [len=0.5]; }"), 1);
+        assert_eq!(gap("digraph { a -> b; a -> c [len=5]; }"), 4);
+        // The node 'c' can't sink below its successor 'd'.
+        assert_eq!(
+            gap("digraph { a; b; c; a -> b -> d; a -> c [len=5]; c -> d; }"),
+            1
+        );
+        // The length is measured in inches, and wider ranks fit the same
+        // length in fewer ranks.
+        assert_eq!(gap("digraph { ranksep=1; a -> b; a -> c [len=5]; }"), 2);
+
+        // Stretched edges next to parallel and reversed edges.
+        render_graph("digraph { a -> b [len=2]; a -> b; }");
+        render_graph("digraph { a -> b; b -> a [len=3]; }");
+    }
+
+    #[test]
+    fn shared_text_cache() {
+        use layout::core::geometry::TextSizeCache;
+        let program = "digraph { a -> b [label=\"edge\"]; b [shape=box]; \
+                       r [shape=record, label=\"x|y\"]; }";
+        let cache = TextSizeCache::new();
+        let mut outputs = Vec::new();
+        for _ in 0..2 {
+            let ast = DotParser::new(program).process().unwrap();
+            let mut gb = GraphBuilder::new();
+            gb.set_text_cache(cache.clone());
+            gb.visit_graph(&ast);
+            let mut vg = gb.get();
+            let mut svg = SVGWriter::new();
+            vg.do_it(false, false, false, &mut svg);
+            outputs.push(svg.finalize());
+        }
+        // The node labels, the record fields and the edge label are measured
+        // once.
+        assert_eq!(cache.len(), 5);
+        assert_eq!(outputs[0], outputs[1]);
+        assert_eq!(outputs[0], render_graph(program).1);
+    }
+
+    #[test]
+    fn point_shape() {
+        use layout::adt::dag::NodeHandle;
+        let (vg, svg) = render_graph(
+            "digraph { p [shape=point, label=\"hidden\"]; p -> a; }",
+        );
+        let size = vg.pos(NodeHandle::new(0)).size(false);
+        assert_eq!(size.x, 3.6);
+        assert_eq!(size.x, size.y);
+        assert!(svg.contains("rx=\"1.8\" ry=\"1.8\" fill=\"#000000ff\""));
+        assert!(!svg.contains("hidden"));
+
+        let (vg, _) = render_graph("digraph { p [shape=point, width=0.5]; }");
+        assert_eq!(vg.pos(NodeHandle::new(0)).size(false).x, 36.);
+    }
+
+    #[test]
+    fn open_arrow_head() {
+        let (_, svg) = render_graph("digraph { a -> b [arrowhead=onormal]; }");
+        assert!(svg.contains("marker-end=\"url(#endopenarrow)\""));
+        assert!(svg.contains("fill=\"none\" stroke=\"context-stroke\""));
+        assert!(!svg.contains("url(#endarrow)"));
+
+        let (_, svg) = render_graph("digraph { a -> b [arrowhead=normal]; }");
+        assert!(svg.contains("marker-end=\"url(#endarrow)\""));
+        assert!(!svg.contains("fill=\"none\" stroke=\"context-stroke\""));
+    }
+
+    #[test]
+    fn uml_arrow_heads() {
+        let heads = [
+            ("empty", "endopenarrow"),
+            ("diamond", "enddiamond"),
+            ("odiamond", "endopendiamond"),
+            ("vee", "endvee"),
+            ("open", "endvee"),
+            ("box", "endbox"),
+            ("obox", "endobox"),
+            ("inv", "endinv"),
+            ("oinv", "endoinv"),
+            ("tee", "endtee"),
+        ];
+        for (name, marker) in heads {
+            let program = format!("digraph {{ a -> b [arrowhead={}]; }}", name);
+            let (_, svg) = render_graph(&program);
+            assert!(svg.contains(&format!("marker-end=\"url(#{})\"", marker)));
+        }
+    }
+
+    #[test]
+    fn box_and_tee_arrow_heads() {
+        let program =
+            "digraph { a -> b [dir=both, arrowhead=obox, arrowtail=tee]; }";
+        let (_, svg) = render_graph(program);
+        assert!(svg.contains(
+            "marker-start=\"url(#starttee)\" marker-end=\"url(#endobox)\""
+        ));
+        // The open box is not filled, and the tee is a filled bar.
+        assert!(svg.contains(
+            "points=\"0 0, 7 0, 7 7, 0 7\" fill=\"none\" stroke=\"context-stroke\""
+        ));
+        assert!(svg
+            .contains("points=\"3 0, 0 0, 0 7, 3 7\" fill=\"context-stroke\""));
+
+        // The unsupported shapes fall back to the default head.
+        let mut parser = DotParser::new("digraph { a -> b [arrowhead=crow]; }");
+        let graph = parser.process().unwrap();
+        let mut gb = GraphBuilder::new();
+        gb.visit_graph(&graph);
+        let (_, warnings) = gb.get_with_warnings();
+        assert!(warnings.iter().any(|w| w.contains("\"crow\"")));
+    }
+
+    #[test]
+    fn arrow_size_and_direction() {
+        let program = "digraph { a -> b [arrowsize=2]; c -> d; }";
+        let (_, svg) = render_graph(program);
+        // The scaled head has its own marker, with twice the dimensions.
+        assert!(svg.contains("marker-end=\"url(#endarrow-2)\""));
+        assert!(svg.contains("marker-end=\"url(#endarrow)\""));
+        assert!(svg.contains(
+            "<marker id=\"endarrow-2\" markerWidth=\"20\" markerHeight=\"14\""
+        ));
+        assert!(svg.contains(
+            "<marker id=\"endarrow\" markerWidth=\"10\" markerHeight=\"7\""
+        ));
+        assert!(svg.contains("points=\"0 0, 20 7, 0 14\""));
+
+        let program = "digraph { a -> b [dir=both, arrowtail=diamond]; \
+                       c -> d [dir=back]; e -> f [dir=none]; }";
+        let (_, svg) = render_graph(program);
+        assert!(svg.contains("marker-start=\"url(#startdiamond)\""));
+        assert!(svg.contains("marker-start=\"url(#startarrow)\""));
+        assert_eq!(svg.matches("marker-end=").count(), 1);
+    }
+
+    #[test]
+    fn mixed_head_and_tail_shapes() {
+        // The edge b -> a is reversed, and is drawn from a to b.
+        let program = "digraph { \
+            a -> b [dir=both, arrowhead=diamond, arrowtail=dot]; \
+            b -> a [dir=both, arrowhead=odot, arrowtail=vee]; }";
+        let (_, svg) = render_graph(program);
+        assert!(svg.contains(
+            "marker-start=\"url(#startdot)\" marker-end=\"url(#enddiamond)\""
+        ));
+        assert!(svg.contains(
+            "marker-start=\"url(#startodot)\" marker-end=\"url(#endvee)\""
+        ));
+        assert!(svg.contains(
+            "<circle cx=\"3.5\" cy=\"3.5\" r=\"3.5\" \
+            fill=\"context-stroke\" />"
+        ));
+        assert!(svg.contains(
+            "<circle cx=\"3.5\" cy=\"3.5\" r=\"3.5\" \
+            fill=\"none\" stroke=\"context-stroke\" />"
+        ));
+    }
+
+    #[test]
+    fn org_chart_edges() {
+        use layout::core::base::EdgeRouting;
+        use layout::core::geometry::Point;
+
+        // Returns the points of the paths of the edges in the drawing.
+        let get_paths = |svg: &str| -> Vec<Vec<Point>> {
+            let mut paths = Vec::new();
+            for part in svg.split(" d=\"").skip(1) {
+                let d = &part[..part.find('"').unwrap()];
+                let nums: Vec<f64> = d
+                    .split([' ', ','])
+                    .filter_map(|x| x.parse::<f64>().ok())
+                    .collect();
+                let mut points: Vec<Point> =
+                    nums.chunks(2).map(|p| Point::new(p[0], p[1])).collect();
+                points.dedup_by(|a, b| a.x == b.x && a.y == b.y);
+                paths.push(points);
+            }
+            paths
+        };
+
+        for rankdir in ["TB", "LR"] {
+            let program = format!(
+                "digraph {{ rankdir={}; p -> a; p -> b; p -> c; }}",
+                rankdir
+            );
+            let mut vg = build_graph(&program);
+            vg.set_edge_routing(EdgeRouting::OrgChart);
+            let mut svg = SVGWriter::new();
+            vg.do_it(false, false, false, &mut svg);
+            let paths = get_paths(&svg.finalize());
+            assert_eq!(paths.len(), 3);
+
+            // Work in the coordinates of a top-to-bottom graph.
+            let lr = rankdir == "LR";
+            let flip = |p: &Point| if lr { p.transpose() } else { *p };
+            let mut buses = Vec::new();
+            for path in &paths {
+                let path: Vec<Point> = path.iter().map(flip).collect();
+                // The path is made of vertical and horizontal segments. The
+                // outer edges drop to the bus, cross, and drop to the child.
+                for seg in path.windows(2) {
+                    assert!(seg[0].x == seg[1].x || seg[0].y == seg[1].y);
+                }
+                assert_eq!(path[0].x, path[1].x);
+                // A child that is right below the parent has a straight edge.
+                if path.len() == 2 {
+                    continue;
+                }
+                assert_eq!(path.len(), 4);
+                buses.push(path[1].y);
+            }
+            // The edges share the same horizontal bus, and it spans the
+            // children.
+            assert!(buses.len() >= 2);
+            assert!(buses.iter().all(|y| *y == buses[0]));
+            let xs: Vec<f64> =
+                paths.iter().map(|p| flip(&p[p.len() - 1]).x).collect();
+            assert!(xs[0] != xs[1] && xs[1] != xs[2] && xs[0] != xs[2]);
+        }
+    }
+
+    #[test]
+    fn line_caps_and_joins() {
+        use layout::core::base::Orientation;
+        use layout::core::style::{LineCap, LineJoin, StyleAttr};
+        use layout::std_shapes::render::get_shape_size;
+        use layout::std_shapes::shapes::{Arrow, Element, ShapeKind};
+
+        // Edges have round ends and corners, and shapes keep the defaults.
+        let (_, svg) = render_graph("digraph { a -> b; }");
+        let path = svg.lines().find(|l| l.contains("<path id=")).unwrap();
+        assert!(path.contains("stroke-linecap=\"round\""));
+        assert!(path.contains("stroke-linejoin=\"round\""));
+        assert_eq!(svg.matches("stroke-linecap=").count(), 1);
+
+        let dir = Orientation::TopToBottom;
+        let mut vg = VisualGraph::new(dir);
+        let mut look = StyleAttr::simple();
+        look.line_join = Option::Some(LineJoin::Bevel);
+        let shape = ShapeKind::new_box("a");
+        let size = get_shape_size(dir, &shape, 14, false);
+        let a = vg.add_node(Element::create(shape, look, dir, size));
+        let shape = ShapeKind::new_box("b");
+        let size = get_shape_size(dir, &shape, 14, false);
+        let b =
+            vg.add_node(Element::create(shape, StyleAttr::simple(), dir, size));
+        let mut arrow = Arrow::simple("");
+        arrow.look.line_cap = Option::Some(LineCap::Butt);
+        arrow.look.line_join = Option::Some(LineJoin::Miter);
+        vg.add_edge(arrow, a, b);
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+        let svg = svg.finalize();
+        let path = svg.lines().find(|l| l.contains("<path id=")).unwrap();
+        assert!(path.contains("stroke-linecap=\"butt\""));
+        assert!(path.contains("stroke-linejoin=\"miter\""));
+        assert_eq!(svg.matches("stroke-linejoin=\"bevel\"").count(), 1);
+    }
+
+    #[test]
+    fn ortho_edges() {
+        use layout::adt::dag::NodeHandle;
+        use layout::core::geometry::Point;
+
+        for rankdir in ["TB", "LR", "BT"] {
+            let program = format!(
+                "digraph {{ rankdir={}; splines=ortho; a -> b; a -> c; \
+                 b -> m; c -> m; a -> m; m [shape=box]; \
+                 b -> c [constraint=false]; }}",
+                rankdir
+            );
+            let (vg, svg) = render_graph(&program);
+            let boxes: Vec<(Point, Point)> = (0..vg.num_nodes())
+                .filter(|i| !vg.is_connector(NodeHandle::new(*i)))
+                .map(|i| vg.pos(NodeHandle::new(i)).bbox(false))
+                .collect();
+
+            let mut count = 0;
+            for part in svg.split(" d=\"").skip(1) {
+                let d = &part[..part.find('"').unwrap()];
+                // The paths are made of straight lines.
+                assert!(d.starts_with("M ") && !d.contains('C'));
+                let nums: Vec<f64> = d
+                    .split([' ', ','])
+                    .filter_map(|x| x.parse::<f64>().ok())
+                    .collect();
+                let points: Vec<Point> =
+                    nums.chunks(2).map(|p| Point::new(p[0], p[1])).collect();
+                assert!(points.len() >= 2);
+                for seg in points.windows(2) {
+                    let (a, b) = (seg[0], seg[1]);
+                    assert!(a.x == b.x || a.y == b.y, "{}", d);
+                    // The segments don't pass through the nodes.
+                    let lo = Point::new(a.x.min(b.x), a.y.min(b.y));
+                    let hi = Point::new(a.x.max(b.x), a.y.max(b.y));
+                    for bb in &boxes {
+                        let inside = lo.x < bb.1.x - 0.01
+                            && hi.x > bb.0.x + 0.01
+                            && lo.y < bb.1.y - 0.01
+                            && hi.y > bb.0.y + 0.01;
+                        assert!(!inside, "{} crosses {:?}", d, bb);
+                    }
+                }
+                count += 1;
+            }
+            assert_eq!(count, 6);
+        }
+    }
+
+    #[test]
+    fn no_edges() {
+        use layout::adt::dag::NodeHandle;
+
+        let (_, svg) = render_graph("digraph { a -> b; }");
+        assert!(svg.contains("<path"));
+
+        // The edges still place the nodes in ranks, but are not drawn.
+        for splines in ["none", "false"] {
+            let program = format!(
+                "digraph {{ splines={}; a -> b -> c; a -> d; }}",
+                splines
+            );
+            let (vg, svg) = render_graph(&program);
+            let y = |i| vg.pos(NodeHandle::new(i)).center().y;
+            assert!(y(0) < y(1) && y(1) < y(2) && y(0) < y(3));
+            assert!(!svg.contains("<path"));
+            assert!(svg.contains("<ellipse"));
+        }
+    }
+
+    #[test]
+    fn polygon_shapes() {
+        use layout::adt::dag::NodeHandle;
+        use layout::std_shapes::shapes::{PolygonDef, ShapeKind};
+        let program = "digraph { a [shape=polygon, sides=5, skew=0.4, \
+                       distortion=-0.2]; b [shape=parallelogram]; \
+                       c [shape=polygon]; a -> b -> c; }";
+        let (vg, svg) = render_graph(program);
+        let poly = |i: usize| match &vg.element(NodeHandle::new(i)).shape {
+            ShapeKind::Polygon(_, poly) => poly.clone(),
+            _ => panic!("expected a polygon"),
+        };
+        assert_eq!(poly(0), PolygonDef::new(5, 0.4, -0.2));
+        assert_eq!(poly(1), PolygonDef::new(4, 0.6, 0.));
+        assert_eq!(poly(2), PolygonDef::new(4, 0., 0.));
+        // The arrow head marker is also a polygon.
+        let shapes = svg.lines().filter(|l| l.contains("<polygon points="));
+        assert_eq!(shapes.filter(|l| l.contains("stroke-width")).count(), 3);
+    }
+
+    #[test]
+    fn default_polygon_drawing() {
+        use layout::core::format::{ClipHandle, RenderBackend};
+        use layout::core::geometry::Point;
+        use layout::core::style::{LineStyleKind, StyleAttr};
+        use layout::std_shapes::shapes::LineEndKind;
+
+        // A backend that only records the lines that it draws.
+        #[derive(Default)]
+        struct Lines(Vec<(Point, Point)>);
+        impl RenderBackend for Lines {
+            fn draw_rect(
+                &mut self,
+                _: Point,
+                _: Point,
+                _: &StyleAttr,
+                _: Option<String>,
+                _: Option<ClipHandle>,
+            ) {
+            }
+            fn draw_line(
+                &mut self,
+                start: Point,
+                stop: Point,
+                _: &StyleAttr,
+                _: Option<String>,
+            ) {
+                self.0.push((start, stop));
+            }
+            fn draw_circle(
+                &mut self,
+                _: Point,
+                _: Point,
+                _: &StyleAttr,
+                _: Option<String>,
+            ) {
+            }
+            fn draw_text(&mut self, _: Point, _: &str, _: &StyleAttr) {}
+            fn draw_arrow(
+                &mut self,
+                _: &[(Point, Point)],
+                _: LineStyleKind,
+                _: (LineEndKind, LineEndKind),
+                _: &StyleAttr,
+                _: Option<String>,
+                _: &str,
+            ) {
+            }
+            fn create_clip(&mut self, _: Point, _: Point, _: usize) -> usize {
+                0
+            }
+        }
+
+        // Backends without polygons draw the outline with lines.
+        let mut vg = build_graph("digraph { a [shape=polygon, sides=5]; }");
+        let mut lines = Lines::default();
+        vg.do_it(false, false, false, &mut lines);
+        assert_eq!(lines.0.len(), 5);
+        for i in 0..5 {
+            assert_eq!(lines.0[i].1, lines.0[(i + 1) % 5].0);
+        }
+    }
+
+    #[test]
+    fn graph_ratio() {
+        let aspect = |program: &str| {
+            let (vg, _) = render_graph(program);
+            let bb = vg.bounding_box();
+            (bb.1.y - bb.0.y) / (bb.1.x - bb.0.x)
+        };
+        let plain = aspect("digraph { a -> b; a -> c; a -> d; }");
+        let tall = aspect("digraph { ratio=2; a -> b; a -> c; a -> d; }");
+        let wide = aspect("digraph { ratio=0.1; a -> b; a -> c; a -> d; }");
+        assert!(plain < 1.);
+        assert!((tall - 2.).abs() < 0.001);
+        assert!((wide - 0.1).abs() < 0.001);
+        // The keywords, which fit the drawing to its size, are not supported
+        // and are ignored, like values that are not finite.
+        for ratio in ["fill", "compress", "inf", "NaN"] {
+            let program = format!(
+                "digraph {{ ratio={}; a -> b; a -> c; a -> d; }}",
+                ratio
+            );
+            assert_eq!(aspect(&program), plain);
+        }
+    }
+
+    #[test]
+    fn overlapping_nodes() {
+        use layout::adt::dag::NodeHandle;
+        let program = "digraph { a -> b; a -> c [label=\"x\"]; b -> d; }";
+        let (mut vg, _) = render_graph(program);
+        assert!(vg.overlapping_nodes().is_empty());
+
+        // Move 'd' on top of 'a'.
+        let a = NodeHandle::new(0);
+        let d = NodeHandle::new(3);
+        let center = vg.pos(a).center();
+        vg.pos_mut(d).move_to(center);
+        let overlaps = vg.overlapping_nodes();
+        assert_eq!(overlaps.len(), 1);
+        let pair = overlaps[0];
+        assert!(pair == (a, d) || pair == (d, a));
+    }
+
+    #[test]
+    fn edges_under_translucent_nodes() {
+        let program = "digraph { outputorder=edgesfirst; a -> b; \
+            a [style=filled, fillcolor=\"#4682b480\"]; }";
+        let (_, svg) = render_graph(program);
+        assert!(svg.contains("fill=\"#4682b4\" fill-opacity=\"0.502\""));
+        // The edge is drawn below the nodes.
+        assert!(svg.find("<path").unwrap() < svg.find("<ellipse").unwrap());
+
+        let (_, svg) = render_graph("digraph { a -> b; }");
+        assert!(svg.find("<path").unwrap() > svg.find("<ellipse").unwrap());
+    }
+
+    #[test]
+    fn node_defaults_at_first_mention() {
+        use layout::adt::dag::NodeHandle;
+        use layout::std_shapes::shapes::ShapeKind;
+        let program = "digraph { a -> b; node [shape=box]; c -> d; \
+                       b [color=red]; c [shape=circle]; }";
+        let vg = build_graph(program);
+        let is_box = |i: usize| {
+            matches!(vg.element(NodeHandle::new(i)).shape, ShapeKind::Box(_))
+        };
+        // The nodes are created in the order: a, b, c, d.
+        assert!(!is_box(0));
+        // Declaring 'b' after the new default does not change its shape.
+        assert!(!is_box(1));
+        assert!(!is_box(2));
+        assert!(is_box(3));
+        let red = vg.element(NodeHandle::new(1)).look.line_color;
+        assert_eq!(red.to_web_color(), "#ff0000ff");
+    }
+
+    #[test]
+    fn filled_node_defaults_for_edge_only_nodes() {
+        use layout::adt::dag::NodeHandle;
+        let program = "digraph { node [style=filled, fillcolor=lightblue]; \
+                       a -> b; subgraph { c -> d; } e [fillcolor=red]; }";
+        let vg = build_graph(program);
+        let fill = |i: usize| {
+            let look = &vg.element(NodeHandle::new(i)).look;
+            look.fill_color.map(|c| c.to_web_color())
+        };
+        // The nodes are created in the order: a, b, c, d, e.
+        let blue = Option::Some("#add8e6ff".to_string());
+        for i in 0..4 {
+            assert_eq!(fill(i), blue);
+        }
+        // Explicit attributes override the inherited fill color.
+        assert_eq!(fill(4), Option::Some("#ff0000ff".to_string()));
+
+        let (_, svg) = render_graph(program);
+        assert_eq!(svg.matches("fill=\"#add8e6ff\"").count(), 4);
+    }
+
+    #[test]
+    fn pinned_node_rank() {
+        use layout::adt::dag::NodeHandle;
+        // The nodes are created in the order: a, b, c, d, e.
+        let program = "digraph { a -> b; b -> c; d -> c; e; }";
+        let mut vg = build_graph(program);
+        let (a, b, c, d, e) = (0, 1, 2, 3, 4);
+        let h = NodeHandle::new;
+        vg.set_node_rank(h(d), 3);
+        vg.set_node_rank(h(e), 5);
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+
+        // The pinned nodes stay at their rank, and 'c' moves below 'd'.
+        assert_eq!(vg.dag.level(h(d)), 3);
+        assert_eq!(vg.dag.level(h(e)), 5);
+        assert_eq!(vg.dag.level(h(c)), 4);
+        assert!(vg.dag.level(h(a)) < vg.dag.level(h(b)));
+        let y = |n: usize| vg.pos(h(n)).center().y;
+        assert!(y(d) < y(c) && y(c) < y(e));
+        let bands = vg.rank_bands();
+        assert!(bands.iter().all(|b| b.0.y.is_finite() && b.1.y.is_finite()));
+
+        // An edge from a node to a node that is pinned above it is reversed.
+        let mut vg = build_graph("digraph { a -> b; b -> c; }");
+        vg.set_node_rank(h(1), 2);
+        vg.set_node_rank(h(2), 0);
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+        assert_eq!(vg.dag.level(h(2)), 0);
+        assert_eq!(vg.dag.level(h(1)), 2);
+
+        // A pin that the edges can't satisfy places the node right below its
+        // predecessors.
+        let mut vg = build_graph("digraph { a -> b; b -> c; }");
+        vg.set_node_rank(h(2), 1);
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+        assert_eq!(vg.dag.level(h(2)), 2);
+    }
+
+    #[test]
+    fn palette_by_category() {
+        use layout::adt::dag::NodeHandle;
+        use layout::std_shapes::shapes::ShapeKind;
+        let mut vg = build_graph("digraph { a1 -> b1; a2 -> b2; a3 -> c1; }");
+        // Categorize the nodes by the first letter of their label.
+        vg.assign_palette_by(|elem| match &elem.shape {
+            ShapeKind::Circle(label) => label.chars().next(),
+            _ => None,
+        });
+        vg.assign_edge_palette_by(|arrow| arrow.text.clone());
+
+        // The nodes are created in the order: a1, b1, a2, b2, a3, c1.
+        let fill = |i: usize| {
+            let look = &vg.element(NodeHandle::new(i)).look;
+            look.fill_color.unwrap().to_web_color()
+        };
+        assert_eq!(fill(0), fill(2));
+        assert_eq!(fill(0), fill(4));
+        assert_eq!(fill(1), fill(3));
+        assert_ne!(fill(0), fill(1));
+        assert_ne!(fill(0), fill(5));
+        assert_ne!(fill(1), fill(5));
+    }
+
+    #[test]
+    fn bold_edges() {
+        let width = |attrs: &str| {
+            let program = format!("digraph {{ a -> b [{}]; }}", attrs);
+            let (_, svg) = render_graph(&program);
+            let tag = "stroke-width=\"";
+            let idx = svg.find("<path").unwrap();
+            let idx = idx + svg[idx..].find(tag).unwrap() + tag.len();
+            let num = svg[idx..].split('"').next().unwrap();
+            num.parse::<usize>().unwrap()
+        };
+        assert_eq!(width(""), 1);
+        assert_eq!(width("style=bold"), 2);
+        assert_eq!(width("style=\"bold,dashed\""), 2);
+        assert_eq!(width("style=bold, penwidth=5"), 5);
+    }
+
+    #[test]
+    fn unknown_shape_warning() {
+        let program = "digraph { a [shape=hexagonn]; b [shape=ellipse]; \
+            a -> b [arrowhead=crow]; }";
+        let ast = DotParser::new(program).process().unwrap();
+        let mut gb = GraphBuilder::new();
+        gb.visit_graph(&ast);
+        let (vg, warnings) = gb.get_with_warnings();
+        assert_eq!(vg.num_nodes(), 2);
+        assert_eq!(warnings.len(), 2);
+        assert!(warnings[0].contains("hexagonn"));
+        assert!(warnings[1].contains("crow"));
+    }
+
+    #[test]
+    fn dashed_node_borders() {
+        let (_, svg) = render_graph(
+            "digraph { a [shape=box, style=dashed]; \
+            b [shape=box, style=\"filled,dashed\", penwidth=3]; \
+            c [style=dotted]; d [shape=box]; }",
+        );
+        let dashes: Vec<&str> = svg
+            .split("stroke-dasharray=\"")
+            .skip(1)
+            .map(|s| s.split('"').next().unwrap())
+            .collect();
+        assert_eq!(dashes, vec!["6,4", "18,12", "1,4"]);
+    }
+
+    #[test]
+    fn dotted_and_dashed_edges() {
+        let dashes = |style: &str| -> Vec<String> {
+            let program = format!("digraph {{ a -> b [style={}]; }}", style);
+            let (_, svg) = render_graph(&program);
+            svg.split("stroke-dasharray=\"")
+                .skip(1)
+                .map(|s| s.split('"').next().unwrap().to_string())
+                .collect()
+        };
+        assert_eq!(dashes("dashed"), vec!["6,4"]);
+        assert_eq!(dashes("dotted"), vec!["1,4"]);
+        assert!(dashes("solid").is_empty());
+    }
+
+    #[test]
+    fn parallel_edge_colors() {
+        let strokes = |program: &str| -> Vec<String> {
+            let (_, svg) = render_graph(program);
+            svg.split("<path id=")
+                .skip(1)
+                .map(|s| s.split("stroke=\"").nth(1).unwrap().to_string())
+                .map(|s| s.split('"').next().unwrap().to_string())
+                .collect()
+        };
+        let colors = strokes("digraph { a -> b [color=\"green:yellow\"]; }");
+        assert_eq!(colors, vec!["#008000ff", "#ffff00ff"]);
+        let colors = strokes("digraph { a -> b [color=green]; }");
+        assert_eq!(colors, vec!["#008000ff"]);
+
+        // The strokes are drawn next to each other.
+        use layout::core::geometry::Point;
+        use layout::std_shapes::render::render_arrow_path;
+        let vg = build_graph("digraph { a -> b [color=\"red:blue:green\"]; }");
+        let path = [
+            (Point::new(0., 0.), Point::new(0., 10.)),
+            (Point::new(0., 90.), Point::new(0., 100.)),
+        ];
+        let mut svg = SVGWriter::new();
+        let arrow = &vg.edge_list()[0].0;
+        assert_eq!(arrow.colors.len(), 3);
+        render_arrow_path(&mut svg, false, &path, arrow);
+        let svg = svg.finalize();
+        assert!(svg.contains("M 2 0 C 2 10, 2 90, 2 100"));
+        assert!(svg.contains("M 0 0 C 0 10, 0 90, 0 100"));
+        assert!(svg.contains("M -2 0 C -2 10, -2 90, -2 100"));
+
+        // The segments of straight lines move along their own normals, and
+        // meet at the corners.
+        let path = [
+            (Point::new(0., 0.), Point::new(0., 0.)),
+            (Point::new(0., 50.), Point::new(0., 50.)),
+            (Point::new(100., 50.), Point::new(100., 50.)),
+        ];
+        let mut svg = SVGWriter::new();
+        render_arrow_path(&mut svg, false, &path, arrow);
+        let svg = svg.finalize();
+        assert!(svg.contains("M 2 0 L 2 48 L 100 48"));
+        assert!(svg.contains("M 0 0 L 0 50 L 100 50"));
+        assert!(svg.contains("M -2 0 L -2 52 L 100 52"));
+
+        // The strokes of orthogonal edges are horizontal and vertical.
+        let (_, svg) = render_graph(
+            "digraph { splines=ortho; a -> b [color=\"red:blue\"]; \
+             a -> c; a -> d; }",
+        );
+        let points = |color: &str| -> Vec<Point> {
+            let part = svg.split("<path id=").find(|s| s.contains(color));
+            let d = part.unwrap().split(" d=\"").nth(1).unwrap();
+            let d = &d[..d.find('"').unwrap()];
+            let nums: Vec<f64> =
+                d.split(' ').filter_map(|x| x.parse().ok()).collect();
+            nums.chunks(2).map(|p| Point::new(p[0], p[1])).collect()
+        };
+        let (red, blue) = (points("#ff0000ff"), points("#0000ffff"));
+        assert_eq!(red.len(), blue.len());
+        assert!(red.len() > 2);
+        for i in 1..red.len() {
+            for line in [&red, &blue] {
+                let (a, b) = (line[i - 1], line[i]);
+                assert!(a.x == b.x || a.y == b.y, "{:?}", line);
+            }
+            // The matching segments are 2 points apart.
+            let (a, b) = (red[i].sub(blue[i]), red[i - 1].sub(blue[i - 1]));
+            let gap = if red[i].x == red[i - 1].x { a.x } else { a.y };
+            assert_eq!(gap.abs(), 2., "{:?} {:?}", red, blue);
+            assert!(b.x.abs() <= 2. && b.y.abs() <= 2.);
+        }
+    }
+
+    #[test]
+    fn format_inputs_round_trip() {
+        use layout::gv::to_dot;
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/inputs");
+        let mut count = 0;
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().is_none_or(|x| x != "dot") {
+                continue;
+            }
+            let program = std::fs::read_to_string(&path).unwrap();
+            let ast = DotParser::new(&program).process().unwrap();
+            let dot = to_dot(&ast);
+            // Parsing the output gives back the same statements, in the same
+            // order, and formatting them again does not change the output.
+            let ast2 = DotParser::new(&dot).process().unwrap();
+            assert_eq!(
+                format!("{:?}", ast),
+                format!("{:?}", ast2),
+                "{:?}",
+                path
+            );
+            assert_eq!(to_dot(&ast2), dot, "{:?}", path);
+            count += 1;
+        }
+        assert!(count > 0);
+    }
+
+    #[test]
+    fn write_visual_graph_to_dot() {
+        use layout::core::base::Orientation;
+        use layout::core::style::{LineStyleKind, StyleAttr};
+        use layout::gv::writer;
+        use layout::std_shapes::render::get_shape_size;
+        use layout::std_shapes::shapes::{
+            Arrow, Element, LineEndKind, ShapeKind,
+        };
+
+        let dir = Orientation::LeftToRight;
+        let mut vg = VisualGraph::new(dir);
+        let shape = ShapeKind::new_box("start \"here\"");
+        let size = get_shape_size(dir, &shape, 14, false);
+        let a =
+            vg.add_node(Element::create(shape, StyleAttr::simple(), dir, size));
+        let shape = ShapeKind::new_circle("end");
+        let size = get_shape_size(dir, &shape, 14, false);
+        let b =
+            vg.add_node(Element::create(shape, StyleAttr::simple(), dir, size));
+        let mut arrow = Arrow::simple("go");
+        arrow.line_style = LineStyleKind::Dashed;
+        arrow.start = LineEndKind::Diamond;
+        vg.add_edge(arrow, a, b);
+
+        let dot = writer::to_dot(&vg);
+        assert!(dot.starts_with("digraph {"));
+        assert!(dot.contains("rankdir=LR"));
+        assert!(dot.contains("n0 -> n1"));
+
+        // Parse the output and check that it describes the same graph.
+        let mut vg2 = build_graph(&dot);
+        assert_eq!(vg2.num_nodes(), vg.num_nodes());
+        assert_eq!(vg2.edge_list().len(), vg.edge_list().len());
+        assert!(matches!(vg2.orientation(), Orientation::LeftToRight));
+        let node = vg2.handle_for_name("n0").unwrap();
+        match &vg2.element(node).shape {
+            ShapeKind::Box(label) => assert_eq!(label, "start \"here\""),
+            _ => panic!("Expected a box"),
+        }
+        let arrow = &vg2.edge_list()[0].0;
+        assert_eq!(arrow.text, "go");
+        assert!(matches!(arrow.line_style, LineStyleKind::Dashed));
+        assert!(matches!(arrow.start, LineEndKind::Diamond));
+        assert!(matches!(arrow.end, LineEndKind::Arrow));
+
+        // Writing the parsed graph again gives the same file.
+        assert_eq!(writer::to_dot(&vg2), dot);
+        let mut svg = SVGWriter::new();
+        vg2.do_it(false, false, false, &mut svg);
+    }
+
+    #[test]
+    fn write_undirected_graph_with_records_to_dot() {
+        use layout::gv::writer;
+        let program = "graph { a [shape=record, label=\"<p> x|{y|z\\|w}\"]; \
+                       b [color=red, style=filled, fillcolor=blue]; a:p -- b; }";
+        let vg = build_graph(program);
+        let dot = writer::to_dot(&vg);
+        assert!(dot.starts_with("graph {"));
+        assert!(dot.contains("a:p -- b"));
+        assert_eq!(writer::to_dot(&build_graph(&dot)), dot);
+    }
+
+    #[test]
+    fn fit_text_to_reserved_width() {
+        use layout::core::geometry::get_size_for_str;
+        let program = "digraph { a [label=\"hello\\nworld!\", fontsize=20]; }";
+        let mut vg = build_graph(program);
+        let mut svg = SVGWriter::new();
+        svg.set_fit_text(true);
+        vg.do_it(false, false, false, &mut svg);
+        let svg = svg.finalize();
+
+        let lengths: Vec<f64> = svg
+            .split("textLength=\"")
+            .skip(1)
+            .map(|s| s.split('"').next().unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(lengths, vec![100., 120.]);
+        // The widest line fills the width that the layout reserved.
+        assert_eq!(get_size_for_str("hello\nworld!", 20).x, 120.);
+        assert!(svg.contains("lengthAdjust=\"spacingAndGlyphs\""));
+
+        // The text is not stretched by default.
+        let (_, svg) = render_graph(program);
+        assert!(!svg.contains("textLength"));
+    }
+
+    #[test]
+    fn edge_port_attributes() {
+        use layout::adt::dag::NodeHandle;
+
+        // Returns the first point of the path of the edge in the drawing.
+        let start_of_edge = |program: &str| {
+            let (vg, svg) = render_graph(program);
+            let d = svg.split(" d=\"M ").nth(1).unwrap();
+            let nums: Vec<f64> = d
+                .split([' ', ','])
+                .take(2)
+                .map(|x| x.parse::<f64>().unwrap())
+                .collect();
+            let pos = vg.pos(NodeHandle::new(0));
+            (nums[0], nums[1], pos.center(), pos.size(false))
+        };
+
+        let (x, y, center, size) =
+            start_of_edge("digraph { a [shape=box]; a -> b [tailport=w]; }");
+        assert_eq!(x, center.x - size.x / 2.);
+        assert_eq!(y, center.y);
+
+        // The colon syntax takes precedence over the attribute.
+        let (x, y, center, size) =
+            start_of_edge("digraph { a [shape=box]; a:e -> b [tailport=w]; }");
+        assert_eq!(x, center.x + size.x / 2.);
+        assert_eq!(y, center.y);
+
+        // Record fields with the name of a compass point come first.
+        let (x, _, center, size) = start_of_edge(
+            "digraph { a [shape=record, label=\"<w> left|<e> right\"]; \
+             a:e -> b; }",
+        );
+        assert!(x > center.x && x < center.x + size.x / 2.);
+        let (x, _, center, size) = start_of_edge(
+            "digraph { a [shape=record, label=\"<x> left|<y> right\"]; \
+             a:e -> b; }",
+        );
+        assert_eq!(x, center.x + size.x / 2.);
+    }
+
+    #[test]
+    fn graph_stats() {
+        // The edges between the first two ranks must cross once, the edge
+        // a -> e passes through a connector in the middle rank, and the self
+        // edge loops through a connector next to e.
+        let program = "digraph { a -> c; a -> d; b -> c; b -> d; \
+            c -> e; a -> e; e -> e; }";
+        let (vg, _) = render_graph(program);
+        let stats = vg.stats();
+        assert_eq!(stats.num_nodes, 5);
+        assert_eq!(stats.num_edges, 7);
+        assert_eq!(stats.num_ranks, 3);
+        assert_eq!(stats.max_rank_width, 3);
+        assert_eq!(stats.num_connectors, 2);
+        assert_eq!(stats.num_crossings, 1);
+        assert_eq!(stats.bounds, vg.bounding_box());
+    }
+
+    #[test]
+    fn drawing_bounding_box() {
+        use layout::core::geometry::Point;
+
+        let program = "digraph { a -> b [label=\"a long label\"]; \
+            a -> c; c -> a; b -> b; }";
+        let (vg, svg) = render_graph(program);
+        let (top_left, bottom_right) = vg.drawing_bounding_box();
+        let inside = |p: Point| {
+            p.x >= top_left.x
+                && p.y >= top_left.y
+                && p.x <= bottom_right.x
+                && p.y <= bottom_right.y
+        };
+
+        // Every node is listed once, and is in the box.
+        let positions: Vec<_> = vg.node_positions().collect();
+        assert_eq!(positions.len(), vg.num_nodes());
+        for (i, (node, pos)) in positions.iter().enumerate() {
+            assert_eq!(node.get_index(), i);
+            let bb = pos.bbox(true);
+            assert!(inside(bb.0) && inside(bb.1));
+        }
+
+        // The points of the edges are in the box.
+        for d in svg.split(" d=\"M ").skip(1) {
+            let d = &d[..d.find('"').unwrap()];
+            let nums: Vec<f64> =
+                d.split([' ', ',']).filter_map(|x| x.parse().ok()).collect();
+            for p in nums.chunks(2) {
+                assert!(inside(Point::new(p[0], p[1])));
+            }
+        }
+
+        // The box of an empty drawing is empty.
+        let vg = build_graph("digraph { }");
+        let empty = (Point::zero(), Point::zero());
+        assert_eq!(vg.drawing_bounding_box(), empty);
+    }
+
+    #[test]
+    fn edge_length_stats() {
+        use layout::adt::dag::NodeHandle;
+
+        let (vg, _) = render_graph("digraph { a -> b; a -> c; b -> c; }");
+        let lengths = vg.edge_lengths();
+        assert_eq!(lengths.len(), 3);
+        let total: f64 = lengths.iter().sum();
+        assert!((vg.stats().total_edge_length - total).abs() < 0.001);
+
+        // Each edge is at least as long as the gap between its nodes, and the
+        // edge a -> c skips a rank, so it is longer than a -> b.
+        let pos = |i| vg.pos(NodeHandle::new(i));
+        let gap = pos(1).bbox(false).0.y - pos(0).bbox(false).1.y;
+        assert!(gap > 0.);
+        assert!(lengths.iter().all(|l| *l >= gap));
+        assert!(lengths[1] > lengths[0]);
+    }
+
+    #[test]
+    fn comments_in_svg() {
+        let (_, svg) = render_graph(
+            "digraph { comment=\"the graph\"; \
+            a [comment=\"made by --tool\"]; a -> b [comment=\"edge\"]; }",
+        );
+        let comments: Vec<&str> = svg
+            .split("<!-- ")
+            .skip(1)
+            .map(|s| &s[..s.find(" -->").unwrap()])
+            .collect();
+        assert_eq!(comments, vec!["the graph", "made by - -tool", "edge"]);
+    }
+
+    #[test]
+    fn connector_padding() {
+        use layout::adt::dag::NodeHandle;
+
+        // Returns the distance between the nodes of the labeled edge.
+        let gap = |padding: Option<f64>| {
+            let mut vg = build_graph("digraph { a -> b [label=\"label\"]; }");
+            if let Some(padding) = padding {
+                vg.set_connector_padding(padding);
+            }
+            let mut svg = SVGWriter::new();
+            vg.do_it(false, false, false, &mut svg);
+            let a = vg.pos(NodeHandle::new(0)).bbox(false);
+            let b = vg.pos(NodeHandle::new(1)).bbox(false);
+            b.0.y - a.1.y
+        };
+        let default = gap(None);
+        assert!(gap(Some(0.)) < default);
+        assert!(gap(Some(40.)) > default);
+        assert!(gap(Some(80.)) > gap(Some(40.)));
+    }
+
+    #[test]
+    fn node_separation() {
+        use layout::adt::dag::NodeHandle;
+
+        // Returns the distance between the siblings b and c.
+        let distance = |attr: &str| {
+            let program = format!("digraph {{ {} a -> b; a -> c; }}", attr);
+            let (vg, _) = render_graph(&program);
+            let b = vg.pos(NodeHandle::new(1)).center();
+            let c = vg.pos(NodeHandle::new(2)).center();
+            (b.x - c.x).abs()
+        };
+        let default = distance("");
+        assert_eq!(distance("nodesep=0;"), default);
+        assert!((distance("nodesep=1;") - default - 72.).abs() < 0.001);
+        assert!(distance("nodesep=2;") > distance("nodesep=1;"));
+    }
+
+    #[test]
+    fn rank_separation() {
+        use layout::adt::dag::NodeHandle;
+
+        // Returns the vertical distances between the nodes of the chain.
+        let distances = |attr: &str| {
+            let program = format!("digraph {{ {} a -> b -> c; }}", attr);
+            let (vg, _) = render_graph(&program);
+            let y = |i| vg.pos(NodeHandle::new(i)).center().y;
+            (y(1) - y(0), y(2) - y(1))
+        };
+        let default = distances("");
+        assert_eq!(distances("ranksep=0;"), default);
+        let near = distances("ranksep=10;");
+        let far = distances("ranksep=200;");
+        // Every rank moves by the same distance.
+        assert!((far.0 - near.0 - 190. * 72.).abs() < 0.001);
+        assert!((far.1 - near.1 - 190. * 72.).abs() < 0.001);
+        assert!((near.0 - default.0 - 10. * 72.).abs() < 0.001);
+    }
+
+    #[test]
+    fn clusters() {
+        use layout::adt::dag::NodeHandle;
+        let program = "digraph { a; c; b; subgraph cluster_x { label=\"X\"; \
+                       bgcolor=lightyellow; a; b; subgraph cluster_y { a; b; } } }";
+        let (vg, svg) = render_graph(program);
+        assert_eq!(vg.clusters().len(), 2);
+        assert_eq!(vg.clusters()[0].label, "X");
+        assert_eq!(vg.clusters()[0].nodes.len(), 2);
+        assert_eq!(vg.clusters()[1].nodes.len(), 2);
+
+        // The nodes of the cluster are placed next to each other.
+        let center = |i: usize| vg.pos(NodeHandle::new(i)).center();
+        let (a, c, b) = (center(0), center(1), center(2));
+        assert!(a.sub(b).length() < a.sub(c).length());
+
+        // The box surrounds the nodes and the nested cluster, and is drawn
+        // behind them.
+        let boxes = vg.cluster_boxes();
+        let (outer, inner) = (boxes[0], boxes[1]);
+        for p in [a, b, inner.0, inner.1] {
+            assert!(p.x >= outer.0.x && p.x <= outer.1.x);
+            assert!(p.y >= outer.0.y && p.y <= outer.1.y);
+        }
+        assert!(c.x < outer.0.x || c.x > outer.1.x);
+        assert!(outer.0.y >= 0.);
+        assert!(svg.contains("fill=\"#ffffe0ff\""));
+        assert!(svg.find("<rect").unwrap() < svg.find("<ellipse").unwrap());
+        assert!(svg.contains(">X<"));
+
+        // Subgraphs that are not clusters don't have boxes.
+        let (vg, svg) = render_graph("digraph { subgraph x { a; b; } }");
+        assert!(vg.clusters().is_empty());
+        assert!(!svg.contains("<rect"));
+    }
+
+    #[test]
+    fn cluster_spans_ranks() {
+        use layout::core::geometry::Point;
+        let overlap = |a: (Point, Point), b: (Point, Point)| {
+            a.0.x < b.1.x && b.0.x < a.1.x && a.0.y < b.1.y && b.0.y < a.1.y
+        };
+        // The edges inside of the clusters pass through the middle ranks,
+        // next to the nodes that are not in the clusters.
+        let programs = [
+            "digraph { subgraph cluster_x { a; c; } a -> c; a -> m; m -> c; }",
+            "digraph { subgraph cluster_x { a -> b -> c; a -> c; } \
+             x -> m -> y; a -> m; m -> c; }",
+            "digraph { subgraph cluster_x { a; d; } subgraph cluster_y { b; c; } \
+             a -> b -> c -> d; a -> d; a -> c; b -> d; }",
+            "digraph { rankdir=LR; subgraph cluster_x { a -> c; } \
+             a -> m -> c; subgraph cluster_y { m -> n; } }",
+        ];
+        for program in programs {
+            let (vg, _) = render_graph(program);
+            let boxes = vg.cluster_boxes();
+            for (i, cluster) in vg.clusters().iter().enumerate() {
+                for node in vg.iter_nodes() {
+                    if !cluster.nodes.contains(&node) {
+                        let bb = vg.pos(node).bbox(false);
+                        assert!(!overlap(bb, boxes[i]), "{}", program);
+                    }
+                }
+                for (j, other) in vg.clusters().iter().enumerate() {
+                    if other.nodes.iter().all(|n| !cluster.nodes.contains(n)) {
+                        assert!(!overlap(boxes[i], boxes[j]), "{}", program);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn cluster_rankdir() {
+        use layout::adt::dag::NodeHandle;
+        use layout::core::geometry::Point;
+        let program = |rankdir: &str| {
+            format!(
+                "digraph {{ top -> a; subgraph cluster_p {{ {} \
+                 a -> b -> c; }} c -> bottom; }}",
+                rankdir
+            )
+        };
+        let centers = |vg: &VisualGraph| -> Vec<Point> {
+            (0..5)
+                .map(|i| vg.pos(NodeHandle::new(i)).center())
+                .collect()
+        };
+
+        // The edges of a cluster without a rankdir go down the ranks.
+        let (vg, _) = render_graph(&program(""));
+        let p = centers(&vg);
+        assert!(p[1].y < p[2].y && p[2].y < p[3].y);
+
+        // The edges of a left-to-right cluster go across one rank, and the
+        // edges to the nodes outside of the cluster still go down.
+        let (vg, svg) = render_graph(&program("rankdir=LR;"));
+        let p = centers(&vg);
+        assert_eq!(vg.clusters()[0].nodes.len(), 3);
+        assert!(p[1].x < p[2].x && p[2].x < p[3].x);
+        assert!((p[1].y - p[2].y).abs() < 0.01);
+        assert!((p[2].y - p[3].y).abs() < 0.01);
+        assert!(p[0].y < p[1].y && p[3].y < p[4].y);
+        assert!(svg.contains("<rect"));
+
+        // Right-to-left clusters order the nodes the other way.
+        let (vg, _) = render_graph(&program("rankdir=RL;"));
+        let p = centers(&vg);
+        assert!(p[3].x < p[2].x && p[2].x < p[1].x);
+
+        // Clusters that follow the direction of the graph are not changed.
+        let (vg, _) = render_graph(&program("rankdir=BT;"));
+        let p = centers(&vg);
+        assert!(p[1].y < p[2].y && p[2].y < p[3].y);
+    }
+
+    #[test]
+    fn edge_gradient() {
+        // The gradient of the back edge c -> a still starts at its tail.
+        let (_, svg) = render_graph(
+            "digraph { a -> b -> c; \
+            a -> c [style=gradient, color=\"red:blue\"]; \
+            c -> a [style=gradient, color=\"green:black\"]; \
+            b -> c [color=\"red:blue\"]; }",
+        );
+        assert_eq!(svg.matches("<linearGradient").count(), 2);
+        assert!(svg.contains(
+            "<stop offset=\"0\" stop-color=\"#ff0000ff\" />\n\
+            <stop offset=\"1\" stop-color=\"#0000ffff\" />"
+        ));
+        assert!(svg.contains(
+            "<stop offset=\"0\" stop-color=\"#000000ff\" />\n\
+            <stop offset=\"1\" stop-color=\"#008000ff\" />"
+        ));
+        assert_eq!(svg.matches("stroke=\"url(#gradient").count(), 2);
+    }
+
+    #[test]
+    fn clear_and_reuse_graph() {
+        use layout::core::base::Orientation;
+        use layout::core::style::StyleAttr;
+        use layout::std_shapes::render::get_shape_size;
+        use layout::std_shapes::shapes::{Arrow, Element, ShapeKind};
+
+        // Adds a chain of \p n nodes, and an edge that skips over it.
+        let build = |vg: &mut VisualGraph, n: usize| {
+            let dir = Orientation::LeftToRight;
+            let mut nodes = Vec::new();
+            for i in 0..n {
+                let shape = ShapeKind::new_box(&format!("node{}", i));
+                let size = get_shape_size(dir, &shape, 15, false);
+                let elem =
+                    Element::create(shape, StyleAttr::simple(), dir, size);
+                nodes.push(vg.add_node(elem));
+            }
+            for i in 1..n {
+                vg.add_edge(Arrow::simple(""), nodes[i - 1], nodes[i]);
+            }
+            vg.add_edge(Arrow::simple("skip"), nodes[0], nodes[n - 1]);
+        };
+        let render = |vg: &mut VisualGraph| {
+            let mut svg = SVGWriter::new();
+            vg.do_it(false, false, false, &mut svg);
+            svg.finalize()
+        };
+
+        let mut fresh = VisualGraph::new(Orientation::LeftToRight);
+        build(&mut fresh, 3);
+        let expected = render(&mut fresh);
+
+        let mut vg = VisualGraph::new(Orientation::LeftToRight);
+        build(&mut vg, 5);
+        render(&mut vg);
+        vg.clear();
+        assert_eq!(vg.num_nodes(), 0);
+        assert!(vg.edge_list().is_empty());
+        assert!(vg.orientation().is_left_right());
+        build(&mut vg, 3);
+        assert_eq!(render(&mut vg), expected);
+    }
+
+    #[test]
+    fn layer_groups() {
+        let (_, svg) = render_graph(
+            "digraph { a [layer=back]; b; \
+            a -> b [layer=\"front edges\", label=x]; }",
+        );
+        // The nodes, the label of the edge, and the edge.
+        let layers: Vec<&str> = svg
+            .split("<g class=\"layer-")
+            .skip(1)
+            .map(|s| s.split('"').next().unwrap())
+            .collect();
+        assert_eq!(
+            layers,
+            vec!["back", "default", "front_edges", "front_edges"]
+        );
+
+        // Graphs without layers don't have layer groups.
+        let (_, svg) = render_graph("digraph { a -> b; }");
+        assert!(!svg.contains("layer-"));
+    }
+
+    #[test]
+    fn graph_title_font() {
+        use layout::adt::dag::NodeHandle;
+        use layout::core::base::Anchor;
+
+        let program = "digraph { label=\"The title\"; labelloc=t; \
+            fontsize=30; fontname=Courier; fontcolor=red; \
+            node [fontsize=12]; a -> b; }";
+        let (vg, svg) = render_graph(program);
+        let (title, anchor) = &vg.floating_elements()[0];
+        assert_eq!(*anchor, Anchor::TopCenter);
+        assert_eq!(title.look.font_size, 30);
+        assert!(title.look.monospace);
+        assert_eq!(title.look.font_color.unwrap().to_web_color(), "#ff0000ff");
+
+        // The graph font does not apply to the nodes.
+        let a = vg.element(NodeHandle::new(0));
+        assert_eq!(a.look.font_size, 12);
+        assert!(!a.look.monospace);
+        assert!(a.look.font_color.is_none());
+        assert!(svg.contains("class=\"m30\""));
+        assert!(svg.contains("class=\"a12\""));
+
+        // The title is centered above the graph.
+        let bb = vg.bounding_box();
+        let center = title.pos.center();
+        assert!((center.x - (bb.0.x + bb.1.x) / 2.).abs() < 0.001);
+        assert!(title.pos.bbox(false).1.y < bb.0.y);
+    }
+
+    #[test]
+    fn record_orientation_ignores_rankdir() {
+        use layout::adt::dag::NodeHandle;
+
+        // Returns the size of the record node 'a' with the label \p label in
+        // a graph with the direction \p rankdir.
+        let record_size = |label: &str, rankdir: &str| {
+            let program = format!(
+                "digraph {{ rankdir={}; a [shape=record, label=\"{}\"]; \
+                a -> b; }}",
+                rankdir, label
+            );
+            let (vg, _) = render_graph(&program);
+            vg.pos(NodeHandle::new(0)).size(false)
+        };
+
+        let long = "a long field";
+        // The fields of the top level are placed side by side.
+        let tb = record_size(&format!("{}|b", long), "TB");
+        let lr = record_size(&format!("{}|b", long), "LR");
+        assert_eq!(tb, lr);
+        // The braces stack the fields.
+        let tb_stacked = record_size(&format!("{{{}|b}}", long), "TB");
+        let lr_stacked = record_size(&format!("{{{}|b}}", long), "LR");
+        assert_eq!(tb_stacked, lr_stacked);
+        assert!(tb.x > tb_stacked.x);
+        assert!(tb.y < tb_stacked.y);
+    }
+
+    #[test]
+    fn edge_labels_leaving_records() {
+        // The label of an edge that leaves a record is placed like the label
+        // of an edge that leaves a box, in all of the graph directions.
+        for rankdir in ["TB", "LR"] {
+            let label_of = |shape: &str| {
+                let program = format!(
+                    "digraph {{ rankdir={}; a [{}]; \
+                     a -> b [label=\"hello\"]; }}",
+                    rankdir, shape
+                );
+                let (vg, _) = render_graph(&program);
+                let conn = vg.edge_chains()[0][1];
+                let a = vg.pos(layout::adt::dag::NodeHandle::new(0));
+                // The side of 'a' that the edge leaves from.
+                let side = if rankdir == "LR" {
+                    a.east(false)
+                } else {
+                    a.south(false)
+                };
+                let pos = vg.pos(conn);
+                (pos.center().sub(side), pos.size(false))
+            };
+            let record = label_of("shape=record, label=\"x|y\"");
+            let boxed = label_of("shape=box, label=\"x|y\"");
+            assert_eq!(record, boxed, "{}", rankdir);
+        }
+    }
+
+    #[test]
+    fn record_field_order_in_all_directions() {
+        use layout::core::geometry::Point;
+
+        for rankdir in ["TB", "BT", "LR", "RL"] {
+            let program = format!(
+                "digraph {{ rankdir={}; \
+                a [shape=record, label=\"<x>x|y|{{<p>p|<q>q}}\"]; \
+                a:x -> b; a:q -> c; }}",
+                rankdir
+            );
+            let (_, svg) = render_graph(&program);
+
+            // Returns the location of the text of the field \p name.
+            let field = |name: &str| {
+                let end = svg.find(&format!(">{}</tspan>", name)).unwrap();
+                let start = svg[..end].rfind("<text").unwrap();
+                let nums: Vec<f64> = svg[start..end]
+                    .split(['"', '='])
+                    .filter_map(|x| x.parse().ok())
+                    .collect();
+                Point::new(nums[0], nums[1])
+            };
+            let (x, y, p, q) = (field("x"), field("y"), field("p"), field("q"));
+            assert!(x.x < y.x && y.x < p.x, "{}", rankdir);
+            assert_eq!(p.x, q.x, "{}", rankdir);
+            assert!(p.y < q.y, "{}", rankdir);
+
+            // The edges leave the record from the fields of their ports.
+            let starts: Vec<f64> = svg
+                .split(" d=\"M ")
+                .skip(1)
+                .map(|d| d.split(' ').next().unwrap().parse().unwrap())
+                .collect();
+            assert!(starts[0] < y.x && starts[1] > y.x, "{}", rankdir);
+        }
+    }
+
+    #[test]
+    fn same_rank_with_newrank() {
+        use layout::adt::dag::NodeHandle;
+
+        // Returns the vertical distance between the nodes b and d.
+        let distance = |attrs: &str| {
+            let program = format!(
+                "digraph {{ a -> b; a -> c; c -> d; {} \
+                {{ rank=same; b; d; }} }}",
+                attrs
+            );
+            let (vg, _) = render_graph(&program);
+            let b = vg.pos(NodeHandle::new(1)).center();
+            let d = vg.pos(NodeHandle::new(3)).center();
+            (b.y - d.y).abs()
+        };
+
+        // Nodes are aligned, even if they are in different groups.
+        assert!(distance("") < 0.001);
+        assert!(distance("b [group=g1]; d [group=g2];") < 0.001);
+        // Nodes in different clusters are only aligned under 'newrank'.
+        let clusters = "subgraph cluster_x { b; } subgraph cluster_y { d; }";
+        assert!(distance(clusters) > 1.);
+        let newrank = format!("newrank=true; {}", clusters);
+        assert!(distance(&newrank) < 0.001);
+        // Nodes in the same cluster are aligned.
+        assert!(distance("subgraph cluster_x { b; d; }") < 0.001);
+    }
+
+    #[test]
+    fn same_rank_flat_edges() {
+        for program in [
+            "digraph { nodesep=1; {rank=same; a; b} a -> b; }",
+            "digraph { nodesep=1; x -> a; x -> b; {rank=same; a; b} \
+            a -> b; b -> a; }",
+        ] {
+            let (vg, _) = render_graph(program);
+            let a = vg.handle_for_name("a").unwrap();
+            let b = vg.handle_for_name("b").unwrap();
+            let (a, b) = (vg.pos(a).bbox(false), vg.pos(b).bbox(false));
+            // The edge between the nodes is flat, and the nodes are at least
+            // 'nodesep' apart.
+            assert!((a.0.y - b.0.y).abs() < 0.001, "{}", program);
+            let gap = (b.0.x - a.1.x).max(a.0.x - b.1.x);
+            assert!(gap >= 72. - 0.001, "{}", program);
+        }
+    }
+
+    #[test]
+    fn layout_dot_positions() {
+        let positions =
+            layout::gv::layout_dot("digraph { a -> b -> c; }").unwrap();
+        assert_eq!(positions.len(), 3);
+        let (a, b, c) = (positions["a"], positions["b"], positions["c"]);
+        // The chain goes down, and the nodes are aligned.
+        assert!(a.0.y < b.0.y && b.0.y < c.0.y);
+        assert!((a.0.x - c.0.x).abs() < 0.001);
+        assert!(a.1.x > 0. && a.1.y > 0.);
+
+        // The positions match the rendered graph.
+        let (vg, _) = render_graph("digraph { a -> b -> c; }");
+        assert_eq!(vg.pos(layout::adt::dag::NodeHandle::new(1)).center(), b.0);
+
+        assert!(layout::gv::layout_dot("digraph { }").unwrap().is_empty());
+        assert!(layout::gv::layout_dot("digraph { a -> }").is_err());
+    }
+
+    #[test]
+    fn node_names() {
+        use layout::adt::dag::NodeHandle;
+
+        let (mut vg, _) = render_graph("digraph { b -> a; c; a -> c; }");
+        for (i, name) in ["b", "a", "c"].iter().enumerate() {
+            let handle = NodeHandle::new(i);
+            assert_eq!(vg.handle_for_name(name), Some(handle));
+            assert_eq!(vg.name_of(handle), Some(*name));
+        }
+        assert_eq!(vg.handle_for_name("d"), None);
+
+        // Renaming a node releases the old name.
+        vg.set_node_name(NodeHandle::new(0), "d");
+        assert_eq!(vg.handle_for_name("b"), None);
+        assert_eq!(vg.name_of(NodeHandle::new(0)), Some("d"));
+
+        // Taking the name of another node removes the name from that node.
+        vg.set_node_name(NodeHandle::new(1), "c");
+        assert_eq!(vg.handle_for_name("c"), Some(NodeHandle::new(1)));
+        assert_eq!(vg.handle_for_name("a"), None);
+        assert_eq!(vg.name_of(NodeHandle::new(2)), None);
+    }
+
+    #[test]
+    fn fixed_node_positions() {
+        use layout::adt::dag::NodeHandle;
+        use layout::core::geometry::Point;
+
+        // The positions use the GraphViz coordinates, where y points up.
+        let program = "digraph { bb=\"0,0,300,200\"; \
+            a [pos=\"50,150\"]; b [pos=\"250,50!\"]; c [pos=\"50,50\"]; \
+            a -> b [label=\"x\"]; a -> c; c -> c; }";
+        let (vg, svg) = render_graph(program);
+        let center = |i| vg.pos(NodeHandle::new(i)).center();
+        assert_eq!(center(0), Point::new(50., 50.));
+        assert_eq!(center(1), Point::new(250., 150.));
+        assert_eq!(center(2), Point::new(50., 150.));
+        // The label of the edge is halfway between the nodes.
+        let label = vg.edge_chains()[0][1];
+        assert_eq!(vg.pos(label).center(), Point::new(150., 100.));
+        assert!(svg.contains("cx=\"50\" cy=\"50\""));
+
+        // Positions are ignored unless all of the nodes have one.
+        let program = "digraph { a [pos=\"50,150\"]; a -> b; }";
+        let mut gb = GraphBuilder::new();
+        gb.visit_graph(&DotParser::new(program).process().unwrap());
+        let (vg, warnings) = gb.get_with_warnings();
+        assert!(!vg.has_fixed_positions());
+        assert_eq!(warnings.len(), 1);
+    }
+
+    #[test]
+    fn pinned_node_positions() {
+        use layout::adt::dag::NodeHandle;
+        use layout::core::geometry::Point;
+
+        // Only 'b' is pinned, and 'a' and 'c' are laid out around it.
+        for rankdir in ["TB", "LR", "BT"] {
+            let program = format!(
+                "digraph {{ rankdir={}; bb=\"0,0,400,400\"; \
+                b [pos=\"200,100!\"]; a -> b; b -> c; }}",
+                rankdir
+            );
+            let mut gb = GraphBuilder::new();
+            gb.visit_graph(&DotParser::new(&program).process().unwrap());
+            let (mut vg, warnings) = gb.get_with_warnings();
+            assert!(warnings.is_empty());
+            assert!(!vg.has_fixed_positions());
+            let mut svg = SVGWriter::new();
+            vg.do_it(false, false, false, &mut svg);
+
+            let center = |i| vg.pos(NodeHandle::new(i)).center();
+            assert_eq!(center(0), Point::new(200., 300.));
+            let (a, c) = (center(1), center(2));
+            let dist =
+                |p: Point, q: Point| (p.x - q.x).abs() + (p.y - q.y).abs();
+            assert!(dist(a, center(0)) > 30., "{} {:?}", rankdir, a);
+            assert!(dist(c, center(0)) > 30., "{} {:?}", rankdir, c);
+            assert!(dist(a, c) > 30., "{} {:?} {:?}", rankdir, a, c);
+        }
+    }
+
+    #[test]
+    fn pinned_nodes_stay_in_the_drawing() {
+        use layout::adt::dag::NodeHandle;
+        use layout::core::geometry::Point;
+
+        // Lays out the program, and checks that all of the nodes are in the
+        // drawing. \returns the centers of the nodes.
+        let layout = |program: &str| {
+            let mut gb = GraphBuilder::new();
+            gb.visit_graph(&DotParser::new(program).process().unwrap());
+            let mut vg = gb.get();
+            let mut svg = SVGWriter::new();
+            vg.do_it(false, false, false, &mut svg);
+            let size = svg.size();
+            (0..vg.num_nodes())
+                .map(|i| {
+                    let bb = vg.pos(NodeHandle::new(i)).bbox(false);
+                    assert!(bb.0.x >= 0. && bb.0.y >= 0., "{}", program);
+                    assert!(
+                        bb.1.x <= size.x && bb.1.y <= size.y,
+                        "{}",
+                        program
+                    );
+                    vg.pos(NodeHandle::new(i)).center()
+                })
+                .collect::<Vec<Point>>()
+        };
+
+        // A pin at the origin.
+        let c = layout("digraph { b [pos=\"0,0!\"]; a -> b; b -> c; }");
+        assert!(c[1].y < c[0].y && c[0].y < c[2].y);
+
+        // Two pins in the same rank keep their distance.
+        let c = layout(
+            "digraph { b [pos=\"50,300!\"]; c [pos=\"300,300!\"]; \
+             a -> b; a -> c; }",
+        );
+        assert_eq!(c[1].sub(c[0]), Point::new(250., 0.));
+        assert!(c[2].y < c[0].y);
+
+        // Pins in different ranks keep their distances, and the y axis of
+        // the pins points up.
+        let c = layout(
+            "digraph { a [pos=\"100,400!\"]; b [pos=\"300,200!\"]; \
+             c [pos=\"100,0!\"]; a -> b -> c; a -> c; a -> d; }",
+        );
+        assert_eq!(c[1].sub(c[0]), Point::new(200., 200.));
+        assert_eq!(c[2].sub(c[0]), Point::new(0., 400.));
+    }
+
+    #[test]
+    fn edge_splines() {
+        use layout::core::geometry::Point;
+
+        // The edge ends with an arrow head at 'e', and 'c -> a' is drawn
+        // against the direction of the ranks. The control points around the
+        // joint of the segments of 'c -> a' are not symmetric.
+        let program = "digraph { bb=\"0,0,200,200\"; \
+            a [pos=\"50,150\"]; b [pos=\"150,50\"]; c [pos=\"150,150\"]; \
+            a -> b [pos=\"e,140,60 60,140 80,140 120,60 130,60\"]; \
+            c -> a [pos=\"s,140,150 130,150 110,150 100,160 90,160 \
+            60,160 70,150 60,150\"]; \
+            a -> c [pos=\"bad\"]; }";
+        let mut gb = GraphBuilder::new();
+        gb.visit_graph(&DotParser::new(program).process().unwrap());
+        let (vg, warnings) = gb.get_with_warnings();
+        assert_eq!(warnings, vec!["pos=\"bad\" is not a spline; ignored"]);
+        let edges = vg.edge_list();
+        let spline = edges[0].0.spline.as_ref().unwrap();
+        assert_eq!(spline[0], Point::new(60., 60.));
+        assert_eq!(spline[3], Point::new(130., 140.));
+        // The arrow head is drawn from the end of the curve to 'e'.
+        assert_eq!(spline.len(), 7);
+        assert_eq!(spline[6], Point::new(140., 140.));
+        assert!(edges[2].0.spline.is_none());
+
+        let (_, svg) = render_graph(program);
+        assert!(svg.contains(
+            "M 60 60 C 80 60, 120 140, 130 140 C 130 140, 140 140, 140 140"
+        ));
+        // Every segment keeps both of its control points.
+        assert!(svg.contains(
+            "M 140 50 C 140 50, 130 50, 130 50 C 110 50, 100 40, 90 40 \
+             C 60 40, 70 50, 60 50"
+        ));
+    }
+
+    #[test]
+    fn render_pages() {
+        use layout::core::geometry::Point;
+
+        let program = "digraph { a -> b -> c -> d -> e -> f -> g -> h; \
+            a -> x; a -> y; a -> z; }";
+        let mut vg = build_graph(program);
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+        let size = svg.size();
+
+        // Three pages fit the height of the drawing, and two the width.
+        let page = Point::new(size.x / 2. + 1., size.y / 3. + 1.);
+        let pages = vg.render_pages(page);
+        assert_eq!(pages.len(), 6);
+        let header = |svg: &SVGWriter| {
+            let content = svg.finalize();
+            let start = content.find("viewBox=").unwrap();
+            content[start..].split('"').nth(1).unwrap().to_string()
+        };
+        let expected = format!("0 {} {} {}", page.y, page.x, page.y);
+        assert_eq!(header(&pages[2]), expected);
+        // Each page holds the whole drawing.
+        let body = |c: String| c[c.find("xmlns=").unwrap()..].to_string();
+        assert_eq!(body(pages[5].finalize()), body(svg.finalize()));
+
+        // A page that is larger than the drawing holds all of it.
+        assert_eq!(vg.render_pages(size.scale(2.)).len(), 1);
+    }
+
+    #[test]
+    fn flat_non_constraint_edges() {
+        use layout::adt::dag::NodeHandle;
+
+        // The edge a -> c does not push c below a, so both stay in the rank
+        // below x, with b between them.
+        let program = "digraph { x -> a; x -> b; x -> c; \
+            a -> c [constraint=false]; a -> b [constraint=false]; }";
+        let (vg, svg) = render_graph(program);
+        let pos = |i| vg.pos(NodeHandle::new(i));
+        assert_eq!(vg.dag.level(NodeHandle::new(1)), 1);
+        assert_eq!(vg.dag.level(NodeHandle::new(3)), 1);
+        assert_eq!(vg.edge_chains()[3].len(), 2);
+
+        // The nodes that are not adjacent are joined by an arc that leaves
+        // and enters them from the top.
+        let (a, c) = (pos(1), pos(3));
+        assert!(a.center().x < pos(2).center().x);
+        assert!(pos(2).center().x < c.center().x);
+        let start = format!("M {} {} C", a.center().x, a.bbox(false).0.y);
+        assert!(svg.contains(&start));
+        let end = format!("{} {} \"", c.center().x, c.bbox(false).0.y);
+        assert!(svg.contains(&end));
+
+        // The adjacent nodes are joined by a straight line between them.
+        let start = format!("M {} {} C", a.bbox(false).1.x, a.center().y);
+        assert!(svg.contains(&start));
+    }
+
+    #[test]
+    fn non_constraint_feedback_edge_ranks() {
+        // Returns the rank of each node.
+        let ranks = |program: &str| {
+            let (vg, _) = render_graph(program);
+            ["a", "b", "c", "x", "y"]
+                .iter()
+                .map(|name| vg.dag.level(vg.handle_for_name(name).unwrap()))
+                .collect::<Vec<usize>>()
+        };
+        // The edge y -> a points back up, from the second rank to the first.
+        let base = "a -> b -> c; x -> y;";
+        let without = ranks(&format!("digraph {{ {} }}", base));
+        let free = format!("digraph {{ {} y -> a [constraint=false]; }}", base);
+        assert_eq!(ranks(&free), without);
+        // A constraining edge between the same nodes pushes 'a' down.
+        let constrained = format!("digraph {{ {} y -> a; }}", base);
+        assert_ne!(ranks(&constrained), without);
+    }
+
+    #[test]
+    fn highlight_path() {
+        use layout::adt::dag::NodeHandle;
+        use layout::core::color::Color;
+        use layout::core::style::StyleAttr;
+
+        let mut vg = build_graph(
+            "digraph { a [fontsize=20]; a -> b; b -> c; c -> a; a -> d; }",
+        );
+        let style = StyleAttr::new(Color::fast("red"), 3, None, 0, 10);
+        let red = |c: Color| c.to_web_color() == "#ff0000ff";
+        let (a, b) = (NodeHandle::new(0), NodeHandle::new(1));
+        vg.highlight(&[a, b], &[0, 2], style);
+
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+        for node in [a, b] {
+            assert!(red(vg.element(node).look.line_color));
+            assert_eq!(vg.element(node).look.line_width, 3);
+            assert!(vg.element(node).look.fill_color.is_some());
+        }
+        assert_eq!(vg.element(a).look.font_size, 20);
+        assert!(!red(vg.element(NodeHandle::new(2)).look.line_color));
+
+        // The back edge c -> a is reversed during the layout.
+        let chains = vg.edge_chains();
+        for (arrow, lst) in vg.edge_list() {
+            let idx = chains.iter().position(|c| {
+                c.first() == lst.first() && c.last() == lst.last()
+                    || c.first() == lst.last() && c.last() == lst.first()
+            });
+            let highlighted = matches!(idx, Some(0) | Some(2));
+            assert_eq!(red(arrow.look.line_color), highlighted);
+        }
+    }
+
+    #[test]
+    fn record_separators() {
+        use layout::adt::dag::NodeHandle;
+        use layout::core::color::Color;
+
+        // Returns the number of rectangles with the stroke width \p width.
+        let count = |svg: &str, width: usize| {
+            let attr = format!("stroke-width=\"{}\"", width);
+            svg.split("<rect").filter(|r| r.contains(&attr)).count()
+        };
+
+        // The lines between the fields are thinner than the outline. The
+        // fields and the row that holds them are drawn as boxes.
+        let program = "digraph { a [shape=record, penwidth=4, \
+            label=\"x|y|z\"]; }";
+        let (_, svg) = render_graph(program);
+        assert_eq!(count(&svg, 4), 1);
+        assert_eq!(count(&svg, 2), 4);
+
+        // The separators can be styled.
+        let mut vg = build_graph(program);
+        let look = &mut vg.element_mut(NodeHandle::new(0)).look;
+        look.separator_width = Some(1);
+        look.separator_color = Some(Color::fast("gray"));
+        let mut writer = SVGWriter::new();
+        vg.do_it(false, false, false, &mut writer);
+        let svg = writer.finalize();
+        assert_eq!(count(&svg, 4), 1);
+        assert_eq!(count(&svg, 1), 4);
+        assert!(svg.contains("stroke=\"#808080ff\""));
+    }
+
+    #[test]
+    fn edge_separation() {
+        // Returns the smallest distance between an edge that skips ranks and
+        // the boxes of the nodes that it passes.
+        let clearance = |attr: &str| {
+            let program = format!(
+                "digraph {{ {} a -> b -> c -> d; a -> d; a -> e -> f -> d; }}",
+                attr
+            );
+            let (vg, _) = render_graph(&program);
+            let mut min = f64::INFINITY;
+            for level in 0..vg.dag.num_levels() {
+                let row = vg.dag.row(level);
+                for conn in row.iter().filter(|x| vg.is_connector(**x)) {
+                    let x = vg.pos(*conn).center().x;
+                    for node in row.iter().filter(|x| !vg.is_connector(**x)) {
+                        let (tl, br) = vg.pos(*node).bbox(false);
+                        min = min.min((tl.x - x).max(x - br.x));
+                    }
+                }
+            }
+            min
+        };
+        assert!(clearance("esep=40;") >= 40.);
+        assert!(clearance("esep=80;") >= 80.);
+        assert!(clearance("esep=80;") > clearance(""));
+    }
+
+    #[test]
+    fn rankdir_directions() {
+        use layout::adt::dag::NodeHandle;
+
+        // Returns the vector from the center of 'a' to the center of 'b'.
+        let delta = |dir: &str| {
+            let program = format!("digraph {{ rankdir={}; a->b }}", dir);
+            let (vg, _) = render_graph(&program);
+            let a = vg.pos(NodeHandle::new(0)).center();
+            let b = vg.pos(NodeHandle::new(1)).center();
+            b.sub(a)
+        };
+        let tb = delta("TB");
+        assert!(tb.y > 0. && tb.x.abs() < 0.001);
+        let bt = delta("BT");
+        assert!(bt.y < 0. && bt.x.abs() < 0.001);
+        let lr = delta("LR");
+        assert!(lr.x > 0. && lr.y.abs() < 0.001);
+        let rl = delta("RL");
+        assert!(rl.x < 0. && rl.y.abs() < 0.001);
+
+        // The edge still starts at 'a' and points up.
+        let (_, svg) = render_graph("digraph { rankdir=BT; a->b }");
+        let start = svg.find("d=\"M ").unwrap() + 5;
+        let end = start + svg[start..].find('"').unwrap();
+        let nums: Vec<f64> = svg[start..end]
+            .split([' ', ',', 'C'])
+            .filter_map(|x| x.parse().ok())
+            .collect();
+        assert!(nums[1] > nums[nums.len() - 1]);
+    }
+
+    #[test]
+    fn render_filtered_view() {
+        let (vg, full) =
+            render_graph("digraph { a -> b [label=x]; b -> c; a -> c; }");
+        assert_eq!(full.matches("<ellipse").count(), 3);
+        assert_eq!(full.matches("<path").count(), 3);
+
+        // Hide 'c', with the edges that point to it.
+        let c = vg.handle_for_name("c").unwrap();
+        let mut svg = SVGWriter::new();
+        vg.render_filtered(&mut svg, |h| h != c);
+        let content = svg.finalize();
+        assert_eq!(content.matches("<ellipse").count(), 2);
+        assert_eq!(content.matches("<path").count(), 1);
+        assert!(content.contains(">x<"));
+
+        // Hide 'a', with the label of its edge.
+        let a = vg.handle_for_name("a").unwrap();
+        let mut svg = SVGWriter::new();
+        vg.render_filtered(&mut svg, |h| h != a);
+        let content = svg.finalize();
+        assert_eq!(content.matches("<ellipse").count(), 2);
+        assert_eq!(content.matches("<path").count(), 1);
+        assert!(!content.contains(">x<"));
+    }
+
+    #[test]
+    fn node_peripheries() {
+        let (_, svg) =
+            render_graph("digraph { a [shape=box, peripheries=3]; }");
+        assert_eq!(svg.matches("<rect").count(), 3);
+
+        // Double circles have two outlines by default.
+        let (_, svg) = render_graph("digraph { a [shape=doublecircle]; }");
+        assert_eq!(svg.matches("<ellipse").count(), 2);
+        let (_, svg) = render_graph(
+            "digraph { a [shape=doublecircle, peripheries=1]; b [peripheries=2]; }",
+        );
+        assert_eq!(svg.matches("<ellipse").count(), 3);
+
+        // Without peripheries the shape has no border.
+        let (_, svg) =
+            render_graph("digraph { a [shape=box, peripheries=0]; }");
+        assert_eq!(svg.matches("<rect").count(), 1);
+        assert!(svg.contains("stroke-width=\"0\""));
+    }
+
+    #[test]
+    fn records_without_a_frame() {
+        let program = "digraph { a [shape=record, peripheries=0, \
+                       label=\"one|{two|three}\"]; }";
+        let (vg, svg) = render_graph(program);
+        // The only rectangle is the background, which has no border.
+        assert_eq!(svg.matches("<rect").count(), 1);
+        assert!(svg.contains("stroke-width=\"0\""));
+        for text in [">one<", ">two<", ">three<"] {
+            assert!(svg.contains(text));
+        }
+        // The lines between the fields are inside of the record.
+        use layout::adt::dag::NodeHandle;
+        let (tl, br) = vg.pos(NodeHandle::new(0)).bbox(false);
+        let lines: Vec<&str> = svg.split("<line").skip(1).collect();
+        assert!(lines.len() >= 2);
+        let attr = |line: &str, name: &str| -> f64 {
+            let start = line.find(&format!("{}=\"", name)).unwrap();
+            let rest = &line[start + name.len() + 2..];
+            rest[..rest.find('"').unwrap()].parse().unwrap()
+        };
+        for line in lines {
+            for x in [attr(line, "x1"), attr(line, "x2")] {
+                assert!(x > tl.x && x <= br.x);
+            }
+        }
+
+        // Boxes without a shape show only the label.
+        let (_, svg) = render_graph("digraph { a [shape=none]; }");
+        assert_eq!(svg.matches("<rect").count(), 1);
+        assert!(svg.contains("stroke-width=\"0\""));
+        assert!(svg.contains(">a<"));
+    }
+
+    #[test]
+    fn node_shadow() {
+        let (vg, svg) =
+            render_graph("digraph { a [shape=box, style=shadow]; }");
+        let rects: Vec<&str> = svg.split("<rect").skip(1).collect();
+        assert_eq!(rects.len(), 2);
+        // The shadow is drawn first, behind the node, and is offset from it.
+        assert!(rects[0].contains("fill-opacity"));
+        assert!(!rects[1].contains("fill-opacity"));
+        let x = |rect: &str| -> f64 {
+            let start = rect.find("x=\"").unwrap() + 3;
+            let end = start + rect[start..].find('"').unwrap();
+            rect[start..end].parse().unwrap()
+        };
+        assert!(x(rects[0]) > x(rects[1]));
+        assert!((vg.bounding_box().0.x - x(rects[1])).abs() < 0.001);
+
+        let (_, svg) = render_graph("digraph { a [style=\"filled,shadow\"]; }");
+        assert_eq!(svg.matches("<ellipse").count(), 2);
+    }
+
+    #[test]
+    fn edge_tension() {
+        // Returns the control points of the edge a -> d, which passes
+        // through two connectors.
+        let curve = |tension: Option<f64>| {
+            let mut vg = build_graph("digraph { a -> b -> c -> d; a -> d; }");
+            if let Some(tension) = tension {
+                vg.set_edge_tension(tension);
+            }
+            let mut svg = SVGWriter::new();
+            vg.do_it(false, false, false, &mut svg);
+            let lengths = vg.edge_lengths();
+            (lengths[3], svg.finalize())
+        };
+        let (default, default_svg) = curve(None);
+        let (same, same_svg) = curve(Some(1.));
+        assert_eq!(default, same);
+        assert_eq!(default_svg, same_svg);
+
+        // Higher tension makes longer tangents, and a longer control polygon.
+        let (low, low_svg) = curve(Some(0.5));
+        let (high, high_svg) = curve(Some(2.));
+        assert!(low < default && default < high);
+        assert_ne!(low_svg, default_svg);
+        assert_ne!(high_svg, default_svg);
+    }
+
+    #[test]
+    fn tikz_output() {
+        use layout::backends::tikz::TikZWriter;
+
+        let mut vg = build_graph(
+            "digraph { a [shape=box, label=\"x_1\"]; a -> b [label=\"50%\"]; }",
+        );
+        let mut tikz = TikZWriter::new();
+        vg.do_it(false, false, false, &mut tikz);
+        let content = tikz.finalize();
+        assert!(content.starts_with("\\begin{tikzpicture}"));
+        assert!(content.ends_with("\\end{tikzpicture}\n"));
+        assert_eq!(content.matches(" rectangle ").count(), 1);
+        assert_eq!(content.matches(" ellipse ").count(), 1);
+        assert!(content.contains("{x\\_1}"));
+        assert!(content.contains("{50\\%}"));
+
+        // The edge is a bezier curve with explicit control points, and the
+        // y axis points up.
+        let edge = content.lines().find(|x| x.contains("-latex]")).unwrap();
+        assert_eq!(edge.matches(".. controls").count(), 2);
+        let a = vg.pos(vg.handle_for_name("a").unwrap()).center();
+        let b = vg.pos(vg.handle_for_name("b").unwrap()).center();
+        assert!(a.y < b.y);
+        assert!(edge.contains(",-"));
+        assert!(!edge.contains(",0)"));
+    }
+
+    #[test]
+    fn connector_separation() {
+        // Returns the smallest distance between two connectors in the same
+        // rank, for four parallel long edges.
+        let spread = |sep: Option<f64>| {
+            let mut vg = build_graph(
+                "digraph { t -> m -> n -> z; t -> z; t -> z; t -> z; t -> z; }",
+            );
+            if let Some(sep) = sep {
+                vg.set_connector_sep(sep);
+            }
+            let mut svg = SVGWriter::new();
+            vg.do_it(false, false, false, &mut svg);
+            let mut min = f64::INFINITY;
+            for level in 0..vg.dag.num_levels() {
+                let mut xs: Vec<f64> = vg
+                    .dag
+                    .row(level)
+                    .iter()
+                    .filter(|x| vg.is_connector(**x))
+                    .map(|x| vg.pos(*x).center().x)
+                    .collect();
+                xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                for pair in xs.windows(2) {
+                    min = min.min(pair[1] - pair[0]);
+                }
+            }
+            min
+        };
+        let default = spread(None);
+        assert!(default.is_finite());
+        assert!(spread(Some(40.)) >= 40. - 0.001);
+        assert!(spread(Some(40.)) > default);
+    }
+
+    #[test]
+    fn invalid_attribute_warnings() {
+        use layout::adt::dag::NodeHandle;
+        let program = "digraph { ratio=-1; a [fontsize=-5]; \
+            b [shape=polygon, sides=1, opacity=2]; \
+            a -> b [penwidth=thick, arrowsize=0, fontsize=12]; }";
+        let ast = DotParser::new(program).process().unwrap();
+        let mut gb = GraphBuilder::new();
+        gb.visit_graph(&ast);
+        let (mut vg, warnings) = gb.get_with_warnings();
+        assert_eq!(
+            warnings,
+            vec![
+                "ratio=-1 is out of range; ignored",
+                "fontsize=-5 is out of range; ignored",
+                "sides=1 is out of range; ignored",
+                "opacity=2 is out of range; ignored",
+                "arrowsize=0 is out of range; ignored",
+                "penwidth=\"thick\" is not a number; ignored",
+            ]
+        );
+
+        // The invalid values fall back to the defaults, and the valid ones
+        // still apply.
+        let a = vg.element(NodeHandle::new(0));
+        assert_eq!(a.look.font_size, 14);
+        let arrow = &vg.edge_list()[0].0;
+        assert_eq!(arrow.look.line_width, 1);
+        assert_eq!(arrow.look.font_size, 12);
+        assert_eq!(vg.ratio(), None);
+
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+        assert!(svg.finalize().contains("<svg"));
+
+        // The ratio keywords are valid, and only unknown strings warn.
+        let warnings = |ratio: &str| {
+            let program = format!("digraph {{ ratio={}; a -> b; }}", ratio);
+            let ast = DotParser::new(&program).process().unwrap();
+            let mut gb = GraphBuilder::new();
+            gb.visit_graph(&ast);
+            gb.get_with_warnings().1
+        };
+        for keyword in ["fill", "compress", "expand", "auto"] {
+            assert!(warnings(keyword).is_empty());
+        }
+        assert_eq!(
+            warnings("tall"),
+            vec!["ratio=\"tall\" is not a number; ignored"]
+        );
+    }
+
+    #[test]
+    fn floating_legend() {
+        use layout::core::base::{Anchor, Orientation};
+        use layout::core::format::Visible;
+        use layout::core::style::StyleAttr;
+        use layout::std_shapes::render::get_shape_size;
+        use layout::std_shapes::shapes::{Element, ShapeKind};
+
+        let legend = |label: &str| {
+            let dir = Orientation::TopToBottom;
+            let shape = ShapeKind::new_record(&RecordDef::Array(vec![
+                RecordDef::new_text("Legend"),
+                RecordDef::new_text(label),
+            ]));
+            let size = get_shape_size(dir, &shape, 15, false);
+            Element::create(shape, StyleAttr::simple(), dir, size)
+        };
+
+        let mut vg = build_graph("digraph { a -> b; a -> c; a -> d; a -> e; }");
+        vg.add_floating(legend("solid: call"), Anchor::BottomRight);
+        vg.add_floating(legend("title"), Anchor::TopLeft);
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+        let content = svg.finalize();
+        assert!(content.contains("solid: call"));
+
+        let bb = vg.bounding_box();
+        let bottom = vg.floating_elements()[0].0.position().bbox(false);
+        let top = vg.floating_elements()[1].0.position().bbox(false);
+        // The legend is below the graph and aligned to its right side.
+        assert!(bottom.0.y > bb.1.y);
+        assert!((bottom.1.x - bb.1.x).abs() < 1e-6);
+        // The title is above the graph, which was moved down to make room.
+        assert!(top.1.y < bb.0.y);
+        assert!(top.0.y >= 0.);
+        assert!((top.0.x - bb.0.x).abs() < 1e-6);
+    }
+
+    #[test]
+    fn rank_bands_align_with_ranks() {
+        use layout::adt::dag::NodeHandle;
+        use layout::core::color::Color;
+
+        for rankdir in ["TB", "LR"] {
+            let program = format!(
+                "digraph {{ rankdir={}; a -> b; a -> c; b -> d; c -> d; \
+                 d -> e; a -> e; }}",
+                rankdir
+            );
+            let mut vg = build_graph(&program);
+            vg.set_rank_bands(Color::from_name("#eeeeee"));
+            let mut svg = SVGWriter::new();
+            vg.do_it(false, false, false, &mut svg);
+            let content = svg.finalize();
+            // Every other rank is drawn, behind the nodes.
+            assert!(content.contains("fill=\"#eeeeeeff\""));
+
+            let bands = vg.rank_bands();
+            let bb = vg.bounding_box();
+            assert_eq!(bands.len(), 4);
+            let lr = rankdir == "LR";
+            for (i, band) in bands.iter().enumerate() {
+                // The bands span the whole drawing and do not overlap.
+                if lr {
+                    assert_eq!((band.0.y, band.1.y), (bb.0.y, bb.1.y));
+                } else {
+                    assert_eq!((band.0.x, band.1.x), (bb.0.x, bb.1.x));
+                }
+                if i > 0 {
+                    let prev = bands[i - 1].1;
+                    if lr {
+                        assert!(prev.x <= band.0.x + 1e-6);
+                    } else {
+                        assert!(prev.y <= band.0.y + 1e-6);
+                    }
+                }
+            }
+            // The nodes are inside the band of their rank.
+            // The handles of a, b, d and e, and the ranks of the nodes.
+            for (node, rank) in [(0, 0), (1, 1), (3, 2), (4, 3)] {
+                let pos = vg.pos(NodeHandle::new(node)).bbox(false);
+                let band = bands[rank];
+                assert!(pos.0.x >= band.0.x && pos.1.x <= band.1.x);
+                assert!(pos.0.y >= band.0.y && pos.1.y <= band.1.y);
+            }
+        }
+    }
+
+    #[test]
+    fn svg_finalize_to_writer() {
+        let mut vg = build_graph("digraph { a -> b [label=\"x < y\"]; }");
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+        let mut buffer: Vec<u8> = Vec::new();
+        svg.finalize_to(&mut buffer).unwrap();
+        assert_eq!(String::from_utf8(buffer).unwrap(), svg.finalize());
+    }
+
+    #[test]
+    fn node_and_edge_opacity() {
+        let program = "digraph { a [opacity=0.5, style=filled, \
+                       fillcolor=red]; b; a -> b [color=\"#0000ff80\"]; \
+                       b -> c [opacity=0.25]; }";
+        let (_, svg) = render_graph(program);
+        // The node is faded, including its label.
+        assert!(svg.contains("fill=\"#ff0000\" fill-opacity=\"0.502\""));
+        assert!(svg.contains("stroke=\"#000000\" stroke-opacity=\"0.502\""));
+        assert!(svg.contains("fill=\"#000000\" fill-opacity=\"0.502\"><tspan"));
+        // The alpha channel of the color is used for the line of the edge.
+        assert!(svg.contains("stroke=\"#0000ff\" stroke-opacity=\"0.502\""));
+        assert!(svg.contains("stroke=\"#000000\" stroke-opacity=\"0.251\""));
+        // Opaque elements are not affected.
+        assert!(svg.contains("stroke=\"#000000ff\""));
+    }
+
+    #[test]
+    fn svg_fragments_with_unique_ids() {
+        // Returns the values of the attribute \p attr in \p svg.
+        let values = |svg: &str, attr: &str| -> Vec<String> {
+            let pattern = format!("{}=\"", attr);
+            svg.split(&pattern)
+                .skip(1)
+                .map(|x| x[..x.find('"').unwrap()].to_string())
+                .collect()
+        };
+
+        let program = "digraph { a [shape=Mrecord, label=\"{x|y}\"]; \
+                       a -> b [label=\"e\"]; }";
+        let mut fragments = Vec::new();
+        for prefix in ["first-", "second-"] {
+            let mut vg = build_graph(program);
+            let mut svg = SVGWriter::new();
+            svg.set_id_prefix(prefix);
+            vg.do_it(false, false, false, &mut svg);
+            let fragment = svg.finalize_fragment();
+            assert!(fragment.starts_with("<g>"));
+            assert!(!fragment.contains("<?xml") && !fragment.contains("<svg"));
+            assert!(svg.size().x > 0. && svg.size().y > 0.);
+            fragments.push(fragment);
+        }
+
+        // The ids of the fragments don't collide, and all of the references
+        // point into the same fragment.
+        let ids0 = values(&fragments[0], "id");
+        let ids1 = values(&fragments[1], "id");
+        assert!(ids0.iter().any(|id| id.starts_with("first-C")));
+        assert!(ids0.iter().any(|id| id == "first-endarrow"));
+        assert!(ids0.iter().all(|id| !ids1.contains(id)));
+        for (fragment, prefix) in fragments.iter().zip(["first-", "second-"]) {
+            let ids = values(fragment, "id");
+            for href in values(fragment, "href") {
+                assert!(ids.contains(&href[1..].to_string()));
+            }
+            for class in values(fragment, "class") {
+                assert!(class.starts_with(prefix));
+                assert!(fragment.contains(&format!(".{} {{", class)));
+            }
+        }
+
+        // Documents without a prefix are not affected.
+        let (_, svg) = render_graph(program);
+        assert!(svg.contains("url(#endarrow)") && svg.contains("id=\"C0\""));
+    }
+
+    #[test]
+    fn monospace_label_whitespace() {
+        let program = "digraph { a [shape=box, fontname=Courier, \
+            label=\"if (x) {\\n    y();\\n\tz();\\n}\"]; b; a -> b; }";
+        let (_, svg) = render_graph(program);
+        assert!(svg.contains("font-family: Courier, monospace"));
+        assert!(svg.contains("xml:space=\"preserve\""));
+        // The leading spaces are kept, and the tab is expanded.
+        assert!(svg.contains(">    y();    <"));
+        assert!(svg.contains(">        z();<"));
+
+        // Labels in the default font are not padded.
+        let (_, svg) = render_graph("digraph { a [label=\"x\\n  yy\"]; }");
+        assert!(!svg.contains("xml:space"));
+        assert!(svg.contains(">x<"));
+    }
+
+    #[test]
+    fn custom_node_renderer() {
+        use layout::adt::dag::NodeHandle;
+
+        let mut vg = build_graph("digraph { a -> b; }");
+        let b = NodeHandle::new(1);
+        let size = vg.element(b).pos.size(false);
+        vg.element_mut(b)
+            .set_renderer(move |canvas, top_left, sz, look| {
+                assert_eq!(sz.x, size.x);
+                let center = top_left.add(sz.scale(0.5));
+                canvas.draw_text(center, "custom drawing", look);
+            });
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+        let content = svg.finalize();
+        assert!(content.contains("custom drawing"));
+        // The standard shape of 'b' is not drawn.
+        assert!(content.contains(">a<"));
+        assert!(!content.contains(">b<"));
+        assert_eq!(content.matches("<ellipse").count(), 1);
+    }
+
+    #[test]
+    fn edge_chains_after_lowering() {
+        use layout::adt::dag::NodeHandle;
+        use layout::core::format::Visible;
+
+        let program = "digraph { a -> b; b -> c; a -> c; a -> a; c -> a; }";
+        let (vg, _) = render_graph(program);
+        let (a, b, c) =
+            (NodeHandle::new(0), NodeHandle::new(1), NodeHandle::new(2));
+        let chains = vg.edge_chains();
+        assert_eq!(chains.len(), 5);
+        assert_eq!(chains[0], vec![a, b]);
+        assert_eq!(chains[1], vec![b, c]);
+
+        // The long edges go through one connector, in their own direction.
+        for (chain, from, to) in [(&chains[2], a, c), (&chains[4], c, a)] {
+            assert_eq!(chain.len(), 3);
+            assert_eq!((chain[0], chain[2]), (from, to));
+            assert!(vg.element(chain[1]).is_connector());
+        }
+
+        // The self edge loops through a connector.
+        assert_eq!(chains[3].len(), 3);
+        assert_eq!((chains[3][0], chains[3][2]), (a, a));
+        assert!(vg.element(chains[3][1]).is_connector());
+    }
+
+    #[test]
+    fn head_label_font() {
+        let program = "digraph { a -> b [color=blue, headlabel=\"H1\", \
+            taillabel=\"T1\", labelfontcolor=red, labelfontsize=9]; }";
+        let (_, svg) = render_graph(program);
+        // Find the text element of each label.
+        let text_of = |label: &str| {
+            let end = svg.find(&format!(">{}<", label)).unwrap();
+            let start = svg[..end].rfind("<text").unwrap();
+            svg[start..end].to_string()
+        };
+        for label in ["H1", "T1"] {
+            let text = text_of(label);
+            assert!(text.contains("fill=\"#ff0000ff\""));
+            assert!(text.contains("class=\"a9\""));
+        }
+        // The edge itself keeps its color.
+        assert!(svg.contains("stroke=\"#0000ffff\""));
+        assert!(!svg.contains("stroke=\"#ff0000ff\""));
+    }
+
+    #[test]
+    fn truncated_labels() {
+        let name = "a_very_long_automatically_generated_identifier";
+        let program = format!(
+            "digraph {{ {} [shape=box]; {} -> b [label={}]; }}",
+            name, name, name
+        );
+        let ast = DotParser::new(&program).process().unwrap();
+        let mut gb = GraphBuilder::new();
+        gb.set_max_label_width(140.);
+        gb.visit_graph(&ast);
+        let mut vg = gb.get();
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+        let content = svg.finalize();
+        assert!(!content.contains(name));
+        assert!(content.contains(&format!(">{}\u{2026}<", &name[..9])));
+
+        // The box is as wide as a box with a label of the maximum width.
+        let limit =
+            build_graph("digraph { a [shape=box, label=\"0123456789\"]; }");
+        let width = |vg: &VisualGraph, i: usize| {
+            let node = layout::adt::dag::NodeHandle::new(i);
+            vg.element(node).pos.size(false).x
+        };
+        assert!(width(&vg, 0) <= width(&limit, 0));
+    }
+
+    #[test]
+    fn vertical_label() {
+        use layout::core::base::Orientation;
+        use layout::core::style::StyleAttr;
+        use layout::std_shapes::render::get_shape_size_with_style;
+        use layout::std_shapes::shapes::{Arrow, Element, ShapeKind};
+
+        let dir = Orientation::LeftToRight;
+        let shape = ShapeKind::new_box("a long column header");
+        let mut look = StyleAttr::simple();
+        let flat = get_shape_size_with_style(dir, &shape, &look, false, None);
+        look.vertical_text = true;
+        let size = get_shape_size_with_style(dir, &shape, &look, false, None);
+        assert!(flat.x > flat.y);
+        assert_eq!((size.x, size.y), (flat.y, flat.x));
+
+        let mut vg = VisualGraph::new(Orientation::TopToBottom);
+        let header = vg.add_node(Element::create(shape, look, dir, size));
+        let shape = ShapeKind::new_box("cell");
+        let size = get_shape_size_with_style(
+            dir,
+            &shape,
+            &StyleAttr::simple(),
+            false,
+            None,
+        );
+        let cell =
+            vg.add_node(Element::create(shape, StyleAttr::simple(), dir, size));
+        vg.add_edge(Arrow::simple(""), header, cell);
+        let mut svg = SVGWriter::new();
+        vg.do_it(false, false, false, &mut svg);
+        let content = svg.finalize();
+
+        // The header is taller than it is wide, and its text is rotated
+        // around its center.
+        let pos = vg.element(header).pos;
+        assert!(pos.size(false).y > pos.size(false).x);
+        let center = pos.center();
+        let rotate = format!("rotate(-90 {} {})", center.x, center.y);
+        assert!(content.contains(&rotate));
+        assert_eq!(content.matches("rotate(").count(), 1);
+    }
+
     #[test]
     fn test_median() {
         let k = weighted_median(&[1.]);